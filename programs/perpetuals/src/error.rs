@@ -64,4 +64,54 @@ pub enum PerpetualsError {
     PermissionlessOracleSignerMismatch,
     #[msg("Signed message does not match instruction params")]
     PermissionlessOracleMessageMismatch,
+    #[msg("Auto-deleveraging is not required")]
+    AdlNotRequired,
+    #[msg("Position is not eligible for auto-deleveraging")]
+    AdlInvalidPosition,
+    #[msg("Invalid tranche config")]
+    InvalidTrancheConfig,
+    #[msg("Invalid tranche account")]
+    InvalidTranche,
+    #[msg("Invalid swap route")]
+    InvalidSwapRoute,
+    #[msg("Delayed orders are required for this pool")]
+    DelayedOrdersRequired,
+    #[msg("Order can't be executed at this time")]
+    OrderNotExecutable,
+    #[msg("Oracle price was not published after the order request")]
+    OrderOraclePriceNotUpdated,
+    #[msg("Order can't be cancelled before it expires")]
+    OrderNotExpired,
+    #[msg("Referral rewards limit exceeded")]
+    ReferralRewardsLimit,
+    #[msg("Invalid trading delegate")]
+    InvalidTradingDelegate,
+    #[msg("Unsupported account version")]
+    UnsupportedAccountVersion,
+    #[msg("Invalid trading schedule")]
+    InvalidTradingSchedule,
+    #[msg("Market is closed")]
+    MarketClosed,
+    #[msg("Price gap after market re-open exceeds the limit")]
+    MarketGapProtection,
+    #[msg("Oracle circuit breaker is tripped, only closing positions is allowed")]
+    CircuitBreakerTripped,
+    #[msg("Invalid derived oracle config")]
+    InvalidDerivedOracleConfig,
+    #[msg("Stablecoin is depegged")]
+    StablecoinDepegged,
+    #[msg("Invalid dated market config")]
+    InvalidDatedMarketConfig,
+    #[msg("Invalid dated market state")]
+    InvalidDatedMarketState,
+    #[msg("Dated market is closed for new positions")]
+    DatedMarketClosed,
+    #[msg("Position is expired and can only be settled")]
+    PositionExpired,
+    #[msg("Failed to compute the amount before fees")]
+    FeeInversionFailed,
+    #[msg("Invalid referral")]
+    InvalidReferral,
+    #[msg("Deprecated custody has to be converted with upgrade_custody")]
+    DeprecatedCustodyUpgradeRequired,
 }
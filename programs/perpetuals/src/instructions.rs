@@ -1,13 +1,22 @@
 // admin instructions
 pub mod add_custody;
+pub mod add_dated_market;
 pub mod add_pool;
+pub mod add_tranche;
 pub mod init;
+pub mod migrate_account;
 pub mod remove_custody;
 pub mod remove_pool;
 pub mod set_admin_signers;
+pub mod set_borrow_rate_model;
+pub mod set_circuit_breaker;
 pub mod set_custody_config;
 pub mod set_custom_oracle_price;
+pub mod set_derived_oracle;
 pub mod set_permissions;
+pub mod set_pool_config;
+pub mod set_target_ratios;
+pub mod set_trading_schedule;
 pub mod upgrade_custody;
 pub mod withdraw_fees;
 pub mod withdraw_sol_fees;
@@ -18,9 +27,18 @@ pub mod set_test_time;
 // public instructions
 pub mod add_collateral;
 pub mod add_liquidity;
+pub mod add_liquidity_exact_out;
+pub mod add_tranche_liquidity;
+pub mod auto_deleverage;
+pub mod cancel_order;
+pub mod claim_referral_rewards;
 pub mod close_position;
+pub mod execute_close_position;
+pub mod execute_open_position;
 pub mod get_add_liquidity_amount_and_fee;
+pub mod get_add_liquidity_amount_in_and_fee;
 pub mod get_assets_under_management;
+pub mod get_custody_stats_history;
 pub mod get_entry_price_and_fee;
 pub mod get_exit_price_and_fee;
 pub mod get_liquidation_price;
@@ -29,24 +47,54 @@ pub mod get_lp_token_price;
 pub mod get_oracle_price;
 pub mod get_pnl;
 pub mod get_remove_liquidity_amount_and_fee;
+pub mod get_remove_liquidity_amount_in_and_fee;
 pub mod get_swap_amount_and_fees;
+pub mod get_swap_amount_in_and_fees;
+pub mod init_trader_stats;
+pub mod init_user_stats;
 pub mod liquidate;
 pub mod open_position;
+pub mod register_referral;
 pub mod remove_collateral;
 pub mod remove_liquidity;
+pub mod remove_liquidity_exact_out;
+pub mod remove_tranche_liquidity;
+pub mod request_close_position;
+pub mod request_open_position;
+pub mod revoke_trading_delegate;
 pub mod set_custom_oracle_price_permissionless;
+pub mod set_trading_delegate;
+pub mod settle_expired_position;
 pub mod swap;
+pub mod swap_exact_out;
+pub mod swap_route;
+pub mod transfer_position;
+pub mod update_circuit_breaker;
+pub mod update_custody_stats_history;
+pub mod update_dated_market;
+pub mod update_derived_oracle;
 pub mod update_pool_aum;
 
 // bring everything in scope
 pub use {
-    add_collateral::*, add_custody::*, add_liquidity::*, add_pool::*, close_position::*,
-    get_add_liquidity_amount_and_fee::*, get_assets_under_management::*,
-    get_entry_price_and_fee::*, get_exit_price_and_fee::*, get_liquidation_price::*,
-    get_liquidation_state::*, get_lp_token_price::*, get_oracle_price::*, get_pnl::*,
-    get_remove_liquidity_amount_and_fee::*, get_swap_amount_and_fees::*, init::*, liquidate::*,
-    open_position::*, remove_collateral::*, remove_custody::*, remove_liquidity::*, remove_pool::*,
-    set_admin_signers::*, set_custody_config::*, set_custom_oracle_price::*,
-    set_custom_oracle_price_permissionless::*, set_permissions::*, set_test_time::*, swap::*,
+    add_collateral::*, add_custody::*, add_dated_market::*, add_liquidity::*,
+    add_liquidity_exact_out::*, add_pool::*, add_tranche::*, add_tranche_liquidity::*,
+    auto_deleverage::*, cancel_order::*, claim_referral_rewards::*, close_position::*,
+    execute_close_position::*, execute_open_position::*, get_add_liquidity_amount_and_fee::*,
+    get_add_liquidity_amount_in_and_fee::*, get_assets_under_management::*,
+    get_custody_stats_history::*, get_entry_price_and_fee::*, get_exit_price_and_fee::*,
+    get_liquidation_price::*, get_liquidation_state::*, get_lp_token_price::*, get_oracle_price::*,
+    get_pnl::*, get_remove_liquidity_amount_and_fee::*, get_remove_liquidity_amount_in_and_fee::*,
+    get_swap_amount_and_fees::*, get_swap_amount_in_and_fees::*, init::*, init_trader_stats::*,
+    init_user_stats::*, liquidate::*, migrate_account::*, open_position::*, register_referral::*,
+    remove_collateral::*, remove_custody::*, remove_liquidity::*, remove_liquidity_exact_out::*,
+    remove_pool::*, remove_tranche_liquidity::*, request_close_position::*,
+    request_open_position::*, revoke_trading_delegate::*, set_admin_signers::*,
+    set_borrow_rate_model::*, set_circuit_breaker::*, set_custody_config::*,
+    set_custom_oracle_price::*, set_custom_oracle_price_permissionless::*, set_derived_oracle::*,
+    set_permissions::*, set_pool_config::*, set_target_ratios::*, set_test_time::*,
+    set_trading_delegate::*, set_trading_schedule::*, settle_expired_position::*, swap::*,
+    swap_exact_out::*, swap_route::*, transfer_position::*, update_circuit_breaker::*,
+    update_custody_stats_history::*, update_dated_market::*, update_derived_oracle::*,
     update_pool_aum::*, upgrade_custody::*, withdraw_fees::*, withdraw_sol_fees::*,
 };
@@ -5,11 +5,13 @@ use {
         error::PerpetualsError,
         math,
         state::{
-            custody::Custody,
-            oracle::OraclePrice,
+            custody::{Custody, DepegStatus},
+            oracle::{ActionClass, OraclePrice},
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            trading_delegate::TradingDelegate,
+            user_stats::UserStats,
         },
     },
     anchor_lang::prelude::*,
@@ -20,13 +22,19 @@ use {
 #[derive(Accounts)]
 #[instruction(params: AddCollateralParams)]
 pub struct AddCollateral<'info> {
+    // position owner or its trading delegate
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, doesn't sign if the authority is a trading delegate
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
         constraint = funding_account.mint == custody.mint,
-        has_one = owner
+        constraint = funding_account.owner == owner.key()
+            || funding_account.owner == authority.key()
     )]
     pub funding_account: Box<Account<'info, TokenAccount>>,
 
@@ -58,7 +66,8 @@ pub struct AddCollateral<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -96,6 +105,22 @@ pub struct AddCollateral<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // required if the authority is not the position owner
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
+
+    // records realized pnl and fees of the owner, created on first use
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
 
@@ -116,14 +141,27 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
 
-    // compute position price
+    // check trading authority
     let curtime = perpetuals.get_time()?;
+    require!(
+        !position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
+    TradingDelegate::check_authority(
+        ctx.accounts.trading_delegate.as_deref().map(|d| &**d),
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        &custody.key(),
+        curtime,
+    )?;
 
+    // compute position price
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -131,6 +169,7 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
@@ -140,6 +179,7 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
         &collateral_custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -149,8 +189,15 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
+    // check stablecoin depeg of the collateral
+    require!(
+        collateral_custody.get_depeg_status(&collateral_token_price)? != DepegStatus::Hard,
+        PerpetualsError::StablecoinDepegged
+    );
+
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
@@ -190,7 +237,7 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
         ctx.accounts
             .collateral_custody_token_account
             .to_account_info(),
-        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.collateral,
     )?;
@@ -200,6 +247,17 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     collateral_custody.assets.collateral =
         math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
 
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        &pool.key(),
+        *ctx.bumps
+            .get("user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+    user_stats.record_add_collateral(collateral_usd, curtime);
+
     // if custody and collateral_custody accounts are the same, ensure that data is in sync
     if position.side == Side::Long && !custody.is_virtual {
         *custody = collateral_custody.clone();
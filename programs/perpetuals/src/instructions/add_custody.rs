@@ -5,6 +5,7 @@ use {
         error::PerpetualsError,
         state::{
             custody::{BorrowRateParams, Custody, Fees, PricingParams},
+            migration::Versioned,
             multisig::{AdminInstruction, Multisig},
             oracle::OracleParams,
             perpetuals::{Permissions, Perpetuals},
@@ -129,12 +130,15 @@ pub fn add_custody<'info>(
     // update pool data
     pool.custodies.push(ctx.accounts.custody.key());
     pool.ratios = params.ratios.clone();
+    // explicitly set ratios cancel scheduled re-weighting
+    pool.next_targets = Vec::new();
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
 
     // record custody data
     let custody = ctx.accounts.custody.as_mut();
+    custody.version = Custody::CURRENT_VERSION;
     custody.pool = pool.key();
     custody.mint = ctx.accounts.custody_token_mint.key();
     custody.token_account = ctx.accounts.custody_token_account.key();
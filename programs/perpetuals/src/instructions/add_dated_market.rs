@@ -0,0 +1,142 @@
+//! AddDatedMarket instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            dated_market::DatedMarket,
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: AddDatedMarketParams)]
+pub struct AddDatedMarket<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = DatedMarket::LEN,
+        seeds = [b"dated_market",
+                 custody.key().as_ref(),
+                 &params.expiry_time.to_le_bytes()],
+        bump
+    )]
+    pub dated_market: Box<Account<'info, DatedMarket>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddDatedMarketParams {
+    pub expiry_time: i64,
+    pub open_cutoff_sec: u32,
+    pub settlement_window_sec: u32,
+    pub max_sample_interval_sec: u32,
+    pub min_samples: u32,
+}
+
+pub fn add_dated_market<'info>(
+    ctx: Context<'_, '_, '_, 'info, AddDatedMarket<'info>>,
+    params: &AddDatedMarketParams,
+) -> Result<u8> {
+    // validate inputs
+    require!(
+        !ctx.accounts.custody.is_stable,
+        PerpetualsError::InvalidDatedMarketConfig
+    );
+    require!(
+        ctx.accounts.collateral_custody.is_stable && !ctx.accounts.collateral_custody.is_virtual,
+        PerpetualsError::InvalidCollateralCustody
+    );
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::AddDatedMarket, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // record dated market data
+    let dated_market = ctx.accounts.dated_market.as_mut();
+    if dated_market.expiry_time != 0 {
+        // return error if the market is already initialized
+        return Err(ProgramError::AccountAlreadyInitialized.into());
+    }
+    msg!("Record dated market, expiry: {}", params.expiry_time);
+    **dated_market = DatedMarket {
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        collateral_custody: ctx.accounts.collateral_custody.key(),
+        expiry_time: params.expiry_time,
+        open_cutoff_sec: params.open_cutoff_sec,
+        settlement_window_sec: params.settlement_window_sec,
+        max_sample_interval_sec: params.max_sample_interval_sec,
+        min_samples: params.min_samples,
+        bump: *ctx
+            .bumps
+            .get("dated_market")
+            .ok_or(ProgramError::InvalidSeeds)?,
+        ..DatedMarket::default()
+    };
+
+    if !dated_market.validate()
+        || !dated_market.is_open_allowed(ctx.accounts.perpetuals.get_time()?)
+    {
+        return err!(PerpetualsError::InvalidDatedMarketConfig);
+    }
+
+    Ok(0)
+}
@@ -6,7 +6,6 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::{AumCalcMode, Pool},
         },
@@ -17,7 +16,6 @@ use {
 };
 
 #[derive(Accounts)]
-#[instruction(params: AddLiquidityParams)]
 pub struct AddLiquidity<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -89,7 +87,7 @@ pub struct AddLiquidity<'info> {
     )]
     pub lp_token_mint: Box<Account<'info, Mint>>,
 
-    token_program: Program<'info, Token>,
+    pub token_program: Program<'info, Token>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
@@ -128,18 +126,10 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
     pool.aum_usd =
         pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
 
-    let token_price = OraclePrice::new_from_oracle(
+    let (token_price, token_ema_price) = custody.get_liquidity_prices(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
         curtime,
-        false,
-    )?;
-
-    let token_ema_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
-        curtime,
-        custody.pricing.use_ema,
+        true,
     )?;
 
     let min_price = if token_price < token_ema_price {
@@ -148,28 +138,14 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         token_ema_price
     };
 
-    let fee_amount =
-        pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_ema_price)?;
-    msg!("Collected fee: {}", fee_amount);
-
-    // check pool constraints
-    msg!("Check pool constraints");
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-    let deposit_amount = math::checked_sub(params.amount_in, protocol_fee)?;
-    require!(
-        pool.check_token_ratio(token_id, deposit_amount, 0, custody, &token_ema_price)?,
-        PerpetualsError::TokenRatioOutOfRange
-    );
-
-    // transfer tokens
-    msg!("Transfer tokens");
-    perpetuals.transfer_tokens_from_user(
-        ctx.accounts.funding_account.to_account_info(),
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.owner.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
+    let fee_amount = pool.get_add_liquidity_fee(
+        token_id,
         params.amount_in,
+        custody,
+        &token_ema_price,
+        curtime,
     )?;
+    msg!("Collected fee: {}", fee_amount);
 
     // compute assets under management
     msg!("Compute assets under management");
@@ -204,6 +180,27 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         PerpetualsError::MaxPriceSlippage
     );
 
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    pool.settle_add_liquidity(
+        token_id,
+        params.amount_in,
+        fee_amount,
+        custody,
+        &token_ema_price,
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount_in,
+    )?;
+
     // mint lp tokens
     perpetuals.mint_tokens(
         ctx.accounts.lp_token_mint.to_account_info(),
@@ -213,24 +210,6 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         lp_amount,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    custody.collected_fees.add_liquidity_usd = custody
-        .collected_fees
-        .add_liquidity_usd
-        .wrapping_add(token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
-
-    custody.volume_stats.add_liquidity_usd = custody
-        .volume_stats
-        .add_liquidity_usd
-        .wrapping_add(token_ema_price.get_asset_amount_usd(params.amount_in, custody.decimals)?);
-
-    custody.assets.protocol_fees = math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
-
-    custody.assets.owned = math::checked_add(custody.assets.owned, deposit_amount)?;
-
-    custody.update_borrow_rate(curtime)?;
-
     // update pool stats
     msg!("Update pool stats");
     custody.exit(&crate::ID)?;
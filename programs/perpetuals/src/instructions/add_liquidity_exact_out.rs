@@ -0,0 +1,120 @@
+//! AddLiquidityExactOut instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError, instructions::add_liquidity::AddLiquidity, state::pool::AumCalcMode,
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddLiquidityExactOutParams {
+    pub lp_amount_out: u64,
+    pub max_amount_in: u64,
+}
+
+pub fn add_liquidity_exact_out(
+    ctx: Context<AddLiquidity>,
+    params: &AddLiquidityExactOutParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_add_liquidity
+            && custody.permissions.allow_add_liquidity
+            && !custody.is_virtual,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.lp_amount_out == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_mut();
+    let token_id = pool.get_token_id(&custody.key())?;
+
+    // calculate fee
+    let curtime = perpetuals.get_time()?;
+
+    // Refresh pool.aum_usm to adapt to token price change
+    pool.aum_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+
+    let (token_price, token_ema_price) = custody.get_liquidity_prices(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        curtime,
+        true,
+    )?;
+
+    let min_price = if token_price < token_ema_price {
+        token_price
+    } else {
+        token_ema_price
+    };
+
+    // compute assets under management
+    msg!("Compute assets under management");
+    let pool_amount_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::Max, ctx.remaining_accounts, curtime)?;
+
+    // compute amount of tokens required to mint lp tokens
+    let (amount_in, fee_amount) = pool.get_add_liquidity_amount_in(
+        token_id,
+        params.lp_amount_out,
+        ctx.accounts.lp_token_mint.supply,
+        pool_amount_usd,
+        custody,
+        &token_ema_price,
+        &min_price,
+        curtime,
+    )?;
+    msg!("Amount in: {}", amount_in);
+    msg!("Collected fee: {}", fee_amount);
+
+    require!(
+        amount_in <= params.max_amount_in,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    pool.settle_add_liquidity(
+        token_id,
+        amount_in,
+        fee_amount,
+        custody,
+        &token_ema_price,
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount_in,
+    )?;
+
+    // mint lp tokens
+    perpetuals.mint_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.lp_amount_out,
+    )?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    custody.exit(&crate::ID)?;
+    pool.aum_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+
+    Ok(())
+}
@@ -4,6 +4,7 @@ use {
     crate::{
         error::PerpetualsError,
         state::{
+            migration::Versioned,
             multisig::{AdminInstruction, Multisig},
             perpetuals::Perpetuals,
             pool::Pool,
@@ -115,6 +116,7 @@ pub fn add_pool<'info>(
     }
     msg!("Record pool: {}", params.name);
     pool.inception_time = perpetuals.get_time()?;
+    pool.version = Pool::CURRENT_VERSION;
     pool.name = params.name.clone();
     pool.bump = *ctx.bumps.get("pool").ok_or(ProgramError::InvalidSeeds)?;
     pool.lp_token_bump = *ctx
@@ -0,0 +1,172 @@
+//! AddTranche instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            tranche::Tranche,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token},
+};
+
+#[derive(Accounts)]
+#[instruction(params: AddTrancheParams)]
+pub struct AddTranche<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = Tranche::LEN,
+        seeds = [b"tranche",
+                 pool.key().as_ref(),
+                 params.name.as_bytes()],
+        bump
+    )]
+    pub tranche: Box<Account<'info, Tranche>>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        mint::authority = transfer_authority,
+        mint::freeze_authority = transfer_authority,
+        mint::decimals = Perpetuals::LP_DECIMALS,
+        seeds = [b"tranche_lp_token_mint",
+                 tranche.key().as_ref()],
+        bump
+    )]
+    pub tranche_lp_token_mint: Box<Account<'info, Mint>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+    // remaining accounts:
+    //   params.markets.len() market custody accounts (write, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddTrancheParams {
+    pub name: String,
+    pub markets: Vec<Pubkey>,
+    pub max_utilization: u64,
+}
+
+pub fn add_tranche<'info>(
+    ctx: Context<'_, '_, '_, 'info, AddTranche<'info>>,
+    params: &AddTrancheParams,
+) -> Result<u8> {
+    // validate inputs
+    if params.name.is_empty()
+        || params.name.len() > 64
+        || ctx.remaining_accounts.len() != params.markets.len()
+    {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require!(
+        ctx.accounts.custody.is_stable && !ctx.accounts.custody.is_virtual,
+        PerpetualsError::InvalidCollateralCustody
+    );
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::AddTranche, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // record tranche data
+    let pool = ctx.accounts.pool.as_ref();
+    let tranche = ctx.accounts.tranche.as_mut();
+
+    if tranche.inception_time != 0 {
+        // return error if tranche is already initialized
+        return Err(ProgramError::AccountAlreadyInitialized.into());
+    }
+    msg!("Record tranche: {}", params.name);
+    tranche.name = params.name.clone();
+    tranche.pool = pool.key();
+    tranche.custody = ctx.accounts.custody.key();
+    tranche.markets = params.markets.clone();
+    tranche.max_utilization = params.max_utilization;
+    tranche.inception_time = ctx.accounts.perpetuals.get_time()?;
+    tranche.bump = *ctx.bumps.get("tranche").ok_or(ProgramError::InvalidSeeds)?;
+    tranche.lp_token_bump = *ctx
+        .bumps
+        .get("tranche_lp_token_mint")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    if !tranche.validate() {
+        return err!(PerpetualsError::InvalidTrancheConfig);
+    }
+
+    // assign markets to the tranche
+    for (account, market) in ctx.remaining_accounts.iter().zip(params.markets.iter()) {
+        require_keys_eq!(account.key(), *market);
+        pool.get_token_id(market)?;
+
+        let mut market_custody = Account::<Custody>::try_from(account)?;
+        // only markets without open positions can be moved to a tranche
+        require!(
+            market_custody.is_virtual
+                && !market_custody.is_tranche_backed()
+                && market_custody.long_positions.open_positions == 0
+                && market_custody.short_positions.open_positions == 0,
+            PerpetualsError::InvalidTrancheConfig
+        );
+        market_custody.tranche = tranche.key();
+        market_custody.exit(&crate::ID)?;
+    }
+
+    Ok(0)
+}
@@ -0,0 +1,362 @@
+//! AutoDeleverage instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::{ActionClass, OraclePrice},
+            perpetuals::Perpetuals,
+            pool::{AumCalcMode, Pool},
+            position::Position,
+            user_stats::UserStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct AutoDeleverage<'info> {
+    pub signer: Signer<'info>,
+
+    /// CHECK: account that paid for the position, receives position account rent
+    /// if the position is closed
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == position.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = rent_payer,
+        seeds = [b"position",
+                 position.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    // records realized pnl and fees of the owner, created by the owner when the position
+    // is opened so that keepers don't pay for it
+    #[account(
+        mut,
+        seeds = [b"user_stats",
+                 position.owner.as_ref(),
+                 pool.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   any number of other positions with the same custody and collateral custody
+    //   (read-only, unsigned), the deleveraged position must rank above all of them
+    //
+    // The ranking is only checked against the positions passed in, the program can't
+    // enumerate positions on-chain. The only global guarantee is the adl.min_pnl_ratio
+    // floor, so which eligible position gets deleveraged first is up to the caller.
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AutoDeleverageParams {}
+
+pub fn auto_deleverage(ctx: Context<AutoDeleverage>, _params: &AutoDeleverageParams) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+
+    // tranche positions don't contribute to the pool profit
+    require_keys_eq!(
+        position.tranche,
+        Pubkey::default(),
+        PerpetualsError::AdlInvalidPosition
+    );
+
+    // check if pool needs to be deleveraged
+    msg!("Check pool state");
+    let curtime = perpetuals.get_time()?;
+    require!(
+        !position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
+
+    let (assets_usd, profit_usd) = pool.get_assets_and_unrealized_profit_usd(
+        AumCalcMode::EMA,
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+    msg!(
+        "Pool assets: {}, unrealized profit: {}",
+        assets_usd,
+        profit_usd
+    );
+
+    require!(
+        pool.check_adl_required(assets_usd, profit_usd)?,
+        PerpetualsError::AdlNotRequired
+    );
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        false,
+        ActionClass::Reduce,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        ActionClass::Reduce,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        ActionClass::Reduce,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
+    )?;
+
+    // check position ranking against the supplied positions only, see the
+    // remaining accounts note above
+    msg!("Check position state");
+    let adl_score = pool.get_adl_score(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+    )?;
+    msg!("ADL score: {}", adl_score);
+    require_gt!(adl_score, 0, PerpetualsError::AdlInvalidPosition);
+
+    let position_key = position.key();
+    for account in &ctx.remaining_accounts[(pool.custodies.len() * 2)..] {
+        require_keys_neq!(account.key(), position_key);
+        let other_position = Account::<Position>::try_from(account)?;
+        require!(
+            other_position.pool == pool.key()
+                && other_position.custody == custody.key()
+                && other_position.collateral_custody == collateral_custody.key(),
+            PerpetualsError::AdlInvalidPosition
+        );
+        let other_score = pool.get_adl_score(
+            &other_position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+        )?;
+        require_gte!(adl_score, other_score, PerpetualsError::AdlInvalidPosition);
+    }
+
+    // deleveraged positions don't pay the close fee
+    let (position_profit_usd, _, _) = pool.get_pnl_usd_with_fee(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        0,
+    )?;
+    require!(
+        pool.check_adl_eligible(position_profit_usd, position.collateral_usd)?,
+        PerpetualsError::AdlInvalidPosition
+    );
+
+    // reduce the position only by the size needed to get back under the threshold
+    let required_profit_usd = pool.get_adl_required_profit_usd(assets_usd, profit_usd)?;
+    let close_size_usd = if required_profit_usd < position_profit_usd as u128 {
+        math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(position.size_usd as u128, required_profit_usd)?,
+            position_profit_usd as u128,
+        )?)?
+    } else {
+        position.size_usd
+    };
+    let full_close = close_size_usd >= position.size_usd;
+    let closed_position = if full_close {
+        (**position).clone()
+    } else {
+        position.split(close_size_usd)?
+    };
+    msg!("Deleveraged size: {}", closed_position.size_usd);
+
+    let interest_usd = collateral_custody.get_interest_amount_usd(&closed_position, curtime)?;
+
+    msg!("Settle position");
+    let (transfer_amount, _, profit_usd, loss_usd) = pool.get_close_amount_with_fee(
+        &closed_position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        0,
+    )?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Amount out: {}", transfer_amount);
+
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    pool.settle_close_position(
+        &closed_position,
+        full_close,
+        false,
+        transfer_amount,
+        (0, 0),
+        0,
+        (profit_usd, loss_usd),
+        custody,
+        collateral_custody,
+        None,
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    if full_close {
+        user_stats.record_close(
+            closed_position.size_usd,
+            0,
+            interest_usd,
+            profit_usd,
+            loss_usd,
+            false,
+            curtime,
+        );
+    } else {
+        user_stats.record_reduce(
+            closed_position.size_usd,
+            0,
+            interest_usd,
+            profit_usd,
+            loss_usd,
+            curtime,
+        );
+    }
+
+    if full_close {
+        ctx.accounts
+            .position
+            .close(ctx.accounts.rent_payer.to_account_info())?;
+    } else {
+        position.update_time = curtime;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! CancelOrder instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            order::{OrderType, PendingOrder},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: account that paid for the order, receives order accounts rent
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    // required for open position orders
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        has_one = rent_payer,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 order.custody.as_ref(),
+                 &[order.side as u8],
+                 order.get_market_seed()],
+        bump = order.bump,
+        close = rent_payer
+    )]
+    pub order: Box<Account<'info, PendingOrder>>,
+
+    // required for open position orders
+    #[account(
+        mut,
+        seeds = [b"order_token_account",
+                 order.key().as_ref()],
+        bump = order.token_account_bump
+    )]
+    pub order_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct CancelOrderParams {}
+
+pub fn cancel_order(ctx: Context<CancelOrder>, _params: &CancelOrderParams) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let pool = ctx.accounts.pool.as_ref();
+    let order = ctx.accounts.order.as_ref();
+    let curtime = perpetuals.get_time()?;
+    require!(
+        !pool.orders.is_enabled() || pool.orders.is_expired(order.request_time, curtime)?,
+        PerpetualsError::OrderNotExpired
+    );
+
+    // return escrowed collateral
+    if order.order_type == OrderType::OpenPosition {
+        let receiving_account = ctx
+            .accounts
+            .receiving_account
+            .as_ref()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let order_token_account = ctx
+            .accounts
+            .order_token_account
+            .as_ref()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        require_keys_eq!(receiving_account.mint, order_token_account.mint);
+
+        msg!("Transfer tokens");
+        perpetuals.transfer_tokens(
+            order_token_account.to_account_info(),
+            receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            order_token_account.amount,
+        )?;
+
+        Perpetuals::close_token_account(
+            ctx.accounts.rent_payer.to_account_info(),
+            order_token_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+        )?;
+    }
+
+    Ok(())
+}
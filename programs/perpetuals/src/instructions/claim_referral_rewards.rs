@@ -0,0 +1,98 @@
+//! ClaimReferralRewards instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{custody::Custody, perpetuals::Perpetuals, pool::Pool, referral::Referral},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account()]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint,
+        constraint = receiving_account.owner == referrer.key()
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = referrer,
+        seeds = [b"referral",
+                 referral.code.as_bytes()],
+        bump = referral.bump
+    )]
+    pub referral: Box<Account<'info, Referral>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ClaimReferralRewardsParams {}
+
+pub fn claim_referral_rewards(
+    ctx: Context<ClaimReferralRewards>,
+    _params: &ClaimReferralRewardsParams,
+) -> Result<()> {
+    // collect rewards
+    msg!("Collect rewards");
+    let custody_key = ctx.accounts.custody.key();
+    let amount = ctx.accounts.referral.take_reward(&custody_key);
+    require!(amount > 0, PerpetualsError::InsufficientAmountReturned);
+    msg!("Amount out: {}", amount);
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    ctx.accounts.perpetuals.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount,
+    )?;
+
+    Ok(())
+}
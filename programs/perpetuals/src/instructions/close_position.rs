@@ -6,10 +6,15 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            trading_delegate::TradingDelegate,
+            tranche::Tranche,
+            user_stats::UserStats,
         },
     },
     anchor_lang::prelude::*,
@@ -18,8 +23,17 @@ use {
 
 #[derive(Accounts)]
 pub struct ClosePosition<'info> {
+    // position owner or its trading delegate
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, doesn't sign if the authority is a trading delegate
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+
+    /// CHECK: account that paid for the position, receives position account rent
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
 
     #[account(
         mut,
@@ -52,13 +66,15 @@ pub struct ClosePosition<'info> {
     #[account(
         mut,
         has_one = owner,
+        has_one = rent_payer,
         seeds = [b"position",
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump,
-        close = owner
+        close = rent_payer
     )]
     pub position: Box<Account<'info, Position>>,
 
@@ -95,6 +111,37 @@ pub struct ClosePosition<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // required if the position funds are locked in a tranche
+    #[account(mut)]
+    pub tranche: Option<Box<Account<'info, Tranche>>>,
+
+    // optional, enables trading volume based fee discounts
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
+    // optional, credits a share of the fee to the referrer
+    #[account(mut)]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // required if the authority is not the position owner
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
+
+    // records realized pnl and fees of the owner, created on first use
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
 
@@ -113,6 +160,10 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
         PerpetualsError::InstructionNotAllowed
     );
+    require!(
+        !ctx.accounts.pool.orders.is_enabled(),
+        PerpetualsError::DelayedOrdersRequired
+    );
 
     // validate inputs
     msg!("Validate inputs");
@@ -121,43 +172,80 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
     }
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
+    let tranche = if position.tranche != Pubkey::default() {
+        let tranche = ctx
+            .accounts
+            .tranche
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidTranche)?;
+        require_keys_eq!(
+            tranche.key(),
+            position.tranche,
+            PerpetualsError::InvalidTranche
+        );
+        Some(tranche)
+    } else {
+        None
+    };
 
-    // compute exit price
+    // check trading authority
     let curtime = perpetuals.get_time()?;
+    require!(
+        !position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
+    TradingDelegate::check_authority(
+        ctx.accounts.trading_delegate.as_deref().map(|d| &**d),
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        &custody.key(),
+        curtime,
+    )?;
 
+    // compute exit price
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+        &custody.get_oracle_params(curtime),
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+        &custody.get_oracle_params(curtime),
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts
             .collateral_custody_oracle_account
             .to_account_info(),
-        &collateral_custody.oracle,
+        &collateral_custody.get_oracle_params(curtime),
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts
             .collateral_custody_oracle_account
             .to_account_info(),
-        &collateral_custody.oracle,
+        &collateral_custody.get_oracle_params(curtime),
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
-    let exit_price = pool.get_exit_price(&token_price, &token_ema_price, position.side, custody)?;
+    let exit_price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        custody,
+        curtime,
+    )?;
     msg!("Exit price: {}", exit_price);
 
     if position.side == Side::Long {
@@ -166,8 +254,25 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         require_gte!(params.price, exit_price, PerpetualsError::MaxPriceSlippage);
     }
 
+    // check referral
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        ctx.accounts
+            .trader_stats
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidReferral)?
+            .bind_referral(&referral.key(), referral)?;
+    }
+
+    let trader_volume_usd = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        trader_stats.get_volume_usd(curtime)?
+    } else {
+        0
+    };
+
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+
     msg!("Settle position");
-    let (transfer_amount, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+    let (mut transfer_amount, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
         position,
         &token_price,
         &token_ema_price,
@@ -177,6 +282,7 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         collateral_custody,
         curtime,
         false,
+        trader_volume_usd,
     )?;
 
     let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
@@ -185,19 +291,44 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
             .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
     }
 
+    // referrer reward and trader rebate are shares of the full fee
+    let (referrer_reward, mut trader_rebate) = if ctx.accounts.referral.is_some() {
+        pool.get_referral_amounts(fee_amount, custody.fees.protocol_share)?
+    } else {
+        (0, 0)
+    };
+    if transfer_amount == 0 {
+        // fee was not collected in full, nothing to rebate
+        trader_rebate = 0;
+    }
+    transfer_amount = math::checked_add(transfer_amount, trader_rebate)?;
+
     msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
-    msg!("Collected fee: {}", fee_amount);
+    msg!(
+        "Collected fee: {}",
+        math::checked_sub(fee_amount, trader_rebate)?
+    );
     msg!("Amount out: {}", transfer_amount);
 
-    // unlock pool funds
-    collateral_custody.unlock_funds(position.locked_amount)?;
-
-    // check pool constraints
+    // check pool constraints and update custody stats
     msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(transfer_amount, collateral_custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
+    let collateral_custody_key = collateral_custody.key();
+    let referrer_reward = pool.settle_close_position(
+        position,
+        true,
+        false,
+        transfer_amount,
+        (fee_amount, fee_amount_usd),
+        referrer_reward,
+        (profit_usd, loss_usd),
+        custody,
+        collateral_custody,
+        tranche.map(|tranche| &mut ***tranche),
+        curtime,
+    )?;
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        referral.add_reward(&collateral_custody_key, referrer_reward)?;
+    }
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -211,92 +342,28 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         transfer_amount,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    collateral_custody.collected_fees.close_position_usd = collateral_custody
-        .collected_fees
-        .close_position_usd
-        .wrapping_add(fee_amount_usd);
-
-    if transfer_amount > position.collateral_amount {
-        let amount_lost = transfer_amount.saturating_sub(position.collateral_amount);
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(transfer_amount);
-        collateral_custody.assets.owned =
-            math::checked_add(collateral_custody.assets.owned, amount_gained)?;
-    }
-    collateral_custody.assets.collateral = math::checked_sub(
-        collateral_custody.assets.collateral,
-        position.collateral_amount,
-    )?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, collateral_custody)? {
-        collateral_custody.assets.protocol_fees =
-            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
-
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
-    }
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        &ctx.accounts.pool.key(),
+        *ctx.bumps
+            .get("user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+    user_stats.record_close(
+        position.size_usd,
+        fee_amount_usd,
+        interest_usd,
+        profit_usd,
+        loss_usd,
+        false,
+        curtime,
+    );
 
-    // if custody and collateral_custody accounts are the same, ensure that data is in sync
-    if position.side == Side::Long && !custody.is_virtual {
-        collateral_custody.volume_stats.close_position_usd = collateral_custody
-            .volume_stats
-            .close_position_usd
-            .wrapping_add(position.size_usd);
-
-        if position.side == Side::Long {
-            collateral_custody.trade_stats.oi_long_usd = collateral_custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            collateral_custody.trade_stats.oi_short_usd = collateral_custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        collateral_custody.trade_stats.profit_usd = collateral_custody
-            .trade_stats
-            .profit_usd
-            .wrapping_add(profit_usd);
-        collateral_custody.trade_stats.loss_usd = collateral_custody
-            .trade_stats
-            .loss_usd
-            .wrapping_add(loss_usd);
-
-        collateral_custody.remove_position(position, curtime, None)?;
-        collateral_custody.update_borrow_rate(curtime)?;
-        *custody = collateral_custody.clone();
-    } else {
-        custody.volume_stats.close_position_usd = custody
-            .volume_stats
-            .close_position_usd
-            .wrapping_add(position.size_usd);
-
-        if position.side == Side::Long {
-            custody.trade_stats.oi_long_usd = custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            custody.trade_stats.oi_short_usd = custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-        custody.remove_position(position, curtime, Some(collateral_custody))?;
-        collateral_custody.update_borrow_rate(curtime)?;
+    // update trader stats
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(position.size_usd, curtime)?;
     }
 
     Ok(())
@@ -0,0 +1,383 @@
+//! ExecuteClosePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::{ActionClass, OraclePrice},
+            order::{OrderType, PendingOrder},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            tranche::Tranche,
+            user_stats::UserStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteClosePosition<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    /// CHECK: position owner
+    #[account(
+        constraint = owner.key() == order.owner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    /// CHECK: account that paid for the position, receives position account rent
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == order.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        seeds = [b"order",
+                 order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[order.side as u8],
+                 order.get_market_seed()],
+        bump = order.bump,
+        close = keeper
+    )]
+    pub order: Box<Account<'info, PendingOrder>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = rent_payer,
+        constraint = position.side == order.side,
+        constraint = position.dated_market == order.dated_market,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump = position.bump,
+        close = rent_payer
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    // required if the position funds are locked in a tranche
+    #[account(mut)]
+    pub tranche: Option<Box<Account<'info, Tranche>>>,
+
+    // records trading volume of the owner, enables fee discounts
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"trader_stats",
+                 owner.key().as_ref()],
+        bump = trader_stats.bump
+    )]
+    pub trader_stats: Box<Account<'info, TraderStats>>,
+
+    // required if the order was requested with a referral
+    #[account(
+        mut,
+        constraint = referral.key() == order.referral
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // records realized pnl and fees of the owner
+    #[account(
+        mut,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ExecuteClosePositionParams {}
+
+pub fn execute_close_position(
+    ctx: Context<ExecuteClosePosition>,
+    _params: &ExecuteClosePositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let order = ctx.accounts.order.as_ref();
+    require!(
+        order.order_type == OrderType::ClosePosition,
+        PerpetualsError::InvalidPositionState
+    );
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let tranche = if position.tranche != Pubkey::default() {
+        let tranche = ctx
+            .accounts
+            .tranche
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidTranche)?;
+        require_keys_eq!(
+            tranche.key(),
+            position.tranche,
+            PerpetualsError::InvalidTranche
+        );
+        Some(tranche)
+    } else {
+        None
+    };
+
+    // check order delay, oracle prices must be published after the request
+    let curtime = perpetuals.get_time()?;
+    require!(
+        !position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
+    require!(
+        pool.orders.is_executable(order.request_time, curtime)?,
+        PerpetualsError::OrderNotExecutable
+    );
+    require!(
+        OraclePrice::get_publish_time(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            &custody.oracle
+        )? > order.request_time
+            && OraclePrice::get_publish_time(
+                &ctx.accounts
+                    .collateral_custody_oracle_account
+                    .to_account_info(),
+                &collateral_custody.oracle
+            )? > order.request_time,
+        PerpetualsError::OrderOraclePriceNotUpdated
+    );
+
+    // compute exit price
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.get_oracle_params(curtime),
+        curtime,
+        false,
+        ActionClass::Reduce,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.get_oracle_params(curtime),
+        curtime,
+        custody.pricing.use_ema,
+        ActionClass::Reduce,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.get_oracle_params(curtime),
+        curtime,
+        false,
+        ActionClass::Reduce,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.get_oracle_params(curtime),
+        curtime,
+        collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
+    )?;
+
+    let exit_price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        custody,
+        curtime,
+    )?;
+    msg!("Exit price: {}", exit_price);
+
+    if position.side == Side::Long {
+        require_gte!(exit_price, order.price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(order.price, exit_price, PerpetualsError::MaxPriceSlippage);
+    }
+
+    // check referral
+    require!(
+        order.referral == Pubkey::default() || ctx.accounts.referral.is_some(),
+        PerpetualsError::InvalidReferral
+    );
+
+    let trader_volume_usd = ctx.accounts.trader_stats.get_volume_usd(curtime)?;
+
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+
+    msg!("Settle position");
+    let (mut transfer_amount, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        false,
+        trader_volume_usd,
+    )?;
+
+    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    if position.side == Side::Short || custody.is_virtual {
+        fee_amount = collateral_token_ema_price
+            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+    }
+
+    // referrer reward and trader rebate are shares of the full fee
+    let (referrer_reward, mut trader_rebate) = if ctx.accounts.referral.is_some() {
+        pool.get_referral_amounts(fee_amount, custody.fees.protocol_share)?
+    } else {
+        (0, 0)
+    };
+    if transfer_amount == 0 {
+        // fee was not collected in full, nothing to rebate
+        trader_rebate = 0;
+    }
+    transfer_amount = math::checked_add(transfer_amount, trader_rebate)?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!(
+        "Collected fee: {}",
+        math::checked_sub(fee_amount, trader_rebate)?
+    );
+    msg!("Amount out: {}", transfer_amount);
+
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    let collateral_custody_key = collateral_custody.key();
+    let referrer_reward = pool.settle_close_position(
+        position,
+        true,
+        false,
+        transfer_amount,
+        (fee_amount, fee_amount_usd),
+        referrer_reward,
+        (profit_usd, loss_usd),
+        custody,
+        collateral_custody,
+        tranche.map(|tranche| &mut ***tranche),
+        curtime,
+    )?;
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        referral.add_reward(&collateral_custody_key, referrer_reward)?;
+    }
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.record_close(
+        position.size_usd,
+        fee_amount_usd,
+        interest_usd,
+        profit_usd,
+        loss_usd,
+        false,
+        curtime,
+    );
+
+    // update trader stats
+    ctx.accounts
+        .trader_stats
+        .add_volume(position.size_usd, curtime)?;
+
+    Ok(())
+}
@@ -0,0 +1,571 @@
+//! ExecuteOpenPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::{Custody, DepegStatus},
+            dated_market::DatedMarket,
+            migration::Versioned,
+            oracle::{ActionClass, OraclePrice},
+            order::{OrderType, PendingOrder},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            tranche::Tranche,
+            user_stats::UserStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct ExecuteOpenPosition<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        constraint = order.custody == custody.key(),
+        constraint = order.collateral_custody == collateral_custody.key(),
+        seeds = [b"order",
+                 order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[order.side as u8],
+                 order.get_market_seed()],
+        bump = order.bump,
+        close = keeper
+    )]
+    pub order: Box<Account<'info, PendingOrder>>,
+
+    #[account(
+        mut,
+        seeds = [b"order_token_account",
+                 order.key().as_ref()],
+        bump = order.token_account_bump
+    )]
+    pub order_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: position owner
+    #[account(
+        constraint = owner.key() == order.owner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    /// CHECK: account that paid for the order, receives escrow account rent
+    #[account(
+        mut,
+        constraint = rent_payer.key() == order.rent_payer
+    )]
+    pub rent_payer: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = keeper,
+        space = Position::LEN,
+        seeds = [b"position",
+                 order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[order.side as u8],
+                 order.get_market_seed()],
+        bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    // required if the position custody is backed by a tranche
+    #[account(mut)]
+    pub tranche: Option<Box<Account<'info, Tranche>>>,
+
+    // records trading volume of the owner, enables fee discounts
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"trader_stats",
+                 owner.key().as_ref()],
+        bump = trader_stats.bump
+    )]
+    pub trader_stats: Box<Account<'info, TraderStats>>,
+
+    // required if the order was requested with a referral
+    #[account(
+        mut,
+        constraint = referral.key() == order.referral
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // records realized pnl and fees of the owner
+    #[account(
+        mut,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    // required if the order is for a dated futures position
+    #[account(
+        constraint = dated_market.key() == order.dated_market
+    )]
+    pub dated_market: Option<Box<Account<'info, DatedMarket>>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ExecuteOpenPositionParams {}
+
+pub fn execute_open_position(
+    ctx: Context<ExecuteOpenPosition>,
+    _params: &ExecuteOpenPositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && !custody.is_stable,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let order = ctx.accounts.order.as_ref();
+    require!(
+        order.order_type == OrderType::OpenPosition,
+        PerpetualsError::InvalidPositionState
+    );
+    let use_collateral_custody = order.side == Side::Short || custody.is_virtual;
+    if use_collateral_custody {
+        require_keys_neq!(custody.key(), collateral_custody.key());
+        require!(
+            collateral_custody.is_stable && !collateral_custody.is_virtual,
+            PerpetualsError::InvalidCollateralCustody
+        );
+    } else {
+        require_keys_eq!(custody.key(), collateral_custody.key());
+    };
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+
+    // check dated market expiry
+    let curtime = perpetuals.get_time()?;
+    if order.dated_market != Pubkey::default() {
+        let dated_market = ctx
+            .accounts
+            .dated_market
+            .as_deref()
+            .ok_or(PerpetualsError::InvalidDatedMarketState)?;
+        require!(
+            dated_market.is_open_allowed(curtime),
+            PerpetualsError::DatedMarketClosed
+        );
+    }
+
+    // check order delay, oracle prices must be published after the request
+    require!(
+        pool.orders.is_executable(order.request_time, curtime)?,
+        PerpetualsError::OrderNotExecutable
+    );
+    require!(
+        OraclePrice::get_publish_time(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            &custody.oracle
+        )? > order.request_time
+            && OraclePrice::get_publish_time(
+                &ctx.accounts
+                    .collateral_custody_oracle_account
+                    .to_account_info(),
+                &collateral_custody.oracle
+            )? > order.request_time,
+        PerpetualsError::OrderOraclePriceNotUpdated
+    );
+
+    // compute position price
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.get_oracle_params(curtime),
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.get_oracle_params(curtime),
+        curtime,
+        custody.pricing.use_ema,
+        ActionClass::Increase,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.get_oracle_params(curtime),
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.get_oracle_params(curtime),
+        curtime,
+        collateral_custody.pricing.use_ema,
+        ActionClass::Increase,
+    )?;
+
+    // check trading hours
+    custody
+        .trading_schedule
+        .check_increase_allowed(&token_price, &token_ema_price, curtime)?;
+
+    // check oracle circuit breaker
+    require!(
+        custody.update_circuit_breaker(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            curtime
+        )?,
+        PerpetualsError::CircuitBreakerTripped
+    );
+    if !use_collateral_custody {
+        collateral_custody.circuit_breaker = custody.circuit_breaker;
+    }
+
+    // check stablecoin depeg of the collateral
+    require!(
+        !use_collateral_custody
+            || collateral_custody.get_depeg_status(&collateral_token_price)? == DepegStatus::Pegged,
+        PerpetualsError::StablecoinDepegged
+    );
+
+    let min_collateral_price = collateral_token_price
+        .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
+
+    let position_price =
+        pool.get_entry_price(&token_price, &token_ema_price, order.side, custody, curtime)?;
+    msg!("Entry price: {}", position_price);
+
+    if order.side == Side::Long {
+        require_gte!(
+            order.price,
+            position_price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    } else {
+        require_gte!(
+            position_price,
+            order.price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    }
+
+    // compute position parameters
+    let position_oracle_price = OraclePrice {
+        price: position_price,
+        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+        conf: 0,
+    };
+    let size_usd = position_oracle_price.get_asset_amount_usd(order.size, custody.decimals)?;
+    require_gte!(
+        order.max_size_usd,
+        size_usd,
+        PerpetualsError::PositionAmountLimit
+    );
+
+    let locked_amount = if use_collateral_custody {
+        custody.get_locked_amount(
+            min_collateral_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+            order.side,
+        )?
+    } else {
+        custody.get_locked_amount(order.size, order.side)?
+    };
+
+    let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+        if use_collateral_custody {
+            let max_collateral_price = if collateral_token_price < collateral_token_ema_price {
+                collateral_token_ema_price
+            } else {
+                collateral_token_price
+            };
+            max_collateral_price.get_asset_amount_usd(locked_amount, collateral_custody.decimals)?
+        } else {
+            position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+        }
+    } else {
+        size_usd
+    };
+
+    // check referral
+    require!(
+        order.referral == Pubkey::default() || ctx.accounts.referral.is_some(),
+        PerpetualsError::InvalidReferral
+    );
+
+    let trader_volume_usd = ctx.accounts.trader_stats.get_volume_usd(curtime)?;
+
+    // compute fee
+    let mut fee_amount = pool.get_entry_fee(
+        custody.fees.open_position,
+        order.size,
+        locked_amount,
+        collateral_custody,
+        trader_volume_usd,
+    )?;
+    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    if use_collateral_custody {
+        fee_amount = collateral_token_ema_price
+            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+    }
+    // protocol fee, referrer reward and trader rebate are all shares of the full fee
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    let (referrer_reward, trader_rebate) = if ctx.accounts.referral.is_some() {
+        pool.get_referral_amounts(fee_amount, custody.fees.protocol_share)?
+    } else {
+        (0, 0)
+    };
+    fee_amount = math::checked_sub(fee_amount, trader_rebate)?;
+    msg!("Collected fee: {}", fee_amount);
+
+    // entry fee is paid out of escrowed collateral
+    require!(
+        order.collateral > fee_amount,
+        PerpetualsError::InsufficientAmountReturned
+    );
+    let transfer_amount = order.collateral;
+    let collateral = math::checked_sub(order.collateral, fee_amount)?;
+    let collateral_usd =
+        min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
+    msg!("Amount in: {}", transfer_amount);
+
+    // init new position
+    msg!("Initialize new position");
+    position.version = Position::CURRENT_VERSION;
+    position.owner = order.owner;
+    position.pool = pool.key();
+    position.custody = custody.key();
+    position.collateral_custody = collateral_custody.key();
+    position.open_time = perpetuals.get_time()?;
+    position.update_time = 0;
+    position.side = order.side;
+    position.price = position_price;
+    position.size_usd = size_usd;
+    position.borrow_size_usd = borrow_size_usd;
+    position.collateral_usd = collateral_usd;
+    position.unrealized_profit_usd = 0;
+    position.unrealized_loss_usd = 0;
+    position.cumulative_interest_snapshot =
+        collateral_custody.get_cumulative_interest(order.side, curtime)?;
+    position.locked_amount = locked_amount;
+    position.collateral_amount = collateral;
+    position.tranche = custody.tranche;
+    position.rent_payer = order.rent_payer;
+    position.bump = *ctx
+        .bumps
+        .get("position")
+        .ok_or(ProgramError::InvalidSeeds)?;
+    if let Some(dated_market) = ctx.accounts.dated_market.as_deref() {
+        position.dated_market = dated_market.key();
+        position.expiry_time = dated_market.expiry_time;
+    }
+
+    // check position risk
+    msg!("Check position risks");
+    require!(
+        position.locked_amount > 0,
+        PerpetualsError::InsufficientAmountReturned
+    );
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // lock funds for potential profit payoff
+    if custody.is_tranche_backed() {
+        let tranche = ctx
+            .accounts
+            .tranche
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidTranche)?;
+        require!(
+            tranche.key() == custody.tranche && tranche.custody == collateral_custody.key(),
+            PerpetualsError::InvalidTranche
+        );
+        tranche.lock_funds(position.locked_amount)?;
+    } else {
+        collateral_custody.lock_funds(position.locked_amount)?;
+    }
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.order_token_account.to_account_info(),
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.rent_payer.to_account_info(),
+        ctx.accounts.order_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    collateral_custody.collected_fees.open_position_usd = collateral_custody
+        .collected_fees
+        .open_position_usd
+        .wrapping_add(fee_amount_usd);
+
+    collateral_custody.assets.collateral =
+        math::checked_add(collateral_custody.assets.collateral, collateral)?;
+
+    collateral_custody.assets.protocol_fees =
+        math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        referral.add_reward(&collateral_custody.key(), referrer_reward)?;
+    }
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if position.side == Side::Long && !custody.is_virtual {
+        collateral_custody.volume_stats.open_position_usd = collateral_custody
+            .volume_stats
+            .open_position_usd
+            .wrapping_add(size_usd);
+
+        if order.side == Side::Long {
+            collateral_custody.trade_stats.oi_long_usd =
+                math::checked_add(collateral_custody.trade_stats.oi_long_usd, size_usd)?;
+        } else {
+            collateral_custody.trade_stats.oi_short_usd =
+                math::checked_add(collateral_custody.trade_stats.oi_short_usd, size_usd)?;
+        }
+
+        collateral_custody.add_position(position, &token_ema_price, curtime, None)?;
+        collateral_custody.update_borrow_rate(curtime)?;
+        *custody = collateral_custody.clone();
+    } else {
+        custody.volume_stats.open_position_usd = custody
+            .volume_stats
+            .open_position_usd
+            .wrapping_add(size_usd);
+
+        if order.side == Side::Long {
+            custody.trade_stats.oi_long_usd =
+                math::checked_add(custody.trade_stats.oi_long_usd, size_usd)?;
+        } else {
+            custody.trade_stats.oi_short_usd =
+                math::checked_add(custody.trade_stats.oi_short_usd, size_usd)?;
+        }
+
+        custody.add_position(
+            position,
+            &token_ema_price,
+            curtime,
+            Some(collateral_custody),
+        )?;
+        collateral_custody.update_borrow_rate(curtime)?;
+    }
+
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.record_open(size_usd, fee_amount_usd, curtime);
+
+    // update trader stats
+    ctx.accounts.trader_stats.add_volume(size_usd, curtime)?;
+
+    Ok(())
+}
@@ -5,7 +5,7 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::{AmountAndFee, Perpetuals},
             pool::{AumCalcMode, Pool},
         },
@@ -77,6 +77,7 @@ pub fn get_add_liquidity_amount_and_fee(
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -84,10 +85,11 @@ pub fn get_add_liquidity_amount_and_fee(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let fee_amount =
-        pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_price)?;
+        pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_price, curtime)?;
     let no_fee_amount = math::checked_sub(params.amount_in, fee_amount)?;
 
     let pool_amount_usd =
@@ -0,0 +1,59 @@
+//! GetCustodyStatsHistory instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        custody_stats_history::{CustodyStatsHistory, StatsBucket},
+        perpetuals::Perpetuals,
+        pool::Pool,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct GetCustodyStatsHistory<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"custody_stats_history",
+                 custody.key().as_ref()],
+        bump = custody_stats_history.bump
+    )]
+    pub custody_stats_history: Box<Account<'info, CustodyStatsHistory>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetCustodyStatsHistoryParams {
+    // number of days to return, up to CustodyStatsHistory::MAX_VIEW_DAYS
+    pub num_days: u8,
+}
+
+pub fn get_custody_stats_history(
+    ctx: Context<GetCustodyStatsHistory>,
+    params: &GetCustodyStatsHistoryParams,
+) -> Result<Vec<StatsBucket>> {
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    ctx.accounts
+        .custody_stats_history
+        .get_window(params.num_days as usize, curtime)
+}
@@ -3,7 +3,7 @@
 use {
     crate::state::{
         custody::Custody,
-        oracle::OraclePrice,
+        oracle::{ActionClass, OraclePrice},
         perpetuals::{NewPositionPricesAndFee, Perpetuals},
         pool::Pool,
         position::{Position, Side},
@@ -83,6 +83,7 @@ pub fn get_entry_price_and_fee(
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -90,6 +91,7 @@ pub fn get_entry_price_and_fee(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
@@ -99,6 +101,7 @@ pub fn get_entry_price_and_fee(
         &collateral_custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -108,16 +111,24 @@ pub fn get_entry_price_and_fee(
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    let entry_price = pool.get_entry_price(&token_price, &token_ema_price, params.side, custody)?;
+    let entry_price = pool.get_entry_price(
+        &token_price,
+        &token_ema_price,
+        params.side,
+        custody,
+        curtime,
+    )?;
 
     let position_oracle_price = OraclePrice {
         price: entry_price,
         exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+        conf: 0,
     };
     let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
     let collateral_usd = min_collateral_price
@@ -137,7 +148,8 @@ pub fn get_entry_price_and_fee(
         price: entry_price,
         size_usd,
         collateral_usd,
-        cumulative_interest_snapshot: collateral_custody.get_cumulative_interest(curtime)?,
+        cumulative_interest_snapshot: collateral_custody
+            .get_cumulative_interest(params.side, curtime)?,
         ..Position::default()
     };
 
@@ -154,6 +166,7 @@ pub fn get_entry_price_and_fee(
         params.size,
         locked_amount,
         collateral_custody,
+        0,
     )?;
 
     if params.side == Side::Short || custody.is_virtual {
@@ -166,5 +179,6 @@ pub fn get_entry_price_and_fee(
         entry_price,
         liquidation_price,
         fee,
+        spread: custody.get_trade_spread(params.side, &token_price, curtime)?,
     })
 }
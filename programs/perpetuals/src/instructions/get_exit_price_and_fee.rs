@@ -3,7 +3,7 @@
 use {
     crate::state::{
         custody::Custody,
-        oracle::OraclePrice,
+        oracle::{ActionClass, OraclePrice},
         perpetuals::{Perpetuals, PriceAndFee},
         pool::Pool,
         position::{Position, Side},
@@ -31,7 +31,8 @@ pub struct GetExitPriceAndFee<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -84,6 +85,7 @@ pub fn get_exit_price_and_fee(
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -91,6 +93,7 @@ pub fn get_exit_price_and_fee(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -100,13 +103,20 @@ pub fn get_exit_price_and_fee(
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
-    let price = pool.get_exit_price(&token_price, &token_ema_price, position.side, custody)?;
+    let price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        custody,
+        curtime,
+    )?;
 
     let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
 
-    let mut fee = pool.get_exit_fee(size, custody)?;
+    let mut fee = pool.get_exit_fee(size, custody, 0)?;
 
     if position.side == Side::Short || custody.is_virtual {
         let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee, custody.decimals)?;
@@ -114,5 +124,12 @@ pub fn get_exit_price_and_fee(
             .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
     }
 
-    Ok(PriceAndFee { price, fee })
+    let exit_side = if position.side == Side::Long {
+        Side::Short
+    } else {
+        Side::Long
+    };
+    let spread = custody.get_trade_spread(exit_side, &token_price, curtime)?;
+
+    Ok(PriceAndFee { price, fee, spread })
 }
@@ -4,7 +4,10 @@ use {
     crate::{
         math,
         state::{
-            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            custody::Custody,
+            oracle::{ActionClass, OraclePrice},
+            perpetuals::Perpetuals,
+            pool::Pool,
             position::Position,
         },
     },
@@ -31,7 +34,8 @@ pub struct GetLiquidationPrice<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -81,6 +85,7 @@ pub fn get_liquidation_price(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
@@ -90,6 +95,7 @@ pub fn get_liquidation_price(
         &collateral_custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -99,6 +105,7 @@ pub fn get_liquidation_price(
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let min_collateral_price = collateral_token_price
@@ -2,7 +2,10 @@
 
 use {
     crate::state::{
-        custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+        custody::Custody,
+        oracle::{ActionClass, OraclePrice},
+        perpetuals::Perpetuals,
+        pool::Pool,
         position::Position,
     },
     anchor_lang::prelude::*,
@@ -28,7 +31,8 @@ pub struct GetLiquidationState<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -75,6 +79,7 @@ pub fn get_liquidation_state(
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -82,6 +87,7 @@ pub fn get_liquidation_state(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
@@ -91,6 +97,7 @@ pub fn get_liquidation_state(
         &collateral_custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -100,6 +107,7 @@ pub fn get_liquidation_state(
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     if ctx.accounts.pool.check_leverage(
@@ -1,7 +1,12 @@
 //! GetOraclePrice instruction handler
 
 use {
-    crate::state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
+    crate::state::{
+        custody::Custody,
+        oracle::{ActionClass, OraclePrice},
+        perpetuals::Perpetuals,
+        pool::Pool,
+    },
     anchor_lang::prelude::*,
 };
 
@@ -52,6 +57,7 @@ pub fn get_oracle_price(
         &custody.oracle,
         curtime,
         params.ema,
+        ActionClass::Increase,
     )?;
 
     Ok(price
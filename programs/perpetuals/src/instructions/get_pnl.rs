@@ -3,7 +3,7 @@
 use {
     crate::state::{
         custody::Custody,
-        oracle::OraclePrice,
+        oracle::{ActionClass, OraclePrice},
         perpetuals::{Perpetuals, ProfitAndLoss},
         pool::Pool,
         position::Position,
@@ -31,7 +31,8 @@ pub struct GetPnl<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -78,6 +79,7 @@ pub fn get_pnl(ctx: Context<GetPnl>, _params: &GetPnlParams) -> Result<ProfitAnd
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -85,6 +87,7 @@ pub fn get_pnl(ctx: Context<GetPnl>, _params: &GetPnlParams) -> Result<ProfitAnd
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
@@ -94,6 +97,7 @@ pub fn get_pnl(ctx: Context<GetPnl>, _params: &GetPnlParams) -> Result<ProfitAnd
         &collateral_custody.oracle,
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -103,6 +107,7 @@ pub fn get_pnl(ctx: Context<GetPnl>, _params: &GetPnlParams) -> Result<ProfitAnd
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     // compute pnl
@@ -116,6 +121,7 @@ pub fn get_pnl(ctx: Context<GetPnl>, _params: &GetPnlParams) -> Result<ProfitAnd
         collateral_custody,
         curtime,
         false,
+        0,
     )?;
 
     Ok(ProfitAndLoss { profit, loss })
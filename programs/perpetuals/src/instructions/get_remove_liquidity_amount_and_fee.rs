@@ -5,7 +5,7 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::{AmountAndFee, Perpetuals},
             pool::{AumCalcMode, Pool},
         },
@@ -77,6 +77,7 @@ pub fn get_remove_liquidity_amount_and_fee(
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -84,6 +85,7 @@ pub fn get_remove_liquidity_amount_and_fee(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let pool_amount_usd =
@@ -102,7 +104,7 @@ pub fn get_remove_liquidity_amount_and_fee(
     let remove_amount = max_price.get_token_amount(remove_amount_usd, custody.decimals)?;
 
     let fee_amount =
-        pool.get_remove_liquidity_fee(token_id, remove_amount, custody, &token_price)?;
+        pool.get_remove_liquidity_fee(token_id, remove_amount, custody, &token_price, curtime)?;
 
     let transfer_amount = math::checked_sub(remove_amount, fee_amount)?;
 
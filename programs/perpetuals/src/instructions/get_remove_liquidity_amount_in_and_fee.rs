@@ -0,0 +1,112 @@
+//! GetRemoveLiquidityAmountInAndFee instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        oracle::{ActionClass, OraclePrice},
+        perpetuals::{AmountAndFee, Perpetuals},
+        pool::{AumCalcMode, Pool},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::Mint,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct GetRemoveLiquidityAmountInAndFee<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"lp_token_mint",
+                 pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetRemoveLiquidityAmountInAndFeeParams {
+    amount_out: u64,
+}
+
+pub fn get_remove_liquidity_amount_in_and_fee(
+    ctx: Context<GetRemoveLiquidityAmountInAndFee>,
+    params: &GetRemoveLiquidityAmountInAndFeeParams,
+) -> Result<AmountAndFee> {
+    // validate inputs
+    if params.amount_out == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = &ctx.accounts.pool;
+    let custody = &ctx.accounts.custody;
+    let token_id = pool.get_token_id(&custody.key())?;
+
+    // compute position price
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        ActionClass::Increase,
+    )?;
+
+    let pool_amount_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::Min, ctx.remaining_accounts, curtime)?;
+
+    let max_price = if token_price > token_ema_price {
+        token_price
+    } else {
+        token_ema_price
+    };
+
+    let (lp_amount_in, _, fee_amount) = pool.get_remove_liquidity_amount_in(
+        token_id,
+        params.amount_out,
+        ctx.accounts.lp_token_mint.supply,
+        pool_amount_usd,
+        custody,
+        &token_price,
+        &max_price,
+        curtime,
+    )?;
+
+    Ok(AmountAndFee {
+        amount: lp_amount_in,
+        fee: fee_amount,
+    })
+}
@@ -3,7 +3,7 @@
 use {
     crate::state::{
         custody::Custody,
-        oracle::OraclePrice,
+        oracle::{ActionClass, OraclePrice},
         perpetuals::{Perpetuals, SwapAmountAndFees},
         pool::Pool,
     },
@@ -89,6 +89,7 @@ pub fn get_swap_amount_and_fees(
         &receiving_custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let received_token_ema_price = OraclePrice::new_from_oracle(
@@ -98,6 +99,7 @@ pub fn get_swap_amount_and_fees(
         &receiving_custody.oracle,
         curtime,
         receiving_custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let dispensed_token_price = OraclePrice::new_from_oracle(
@@ -107,6 +109,7 @@ pub fn get_swap_amount_and_fees(
         &dispensing_custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let dispensed_token_ema_price = OraclePrice::new_from_oracle(
@@ -116,6 +119,7 @@ pub fn get_swap_amount_and_fees(
         &dispensing_custody.oracle,
         curtime,
         dispensing_custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let amount_out = pool.get_swap_amount(
@@ -138,11 +142,19 @@ pub fn get_swap_amount_and_fees(
         &received_token_price,
         dispensing_custody,
         &dispensed_token_price,
+        curtime,
+        0,
     )?;
 
     Ok(SwapAmountAndFees {
         amount_out,
         fee_in: fees.0,
         fee_out: fees.1,
+        spread: pool.get_swap_spread(
+            &received_token_price,
+            &dispensed_token_price,
+            receiving_custody,
+            dispensing_custody,
+        )?,
     })
 }
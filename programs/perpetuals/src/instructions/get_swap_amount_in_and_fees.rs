@@ -0,0 +1,150 @@
+//! GetSwapAmountInAndFees instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        oracle::{ActionClass, OraclePrice},
+        perpetuals::{Perpetuals, SwapAmountInAndFees},
+        pool::Pool,
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct GetSwapAmountInAndFees<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 receiving_custody.mint.as_ref()],
+        bump = receiving_custody.bump
+    )]
+    pub receiving_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the received token
+    #[account(
+        constraint = receiving_custody_oracle_account.key() == receiving_custody.oracle.oracle_account
+    )]
+    pub receiving_custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 dispensing_custody.mint.as_ref()],
+        bump = dispensing_custody.bump
+    )]
+    pub dispensing_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the returned token
+    #[account(
+        constraint = dispensing_custody_oracle_account.key() == dispensing_custody.oracle.oracle_account
+    )]
+    pub dispensing_custody_oracle_account: AccountInfo<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetSwapAmountInAndFeesParams {
+    amount_out: u64,
+}
+
+pub fn get_swap_amount_in_and_fees(
+    ctx: Context<GetSwapAmountInAndFees>,
+    params: &GetSwapAmountInAndFeesParams,
+) -> Result<SwapAmountInAndFees> {
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount_out == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require_keys_neq!(
+        ctx.accounts.receiving_custody.key(),
+        ctx.accounts.dispensing_custody.key()
+    );
+
+    // compute token amount required from the user
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    let pool = &ctx.accounts.pool;
+    let token_id_in = pool.get_token_id(&ctx.accounts.receiving_custody.key())?;
+    let token_id_out = pool.get_token_id(&ctx.accounts.dispensing_custody.key())?;
+    let receiving_custody = &ctx.accounts.receiving_custody;
+    let dispensing_custody = &ctx.accounts.dispensing_custody;
+
+    let received_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .receiving_custody_oracle_account
+            .to_account_info(),
+        &receiving_custody.oracle,
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?;
+
+    let received_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .receiving_custody_oracle_account
+            .to_account_info(),
+        &receiving_custody.oracle,
+        curtime,
+        receiving_custody.pricing.use_ema,
+        ActionClass::Increase,
+    )?;
+
+    let dispensed_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .dispensing_custody_oracle_account
+            .to_account_info(),
+        &dispensing_custody.oracle,
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?;
+
+    let dispensed_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .dispensing_custody_oracle_account
+            .to_account_info(),
+        &dispensing_custody.oracle,
+        curtime,
+        dispensing_custody.pricing.use_ema,
+        ActionClass::Increase,
+    )?;
+
+    let (amount_in, fee_in, fee_out) = pool.get_swap_amount_in_and_fees(
+        token_id_in,
+        token_id_out,
+        params.amount_out,
+        &received_token_price,
+        &received_token_ema_price,
+        &dispensed_token_price,
+        &dispensed_token_ema_price,
+        receiving_custody,
+        dispensing_custody,
+        curtime,
+        0,
+    )?;
+
+    Ok(SwapAmountInAndFees {
+        amount_in,
+        fee_in,
+        fee_out,
+        spread: pool.get_swap_spread(
+            &received_token_price,
+            &dispensed_token_price,
+            receiving_custody,
+            dispensing_custody,
+        )?,
+    })
+}
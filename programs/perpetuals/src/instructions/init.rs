@@ -3,7 +3,7 @@
 use {
     crate::{
         error::PerpetualsError,
-        state::{multisig::Multisig, perpetuals::Perpetuals},
+        state::{migration::Versioned, multisig::Multisig, perpetuals::Perpetuals},
     },
     anchor_lang::prelude::*,
     anchor_spl::token::Token,
@@ -88,6 +88,7 @@ pub fn init(ctx: Context<Init>, params: &InitParams) -> Result<()> {
     // record perpetuals
     let perpetuals = ctx.accounts.perpetuals.as_mut();
 
+    perpetuals.version = Perpetuals::CURRENT_VERSION;
     perpetuals.permissions.allow_swap = params.allow_swap;
     perpetuals.permissions.allow_add_liquidity = params.allow_add_liquidity;
     perpetuals.permissions.allow_remove_liquidity = params.allow_remove_liquidity;
@@ -0,0 +1,41 @@
+//! InitTraderStats instruction handler
+
+use {
+    crate::state::trader_stats::TraderStats, anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct InitTraderStats<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = TraderStats::LEN,
+        seeds = [b"trader_stats",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub trader_stats: Box<Account<'info, TraderStats>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct InitTraderStatsParams {}
+
+pub fn init_trader_stats(
+    ctx: Context<InitTraderStats>,
+    _params: &InitTraderStatsParams,
+) -> Result<()> {
+    let trader_stats = ctx.accounts.trader_stats.as_mut();
+    trader_stats.owner = ctx.accounts.owner.key();
+    trader_stats.bump = *ctx
+        .bumps
+        .get("trader_stats")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    Ok(())
+}
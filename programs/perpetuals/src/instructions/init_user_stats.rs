@@ -0,0 +1,53 @@
+//! InitUserStats instruction handler
+
+use {
+    crate::state::{pool::Pool, user_stats::UserStats},
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct InitUserStats<'info> {
+    // anyone can create the stats of an owner, e.g. a keeper that needs them to liquidate
+    // a position whose owner never created them
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: owner of the stats account
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct InitUserStatsParams {}
+
+pub fn init_user_stats(ctx: Context<InitUserStats>, _params: &InitUserStatsParams) -> Result<()> {
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.owner = ctx.accounts.owner.key();
+    user_stats.pool = ctx.accounts.pool.key();
+    user_stats.bump = *ctx
+        .bumps
+        .get("user_stats")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    Ok(())
+}
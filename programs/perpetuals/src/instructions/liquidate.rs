@@ -6,10 +6,12 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            tranche::Tranche,
+            user_stats::UserStats,
         },
     },
     anchor_lang::prelude::*,
@@ -35,6 +37,10 @@ pub struct Liquidate<'info> {
     )]
     pub rewards_receiving_account: Box<Account<'info, TokenAccount>>,
 
+    /// CHECK: account that paid for the position, receives position account rent
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         seeds = [b"transfer_authority"],
@@ -58,13 +64,15 @@ pub struct Liquidate<'info> {
 
     #[account(
         mut,
+        has_one = rent_payer,
         seeds = [b"position",
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump,
-        close = signer
+        close = rent_payer
     )]
     pub position: Box<Account<'info, Position>>,
 
@@ -101,6 +109,21 @@ pub struct Liquidate<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // required if the position funds are locked in a tranche
+    #[account(mut)]
+    pub tranche: Option<Box<Account<'info, Tranche>>>,
+
+    // records realized pnl and fees of the owner, created by the owner when the position
+    // is opened so that keepers don't pay for it
+    #[account(
+        mut,
+        seeds = [b"user_stats",
+                 position.owner.as_ref(),
+                 pool.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
     token_program: Program<'info, Token>,
 }
 
@@ -120,41 +143,64 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
 
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
+    let tranche = if position.tranche != Pubkey::default() {
+        let tranche = ctx
+            .accounts
+            .tranche
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidTranche)?;
+        require_keys_eq!(
+            tranche.key(),
+            position.tranche,
+            PerpetualsError::InvalidTranche
+        );
+        Some(tranche)
+    } else {
+        None
+    };
 
     // check if position can be liquidated
     msg!("Check position state");
     let curtime = perpetuals.get_time()?;
+    require!(
+        !position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
 
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+        &custody.get_oracle_params(curtime),
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+        &custody.get_oracle_params(curtime),
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts
             .collateral_custody_oracle_account
             .to_account_info(),
-        &collateral_custody.oracle,
+        &collateral_custody.get_oracle_params(curtime),
         curtime,
         false,
+        ActionClass::Reduce,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts
             .collateral_custody_oracle_account
             .to_account_info(),
-        &collateral_custody.oracle,
+        &collateral_custody.get_oracle_params(curtime),
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
     )?;
 
     require!(
@@ -172,6 +218,8 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         PerpetualsError::InvalidPositionState
     );
 
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+
     msg!("Settle position");
     let (total_amount_out, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
         position,
@@ -183,6 +231,7 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         collateral_custody,
         curtime,
         true,
+        0,
     )?;
 
     let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
@@ -194,21 +243,30 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
     msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
     msg!("Collected fee: {}", fee_amount);
 
-    let reward = Pool::get_fee_amount(custody.fees.liquidation, total_amount_out)?;
+    let reward = Pool::get_fee_amount(
+        custody.get_leverage_tier(position.size_usd).liquidation_fee,
+        total_amount_out,
+    )?;
     let user_amount = math::checked_sub(total_amount_out, reward)?;
 
     msg!("Amount out: {}", user_amount);
     msg!("Reward: {}", reward);
 
-    // unlock pool funds
-    collateral_custody.unlock_funds(position.locked_amount)?;
-
-    // check pool constraints
+    // check pool constraints and update custody stats
     msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(total_amount_out, collateral_custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
+    pool.settle_close_position(
+        position,
+        true,
+        true,
+        total_amount_out,
+        (fee_amount, fee_amount_usd),
+        0,
+        (profit_usd, loss_usd),
+        custody,
+        collateral_custody,
+        tranche.map(|tranche| &mut ***tranche),
+        curtime,
+    )?;
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -232,91 +290,17 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         reward,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    collateral_custody.collected_fees.liquidation_usd = collateral_custody
-        .collected_fees
-        .liquidation_usd
-        .wrapping_add(fee_amount_usd);
-
-    if total_amount_out > position.collateral_amount {
-        let amount_lost = total_amount_out.saturating_sub(position.collateral_amount);
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(total_amount_out);
-        collateral_custody.assets.owned =
-            math::checked_add(collateral_custody.assets.owned, amount_gained)?;
-    }
-    collateral_custody.assets.collateral = math::checked_sub(
-        collateral_custody.assets.collateral,
-        position.collateral_amount,
-    )?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, collateral_custody)? {
-        collateral_custody.assets.protocol_fees =
-            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
-
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
-    }
-
-    // if custody and collateral_custody accounts are the same, ensure that data is in sync
-    if position.side == Side::Long && !custody.is_virtual {
-        collateral_custody.volume_stats.liquidation_usd = math::checked_add(
-            collateral_custody.volume_stats.liquidation_usd,
-            position.size_usd,
-        )?;
-
-        if position.side == Side::Long {
-            collateral_custody.trade_stats.oi_long_usd = collateral_custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            collateral_custody.trade_stats.oi_short_usd = collateral_custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        collateral_custody.trade_stats.profit_usd = collateral_custody
-            .trade_stats
-            .profit_usd
-            .wrapping_add(profit_usd);
-        collateral_custody.trade_stats.loss_usd = collateral_custody
-            .trade_stats
-            .loss_usd
-            .wrapping_add(loss_usd);
-
-        collateral_custody.remove_position(position, curtime, None)?;
-        collateral_custody.update_borrow_rate(curtime)?;
-        *custody = collateral_custody.clone();
-    } else {
-        custody.volume_stats.liquidation_usd =
-            math::checked_add(custody.volume_stats.liquidation_usd, position.size_usd)?;
-
-        if position.side == Side::Long {
-            custody.trade_stats.oi_long_usd = custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            custody.trade_stats.oi_short_usd = custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-        custody.remove_position(position, curtime, Some(collateral_custody))?;
-        collateral_custody.update_borrow_rate(curtime)?;
-    }
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.record_close(
+        position.size_usd,
+        fee_amount_usd,
+        interest_usd,
+        profit_usd,
+        loss_usd,
+        true,
+        curtime,
+    );
 
     Ok(())
 }
@@ -0,0 +1,107 @@
+//! MigrateAccount instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        migration,
+        multisig::{AdminInstruction, Multisig},
+        perpetuals::Perpetuals,
+        pool::Pool,
+        position::Position,
+    },
+    anchor_lang::{prelude::*, Discriminator},
+};
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    // pays for the account resize, must be an admin unless the account is a position
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    /// CHECK: account to migrate, the type is resolved by discriminator
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    pub account: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct MigrateAccountParams {}
+
+pub fn migrate_account<'info>(
+    ctx: Context<'_, '_, '_, 'info, MigrateAccount<'info>>,
+    params: &MigrateAccountParams,
+) -> Result<u8> {
+    let account = &ctx.accounts.account;
+    let discriminator: [u8; 8] = account
+        .try_borrow_data()?
+        .get(..8)
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .try_into()
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    // positions can be migrated by anyone, protocol accounts require admin signatures
+    if discriminator != Position::discriminator() {
+        let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+        let signatures_left = multisig.sign_multisig(
+            &ctx.accounts.signer,
+            &Multisig::get_account_infos(&ctx)[1..],
+            &Multisig::get_instruction_data(AdminInstruction::MigrateAccount, params)?,
+        )?;
+        if signatures_left > 0 {
+            msg!(
+                "Instruction has been signed but more signatures are required: {}",
+                signatures_left
+            );
+            return Ok(signatures_left);
+        }
+    }
+
+    // run migrations
+    msg!("Migrate account");
+    let migrated_data = {
+        let data = account.try_borrow_data()?;
+        if discriminator == Position::discriminator() {
+            migration::migrate_data::<Position>(&data)?
+        } else if discriminator == Custody::discriminator() {
+            migration::migrate_data::<Custody>(&data)?
+        } else if discriminator == Pool::discriminator() {
+            migration::migrate_data::<Pool>(&data)?
+        } else if discriminator == Perpetuals::discriminator() {
+            migration::migrate_data::<Perpetuals>(&data)?
+        } else {
+            return err!(ErrorCode::AccountDiscriminatorMismatch);
+        }
+    };
+
+    let Some(migrated_data) = migrated_data else {
+        msg!("Account is up to date");
+        return Ok(0);
+    };
+
+    // resize and write new data
+    if migrated_data.len() > account.try_data_len()? {
+        msg!("Resize account");
+        Perpetuals::realloc(
+            ctx.accounts.signer.to_account_info(),
+            account.clone(),
+            ctx.accounts.system_program.to_account_info(),
+            migrated_data.len(),
+            true,
+        )?;
+    }
+    account.try_borrow_mut_data()?[..migrated_data.len()].copy_from_slice(&migrated_data);
+
+    Ok(0)
+}
@@ -5,11 +5,18 @@ use {
         error::PerpetualsError,
         math,
         state::{
-            custody::Custody,
-            oracle::OraclePrice,
+            custody::{Custody, DepegStatus},
+            dated_market::DatedMarket,
+            migration::Versioned,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            trading_delegate::TradingDelegate,
+            tranche::Tranche,
+            user_stats::UserStats,
         },
     },
     anchor_lang::prelude::*,
@@ -20,13 +27,23 @@ use {
 #[derive(Accounts)]
 #[instruction(params: OpenPositionParams)]
 pub struct OpenPosition<'info> {
+    // position owner or its trading delegate
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    // optional, pays for the position account instead of the authority
+    #[account(mut)]
+    pub rent_payer: Option<Signer<'info>>,
+
+    /// CHECK: position owner, doesn't sign if the authority is a trading delegate
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
         constraint = funding_account.mint == collateral_custody.mint,
-        has_one = owner
+        constraint = funding_account.owner == owner.key()
+            || funding_account.owner == authority.key()
     )]
     pub funding_account: Box<Account<'info, TokenAccount>>,
 
@@ -53,13 +70,14 @@ pub struct OpenPosition<'info> {
 
     #[account(
         init,
-        payer = owner,
+        payer = authority,
         space = Position::LEN,
         seeds = [b"position",
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[params.side as u8]],
+                 &[params.side as u8],
+                 dated_market.as_ref().map(|market| market.key().to_bytes().to_vec()).unwrap_or_default().as_slice()],
         bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -103,6 +121,43 @@ pub struct OpenPosition<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // required if the position custody is backed by a tranche
+    #[account(mut)]
+    pub tranche: Option<Box<Account<'info, Tranche>>>,
+
+    // optional, enables trading volume based fee discounts
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
+    // optional, credits a share of the fee to the referrer
+    #[account(mut)]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // required if the authority is not the position owner
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
+
+    // records realized pnl and fees of the owner, created on first use
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    // required to open a dated futures position, perpetual if not provided
+    #[account(
+        has_one = pool,
+        has_one = custody
+    )]
+    pub dated_market: Option<Box<Account<'info, DatedMarket>>>,
+
     system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
@@ -127,6 +182,10 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
             && !custody.is_stable,
         PerpetualsError::InstructionNotAllowed
     );
+    require!(
+        !ctx.accounts.pool.orders.is_enabled(),
+        PerpetualsError::DelayedOrdersRequired
+    );
 
     // validate inputs
     msg!("Validate inputs");
@@ -147,46 +206,102 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
 
-    // compute position price
+    // check trading authority
     let curtime = perpetuals.get_time()?;
+    TradingDelegate::check_authority(
+        ctx.accounts.trading_delegate.as_deref().map(|d| &**d),
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        &custody.key(),
+        curtime,
+    )?;
 
+    // check dated market expiry
+    if let Some(dated_market) = ctx.accounts.dated_market.as_deref() {
+        if use_collateral_custody {
+            require_keys_eq!(
+                collateral_custody.key(),
+                dated_market.collateral_custody,
+                PerpetualsError::InvalidCollateralCustody
+            );
+        }
+        require!(
+            dated_market.is_open_allowed(curtime),
+            PerpetualsError::DatedMarketClosed
+        );
+    }
+
+    // compute position price
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+        &custody.get_oracle_params(curtime),
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+        &custody.get_oracle_params(curtime),
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts
             .collateral_custody_oracle_account
             .to_account_info(),
-        &collateral_custody.oracle,
+        &collateral_custody.get_oracle_params(curtime),
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts
             .collateral_custody_oracle_account
             .to_account_info(),
-        &collateral_custody.oracle,
+        &collateral_custody.get_oracle_params(curtime),
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
+    // check trading hours
+    custody
+        .trading_schedule
+        .check_increase_allowed(&token_price, &token_ema_price, curtime)?;
+
+    // check oracle circuit breaker
+    require!(
+        custody.update_circuit_breaker(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            curtime
+        )?,
+        PerpetualsError::CircuitBreakerTripped
+    );
+    if !use_collateral_custody {
+        collateral_custody.circuit_breaker = custody.circuit_breaker;
+    }
+
+    // check stablecoin depeg of the collateral
+    require!(
+        !use_collateral_custody
+            || collateral_custody.get_depeg_status(&collateral_token_price)? == DepegStatus::Pegged,
+        PerpetualsError::StablecoinDepegged
+    );
+
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    let position_price =
-        pool.get_entry_price(&token_price, &token_ema_price, params.side, custody)?;
+    let position_price = pool.get_entry_price(
+        &token_price,
+        &token_ema_price,
+        params.side,
+        custody,
+        curtime,
+    )?;
     msg!("Entry price: {}", position_price);
 
     if params.side == Side::Long {
@@ -207,6 +322,7 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let position_oracle_price = OraclePrice {
         price: position_price,
         exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+        conf: 0,
     };
     let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
     let collateral_usd = min_collateral_price
@@ -236,18 +352,42 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         size_usd
     };
 
+    // check referral
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        ctx.accounts
+            .trader_stats
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidReferral)?
+            .bind_referral(&referral.key(), referral)?;
+    }
+
+    let trader_volume_usd = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        trader_stats.get_volume_usd(curtime)?
+    } else {
+        0
+    };
+
     // compute fee
     let mut fee_amount = pool.get_entry_fee(
         custody.fees.open_position,
         params.size,
         locked_amount,
         collateral_custody,
+        trader_volume_usd,
     )?;
     let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
     if use_collateral_custody {
         fee_amount = collateral_token_ema_price
             .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
     }
+    // protocol fee, referrer reward and trader rebate are all shares of the full fee
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    let (referrer_reward, trader_rebate) = if ctx.accounts.referral.is_some() {
+        pool.get_referral_amounts(fee_amount, custody.fees.protocol_share)?
+    } else {
+        (0, 0)
+    };
+    fee_amount = math::checked_sub(fee_amount, trader_rebate)?;
     msg!("Collected fee: {}", fee_amount);
 
     // compute amount to transfer
@@ -256,6 +396,7 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
 
     // init new position
     msg!("Initialize new position");
+    position.version = Position::CURRENT_VERSION;
     position.owner = ctx.accounts.owner.key();
     position.pool = pool.key();
     position.custody = custody.key();
@@ -269,16 +410,40 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     position.collateral_usd = collateral_usd;
     position.unrealized_profit_usd = 0;
     position.unrealized_loss_usd = 0;
-    position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
+    position.cumulative_interest_snapshot =
+        collateral_custody.get_cumulative_interest(params.side, curtime)?;
     position.locked_amount = locked_amount;
     position.collateral_amount = params.collateral;
+    position.tranche = custody.tranche;
+    position.rent_payer = if let Some(rent_payer) = ctx.accounts.rent_payer.as_ref() {
+        // reimburse the authority for the position account rent
+        Perpetuals::transfer_sol(
+            rent_payer.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            Rent::get()?.minimum_balance(Position::LEN),
+        )?;
+        rent_payer.key()
+    } else {
+        ctx.accounts.authority.key()
+    };
     position.bump = *ctx
         .bumps
         .get("position")
         .ok_or(ProgramError::InvalidSeeds)?;
+    if let Some(dated_market) = ctx.accounts.dated_market.as_deref() {
+        position.dated_market = dated_market.key();
+        position.expiry_time = dated_market.expiry_time;
+    }
 
     // check position risk
     msg!("Check position risks");
+    TradingDelegate::check_size(
+        ctx.accounts.trading_delegate.as_deref().map(|d| &**d),
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        size_usd,
+    )?;
     require!(
         position.locked_amount > 0,
         PerpetualsError::InsufficientAmountReturned
@@ -299,7 +464,20 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     );
 
     // lock funds for potential profit payoff
-    collateral_custody.lock_funds(position.locked_amount)?;
+    if custody.is_tranche_backed() {
+        let tranche = ctx
+            .accounts
+            .tranche
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidTranche)?;
+        require!(
+            tranche.key() == custody.tranche && tranche.custody == collateral_custody.key(),
+            PerpetualsError::InvalidTranche
+        );
+        tranche.lock_funds(position.locked_amount)?;
+    } else {
+        collateral_custody.lock_funds(position.locked_amount)?;
+    }
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -308,7 +486,7 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         ctx.accounts
             .collateral_custody_token_account
             .to_account_info(),
-        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         transfer_amount,
     )?;
@@ -323,10 +501,13 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     collateral_custody.assets.collateral =
         math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
 
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
     collateral_custody.assets.protocol_fees =
         math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
 
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        referral.add_reward(&collateral_custody.key(), referrer_reward)?;
+    }
+
     // if custody and collateral_custody accounts are the same, ensure that data is in sync
     if position.side == Side::Long && !custody.is_virtual {
         collateral_custody.volume_stats.open_position_usd = collateral_custody
@@ -368,5 +549,21 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         collateral_custody.update_borrow_rate(curtime)?;
     }
 
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        &ctx.accounts.pool.key(),
+        *ctx.bumps
+            .get("user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+    user_stats.record_open(size_usd, fee_amount_usd, curtime);
+
+    // update trader stats
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(size_usd, curtime)?;
+    }
+
     Ok(())
 }
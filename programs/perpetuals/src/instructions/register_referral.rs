@@ -0,0 +1,49 @@
+//! RegisterReferral instruction handler
+
+use {
+    crate::state::referral::Referral, anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+#[instruction(params: RegisterReferralParams)]
+pub struct RegisterReferral<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = referrer,
+        space = Referral::LEN,
+        seeds = [b"referral",
+                 params.code.as_bytes()],
+        bump
+    )]
+    pub referral: Box<Account<'info, Referral>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RegisterReferralParams {
+    pub code: String,
+}
+
+pub fn register_referral(
+    ctx: Context<RegisterReferral>,
+    params: &RegisterReferralParams,
+) -> Result<()> {
+    let referral = ctx.accounts.referral.as_mut();
+    referral.code = params.code.clone();
+    referral.referrer = ctx.accounts.referrer.key();
+    referral.bump = *ctx
+        .bumps
+        .get("referral")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    if !referral.validate() {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    Ok(())
+}
@@ -6,10 +6,12 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            trading_delegate::TradingDelegate,
+            user_stats::UserStats,
         },
     },
     anchor_lang::prelude::*,
@@ -20,8 +22,13 @@ use {
 #[derive(Accounts)]
 #[instruction(params: RemoveCollateralParams)]
 pub struct RemoveCollateral<'info> {
+    // position owner or its trading delegate
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, doesn't sign if the authority is a trading delegate
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
@@ -58,7 +65,8 @@ pub struct RemoveCollateral<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 position.get_market_seed()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -96,6 +104,22 @@ pub struct RemoveCollateral<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // required if the authority is not the position owner
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
+
+    // records realized pnl and fees of the owner, created on first use
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
 
@@ -127,14 +151,27 @@ pub fn remove_collateral(
     }
     let pool = ctx.accounts.pool.as_mut();
 
-    // compute position price
+    // check trading authority
     let curtime = perpetuals.get_time()?;
+    require!(
+        !position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
+    TradingDelegate::check_authority(
+        ctx.accounts.trading_delegate.as_deref().map(|d| &**d),
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        &custody.key(),
+        curtime,
+    )?;
 
+    // compute position price
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
         &custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let token_ema_price = OraclePrice::new_from_oracle(
@@ -142,6 +179,7 @@ pub fn remove_collateral(
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let collateral_token_price = OraclePrice::new_from_oracle(
@@ -151,6 +189,7 @@ pub fn remove_collateral(
         &collateral_custody.oracle,
         curtime,
         false,
+        ActionClass::Increase,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
@@ -160,6 +199,7 @@ pub fn remove_collateral(
         &collateral_custody.oracle,
         curtime,
         collateral_custody.pricing.use_ema,
+        ActionClass::Increase,
     )?;
 
     let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
@@ -216,6 +256,17 @@ pub fn remove_collateral(
     collateral_custody.assets.collateral =
         math::checked_sub(collateral_custody.assets.collateral, collateral)?;
 
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        &pool.key(),
+        *ctx.bumps
+            .get("user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+    user_stats.record_remove_collateral(params.collateral_usd, curtime);
+
     // if custody and collateral_custody accounts are the same, ensure that data is in sync
     if position.side == Side::Long && !custody.is_virtual {
         *custody = collateral_custody.clone();
@@ -117,6 +117,8 @@ pub fn remove_custody<'info>(
     let token_id = pool.get_token_id(&ctx.accounts.custody.key())?;
     pool.custodies.remove(token_id);
     pool.ratios = params.ratios.clone();
+    // explicitly set ratios cancel scheduled re-weighting
+    pool.next_targets = Vec::new();
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
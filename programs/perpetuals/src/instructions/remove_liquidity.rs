@@ -6,7 +6,6 @@ use {
         math,
         state::{
             custody::Custody,
-            oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::{AumCalcMode, Pool},
         },
@@ -17,7 +16,6 @@ use {
 };
 
 #[derive(Accounts)]
-#[instruction(params: RemoveLiquidityParams)]
 pub struct RemoveLiquidity<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -89,7 +87,7 @@ pub struct RemoveLiquidity<'info> {
     )]
     pub lp_token_mint: Box<Account<'info, Mint>>,
 
-    token_program: Program<'info, Token>,
+    pub token_program: Program<'info, Token>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
@@ -132,20 +130,12 @@ pub fn remove_liquidity(
     pool.aum_usd =
         pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
 
-    let token_price = OraclePrice::new_from_oracle(
+    let (token_price, token_ema_price) = custody.get_liquidity_prices(
         &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
         curtime,
         false,
     )?;
 
-    let token_ema_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
-        curtime,
-        custody.pricing.use_ema,
-    )?;
-
     let max_price = if token_price > token_ema_price {
         token_price
     } else {
@@ -165,7 +155,7 @@ pub fn remove_liquidity(
 
     // calculate fee
     let fee_amount =
-        pool.get_remove_liquidity_fee(token_id, remove_amount, custody, &token_ema_price)?;
+        pool.get_remove_liquidity_fee(token_id, remove_amount, custody, &token_ema_price, curtime)?;
     msg!("Collected fee: {}", fee_amount);
 
     let transfer_amount = math::checked_sub(remove_amount, fee_amount)?;
@@ -176,19 +166,17 @@ pub fn remove_liquidity(
         PerpetualsError::MaxPriceSlippage
     );
 
-    // check pool constraints
+    // check pool constraints and update custody stats
     msg!("Check pool constraints");
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-    let withdrawal_amount = math::checked_add(transfer_amount, protocol_fee)?;
-    require!(
-        pool.check_token_ratio(token_id, 0, withdrawal_amount, custody, &token_ema_price)?,
-        PerpetualsError::TokenRatioOutOfRange
-    );
-
-    require!(
-        math::checked_sub(custody.assets.owned, custody.assets.locked)? >= withdrawal_amount,
-        PerpetualsError::CustodyAmountLimit
-    );
+    pool.settle_remove_liquidity(
+        token_id,
+        transfer_amount,
+        fee_amount,
+        remove_amount_usd,
+        custody,
+        &token_ema_price,
+        curtime,
+    )?;
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -210,24 +198,6 @@ pub fn remove_liquidity(
         params.lp_amount_in,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    custody.collected_fees.remove_liquidity_usd = custody
-        .collected_fees
-        .remove_liquidity_usd
-        .wrapping_add(token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
-
-    custody.volume_stats.remove_liquidity_usd = custody
-        .volume_stats
-        .remove_liquidity_usd
-        .wrapping_add(remove_amount_usd);
-
-    custody.assets.protocol_fees = math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
-
-    custody.assets.owned = math::checked_sub(custody.assets.owned, withdrawal_amount)?;
-
-    custody.update_borrow_rate(curtime)?;
-
     // update pool stats
     msg!("Update pool stats");
     custody.exit(&crate::ID)?;
@@ -0,0 +1,126 @@
+//! RemoveLiquidityExactOut instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError, instructions::remove_liquidity::RemoveLiquidity,
+        state::pool::AumCalcMode,
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveLiquidityExactOutParams {
+    pub amount_out: u64,
+    pub max_lp_amount_in: u64,
+}
+
+pub fn remove_liquidity_exact_out(
+    ctx: Context<RemoveLiquidity>,
+    params: &RemoveLiquidityExactOutParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_remove_liquidity
+            && custody.permissions.allow_remove_liquidity
+            && !custody.is_virtual,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount_out == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_mut();
+    let token_id = pool.get_token_id(&custody.key())?;
+
+    // compute assets under management
+    msg!("Compute assets under management");
+    let curtime = perpetuals.get_time()?;
+
+    // Refresh pool.aum_usm to adapt to token price change
+    pool.aum_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+
+    let (token_price, token_ema_price) = custody.get_liquidity_prices(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        curtime,
+        false,
+    )?;
+
+    let max_price = if token_price > token_ema_price {
+        token_price
+    } else {
+        token_ema_price
+    };
+
+    let pool_amount_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::Min, ctx.remaining_accounts, curtime)?;
+
+    // compute amount of lp tokens to burn
+    let (lp_amount_in, remove_amount, fee_amount) = pool.get_remove_liquidity_amount_in(
+        token_id,
+        params.amount_out,
+        ctx.accounts.lp_token_mint.supply,
+        pool_amount_usd,
+        custody,
+        &token_ema_price,
+        &max_price,
+        curtime,
+    )?;
+    msg!("LP tokens to burn: {}", lp_amount_in);
+    msg!("Collected fee: {}", fee_amount);
+
+    let transfer_amount = params.amount_out;
+    msg!("Amount out: {}", transfer_amount);
+
+    require!(
+        lp_amount_in <= params.max_lp_amount_in,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    let remove_amount_usd = max_price.get_asset_amount_usd(remove_amount, custody.decimals)?;
+    pool.settle_remove_liquidity(
+        token_id,
+        transfer_amount,
+        fee_amount,
+        remove_amount_usd,
+        custody,
+        &token_ema_price,
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // burn lp tokens
+    msg!("Burn LP tokens");
+    perpetuals.burn_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount_in,
+    )?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    custody.exit(&crate::ID)?;
+    pool.aum_usd =
+        pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+
+    Ok(())
+}
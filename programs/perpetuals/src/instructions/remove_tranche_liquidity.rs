@@ -0,0 +1,235 @@
+//! RemoveTrancheLiquidity instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::{ActionClass, OraclePrice},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            tranche::Tranche,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct RemoveTrancheLiquidity<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == tranche_lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the returned token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        has_one = custody,
+        seeds = [b"tranche",
+                 pool.key().as_ref(),
+                 tranche.name.as_bytes()],
+        bump = tranche.bump
+    )]
+    pub tranche: Box<Account<'info, Tranche>>,
+
+    #[account(
+        mut,
+        seeds = [b"tranche_lp_token_mint",
+                 tranche.key().as_ref()],
+        bump = tranche.lp_token_bump
+    )]
+    pub tranche_lp_token_mint: Box<Account<'info, Mint>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   tranche.markets.len() custody accounts (read-only, unsigned)
+    //   tranche.markets.len() custody oracles (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveTrancheLiquidityParams {
+    pub lp_amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+pub fn remove_tranche_liquidity(
+    ctx: Context<RemoveTrancheLiquidity>,
+    params: &RemoveTrancheLiquidityParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_remove_liquidity && custody.permissions.allow_remove_liquidity,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.lp_amount_in == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_ref();
+    let tranche = ctx.accounts.tranche.as_mut();
+
+    // compute assets under management
+    msg!("Compute assets under management");
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        ActionClass::Increase,
+    )?;
+
+    let min_price = token_price.get_min_price(&token_ema_price, custody.is_stable)?;
+    let max_price = if token_price > token_ema_price {
+        token_price
+    } else {
+        token_ema_price
+    };
+
+    let tranche_amount_usd = tranche.get_assets_under_management_usd(
+        pool,
+        &min_price,
+        custody,
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+
+    // compute amount of tokens to return
+    let remove_amount_usd = math::checked_as_u64(math::checked_div(
+        math::checked_mul(tranche_amount_usd, params.lp_amount_in as u128)?,
+        ctx.accounts.tranche_lp_token_mint.supply as u128,
+    )?)?;
+
+    let remove_amount = max_price.get_token_amount(remove_amount_usd, custody.decimals)?;
+
+    // calculate fee
+    let fee_amount = Pool::get_fee_amount(custody.fees.remove_liquidity, remove_amount)?;
+    msg!("Collected fee: {}", fee_amount);
+
+    let transfer_amount = math::checked_sub(remove_amount, fee_amount)?;
+    msg!("Amount out: {}", transfer_amount);
+
+    require!(
+        transfer_amount >= params.min_amount_out,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // check tranche constraints
+    msg!("Check tranche constraints");
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    let withdrawal_amount = math::checked_add(transfer_amount, protocol_fee)?;
+    require!(
+        math::checked_sub(tranche.assets.owned, tranche.assets.locked)? >= withdrawal_amount,
+        PerpetualsError::CustodyAmountLimit
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // burn lp tokens
+    msg!("Burn LP tokens");
+    perpetuals.burn_tokens(
+        ctx.accounts.tranche_lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.lp_amount_in,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    custody.collected_fees.remove_liquidity_usd = custody
+        .collected_fees
+        .remove_liquidity_usd
+        .wrapping_add(token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+
+    custody.volume_stats.remove_liquidity_usd = custody
+        .volume_stats
+        .remove_liquidity_usd
+        .wrapping_add(remove_amount_usd);
+
+    custody.assets.protocol_fees = math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
+
+    tranche.assets.owned = math::checked_sub(tranche.assets.owned, withdrawal_amount)?;
+
+    Ok(())
+}
@@ -0,0 +1,210 @@
+//! RequestClosePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            order::{OrderType, PendingOrder},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::Position,
+            referral::Referral,
+            trader_stats::TraderStats,
+            trading_delegate::TradingDelegate,
+            user_stats::UserStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct RequestClosePosition<'info> {
+    // position owner or its trading delegate
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // optional, pays for the order account instead of the authority
+    #[account(mut)]
+    pub rent_payer: Option<Signer<'info>>,
+
+    /// CHECK: position owner, doesn't sign if the authority is a trading delegate
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        has_one = owner,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PendingOrder::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump
+    )]
+    pub order: Box<Account<'info, PendingOrder>>,
+
+    #[account(
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    // required if the authority is not the position owner
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
+
+    // records realized pnl and fees of the owner, created here so that keepers can update it
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    // records trading volume and referral of the owner, created here so that keepers
+    // must pass it on execution
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = TraderStats::LEN,
+        seeds = [b"trader_stats",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub trader_stats: Box<Account<'info, TraderStats>>,
+
+    // optional, credits a share of the fee to the referrer when the order is executed
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RequestClosePositionParams {
+    pub price: u64,
+}
+
+pub fn request_close_position(
+    ctx: Context<RequestClosePosition>,
+    params: &RequestClosePositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let pool = ctx.accounts.pool.as_ref();
+    let custody = ctx.accounts.custody.as_ref();
+    require!(
+        perpetuals.permissions.allow_close_position
+            && custody.permissions.allow_close_position
+            && pool.orders.is_enabled(),
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.price == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // check trading authority
+    let curtime = perpetuals.get_time()?;
+    require!(
+        !ctx.accounts.position.is_expired(curtime),
+        PerpetualsError::PositionExpired
+    );
+    TradingDelegate::check_authority(
+        ctx.accounts.trading_delegate.as_deref().map(|d| &**d),
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        &custody.key(),
+        curtime,
+    )?;
+
+    // reimburse the authority for the order account rent, it is kept by the keeper
+    // as the execution incentive
+    let rent_payer = if let Some(rent_payer) = ctx.accounts.rent_payer.as_ref() {
+        Perpetuals::transfer_sol(
+            rent_payer.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            Rent::get()?.minimum_balance(PendingOrder::LEN),
+        )?;
+        rent_payer.key()
+    } else {
+        ctx.accounts.authority.key()
+    };
+
+    // record order
+    msg!("Record order");
+    let position = ctx.accounts.position.as_ref();
+    let order = ctx.accounts.order.as_mut();
+    order.owner = ctx.accounts.owner.key();
+    order.rent_payer = rent_payer;
+    order.pool = pool.key();
+    order.custody = custody.key();
+    order.collateral_custody = position.collateral_custody;
+    order.dated_market = position.dated_market;
+    order.order_type = OrderType::ClosePosition;
+    order.side = position.side;
+    order.price = params.price;
+    order.collateral = 0;
+    order.size = 0;
+    order.max_size_usd = 0;
+    order.request_time = curtime;
+    order.bump = *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?;
+    order.token_account_bump = 0;
+
+    // create owner stats so that the keeper can update them on execution
+    ctx.accounts.user_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        &pool.key(),
+        *ctx.bumps
+            .get("user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+    let trader_stats = ctx.accounts.trader_stats.as_mut();
+    trader_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        *ctx.bumps
+            .get("trader_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+
+    // check referral, it is applied when the order is executed
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        trader_stats.bind_referral(&referral.key(), referral)?;
+        ctx.accounts.order.referral = referral.key();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+//! RequestOpenPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            dated_market::DatedMarket,
+            oracle::OraclePrice,
+            order::{OrderType, PendingOrder},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            trading_delegate::TradingDelegate,
+            user_stats::UserStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+#[instruction(params: RequestOpenPositionParams)]
+pub struct RequestOpenPosition<'info> {
+    // position owner or its trading delegate
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // optional, pays for the order and position accounts instead of the authority
+    #[account(mut)]
+    pub rent_payer: Option<Signer<'info>>,
+
+    /// CHECK: position owner, doesn't sign if the authority is a trading delegate
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == collateral_custody.mint,
+        constraint = funding_account.owner == owner.key()
+            || funding_account.owner == authority.key()
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    /// CHECK: position is opened on execution, must not exist yet
+    #[account(
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[params.side as u8],
+                 dated_market.as_ref().map(|market| market.key().to_bytes().to_vec()).unwrap_or_default().as_slice()],
+        bump
+    )]
+    pub position: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PendingOrder::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[params.side as u8],
+                 dated_market.as_ref().map(|market| market.key().to_bytes().to_vec()).unwrap_or_default().as_slice()],
+        bump
+    )]
+    pub order: Box<Account<'info, PendingOrder>>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = collateral_custody_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"order_token_account",
+                 order.key().as_ref()],
+        bump
+    )]
+    pub order_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_token_mint: Box<Account<'info, Mint>>,
+
+    // required if the authority is not the position owner
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
+
+    // records realized pnl and fees of the owner, created here so that keepers can update it
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    // records trading volume and referral of the owner, created here so that keepers
+    // must pass it on execution
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = TraderStats::LEN,
+        seeds = [b"trader_stats",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub trader_stats: Box<Account<'info, TraderStats>>,
+
+    // optional, credits a share of the fee to the referrer when the order is executed
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // required to request a dated futures position, perpetual if not provided
+    #[account(
+        has_one = pool,
+        has_one = custody
+    )]
+    pub dated_market: Option<Box<Account<'info, DatedMarket>>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RequestOpenPositionParams {
+    pub price: u64,
+    pub collateral: u64,
+    pub size: u64,
+    pub side: Side,
+}
+
+pub fn request_open_position(
+    ctx: Context<RequestOpenPosition>,
+    params: &RequestOpenPositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let pool = ctx.accounts.pool.as_ref();
+    let custody = ctx.accounts.custody.as_ref();
+    let collateral_custody = ctx.accounts.collateral_custody.as_ref();
+    require!(
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && !custody.is_stable
+            && pool.orders.is_enabled(),
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.price == 0 || params.collateral == 0 || params.size == 0 || params.side == Side::None
+    {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require!(
+        Perpetuals::is_empty_account(&ctx.accounts.position)?,
+        PerpetualsError::InvalidPositionState
+    );
+    pool.get_token_id(&custody.key())?;
+    pool.get_token_id(&collateral_custody.key())?;
+    let use_collateral_custody = params.side == Side::Short || custody.is_virtual;
+    if use_collateral_custody {
+        require_keys_neq!(custody.key(), collateral_custody.key());
+        require!(
+            collateral_custody.is_stable && !collateral_custody.is_virtual,
+            PerpetualsError::InvalidCollateralCustody
+        );
+    } else {
+        require_keys_eq!(custody.key(), collateral_custody.key());
+    };
+
+    // check trading authority, the size limit is checked at the worst acceptable
+    // price here and at the execution price when the order is executed
+    let curtime = perpetuals.get_time()?;
+    let trading_delegate = ctx.accounts.trading_delegate.as_deref().map(|d| &**d);
+    TradingDelegate::check_authority(
+        trading_delegate,
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        &custody.key(),
+        curtime,
+    )?;
+    let size_usd = OraclePrice::new(params.price, -(Perpetuals::PRICE_DECIMALS as i32))
+        .get_asset_amount_usd(params.size, custody.decimals)?;
+    TradingDelegate::check_size(
+        trading_delegate,
+        ctx.accounts.owner.key,
+        ctx.accounts.authority.key,
+        size_usd,
+    )?;
+    // check dated market expiry, also checked when the order is executed
+    if let Some(dated_market) = ctx.accounts.dated_market.as_deref() {
+        if use_collateral_custody {
+            require_keys_eq!(
+                collateral_custody.key(),
+                dated_market.collateral_custody,
+                PerpetualsError::InvalidCollateralCustody
+            );
+        }
+        require!(
+            dated_market.is_open_allowed(curtime),
+            PerpetualsError::DatedMarketClosed
+        );
+    }
+
+    let max_size_usd = if ctx.accounts.authority.key == ctx.accounts.owner.key {
+        u64::MAX
+    } else {
+        trading_delegate
+            .ok_or(PerpetualsError::InvalidTradingDelegate)?
+            .max_size_usd
+    };
+
+    // escrow collateral
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.order_token_account.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.collateral,
+    )?;
+
+    // reimburse the authority for the order accounts rent
+    let rent_payer = if let Some(rent_payer) = ctx.accounts.rent_payer.as_ref() {
+        Perpetuals::transfer_sol(
+            rent_payer.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            math::checked_add(
+                ctx.accounts.rent.minimum_balance(PendingOrder::LEN),
+                ctx.accounts.rent.minimum_balance(TokenAccount::LEN),
+            )?,
+        )?;
+        rent_payer.to_account_info()
+    } else {
+        ctx.accounts.authority.to_account_info()
+    };
+
+    // pre-fund the position account, keeper pays for it on execution and is
+    // reimbursed when the order account is closed. The order account rent is
+    // kept by the keeper as the execution incentive.
+    Perpetuals::transfer_sol(
+        rent_payer.clone(),
+        ctx.accounts.order.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        ctx.accounts.rent.minimum_balance(Position::LEN),
+    )?;
+
+    // record order
+    msg!("Record order");
+    let order = ctx.accounts.order.as_mut();
+    order.owner = ctx.accounts.owner.key();
+    order.rent_payer = rent_payer.key();
+    order.pool = pool.key();
+    order.custody = custody.key();
+    order.collateral_custody = collateral_custody.key();
+    order.dated_market = ctx
+        .accounts
+        .dated_market
+        .as_ref()
+        .map(|market| market.key())
+        .unwrap_or_default();
+    order.order_type = OrderType::OpenPosition;
+    order.side = params.side;
+    order.price = params.price;
+    order.collateral = params.collateral;
+    order.size = params.size;
+    order.max_size_usd = max_size_usd;
+    order.request_time = curtime;
+    order.bump = *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?;
+    order.token_account_bump = *ctx
+        .bumps
+        .get("order_token_account")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    // create owner stats so that the keeper can update them on execution
+    ctx.accounts.user_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        &pool.key(),
+        *ctx.bumps
+            .get("user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+    let trader_stats = ctx.accounts.trader_stats.as_mut();
+    trader_stats.init_if_empty(
+        ctx.accounts.owner.key,
+        *ctx.bumps
+            .get("trader_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+
+    // check referral, it is applied when the order is executed
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        trader_stats.bind_referral(&referral.key(), referral)?;
+        ctx.accounts.order.referral = referral.key();
+    }
+
+    Ok(())
+}
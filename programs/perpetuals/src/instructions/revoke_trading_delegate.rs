@@ -0,0 +1,30 @@
+//! RevokeTradingDelegate instruction handler
+
+use {crate::state::trading_delegate::TradingDelegate, anchor_lang::prelude::*};
+
+#[derive(Accounts)]
+pub struct RevokeTradingDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 trading_delegate.delegate.as_ref()],
+        bump = trading_delegate.bump,
+        close = owner
+    )]
+    pub trading_delegate: Box<Account<'info, TradingDelegate>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RevokeTradingDelegateParams {}
+
+pub fn revoke_trading_delegate(
+    _ctx: Context<RevokeTradingDelegate>,
+    _params: &RevokeTradingDelegateParams,
+) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! SetBorrowRateModel instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::{AdaptiveRateState, BorrowRateModelParams, Custody},
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetBorrowRateModel<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetBorrowRateModelParams {
+    pub borrow_rate_model: BorrowRateModelParams,
+}
+
+pub fn set_borrow_rate_model<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetBorrowRateModel<'info>>,
+    params: &SetBorrowRateModelParams,
+) -> Result<u8> {
+    // validate inputs
+    if !params
+        .borrow_rate_model
+        .validate(&ctx.accounts.custody.borrow_rate)
+    {
+        return err!(PerpetualsError::InvalidCustodyConfig);
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetBorrowRateModel, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // open positions keep interest snapshots of the current rate states
+    let custody = ctx.accounts.custody.as_mut();
+    let per_side_changed = custody.borrow_rate_model.per_side != params.borrow_rate_model.per_side;
+    if per_side_changed
+        && (custody.long_positions.open_positions > 0 || custody.short_positions.open_positions > 0)
+    {
+        msg!("Per side rates can't be toggled with open positions");
+        return err!(PerpetualsError::InvalidCustodyState);
+    }
+
+    // accrue interest at the previous model's rates
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    custody.update_borrow_rate(curtime)?;
+
+    // update custody data
+    let model_changed = custody.borrow_rate_model.model != params.borrow_rate_model.model;
+    custody.borrow_rate_model = params.borrow_rate_model;
+    let shared_state = custody.borrow_rate_state;
+    let state = &mut custody.borrow_rate_model_state;
+    if per_side_changed {
+        // side rates continue from the shared rate
+        state.long = shared_state;
+        state.short = shared_state;
+    }
+    if model_changed {
+        // adaptive target rates restart from the initial rate
+        state.adaptive = AdaptiveRateState::default();
+        state.long_adaptive = AdaptiveRateState::default();
+        state.short_adaptive = AdaptiveRateState::default();
+    }
+    custody.update_borrow_rate(curtime)?;
+
+    if !custody.validate() {
+        err!(PerpetualsError::InvalidCustodyConfig)
+    } else {
+        Ok(0)
+    }
+}
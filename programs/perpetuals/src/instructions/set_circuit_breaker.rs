@@ -0,0 +1,85 @@
+//! SetCircuitBreaker instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            circuit_breaker::CircuitBreakerParams,
+            custody::Custody,
+            multisig::{AdminInstruction, Multisig},
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetCircuitBreaker<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetCircuitBreakerParams {
+    pub params: CircuitBreakerParams,
+}
+
+pub fn set_circuit_breaker<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetCircuitBreaker<'info>>,
+    params: &SetCircuitBreakerParams,
+) -> Result<u8> {
+    // validate inputs
+    if !params.params.validate() {
+        return err!(PerpetualsError::InvalidCustodyConfig);
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetCircuitBreaker, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update custody data, this also resets a tripped breaker
+    let custody = ctx.accounts.custody.as_mut();
+    custody.circuit_breaker.params = params.params;
+    custody.circuit_breaker.reset();
+
+    if !custody.validate() {
+        err!(PerpetualsError::InvalidCustodyConfig)
+    } else {
+        Ok(0)
+    }
+}
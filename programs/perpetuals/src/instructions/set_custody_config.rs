@@ -4,7 +4,10 @@ use {
     crate::{
         error::PerpetualsError,
         state::{
-            custody::{BorrowRateParams, Custody, Fees, PricingParams},
+            custody::{
+                BorrowRateParams, Custody, DepegParams, Fees, LeverageTier, PricingParams,
+                SpreadParams,
+            },
             multisig::{AdminInstruction, Multisig},
             oracle::OracleParams,
             perpetuals::Permissions,
@@ -54,6 +57,9 @@ pub struct SetCustodyConfigParams {
     pub fees: Fees,
     pub borrow_rate: BorrowRateParams,
     pub ratios: Vec<TokenRatios>,
+    pub leverage_tiers: [LeverageTier; Custody::MAX_LEVERAGE_TIERS],
+    pub spread: SpreadParams,
+    pub depeg: DepegParams,
 }
 
 pub fn set_custody_config<'info>(
@@ -84,6 +90,8 @@ pub fn set_custody_config<'info>(
     // update pool data
     let pool = ctx.accounts.pool.as_mut();
     pool.ratios = params.ratios.clone();
+    // explicitly set ratios cancel scheduled re-weighting
+    pool.next_targets = Vec::new();
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
@@ -97,6 +105,9 @@ pub fn set_custody_config<'info>(
     custody.permissions = params.permissions;
     custody.fees = params.fees;
     custody.borrow_rate = params.borrow_rate;
+    custody.leverage_tiers = params.leverage_tiers;
+    custody.spread = params.spread;
+    custody.depeg = params.depeg;
 
     if !custody.validate() {
         err!(PerpetualsError::InvalidCustodyConfig)
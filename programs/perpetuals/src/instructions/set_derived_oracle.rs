@@ -0,0 +1,103 @@
+//! SetDerivedOracle instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            multisig::{AdminInstruction, Multisig},
+            oracle::{DerivedOperation, DerivedOracle, OracleParams},
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct SetDerivedOracle<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = DerivedOracle::LEN,
+        seeds = [b"derived_oracle",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump
+    )]
+    pub derived_oracle: Box<Account<'info, DerivedOracle>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Copy, Clone)]
+pub struct SetDerivedOracleParams {
+    pub base_oracle: OracleParams,
+    pub quote_oracle: OracleParams,
+    pub operation: DerivedOperation,
+}
+
+pub fn set_derived_oracle<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetDerivedOracle<'info>>,
+    params: &SetDerivedOracleParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetDerivedOracle, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update oracle data, the price is stale until the next update_derived_oracle
+    let derived_oracle = ctx.accounts.derived_oracle.as_mut();
+    **derived_oracle = DerivedOracle {
+        base_oracle: params.base_oracle,
+        quote_oracle: params.quote_oracle,
+        operation: params.operation,
+        bump: *ctx
+            .bumps
+            .get("derived_oracle")
+            .ok_or(ProgramError::InvalidSeeds)?,
+        ..DerivedOracle::default()
+    };
+
+    if !derived_oracle.validate() {
+        err!(PerpetualsError::InvalidDerivedOracleConfig)
+    } else {
+        Ok(0)
+    }
+}
@@ -0,0 +1,75 @@
+//! SetPoolConfig instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            multisig::{AdminInstruction, Multisig},
+            pool::{AdlParams, FeeTier, OrderParams, Pool, ReferralParams},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetPoolConfig<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetPoolConfigParams {
+    pub adl: AdlParams,
+    pub orders: OrderParams,
+    pub fee_tiers: [FeeTier; Pool::MAX_FEE_TIERS],
+    pub referral: ReferralParams,
+}
+
+pub fn set_pool_config<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+    params: &SetPoolConfigParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetPoolConfig, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update pool data
+    let pool = ctx.accounts.pool.as_mut();
+    pool.adl = params.adl;
+    pool.orders = params.orders;
+    pool.fee_tiers = params.fee_tiers;
+    pool.referral = params.referral;
+
+    if !pool.validate() {
+        err!(PerpetualsError::InvalidPoolConfig)
+    } else {
+        Ok(0)
+    }
+}
@@ -0,0 +1,92 @@
+//! SetTargetRatios instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::{Pool, TokenRatios},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: SetTargetRatiosParams)]
+pub struct SetTargetRatios<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        realloc = Pool::LEN + pool.custodies.len() * std::mem::size_of::<Pubkey>() +
+                              pool.ratios.len() * std::mem::size_of::<TokenRatios>() +
+                              params.targets.len() * std::mem::size_of::<u64>(),
+        realloc::payer = admin,
+        realloc::zero = false,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetTargetRatiosParams {
+    pub targets: Vec<u64>,
+    // re-weighting period in seconds, 0 applies new targets immediately
+    pub duration: i64,
+}
+
+pub fn set_target_ratios<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetTargetRatios<'info>>,
+    params: &SetTargetRatiosParams,
+) -> Result<u8> {
+    // validate inputs
+    if params.targets.len() != ctx.accounts.pool.ratios.len() || params.duration < 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetTargetRatios, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update pool data
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    let pool = ctx.accounts.pool.as_mut();
+    pool.set_next_targets(&params.targets, params.duration, curtime)?;
+
+    if !pool.validate() {
+        err!(PerpetualsError::InvalidPoolConfig)
+    } else {
+        Ok(0)
+    }
+}
@@ -0,0 +1,58 @@
+//! SetTradingDelegate instruction handler
+
+use {
+    crate::state::trading_delegate::TradingDelegate, anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct SetTradingDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: key that is allowed to trade on behalf of the owner
+    #[account()]
+    pub delegate: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = TradingDelegate::LEN,
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 delegate.key().as_ref()],
+        bump
+    )]
+    pub trading_delegate: Box<Account<'info, TradingDelegate>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetTradingDelegateParams {
+    pub max_size_usd: u64,
+    pub custodies: Vec<Pubkey>,
+    pub expiry_time: i64,
+}
+
+pub fn set_trading_delegate(
+    ctx: Context<SetTradingDelegate>,
+    params: &SetTradingDelegateParams,
+) -> Result<()> {
+    let trading_delegate = ctx.accounts.trading_delegate.as_mut();
+    trading_delegate.owner = ctx.accounts.owner.key();
+    trading_delegate.delegate = ctx.accounts.delegate.key();
+    trading_delegate.max_size_usd = params.max_size_usd;
+    trading_delegate.custodies = params.custodies.clone();
+    trading_delegate.expiry_time = params.expiry_time;
+    trading_delegate.bump = *ctx
+        .bumps
+        .get("trading_delegate")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    if !trading_delegate.validate() {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    Ok(())
+}
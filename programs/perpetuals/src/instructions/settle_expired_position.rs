@@ -0,0 +1,268 @@
+//! SettleExpiredPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            dated_market::DatedMarket,
+            oracle::{ActionClass, OraclePrice},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            tranche::Tranche,
+            user_stats::UserStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct SettleExpiredPosition<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == position.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: account that paid for the position, receives position account rent
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = rent_payer,
+        seeds = [b"position",
+                 position.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump = position.bump,
+        close = rent_payer
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = position.dated_market == dated_market.key()
+    )]
+    pub dated_market: Box<Account<'info, DatedMarket>>,
+
+    // required if the position funds are locked in a tranche
+    #[account(mut)]
+    pub tranche: Option<Box<Account<'info, Tranche>>>,
+
+    // records realized pnl and fees of the owner, created by the owner when the position
+    // is opened so that keepers don't pay for it
+    #[account(
+        mut,
+        seeds = [b"user_stats",
+                 position.owner.as_ref(),
+                 pool.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SettleExpiredPositionParams {}
+
+// Permissionless, closes an expired dated position at the settlement price and
+// returns the proceeds to the owner
+pub fn settle_expired_position(
+    ctx: Context<SettleExpiredPosition>,
+    _params: &SettleExpiredPositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    let dated_market = ctx.accounts.dated_market.as_ref();
+    if !dated_market.is_settled() {
+        msg!("Dated market is not settled");
+        return err!(PerpetualsError::InvalidDatedMarketState);
+    }
+
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let tranche = if position.tranche != Pubkey::default() {
+        let tranche = ctx
+            .accounts
+            .tranche
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidTranche)?;
+        require_keys_eq!(
+            tranche.key(),
+            position.tranche,
+            PerpetualsError::InvalidTranche
+        );
+        Some(tranche)
+    } else {
+        None
+    };
+
+    // compute prices
+    let curtime = perpetuals.get_time()?;
+
+    let settlement_price = OraclePrice {
+        price: dated_market.settlement_price,
+        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+        conf: 0,
+    };
+    msg!("Settlement price: {}", settlement_price.price);
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.get_oracle_params(curtime),
+        curtime,
+        false,
+        ActionClass::Reduce,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.get_oracle_params(curtime),
+        curtime,
+        collateral_custody.pricing.use_ema,
+        ActionClass::Reduce,
+    )?;
+
+    // interest stops at expiry, move the snapshot by the interest accrued since
+    let cumulative_interest = collateral_custody.get_cumulative_interest(position.side, curtime)?;
+    position.cumulative_interest_snapshot = math::checked_add(
+        position.cumulative_interest_snapshot,
+        cumulative_interest.saturating_sub(dated_market.get_cumulative_interest(position.side)),
+    )?;
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+
+    msg!("Settle position");
+    let (transfer_amount, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        position,
+        &settlement_price,
+        &settlement_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        false,
+        0,
+    )?;
+
+    let fee_amount_usd = settlement_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    if position.side == Side::Short || custody.is_virtual {
+        fee_amount = collateral_token_ema_price
+            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+    }
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Collected fee: {}", fee_amount);
+    msg!("Amount out: {}", transfer_amount);
+
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    pool.settle_close_position(
+        position,
+        true,
+        false,
+        transfer_amount,
+        (fee_amount, fee_amount_usd),
+        0,
+        (profit_usd, loss_usd),
+        custody,
+        collateral_custody,
+        tranche.map(|tranche| &mut ***tranche),
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update user stats
+    let user_stats = ctx.accounts.user_stats.as_mut();
+    user_stats.record_close(
+        position.size_usd,
+        fee_amount_usd,
+        interest_usd,
+        profit_usd,
+        loss_usd,
+        false,
+        curtime,
+    );
+
+    Ok(())
+}
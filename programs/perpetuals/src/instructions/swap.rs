@@ -4,7 +4,10 @@ use {
     crate::{
         error::PerpetualsError,
         math,
-        state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
+        state::{
+            custody::Custody, perpetuals::Perpetuals, pool::Pool, referral::Referral,
+            trader_stats::TraderStats,
+        },
     },
     anchor_lang::prelude::*,
     anchor_spl::token::{Token, TokenAccount},
@@ -12,7 +15,6 @@ use {
 };
 
 #[derive(Accounts)]
-#[instruction(params: SwapParams)]
 pub struct Swap<'info> {
     #[account()]
     pub owner: Signer<'info>,
@@ -100,7 +102,18 @@ pub struct Swap<'info> {
     )]
     pub dispensing_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    token_program: Program<'info, Token>,
+    // optional, enables trading volume based fee discounts
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
+    // optional, credits a share of the fee to the referrer
+    #[account(mut)]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -137,42 +150,22 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     let token_id_in = pool.get_token_id(&receiving_custody.key())?;
     let token_id_out = pool.get_token_id(&dispensing_custody.key())?;
 
-    let received_token_price = OraclePrice::new_from_oracle(
-        &ctx.accounts
-            .receiving_custody_oracle_account
-            .to_account_info(),
-        &receiving_custody.oracle,
-        curtime,
-        false,
-    )?;
-
-    let received_token_ema_price = OraclePrice::new_from_oracle(
+    let (received_token_price, received_token_ema_price) = receiving_custody.get_swap_prices(
         &ctx.accounts
             .receiving_custody_oracle_account
             .to_account_info(),
-        &receiving_custody.oracle,
         curtime,
-        receiving_custody.pricing.use_ema,
+        true,
     )?;
 
-    let dispensed_token_price = OraclePrice::new_from_oracle(
+    let (dispensed_token_price, dispensed_token_ema_price) = dispensing_custody.get_swap_prices(
         &ctx.accounts
             .dispensing_custody_oracle_account
             .to_account_info(),
-        &dispensing_custody.oracle,
         curtime,
         false,
     )?;
 
-    let dispensed_token_ema_price = OraclePrice::new_from_oracle(
-        &ctx.accounts
-            .dispensing_custody_oracle_account
-            .to_account_info(),
-        &dispensing_custody.oracle,
-        curtime,
-        dispensing_custody.pricing.use_ema,
-    )?;
-
     msg!("Compute swap amount");
     let amount_out = pool.get_swap_amount(
         &received_token_price,
@@ -184,6 +177,21 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
         params.amount_in,
     )?;
 
+    // check referral
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        ctx.accounts
+            .trader_stats
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidReferral)?
+            .bind_referral(&referral.key(), referral)?;
+    }
+
+    let trader_volume_usd = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        trader_stats.get_volume_usd(curtime)?
+    } else {
+        0
+    };
+
     // calculate fee
     let fees = pool.get_swap_fees(
         token_id_in,
@@ -194,11 +202,21 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
         &received_token_price,
         dispensing_custody,
         &dispensed_token_price,
+        curtime,
+        trader_volume_usd,
     )?;
-    msg!("Collected fees: {} {}", fees.0, fees.1);
+
+    // split referrer reward and trader rebate off the fee paid in the dispensed token
+    let (referrer_reward, trader_rebate) = if ctx.accounts.referral.is_some() {
+        pool.get_referral_amounts(fees.1, dispensing_custody.fees.protocol_share)?
+    } else {
+        (0, 0)
+    };
+    let fee_out = math::checked_sub(fees.1, trader_rebate)?;
+    msg!("Collected fees: {} {}", fees.0, fee_out);
 
     // check returned amount
-    let no_fee_amount = math::checked_sub(amount_out, fees.1)?;
+    let no_fee_amount = math::checked_sub(amount_out, fee_out)?;
     msg!("Amount out: {}", no_fee_amount);
     require_gte!(
         no_fee_amount,
@@ -206,36 +224,21 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
         PerpetualsError::InsufficientAmountReturned
     );
 
-    // check pool constraints
+    // check pool constraints and update custody stats
     msg!("Check pool constraints");
-    let protocol_fee_in = Pool::get_fee_amount(receiving_custody.fees.protocol_share, fees.0)?;
-    let protocol_fee_out = Pool::get_fee_amount(dispensing_custody.fees.protocol_share, fees.1)?;
-    let deposit_amount = math::checked_sub(params.amount_in, protocol_fee_in)?;
-    let withdrawal_amount = math::checked_add(no_fee_amount, protocol_fee_out)?;
-
-    require!(
-        pool.check_token_ratio(
-            token_id_in,
-            deposit_amount,
-            0,
-            receiving_custody,
-            &received_token_price
-        )? && pool.check_token_ratio(
-            token_id_out,
-            0,
-            withdrawal_amount,
-            dispensing_custody,
-            &dispensed_token_price
-        )?,
-        PerpetualsError::TokenRatioOutOfRange
-    );
-    require!(
-        math::checked_sub(
-            dispensing_custody.assets.owned,
-            dispensing_custody.assets.locked
-        )? >= withdrawal_amount,
-        PerpetualsError::CustodyAmountLimit
-    );
+    pool.settle_swap(
+        token_id_in,
+        token_id_out,
+        params.amount_in,
+        amount_out,
+        fees,
+        (referrer_reward, trader_rebate),
+        receiving_custody,
+        &received_token_price,
+        dispensing_custody,
+        &dispensed_token_price,
+        curtime,
+    )?;
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -259,41 +262,19 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
         no_fee_amount,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    receiving_custody.volume_stats.swap_usd = receiving_custody.volume_stats.swap_usd.wrapping_add(
-        received_token_price.get_asset_amount_usd(params.amount_in, receiving_custody.decimals)?,
-    );
-
-    receiving_custody.collected_fees.swap_usd =
-        receiving_custody.collected_fees.swap_usd.wrapping_add(
-            received_token_price.get_asset_amount_usd(fees.0, receiving_custody.decimals)?,
-        );
-
-    receiving_custody.assets.owned =
-        math::checked_add(receiving_custody.assets.owned, deposit_amount)?;
-
-    receiving_custody.assets.protocol_fees =
-        math::checked_add(receiving_custody.assets.protocol_fees, protocol_fee_in)?;
-
-    dispensing_custody.collected_fees.swap_usd =
-        dispensing_custody.collected_fees.swap_usd.wrapping_add(
-            dispensed_token_price.get_asset_amount_usd(fees.1, dispensing_custody.decimals)?,
-        );
-
-    dispensing_custody.volume_stats.swap_usd =
-        dispensing_custody.volume_stats.swap_usd.wrapping_add(
-            dispensed_token_price.get_asset_amount_usd(amount_out, dispensing_custody.decimals)?,
-        );
-
-    dispensing_custody.assets.protocol_fees =
-        math::checked_add(dispensing_custody.assets.protocol_fees, protocol_fee_out)?;
-
-    dispensing_custody.assets.owned =
-        math::checked_sub(dispensing_custody.assets.owned, withdrawal_amount)?;
+    // credit referrer reward
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        referral.add_reward(&dispensing_custody.key(), referrer_reward)?;
+    }
 
-    receiving_custody.update_borrow_rate(curtime)?;
-    dispensing_custody.update_borrow_rate(curtime)?;
+    // update trader stats
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(
+            received_token_price
+                .get_asset_amount_usd(params.amount_in, receiving_custody.decimals)?,
+            curtime,
+        )?;
+    }
 
     Ok(())
 }
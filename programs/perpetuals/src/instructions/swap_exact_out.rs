@@ -0,0 +1,165 @@
+//! SwapExactOut instruction handler
+
+use {
+    crate::{error::PerpetualsError, instructions::swap::Swap, math},
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SwapExactOutParams {
+    pub amount_out: u64,
+    pub max_amount_in: u64,
+}
+
+pub fn swap_exact_out(ctx: Context<Swap>, params: &SwapExactOutParams) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let receiving_custody = ctx.accounts.receiving_custody.as_mut();
+    let dispensing_custody = ctx.accounts.dispensing_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_swap
+            && receiving_custody.permissions.allow_swap
+            && dispensing_custody.permissions.allow_swap
+            && !receiving_custody.is_virtual
+            && !dispensing_custody.is_virtual,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount_out == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require_keys_neq!(receiving_custody.key(), dispensing_custody.key());
+
+    // compute token amount returned to the user
+    let pool = ctx.accounts.pool.as_mut();
+    let curtime = perpetuals.get_time()?;
+    let token_id_in = pool.get_token_id(&receiving_custody.key())?;
+    let token_id_out = pool.get_token_id(&dispensing_custody.key())?;
+
+    let (received_token_price, received_token_ema_price) = receiving_custody.get_swap_prices(
+        &ctx.accounts
+            .receiving_custody_oracle_account
+            .to_account_info(),
+        curtime,
+        true,
+    )?;
+
+    let (dispensed_token_price, dispensed_token_ema_price) = dispensing_custody.get_swap_prices(
+        &ctx.accounts
+            .dispensing_custody_oracle_account
+            .to_account_info(),
+        curtime,
+        false,
+    )?;
+
+    // check referral
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        ctx.accounts
+            .trader_stats
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidReferral)?
+            .bind_referral(&referral.key(), referral)?;
+    }
+
+    let trader_volume_usd = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        trader_stats.get_volume_usd(curtime)?
+    } else {
+        0
+    };
+
+    msg!("Compute swap amount");
+    let (amount_in, fee_in, fee_out) = pool.get_swap_amount_in_and_fees(
+        token_id_in,
+        token_id_out,
+        params.amount_out,
+        &received_token_price,
+        &received_token_ema_price,
+        &dispensed_token_price,
+        &dispensed_token_ema_price,
+        receiving_custody,
+        dispensing_custody,
+        curtime,
+        trader_volume_usd,
+    )?;
+    let fees = (fee_in, fee_out);
+
+    // split referrer reward and trader rebate off the fee paid in the dispensed token,
+    // the rebate is paid on top of the requested amount
+    let (referrer_reward, trader_rebate) = if ctx.accounts.referral.is_some() {
+        pool.get_referral_amounts(fees.1, dispensing_custody.fees.protocol_share)?
+    } else {
+        (0, 0)
+    };
+    msg!(
+        "Collected fees: {} {}",
+        fees.0,
+        math::checked_sub(fees.1, trader_rebate)?
+    );
+
+    // check required amount
+    let amount_out = math::checked_add(params.amount_out, fees.1)?;
+    let no_fee_amount = math::checked_add(params.amount_out, trader_rebate)?;
+    msg!("Amount in: {}", amount_in);
+    require_gte!(
+        params.max_amount_in,
+        amount_in,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // check pool constraints and update custody stats
+    msg!("Check pool constraints");
+    pool.settle_swap(
+        token_id_in,
+        token_id_out,
+        amount_in,
+        amount_out,
+        fees,
+        (referrer_reward, trader_rebate),
+        receiving_custody,
+        &received_token_price,
+        dispensing_custody,
+        &dispensed_token_price,
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts
+            .receiving_custody_token_account
+            .to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount_in,
+    )?;
+
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .dispensing_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        no_fee_amount,
+    )?;
+
+    // credit referrer reward
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        referral.add_reward(&dispensing_custody.key(), referrer_reward)?;
+    }
+
+    // update trader stats
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(
+            received_token_price.get_asset_amount_usd(amount_in, receiving_custody.decimals)?,
+            curtime,
+        )?;
+    }
+
+    Ok(())
+}
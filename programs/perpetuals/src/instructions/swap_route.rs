@@ -0,0 +1,327 @@
+//! SwapRoute instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, perpetuals::Perpetuals, pool::Pool, referral::Referral,
+            trader_stats::TraderStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct SwapRoute<'info> {
+    #[account()]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    // optional, enables trading volume based fee discounts
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
+    // optional, credits a share of the fee of every leg to the referrer
+    #[account(mut)]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts, for each leg of the route:
+    //   pool (writable, unsigned)
+    //   receiving custody (writable, unsigned)
+    //   receiving custody oracle (read-only, unsigned)
+    //   receiving custody token account (writable, unsigned)
+    //   dispensing custody (writable, unsigned)
+    //   dispensing custody oracle (read-only, unsigned)
+    //   dispensing custody token account (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SwapLeg {
+    pub pool: Pubkey,
+    pub receiving_custody: Pubkey,
+    pub dispensing_custody: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapRouteParams {
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+    pub route: Vec<SwapLeg>,
+}
+
+const MAX_ROUTE_LEGS: usize = 4;
+const ACCOUNTS_PER_LEG: usize = 7;
+
+pub fn swap_route<'info>(
+    ctx: Context<'_, '_, '_, 'info, SwapRoute<'info>>,
+    params: &SwapRouteParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    require!(
+        perpetuals.permissions.allow_swap,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount_in == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require!(
+        !params.route.is_empty() && params.route.len() <= MAX_ROUTE_LEGS,
+        PerpetualsError::InvalidSwapRoute
+    );
+    if ctx.remaining_accounts.len() != params.route.len() * ACCOUNTS_PER_LEG {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    // every pool and custody can only be used once, otherwise updates would be overwritten
+    let mut keys = Vec::with_capacity(params.route.len() * 3);
+    for leg in &params.route {
+        for key in [leg.pool, leg.receiving_custody, leg.dispensing_custody] {
+            require!(!keys.contains(&key), PerpetualsError::InvalidSwapRoute);
+            keys.push(key);
+        }
+    }
+
+    // check referral
+    if let Some(referral) = ctx.accounts.referral.as_ref() {
+        ctx.accounts
+            .trader_stats
+            .as_mut()
+            .ok_or(PerpetualsError::InvalidReferral)?
+            .bind_referral(&referral.key(), referral)?;
+    }
+
+    let curtime = perpetuals.get_time()?;
+    let trader_volume_usd = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        trader_stats.get_volume_usd(curtime)?
+    } else {
+        0
+    };
+    let mut volume_usd = 0;
+    let mut amount_in = params.amount_in;
+    let mut prev_token_account: Option<&AccountInfo<'info>> = None;
+    let mut prev_mint = ctx.accounts.funding_account.mint;
+
+    for (leg, accounts) in params
+        .route
+        .iter()
+        .zip(ctx.remaining_accounts.chunks(ACCOUNTS_PER_LEG))
+    {
+        // load and validate leg accounts
+        require_keys_eq!(
+            accounts[0].key(),
+            leg.pool,
+            PerpetualsError::InvalidSwapRoute
+        );
+        require_keys_eq!(
+            accounts[1].key(),
+            leg.receiving_custody,
+            PerpetualsError::InvalidSwapRoute
+        );
+        require_keys_eq!(
+            accounts[4].key(),
+            leg.dispensing_custody,
+            PerpetualsError::InvalidSwapRoute
+        );
+
+        let mut pool = Account::<Pool>::try_from(&accounts[0])?;
+        let mut receiving_custody = Account::<Custody>::try_from(&accounts[1])?;
+        let mut dispensing_custody = Account::<Custody>::try_from(&accounts[4])?;
+
+        let token_id_in = pool.get_token_id(&leg.receiving_custody)?;
+        let token_id_out = pool.get_token_id(&leg.dispensing_custody)?;
+
+        require_keys_eq!(accounts[2].key(), receiving_custody.oracle.oracle_account);
+        require_keys_eq!(accounts[3].key(), receiving_custody.token_account);
+        require_keys_eq!(accounts[5].key(), dispensing_custody.oracle.oracle_account);
+        require_keys_eq!(accounts[6].key(), dispensing_custody.token_account);
+        require_keys_eq!(
+            receiving_custody.mint,
+            prev_mint,
+            PerpetualsError::InvalidSwapRoute
+        );
+
+        require!(
+            receiving_custody.permissions.allow_swap
+                && dispensing_custody.permissions.allow_swap
+                && !receiving_custody.is_virtual
+                && !dispensing_custody.is_virtual,
+            PerpetualsError::InstructionNotAllowed
+        );
+
+        // compute token amount returned by the leg
+        let (received_token_price, received_token_ema_price) =
+            receiving_custody.get_swap_prices(&accounts[2], curtime, true)?;
+
+        let (dispensed_token_price, dispensed_token_ema_price) =
+            dispensing_custody.get_swap_prices(&accounts[5], curtime, false)?;
+
+        msg!("Compute swap amount");
+        let amount_out = pool.get_swap_amount(
+            &received_token_price,
+            &received_token_ema_price,
+            &dispensed_token_price,
+            &dispensed_token_ema_price,
+            &receiving_custody,
+            &dispensing_custody,
+            amount_in,
+        )?;
+
+        // calculate fee
+        let fees = pool.get_swap_fees(
+            token_id_in,
+            token_id_out,
+            amount_in,
+            amount_out,
+            &receiving_custody,
+            &received_token_price,
+            &dispensing_custody,
+            &dispensed_token_price,
+            curtime,
+            trader_volume_usd,
+        )?;
+
+        // split referrer reward and trader rebate off the fee paid in the dispensed token
+        let (referrer_reward, trader_rebate) = if ctx.accounts.referral.is_some() {
+            pool.get_referral_amounts(fees.1, dispensing_custody.fees.protocol_share)?
+        } else {
+            (0, 0)
+        };
+        let fee_out = math::checked_sub(fees.1, trader_rebate)?;
+        msg!("Collected fees: {} {}", fees.0, fee_out);
+
+        let no_fee_amount = math::checked_sub(amount_out, fee_out)?;
+        msg!("Amount out: {}", no_fee_amount);
+
+        // check pool constraints and update custody stats
+        msg!("Check pool constraints");
+        let (deposit_amount, withdrawal_amount) = pool.settle_swap(
+            token_id_in,
+            token_id_out,
+            amount_in,
+            amount_out,
+            fees,
+            (referrer_reward, trader_rebate),
+            &mut receiving_custody,
+            &received_token_price,
+            &mut dispensing_custody,
+            &dispensed_token_price,
+            curtime,
+        )?;
+
+        // transfer tokens into the leg
+        msg!("Transfer tokens");
+        if let Some(token_account) = prev_token_account {
+            perpetuals.transfer_tokens(
+                token_account.clone(),
+                accounts[3].clone(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount_in,
+            )?;
+        } else {
+            // trading volume is the value of the route input
+            volume_usd =
+                received_token_price.get_asset_amount_usd(amount_in, receiving_custody.decimals)?;
+            perpetuals.transfer_tokens_from_user(
+                ctx.accounts.funding_account.to_account_info(),
+                accounts[3].clone(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                amount_in,
+            )?;
+        }
+
+        if let Some(referral) = ctx.accounts.referral.as_mut() {
+            referral.add_reward(&dispensing_custody.key(), referrer_reward)?;
+        }
+
+        // update pool aum by the net value the leg moved in and out
+        msg!("Update pool aum");
+        pool.aum_usd = math::checked_add(
+            pool.aum_usd,
+            received_token_ema_price
+                .get_asset_amount_usd(deposit_amount, receiving_custody.decimals)?
+                as u128,
+        )?
+        .saturating_sub(
+            dispensed_token_ema_price
+                .get_asset_amount_usd(withdrawal_amount, dispensing_custody.decimals)?
+                as u128,
+        );
+        msg!("AUM: {}", pool.aum_usd);
+
+        pool.exit(&crate::ID)?;
+        receiving_custody.exit(&crate::ID)?;
+        dispensing_custody.exit(&crate::ID)?;
+
+        amount_in = no_fee_amount;
+        prev_mint = dispensing_custody.mint;
+        prev_token_account = Some(&accounts[6]);
+    }
+
+    // check returned amount
+    msg!("Amount out: {}", amount_in);
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        prev_mint,
+        PerpetualsError::InvalidSwapRoute
+    );
+    require_gte!(
+        amount_in,
+        params.min_amount_out,
+        PerpetualsError::InsufficientAmountReturned
+    );
+
+    perpetuals.transfer_tokens(
+        prev_token_account
+            .ok_or(PerpetualsError::InvalidSwapRoute)?
+            .clone(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount_in,
+    )?;
+
+    // update trader stats
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(volume_usd, curtime)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! TransferPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{perpetuals::Perpetuals, pool::Pool, position::Position, user_stats::UserStats},
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: recipient of the position, signs only if the transfer requires its consent
+    #[account(
+        constraint = new_owner.key() != owner.key()
+    )]
+    pub new_owner: AccountInfo<'info>,
+
+    /// CHECK: account that paid for the position, receives position account rent
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        has_one = rent_payer,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 position.custody.as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump = position.bump,
+        close = rent_payer
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [b"position",
+                 new_owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 position.custody.as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump
+    )]
+    pub new_position: Box<Account<'info, Position>>,
+
+    // records realized pnl and fees of the new owner, created here so that keepers can
+    // update it when the position is liquidated or settled
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = UserStats::LEN,
+        seeds = [b"user_stats",
+                 new_owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub new_user_stats: Box<Account<'info, UserStats>>,
+
+    /// CHECK: pending order for the position, must not exist
+    #[account(
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 position.custody.as_ref(),
+                 &[position.side as u8],
+                 position.get_market_seed()],
+        bump
+    )]
+    pub order: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TransferPositionParams {
+    // if set, the new owner must sign the transfer
+    pub require_new_owner_signature: bool,
+}
+
+pub fn transfer_position(
+    ctx: Context<TransferPosition>,
+    params: &TransferPositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    require!(
+        perpetuals.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    require!(
+        Perpetuals::is_empty_account(&ctx.accounts.order)?,
+        PerpetualsError::InvalidPositionState
+    );
+    if params.require_new_owner_signature && !ctx.accounts.new_owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature.into());
+    }
+
+    // move the position, all accounting is carried over as is
+    msg!("Transfer position");
+    let position = ctx.accounts.position.as_ref();
+    let new_position = Position {
+        owner: ctx.accounts.new_owner.key(),
+        rent_payer: ctx.accounts.owner.key(),
+        bump: *ctx
+            .bumps
+            .get("new_position")
+            .ok_or(ProgramError::InvalidSeeds)?,
+        ..Position::clone(position)
+    };
+    ctx.accounts.new_position.set_inner(new_position);
+
+    ctx.accounts.new_user_stats.init_if_empty(
+        ctx.accounts.new_owner.key,
+        &ctx.accounts.pool.key(),
+        *ctx.bumps
+            .get("new_user_stats")
+            .ok_or(ProgramError::InvalidSeeds)?,
+    );
+
+    Ok(())
+}
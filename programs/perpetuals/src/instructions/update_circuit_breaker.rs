@@ -0,0 +1,66 @@
+//! UpdateCircuitBreaker instruction handler
+
+use {
+    crate::state::{custody::Custody, perpetuals::Perpetuals, pool::Pool},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct UpdateCircuitBreaker<'info> {
+    #[account()]
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the custody token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct UpdateCircuitBreakerParams {}
+
+// Permissionless crank that trips the breaker. Trades only reject prices that
+// breach the limits since their state changes are reverted with the error.
+pub fn update_circuit_breaker(
+    ctx: Context<UpdateCircuitBreaker>,
+    _params: &UpdateCircuitBreakerParams,
+) -> Result<bool> {
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    let custody = ctx.accounts.custody.as_mut();
+
+    let allowed = custody.update_circuit_breaker(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        curtime,
+    )?;
+    if !allowed {
+        msg!(
+            "Custody is close-only since {}",
+            custody.circuit_breaker.trip_time
+        );
+    }
+
+    Ok(allowed)
+}
@@ -0,0 +1,88 @@
+//! UpdateCustodyStatsHistory instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        custody_stats_history::CustodyStatsHistory,
+        oracle::{ActionClass, OraclePrice},
+        perpetuals::Perpetuals,
+        pool::Pool,
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct UpdateCustodyStatsHistory<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the custody token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    // permissionless crank, the history account is created on the first call
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = CustodyStatsHistory::LEN,
+        seeds = [b"custody_stats_history",
+                 custody.key().as_ref()],
+        bump
+    )]
+    pub custody_stats_history: Box<Account<'info, CustodyStatsHistory>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct UpdateCustodyStatsHistoryParams {}
+
+pub fn update_custody_stats_history(
+    ctx: Context<UpdateCustodyStatsHistory>,
+    _params: &UpdateCustodyStatsHistoryParams,
+) -> Result<()> {
+    let custody = ctx.accounts.custody.as_ref();
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        ActionClass::Reduce,
+    )?;
+    let owned_usd = token_ema_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
+
+    let custody_stats_history = ctx.accounts.custody_stats_history.as_mut();
+    custody_stats_history.custody = custody.key();
+    custody_stats_history.bump = *ctx
+        .bumps
+        .get("custody_stats_history")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    custody_stats_history.update(custody, owned_usd, curtime)
+}
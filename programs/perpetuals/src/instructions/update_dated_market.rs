@@ -0,0 +1,106 @@
+//! UpdateDatedMarket instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        dated_market::DatedMarket,
+        oracle::{ActionClass, OraclePrice},
+        perpetuals::Perpetuals,
+        pool::Pool,
+        position::Side,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct UpdateDatedMarket<'info> {
+    #[account()]
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the custody token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        constraint = collateral_custody.key() == dated_market.collateral_custody
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = custody,
+        seeds = [b"dated_market",
+                 custody.key().as_ref(),
+                 &dated_market.expiry_time.to_le_bytes()],
+        bump = dated_market.bump
+    )]
+    pub dated_market: Box<Account<'info, DatedMarket>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct UpdateDatedMarketParams {}
+
+// Permissionless crank that samples the oracle price over the settlement window
+// and settles the market after expiry
+pub fn update_dated_market(
+    ctx: Context<UpdateDatedMarket>,
+    _params: &UpdateDatedMarketParams,
+) -> Result<()> {
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    let custody = ctx.accounts.custody.as_ref();
+    let collateral_custody = ctx.accounts.collateral_custody.as_ref();
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.get_oracle_params(curtime),
+        curtime,
+        false,
+        ActionClass::Increase,
+    )?
+    .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?;
+
+    // longs borrow from the custody unless it is virtual
+    let long_rate_state = if custody.is_virtual {
+        collateral_custody.get_borrow_rate_state(Side::Long)
+    } else {
+        custody.get_borrow_rate_state(Side::Long)
+    };
+    let short_rate_state = collateral_custody.get_borrow_rate_state(Side::Short);
+
+    let dated_market = ctx.accounts.dated_market.as_mut();
+    dated_market.update(
+        token_price.price,
+        long_rate_state,
+        short_rate_state,
+        curtime,
+    )?;
+    if dated_market.is_settled() {
+        msg!("Settlement price: {}", dated_market.settlement_price);
+    }
+
+    Ok(())
+}
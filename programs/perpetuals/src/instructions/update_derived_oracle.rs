@@ -0,0 +1,49 @@
+//! UpdateDerivedOracle instruction handler
+
+use {
+    crate::state::{oracle::DerivedOracle, perpetuals::Perpetuals},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct UpdateDerivedOracle<'info> {
+    #[account()]
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(mut)]
+    pub derived_oracle: Box<Account<'info, DerivedOracle>>,
+
+    /// CHECK: oracle account of the base leg
+    #[account(
+        constraint = base_oracle_account.key() == derived_oracle.base_oracle.oracle_account
+    )]
+    pub base_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: oracle account of the quote leg, not used by Invert
+    #[account(
+        constraint = quote_oracle_account.key() == derived_oracle.quote_oracle.oracle_account
+    )]
+    pub quote_oracle_account: Option<AccountInfo<'info>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct UpdateDerivedOracleParams {}
+
+pub fn update_derived_oracle(
+    ctx: Context<UpdateDerivedOracle>,
+    _params: &UpdateDerivedOracleParams,
+) -> Result<()> {
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    ctx.accounts.derived_oracle.update(
+        &ctx.accounts.base_oracle_account,
+        ctx.accounts.quote_oracle_account.as_ref(),
+        curtime,
+    )
+}
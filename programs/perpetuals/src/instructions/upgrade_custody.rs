@@ -4,10 +4,17 @@ use {
     crate::{
         error::PerpetualsError,
         state::{
-            custody::{Custody, DeprecatedCustody},
+            circuit_breaker::CircuitBreaker,
+            custody::{
+                BorrowRateModelParams, BorrowRateModelState, Custody, DepegParams,
+                DeprecatedCustody, LeverageTier, SpreadParams,
+            },
+            migration::Versioned,
             multisig::{AdminInstruction, Multisig},
+            oracle::OracleParams,
             perpetuals::Perpetuals,
             pool::Pool,
+            trading_schedule::TradingSchedule,
         },
     },
     anchor_lang::prelude::*,
@@ -124,17 +131,27 @@ pub fn upgrade_custody<'info>(
 
     // update custody data
     let custody_data = Custody {
+        version: Custody::CURRENT_VERSION,
         pool: deprecated_custody.pool,
         mint: deprecated_custody.mint,
         token_account: deprecated_custody.token_account,
         decimals: deprecated_custody.decimals,
         is_stable: deprecated_custody.is_stable,
         is_virtual: false,
-        oracle: deprecated_custody.oracle,
+        oracle: OracleParams {
+            oracle_account: deprecated_custody.oracle.oracle_account,
+            oracle_type: deprecated_custody.oracle.oracle_type,
+            oracle_authority: deprecated_custody.oracle.oracle_authority,
+            max_price_error: deprecated_custody.oracle.max_price_error,
+            max_price_age_sec: deprecated_custody.oracle.max_price_age_sec,
+            max_reducing_price_age_sec: deprecated_custody.oracle.max_price_age_sec,
+        },
         pricing: deprecated_custody.pricing,
         permissions: deprecated_custody.permissions,
         fees: deprecated_custody.fees,
         borrow_rate: deprecated_custody.borrow_rate,
+        tranche: Pubkey::default(),
+        leverage_tiers: [LeverageTier::default(); Custody::MAX_LEVERAGE_TIERS],
         assets: deprecated_custody.assets,
         collected_fees: deprecated_custody.collected_fees,
         volume_stats: deprecated_custody.volume_stats,
@@ -144,6 +161,13 @@ pub fn upgrade_custody<'info>(
         borrow_rate_state: deprecated_custody.borrow_rate_state,
         bump: deprecated_custody.bump,
         token_account_bump: deprecated_custody.token_account_bump,
+        trading_schedule: TradingSchedule::default(),
+        circuit_breaker: CircuitBreaker::default(),
+        spread: SpreadParams::default(),
+        depeg: DepegParams::default(),
+        borrow_rate_model: BorrowRateModelParams::default(),
+        borrow_rate_model_state: BorrowRateModelState::default(),
+        reserved: [0; 8],
     };
 
     if !custody_data.validate() {
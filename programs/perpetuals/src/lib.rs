@@ -10,8 +10,12 @@ pub mod state;
 use {
     anchor_lang::prelude::*,
     instructions::*,
-    state::perpetuals::{
-        AmountAndFee, NewPositionPricesAndFee, PriceAndFee, ProfitAndLoss, SwapAmountAndFees,
+    state::{
+        custody_stats_history::StatsBucket,
+        perpetuals::{
+            AmountAndFee, NewPositionPricesAndFee, PriceAndFee, ProfitAndLoss, SwapAmountAndFees,
+            SwapAmountInAndFees,
+        },
     },
 };
 
@@ -105,6 +109,34 @@ pub mod perpetuals {
         instructions::upgrade_custody(ctx, &params)
     }
 
+    pub fn migrate_account<'info>(
+        ctx: Context<'_, '_, '_, 'info, MigrateAccount<'info>>,
+        params: MigrateAccountParams,
+    ) -> Result<u8> {
+        instructions::migrate_account(ctx, &params)
+    }
+
+    pub fn set_pool_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+        params: SetPoolConfigParams,
+    ) -> Result<u8> {
+        instructions::set_pool_config(ctx, &params)
+    }
+
+    pub fn set_target_ratios<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetTargetRatios<'info>>,
+        params: SetTargetRatiosParams,
+    ) -> Result<u8> {
+        instructions::set_target_ratios(ctx, &params)
+    }
+
+    pub fn add_tranche<'info>(
+        ctx: Context<'_, '_, '_, 'info, AddTranche<'info>>,
+        params: AddTrancheParams,
+    ) -> Result<u8> {
+        instructions::add_tranche(ctx, &params)
+    }
+
     pub fn set_custom_oracle_price<'info>(
         ctx: Context<'_, '_, '_, 'info, SetCustomOraclePrice<'info>>,
         params: SetCustomOraclePriceParams,
@@ -112,6 +144,41 @@ pub mod perpetuals {
         instructions::set_custom_oracle_price(ctx, &params)
     }
 
+    pub fn set_trading_schedule<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetTradingSchedule<'info>>,
+        params: SetTradingScheduleParams,
+    ) -> Result<u8> {
+        instructions::set_trading_schedule(ctx, &params)
+    }
+
+    pub fn set_circuit_breaker<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetCircuitBreaker<'info>>,
+        params: SetCircuitBreakerParams,
+    ) -> Result<u8> {
+        instructions::set_circuit_breaker(ctx, &params)
+    }
+
+    pub fn set_derived_oracle<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetDerivedOracle<'info>>,
+        params: SetDerivedOracleParams,
+    ) -> Result<u8> {
+        instructions::set_derived_oracle(ctx, &params)
+    }
+
+    pub fn set_borrow_rate_model<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetBorrowRateModel<'info>>,
+        params: SetBorrowRateModelParams,
+    ) -> Result<u8> {
+        instructions::set_borrow_rate_model(ctx, &params)
+    }
+
+    pub fn add_dated_market<'info>(
+        ctx: Context<'_, '_, '_, 'info, AddDatedMarket<'info>>,
+        params: AddDatedMarketParams,
+    ) -> Result<u8> {
+        instructions::add_dated_market(ctx, &params)
+    }
+
     // test instructions
 
     pub fn set_test_time<'info>(
@@ -127,6 +194,17 @@ pub mod perpetuals {
         instructions::swap(ctx, &params)
     }
 
+    pub fn swap_route<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapRoute<'info>>,
+        params: SwapRouteParams,
+    ) -> Result<()> {
+        instructions::swap_route(ctx, &params)
+    }
+
+    pub fn swap_exact_out(ctx: Context<Swap>, params: SwapExactOutParams) -> Result<()> {
+        instructions::swap_exact_out(ctx, &params)
+    }
+
     pub fn add_liquidity(ctx: Context<AddLiquidity>, params: AddLiquidityParams) -> Result<()> {
         instructions::add_liquidity(ctx, &params)
     }
@@ -138,6 +216,34 @@ pub mod perpetuals {
         instructions::remove_liquidity(ctx, &params)
     }
 
+    pub fn add_liquidity_exact_out(
+        ctx: Context<AddLiquidity>,
+        params: AddLiquidityExactOutParams,
+    ) -> Result<()> {
+        instructions::add_liquidity_exact_out(ctx, &params)
+    }
+
+    pub fn remove_liquidity_exact_out(
+        ctx: Context<RemoveLiquidity>,
+        params: RemoveLiquidityExactOutParams,
+    ) -> Result<()> {
+        instructions::remove_liquidity_exact_out(ctx, &params)
+    }
+
+    pub fn add_tranche_liquidity(
+        ctx: Context<AddTrancheLiquidity>,
+        params: AddTrancheLiquidityParams,
+    ) -> Result<()> {
+        instructions::add_tranche_liquidity(ctx, &params)
+    }
+
+    pub fn remove_tranche_liquidity(
+        ctx: Context<RemoveTrancheLiquidity>,
+        params: RemoveTrancheLiquidityParams,
+    ) -> Result<()> {
+        instructions::remove_tranche_liquidity(ctx, &params)
+    }
+
     pub fn open_position(ctx: Context<OpenPosition>, params: OpenPositionParams) -> Result<()> {
         instructions::open_position(ctx, &params)
     }
@@ -157,14 +263,134 @@ pub mod perpetuals {
         instructions::close_position(ctx, &params)
     }
 
+    pub fn transfer_position(
+        ctx: Context<TransferPosition>,
+        params: TransferPositionParams,
+    ) -> Result<()> {
+        instructions::transfer_position(ctx, &params)
+    }
+
+    pub fn init_trader_stats(
+        ctx: Context<InitTraderStats>,
+        params: InitTraderStatsParams,
+    ) -> Result<()> {
+        instructions::init_trader_stats(ctx, &params)
+    }
+
+    pub fn init_user_stats(ctx: Context<InitUserStats>, params: InitUserStatsParams) -> Result<()> {
+        instructions::init_user_stats(ctx, &params)
+    }
+
+    pub fn register_referral(
+        ctx: Context<RegisterReferral>,
+        params: RegisterReferralParams,
+    ) -> Result<()> {
+        instructions::register_referral(ctx, &params)
+    }
+
+    pub fn claim_referral_rewards(
+        ctx: Context<ClaimReferralRewards>,
+        params: ClaimReferralRewardsParams,
+    ) -> Result<()> {
+        instructions::claim_referral_rewards(ctx, &params)
+    }
+
+    pub fn set_trading_delegate(
+        ctx: Context<SetTradingDelegate>,
+        params: SetTradingDelegateParams,
+    ) -> Result<()> {
+        instructions::set_trading_delegate(ctx, &params)
+    }
+
+    pub fn revoke_trading_delegate(
+        ctx: Context<RevokeTradingDelegate>,
+        params: RevokeTradingDelegateParams,
+    ) -> Result<()> {
+        instructions::revoke_trading_delegate(ctx, &params)
+    }
+
+    pub fn request_open_position(
+        ctx: Context<RequestOpenPosition>,
+        params: RequestOpenPositionParams,
+    ) -> Result<()> {
+        instructions::request_open_position(ctx, &params)
+    }
+
+    pub fn request_close_position(
+        ctx: Context<RequestClosePosition>,
+        params: RequestClosePositionParams,
+    ) -> Result<()> {
+        instructions::request_close_position(ctx, &params)
+    }
+
+    pub fn execute_open_position(
+        ctx: Context<ExecuteOpenPosition>,
+        params: ExecuteOpenPositionParams,
+    ) -> Result<()> {
+        instructions::execute_open_position(ctx, &params)
+    }
+
+    pub fn execute_close_position(
+        ctx: Context<ExecuteClosePosition>,
+        params: ExecuteClosePositionParams,
+    ) -> Result<()> {
+        instructions::execute_close_position(ctx, &params)
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>, params: CancelOrderParams) -> Result<()> {
+        instructions::cancel_order(ctx, &params)
+    }
+
     pub fn liquidate(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
         instructions::liquidate(ctx, &params)
     }
 
+    pub fn auto_deleverage(
+        ctx: Context<AutoDeleverage>,
+        params: AutoDeleverageParams,
+    ) -> Result<()> {
+        instructions::auto_deleverage(ctx, &params)
+    }
+
+    pub fn settle_expired_position(
+        ctx: Context<SettleExpiredPosition>,
+        params: SettleExpiredPositionParams,
+    ) -> Result<()> {
+        instructions::settle_expired_position(ctx, &params)
+    }
+
     pub fn update_pool_aum(ctx: Context<UpdatePoolAum>) -> Result<u128> {
         instructions::update_pool_aum(ctx)
     }
 
+    pub fn update_custody_stats_history(
+        ctx: Context<UpdateCustodyStatsHistory>,
+        params: UpdateCustodyStatsHistoryParams,
+    ) -> Result<()> {
+        instructions::update_custody_stats_history(ctx, &params)
+    }
+
+    pub fn update_circuit_breaker(
+        ctx: Context<UpdateCircuitBreaker>,
+        params: UpdateCircuitBreakerParams,
+    ) -> Result<bool> {
+        instructions::update_circuit_breaker(ctx, &params)
+    }
+
+    pub fn update_derived_oracle(
+        ctx: Context<UpdateDerivedOracle>,
+        params: UpdateDerivedOracleParams,
+    ) -> Result<()> {
+        instructions::update_derived_oracle(ctx, &params)
+    }
+
+    pub fn update_dated_market(
+        ctx: Context<UpdateDatedMarket>,
+        params: UpdateDatedMarketParams,
+    ) -> Result<()> {
+        instructions::update_dated_market(ctx, &params)
+    }
+
     pub fn get_add_liquidity_amount_and_fee(
         ctx: Context<GetAddLiquidityAmountAndFee>,
         params: GetAddLiquidityAmountAndFeeParams,
@@ -179,6 +405,20 @@ pub mod perpetuals {
         instructions::get_remove_liquidity_amount_and_fee(ctx, &params)
     }
 
+    pub fn get_add_liquidity_amount_in_and_fee(
+        ctx: Context<GetAddLiquidityAmountInAndFee>,
+        params: GetAddLiquidityAmountInAndFeeParams,
+    ) -> Result<AmountAndFee> {
+        instructions::get_add_liquidity_amount_in_and_fee(ctx, &params)
+    }
+
+    pub fn get_remove_liquidity_amount_in_and_fee(
+        ctx: Context<GetRemoveLiquidityAmountInAndFee>,
+        params: GetRemoveLiquidityAmountInAndFeeParams,
+    ) -> Result<AmountAndFee> {
+        instructions::get_remove_liquidity_amount_in_and_fee(ctx, &params)
+    }
+
     pub fn get_entry_price_and_fee(
         ctx: Context<GetEntryPriceAndFee>,
         params: GetEntryPriceAndFeeParams,
@@ -225,6 +465,13 @@ pub mod perpetuals {
         instructions::get_swap_amount_and_fees(ctx, &params)
     }
 
+    pub fn get_swap_amount_in_and_fees(
+        ctx: Context<GetSwapAmountInAndFees>,
+        params: GetSwapAmountInAndFeesParams,
+    ) -> Result<SwapAmountInAndFees> {
+        instructions::get_swap_amount_in_and_fees(ctx, &params)
+    }
+
     pub fn get_assets_under_management(
         ctx: Context<GetAssetsUnderManagement>,
         params: GetAssetsUnderManagementParams,
@@ -241,6 +488,13 @@ pub mod perpetuals {
 
     // This instruction must be part of a larger transaction where the **first** instruction
     // is an ed25519 verification of the serialized oracle price update params.
+    pub fn get_custody_stats_history(
+        ctx: Context<GetCustodyStatsHistory>,
+        params: GetCustodyStatsHistoryParams,
+    ) -> Result<Vec<StatsBucket>> {
+        instructions::get_custody_stats_history(ctx, &params)
+    }
+
     pub fn set_custom_oracle_price_permissionless(
         ctx: Context<SetCustomOraclePricePermissionless>,
         params: SetCustomOraclePricePermissionlessParams,
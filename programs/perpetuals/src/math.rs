@@ -271,6 +271,42 @@ pub fn checked_decimal_ceil_mul(
     }
 }
 
+// Returns the smallest coefficient1 such that
+// checked_decimal_mul(coefficient1, exponent1, coefficient2, exponent2, target_exponent) >= result
+pub fn checked_decimal_mul_inverse(
+    result: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+) -> Result<u64> {
+    if coefficient2 == 0 {
+        msg!("Error: Overflow in {} / {}", result, coefficient2);
+        return err!(PerpetualsError::MathOverflow);
+    }
+    if result == 0 {
+        return Ok(0);
+    }
+    let target_power = checked_sub(checked_add(exponent1, exponent2)?, target_exponent)?;
+    if target_power >= 0 {
+        checked_as_u64(checked_ceil_div(
+            result as u128,
+            checked_mul(
+                coefficient2 as u128,
+                checked_pow(10u128, target_power as usize)?,
+            )?,
+        )?)
+    } else {
+        checked_as_u64(checked_ceil_div(
+            checked_mul(
+                result as u128,
+                checked_pow(10u128, (-target_power) as usize)?,
+            )?,
+            coefficient2 as u128,
+        )?)
+    }
+}
+
 pub fn checked_token_mul(
     amount1: u64,
     decimals1: u8,
@@ -448,6 +484,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_checked_decimal_mul_inverse() {
+        assert_eq!(
+            4_000_000,
+            checked_decimal_mul_inverse(2_000, -6, 500, -6, -6).unwrap()
+        );
+
+        // rounds up
+        assert_eq!(
+            4_002_000,
+            checked_decimal_mul_inverse(2_001, -6, 500, -6, -6).unwrap()
+        );
+
+        // smallest value that satisfies the forward computation
+        for result in [1u64, 7, 999, 1_000, 123_456_789] {
+            let coefficient1 = checked_decimal_mul_inverse(result, -9, 25_300, -3, -6).unwrap();
+            assert!(checked_decimal_mul(coefficient1, -9, 25_300, -3, -6).unwrap() >= result);
+            assert!(checked_decimal_mul(coefficient1 - 1, -9, 25_300, -3, -6).unwrap() < result);
+        }
+
+        assert_eq!(0, checked_decimal_mul_inverse(0, -6, 500, -6, -6).unwrap());
+        assert!(checked_decimal_mul_inverse(1_000, -6, 0, -6, -6).is_err());
+    }
+
     #[test]
     fn test_checked_decimal_div_ko() {
         // Division by zero
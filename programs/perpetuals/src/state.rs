@@ -1,8 +1,19 @@
 // Program state handling.
 
+pub mod circuit_breaker;
 pub mod custody;
+pub mod custody_stats_history;
+pub mod dated_market;
+pub mod migration;
 pub mod multisig;
 pub mod oracle;
+pub mod order;
 pub mod perpetuals;
 pub mod pool;
 pub mod position;
+pub mod referral;
+pub mod trader_stats;
+pub mod trading_delegate;
+pub mod trading_schedule;
+pub mod tranche;
+pub mod user_stats;
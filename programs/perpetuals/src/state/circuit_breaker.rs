@@ -0,0 +1,213 @@
+use {
+    crate::{
+        math,
+        state::{oracle::OraclePrice, perpetuals::Perpetuals},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct CircuitBreakerParams {
+    // max deviation of the spot price from the ema price, zero disables the check
+    pub max_ema_deviation: u64,
+    // max price change per interval, zero disables the check
+    pub max_price_change: u64,
+    pub price_change_interval_sec: u32,
+    // close-only period after a breach, zero requires an admin reset
+    pub cooldown_sec: u32,
+}
+
+// Switches the custody into close-only mode if oracle prices move beyond the
+// configured limits. Limits have implied BPS_DECIMALS decimals.
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct CircuitBreaker {
+    pub params: CircuitBreakerParams,
+    // price at the start of the current interval
+    pub reference_price: OraclePrice,
+    pub reference_time: i64,
+    // zero if the breaker is not tripped
+    pub trip_time: i64,
+}
+
+impl CircuitBreakerParams {
+    pub fn validate(&self) -> bool {
+        (self.max_ema_deviation as u128) <= Perpetuals::BPS_POWER
+            && (self.max_price_change as u128) <= Perpetuals::BPS_POWER
+            && (self.max_price_change == 0 || self.price_change_interval_sec > 0)
+    }
+}
+
+impl CircuitBreaker {
+    pub fn validate(&self) -> bool {
+        self.params.validate()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.params.max_ema_deviation > 0 || self.params.max_price_change > 0
+    }
+
+    pub fn is_tripped(&self, curtime: i64) -> bool {
+        self.trip_time != 0
+            && (self.params.cooldown_sec == 0
+                || curtime
+                    < self
+                        .trip_time
+                        .saturating_add(self.params.cooldown_sec as i64))
+    }
+
+    // Clears the tripped state and the reference price
+    pub fn reset(&mut self) {
+        self.reference_price = OraclePrice::default();
+        self.reference_time = 0;
+        self.trip_time = 0;
+    }
+
+    // Checks the price against the limits and advances the reference price.
+    // Returns false if the breaker is tripped, either earlier or by this price.
+    pub fn update(
+        &mut self,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<bool> {
+        if self.is_tripped(curtime) {
+            return Ok(false);
+        }
+        if self.trip_time != 0 {
+            // cooldown has passed
+            self.reset();
+        }
+
+        if self.params.max_ema_deviation > 0
+            && Self::get_change(token_ema_price, token_price)? > self.params.max_ema_deviation
+        {
+            msg!("Oracle price deviates from the ema price");
+            self.trip_time = curtime;
+            return Ok(false);
+        }
+
+        if self.params.max_price_change == 0 {
+            return Ok(true);
+        }
+        let elapsed = math::checked_sub(curtime, self.reference_time)?;
+        let interval = self.params.price_change_interval_sec as i64;
+        if self.reference_price.price > 0 && elapsed >= 0 {
+            // the allowed change scales with the number of intervals since the reference
+            let intervals = std::cmp::max(1, math::checked_ceil_div(elapsed, interval)?);
+            let max_change = std::cmp::min(
+                math::checked_mul(self.params.max_price_change as u128, intervals as u128)?,
+                u64::MAX as u128,
+            ) as u64;
+            if Self::get_change(&self.reference_price, token_price)? > max_change {
+                msg!("Oracle price change exceeds the limit");
+                self.trip_time = curtime;
+                return Ok(false);
+            }
+        }
+        if self.reference_price.price == 0 || elapsed >= interval || elapsed < 0 {
+            self.reference_price = *token_price;
+            self.reference_time = curtime;
+        }
+
+        Ok(true)
+    }
+
+    // Returns the relative change from the base price in BPS
+    fn get_change(base_price: &OraclePrice, price: &OraclePrice) -> Result<u64> {
+        let base_price = base_price.scale_to_exponent(price.exponent)?;
+        if base_price.price == 0 {
+            return Ok(u64::MAX);
+        }
+        let diff = if price.price > base_price.price {
+            math::checked_sub(price.price, base_price.price)?
+        } else {
+            math::checked_sub(base_price.price, price.price)?
+        };
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(diff as u128, Perpetuals::BPS_POWER)?,
+            base_price.price as u128,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_fixture() -> CircuitBreaker {
+        CircuitBreaker {
+            params: CircuitBreakerParams {
+                max_ema_deviation: 500,
+                max_price_change: 200,
+                price_change_interval_sec: 60,
+                cooldown_sec: 600,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ema_deviation() {
+        let mut breaker = get_fixture();
+        let ema_price = OraclePrice::new(10_000, -4);
+
+        assert!(breaker
+            .update(&OraclePrice::new(10_400, -4), &ema_price, 1000)
+            .unwrap());
+        assert!(!breaker.is_tripped(1000));
+
+        // scaled prices are compared in the same exponent
+        assert!(!breaker
+            .update(&OraclePrice::new(1_060_000, -6), &ema_price, 1010)
+            .unwrap());
+        assert!(breaker.is_tripped(1010));
+        assert_eq!(breaker.trip_time, 1010);
+
+        // remains tripped until the cooldown passes
+        assert!(!breaker.update(&ema_price, &ema_price, 1500).unwrap());
+        assert!(breaker.update(&ema_price, &ema_price, 1610).unwrap());
+        assert_eq!(breaker.trip_time, 0);
+        assert_eq!(breaker.reference_time, 1610);
+    }
+
+    #[test]
+    fn test_price_change() {
+        let mut breaker = get_fixture();
+        let price = OraclePrice::new(10_000, -4);
+
+        assert!(breaker.update(&price, &price, 1000).unwrap());
+        assert_eq!(breaker.reference_price, price);
+
+        // the reference is kept within the interval
+        let price2 = OraclePrice::new(10_150, -4);
+        assert!(breaker.update(&price2, &price2, 1030).unwrap());
+        assert_eq!(breaker.reference_time, 1000);
+        let price3 = OraclePrice::new(10_250, -4);
+        assert!(!breaker.update(&price3, &price3, 1050).unwrap());
+        assert!(breaker.is_tripped(1050));
+
+        // larger changes are allowed over longer periods
+        breaker.reset();
+        assert!(breaker.update(&price, &price, 1000).unwrap());
+        assert!(breaker.update(&price3, &price3, 1100).unwrap());
+        assert_eq!(breaker.reference_price, price3);
+        assert_eq!(breaker.reference_time, 1100);
+
+        // admin reset is required without a cooldown
+        breaker.params.cooldown_sec = 0;
+        assert!(!breaker.update(&price, &price, 1110).unwrap());
+        assert!(breaker.is_tripped(100_000));
+        breaker.reset();
+        assert!(breaker.update(&price, &price, 100_000).unwrap());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(CircuitBreaker::default().validate());
+        assert!(get_fixture().validate());
+
+        let mut breaker = get_fixture();
+        breaker.params.price_change_interval_sec = 0;
+        assert!(!breaker.validate());
+    }
+}
@@ -3,9 +3,11 @@ use {
         error::PerpetualsError,
         math,
         state::{
-            oracle::{OracleParams, OraclePrice, OracleType},
+            circuit_breaker::CircuitBreaker,
+            oracle::{ActionClass, DeprecatedOracleParams, OracleParams, OraclePrice, OracleType},
             perpetuals::{Permissions, Perpetuals},
             position::{Position, Side},
+            trading_schedule::TradingSchedule,
         },
     },
     anchor_lang::prelude::*,
@@ -100,6 +102,54 @@ pub struct PricingParams {
     pub max_total_locked_usd: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum SpreadMode {
+    // static spreads from pricing params
+    Fixed,
+    // spreads widen with the oracle confidence interval
+    Confidence,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct SpreadParams {
+    pub mode: SpreadMode,
+    // in Confidence mode spread = max(static spread, conf_mult * conf / price),
+    // conf_mult has implied BPS_DECIMALS decimals
+    pub conf_mult: u64,
+}
+
+// Depeg protection for stable custodies. Below the soft threshold positions can't be
+// opened with the stable as collateral and swap in fees go up. Below the hard threshold
+// the custody is withdraw/close-only and the pool values it with a haircut.
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DepegParams {
+    // prices with implied PRICE_DECIMALS decimals, zero disables the threshold
+    pub soft_threshold: u64,
+    pub hard_threshold: u64,
+    // swap in fee multiplier below the soft threshold, zero keeps the regular fee
+    pub swap_in_fee_mult: u64,
+    // valuation haircut below the hard threshold
+    pub haircut: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum DepegStatus {
+    Pegged,
+    Soft,
+    Hard,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct LeverageTier {
+    // tier applies to positions with size_usd >= min_size_usd (USD_DECIMALS decimals),
+    // unused tiers have zero max_leverage
+    pub min_size_usd: u64,
+    // leverage params and liquidation fee have implied BPS_DECIMALS decimals
+    pub max_initial_leverage: u64,
+    pub max_leverage: u64,
+    pub liquidation_fee: u64,
+}
+
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct BorrowRateParams {
     // borrow rate params have implied RATE_DECIMALS decimals
@@ -117,6 +167,57 @@ pub struct BorrowRateState {
     pub last_update: i64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum BorrowRateModel {
+    // two slope curve from borrow rate params
+    Kinked,
+    // piecewise linear curve from base_rate through the breakpoints
+    MultiKink,
+    // the rate at optimal utilization drifts while utilization stays off target
+    Adaptive,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct RateBreakpoint {
+    // utilization and hourly rate have implied RATE_DECIMALS decimals
+    pub utilization: u64,
+    pub rate: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct BorrowRateModelParams {
+    pub model: BorrowRateModel,
+    // separate long and short rates, the crowded side pays more
+    pub per_side: bool,
+    // MultiKink breakpoints sorted by utilization, the last one at 100%, unused are last
+    pub breakpoints: [RateBreakpoint; Custody::MAX_RATE_BREAKPOINTS],
+    // Adaptive max relative change of the target rate per hour, reached at 0% and
+    // 100% utilization. Has implied RATE_DECIMALS decimals.
+    pub adjustment_speed: u64,
+    pub min_target_rate: u64,
+    pub max_target_rate: u64,
+    // Adaptive rate at 100% utilization relative to the target rate, in BPS
+    pub curve_steepness: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct AdaptiveRateState {
+    // rate at optimal utilization, zero if not initialized
+    pub target_rate: u64,
+    // utilization since the last update
+    pub utilization: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct BorrowRateModelState {
+    // per side rates, used instead of borrow_rate_state if per_side is set
+    pub long: BorrowRateState,
+    pub short: BorrowRateState,
+    pub adaptive: AdaptiveRateState,
+    pub long_adaptive: AdaptiveRateState,
+    pub short_adaptive: AdaptiveRateState,
+}
+
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct PositionStats {
     pub open_positions: u64,
@@ -133,6 +234,9 @@ pub struct PositionStats {
 #[account]
 #[derive(Default, Debug, PartialEq)]
 pub struct Custody {
+    // account layout version, see migration::Versioned
+    pub version: u8,
+
     // static parameters
     pub pool: Pubkey,
     pub mint: Pubkey,
@@ -145,6 +249,11 @@ pub struct Custody {
     pub permissions: Permissions,
     pub fees: Fees,
     pub borrow_rate: BorrowRateParams,
+    // isolated tranche backing this custody, default if backed by the pool
+    pub tranche: Pubkey,
+    // position size brackets overriding pricing leverage and liquidation fee,
+    // sorted by min_size_usd
+    pub leverage_tiers: [LeverageTier; Custody::MAX_LEVERAGE_TIERS],
 
     // dynamic variables
     pub assets: Assets,
@@ -158,6 +267,25 @@ pub struct Custody {
     // bumps for address validation
     pub bump: u8,
     pub token_account_bump: u8,
+
+    // weekly trading hours
+    pub trading_schedule: TradingSchedule,
+
+    // oracle price limits
+    pub circuit_breaker: CircuitBreaker,
+
+    // oracle confidence based spreads
+    pub spread: SpreadParams,
+
+    // stablecoin depeg thresholds
+    pub depeg: DepegParams,
+
+    // borrow rate model and per side rates
+    pub borrow_rate_model: BorrowRateModelParams,
+    pub borrow_rate_model_state: BorrowRateModelState,
+
+    // reserved for future fields
+    pub reserved: [u64; 8],
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -184,7 +312,7 @@ pub struct DeprecatedCustody {
     pub token_account: Pubkey,
     pub decimals: u8,
     pub is_stable: bool,
-    pub oracle: OracleParams,
+    pub oracle: DeprecatedOracleParams,
     pub pricing: PricingParams,
     pub permissions: Permissions,
     pub fees: Fees,
@@ -210,6 +338,24 @@ impl Default for FeesMode {
     }
 }
 
+impl Default for SpreadMode {
+    fn default() -> Self {
+        Self::Fixed
+    }
+}
+
+impl Default for BorrowRateModel {
+    fn default() -> Self {
+        Self::Kinked
+    }
+}
+
+impl Default for DepegStatus {
+    fn default() -> Self {
+        Self::Pegged
+    }
+}
+
 impl Fees {
     pub fn validate(&self) -> bool {
         self.swap_in as u128 <= Perpetuals::BPS_POWER
@@ -231,6 +377,14 @@ impl OracleParams {
     pub fn validate(&self) -> bool {
         self.oracle_type == OracleType::None || self.oracle_account != Pubkey::default()
     }
+
+    pub fn get_max_price_age(&self, action: ActionClass) -> u32 {
+        if action == ActionClass::Reduce {
+            std::cmp::max(self.max_price_age_sec, self.max_reducing_price_age_sec)
+        } else {
+            self.max_price_age_sec
+        }
+    }
 }
 
 impl PricingParams {
@@ -246,16 +400,331 @@ impl PricingParams {
     }
 }
 
+impl SpreadParams {
+    pub fn validate(&self) -> bool {
+        self.mode == SpreadMode::Fixed || self.conf_mult > 0
+    }
+}
+
+impl DepegParams {
+    pub fn validate(&self) -> bool {
+        let one_usd = 10u64.pow(Perpetuals::PRICE_DECIMALS as u32);
+        self.soft_threshold <= one_usd
+            && self.hard_threshold <= one_usd
+            && (self.soft_threshold == 0 || self.hard_threshold <= self.soft_threshold)
+            && (self.swap_in_fee_mult == 0
+                || (self.swap_in_fee_mult as u128) >= Perpetuals::BPS_POWER)
+            && (self.haircut as u128) <= Perpetuals::BPS_POWER
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.soft_threshold > 0 || self.hard_threshold > 0
+    }
+}
+
 impl BorrowRateParams {
     pub fn validate(&self) -> bool {
         self.optimal_utilization > 0 && (self.optimal_utilization as u128) <= Perpetuals::RATE_POWER
     }
+
+    pub fn get_kinked_rate(&self, utilization: u128) -> Result<u64> {
+        // if current_utilization < optimal_utilization:
+        //   rate = base_rate + (current_utilization / optimal_utilization) * slope1
+        // else:
+        //   rate = base_rate + slope1 + (current_utilization - optimal_utilization) / (1 - optimal_utilization) * slope2
+        let hourly_rate = if utilization < (self.optimal_utilization as u128)
+            || (self.optimal_utilization as u128) >= Perpetuals::RATE_POWER
+        {
+            math::checked_div(
+                math::checked_mul(utilization, self.slope1 as u128)?,
+                self.optimal_utilization as u128,
+            )?
+        } else {
+            math::checked_add(
+                self.slope1 as u128,
+                math::checked_div(
+                    math::checked_mul(
+                        math::checked_sub(utilization, self.optimal_utilization as u128)?,
+                        self.slope2 as u128,
+                    )?,
+                    Perpetuals::RATE_POWER - self.optimal_utilization as u128,
+                )?,
+            )?
+        };
+        math::checked_add(math::checked_as_u64(hourly_rate)?, self.base_rate)
+    }
+}
+
+impl BorrowRateState {
+    pub fn get_cumulative_interest(&self, curtime: i64) -> Result<u128> {
+        if curtime > self.last_update {
+            let cumulative_interest = math::checked_ceil_div(
+                math::checked_mul(
+                    math::checked_sub(curtime, self.last_update)? as u128,
+                    self.current_rate as u128,
+                )?,
+                3600,
+            )?;
+            math::checked_add(self.cumulative_interest, cumulative_interest)
+        } else {
+            Ok(self.cumulative_interest)
+        }
+    }
+}
+
+impl BorrowRateModelParams {
+    pub fn validate(&self, borrow_rate: &BorrowRateParams) -> bool {
+        match self.model {
+            BorrowRateModel::Kinked => true,
+            BorrowRateModel::MultiKink => {
+                // utilization increases, rates don't decrease and the curve ends at 100%
+                let num_breakpoints = self.get_num_breakpoints();
+                let mut prev = RateBreakpoint {
+                    utilization: 0,
+                    rate: borrow_rate.base_rate,
+                };
+                for breakpoint in &self.breakpoints[..num_breakpoints] {
+                    if breakpoint.utilization <= prev.utilization || breakpoint.rate < prev.rate {
+                        return false;
+                    }
+                    prev = *breakpoint;
+                }
+                num_breakpoints > 0
+                    && (prev.utilization as u128) == Perpetuals::RATE_POWER
+                    && self.breakpoints[num_breakpoints..]
+                        .iter()
+                        .all(|breakpoint| *breakpoint == RateBreakpoint::default())
+            }
+            BorrowRateModel::Adaptive => {
+                (borrow_rate.optimal_utilization as u128) < Perpetuals::RATE_POWER
+                    && (self.adjustment_speed as u128) <= Perpetuals::RATE_POWER
+                    && self.min_target_rate > 0
+                    && self.min_target_rate <= self.max_target_rate
+                    && (self.curve_steepness as u128) >= Perpetuals::BPS_POWER
+            }
+        }
+    }
+
+    pub fn get_rate(
+        &self,
+        borrow_rate: &BorrowRateParams,
+        target_rate: u64,
+        utilization: u128,
+    ) -> Result<u64> {
+        match self.model {
+            BorrowRateModel::Kinked => borrow_rate.get_kinked_rate(utilization),
+            BorrowRateModel::MultiKink => {
+                self.get_multi_kink_rate(borrow_rate.base_rate, utilization)
+            }
+            BorrowRateModel::Adaptive => {
+                self.get_adaptive_rate(target_rate, borrow_rate.optimal_utilization, utilization)
+            }
+        }
+    }
+
+    // Accrues interest at the previous rate, moves the adaptive target rate and sets
+    // the rate for the current utilization
+    pub fn update_rate_state(
+        &self,
+        borrow_rate: &BorrowRateParams,
+        state: &mut BorrowRateState,
+        adaptive: &mut AdaptiveRateState,
+        utilization: u128,
+        curtime: i64,
+    ) -> Result<()> {
+        let elapsed = if curtime > state.last_update {
+            // compute interest accumulated since previous update
+            let elapsed = math::checked_sub(curtime, state.last_update)?;
+            state.cumulative_interest = state.get_cumulative_interest(curtime)?;
+            state.last_update = curtime;
+            elapsed
+        } else {
+            0
+        };
+
+        if self.model == BorrowRateModel::Adaptive {
+            adaptive.target_rate = if adaptive.target_rate == 0 {
+                std::cmp::max(
+                    std::cmp::min(
+                        math::checked_add(borrow_rate.base_rate, borrow_rate.slope1)?,
+                        self.max_target_rate,
+                    ),
+                    self.min_target_rate,
+                )
+            } else {
+                self.get_adaptive_target_rate(adaptive, borrow_rate.optimal_utilization, elapsed)?
+            };
+            adaptive.utilization = math::checked_as_u64(utilization)?;
+        }
+
+        state.current_rate = self.get_rate(borrow_rate, adaptive.target_rate, utilization)?;
+
+        Ok(())
+    }
+
+    fn get_num_breakpoints(&self) -> usize {
+        self.breakpoints
+            .iter()
+            .take_while(|breakpoint| breakpoint.utilization > 0)
+            .count()
+    }
+
+    // Interpolates the rate between the breakpoints, the curve starts at base_rate
+    pub fn get_multi_kink_rate(&self, base_rate: u64, utilization: u128) -> Result<u64> {
+        let mut prev = RateBreakpoint {
+            utilization: 0,
+            rate: base_rate,
+        };
+        for breakpoint in &self.breakpoints[..self.get_num_breakpoints()] {
+            if utilization <= breakpoint.utilization as u128 {
+                let rate_diff = math::checked_div(
+                    math::checked_mul(
+                        math::checked_sub(utilization, prev.utilization as u128)?,
+                        math::checked_sub(breakpoint.rate, prev.rate)? as u128,
+                    )?,
+                    math::checked_sub(breakpoint.utilization, prev.utilization)? as u128,
+                )?;
+                return math::checked_add(prev.rate, math::checked_as_u64(rate_diff)?);
+            }
+            prev = *breakpoint;
+        }
+        Ok(prev.rate)
+    }
+
+    // Returns the rate on a two slope curve through the target rate at optimal
+    // utilization, target / steepness at 0% and target * steepness at 100%
+    pub fn get_adaptive_rate(
+        &self,
+        target_rate: u64,
+        optimal_utilization: u64,
+        utilization: u128,
+    ) -> Result<u64> {
+        let target_rate = target_rate as u128;
+        let optimal_utilization = optimal_utilization as u128;
+        let rate = if utilization < optimal_utilization {
+            let min_rate = math::checked_div(
+                math::checked_mul(target_rate, Perpetuals::BPS_POWER)?,
+                self.curve_steepness as u128,
+            )?;
+            math::checked_add(
+                min_rate,
+                math::checked_div(
+                    math::checked_mul(math::checked_sub(target_rate, min_rate)?, utilization)?,
+                    optimal_utilization,
+                )?,
+            )?
+        } else {
+            let max_rate = math::checked_div(
+                math::checked_mul(target_rate, self.curve_steepness as u128)?,
+                Perpetuals::BPS_POWER,
+            )?;
+            math::checked_add(
+                target_rate,
+                math::checked_div(
+                    math::checked_mul(
+                        math::checked_sub(max_rate, target_rate)?,
+                        math::checked_sub(
+                            std::cmp::min(utilization, Perpetuals::RATE_POWER),
+                            optimal_utilization,
+                        )?,
+                    )?,
+                    math::checked_sub(Perpetuals::RATE_POWER, optimal_utilization)?,
+                )?,
+            )?
+        };
+        math::checked_as_u64(rate)
+    }
+
+    // Moves the target rate up while utilization was above optimal and down while it
+    // was below, proportionally to the distance from optimal and the elapsed time
+    pub fn get_adaptive_target_rate(
+        &self,
+        state: &AdaptiveRateState,
+        optimal_utilization: u64,
+        elapsed_sec: i64,
+    ) -> Result<u64> {
+        let utilization = std::cmp::min(state.utilization as u128, Perpetuals::RATE_POWER);
+        let optimal_utilization = optimal_utilization as u128;
+        let (distance, increase) = if utilization >= optimal_utilization {
+            (
+                math::checked_div(
+                    math::checked_mul(
+                        math::checked_sub(utilization, optimal_utilization)?,
+                        Perpetuals::RATE_POWER,
+                    )?,
+                    math::checked_sub(Perpetuals::RATE_POWER, optimal_utilization)?,
+                )?,
+                true,
+            )
+        } else {
+            (
+                math::checked_div(
+                    math::checked_mul(
+                        math::checked_sub(optimal_utilization, utilization)?,
+                        Perpetuals::RATE_POWER,
+                    )?,
+                    optimal_utilization,
+                )?,
+                false,
+            )
+        };
+        let change = math::checked_div(
+            math::checked_mul(
+                math::checked_mul(
+                    math::checked_mul(state.target_rate as u128, self.adjustment_speed as u128)?,
+                    distance,
+                )?,
+                elapsed_sec as u128,
+            )?,
+            math::checked_mul(
+                math::checked_mul(Perpetuals::RATE_POWER, Perpetuals::RATE_POWER)?,
+                3600,
+            )?,
+        )?;
+        let target_rate = if increase {
+            math::checked_add(state.target_rate as u128, change)?
+        } else {
+            (state.target_rate as u128).saturating_sub(change)
+        };
+        Ok(std::cmp::max(
+            std::cmp::min(target_rate, self.max_target_rate as u128) as u64,
+            self.min_target_rate,
+        ))
+    }
 }
 
 impl Custody {
     pub const LEN: usize = 8 + std::mem::size_of::<Custody>();
+    pub const MAX_LEVERAGE_TIERS: usize = 4;
+    pub const MAX_RATE_BREAKPOINTS: usize = 6;
 
     pub fn validate(&self) -> bool {
+        // check tiers are sorted, only tighten base leverage and unused tiers are last
+        let mut prev_tier: Option<&LeverageTier> = None;
+        for tier in &self.leverage_tiers {
+            if tier.max_leverage == 0 {
+                if *tier != LeverageTier::default() {
+                    return false;
+                }
+                prev_tier = Some(tier);
+                continue;
+            }
+            if let Some(prev_tier) = prev_tier {
+                if prev_tier.max_leverage == 0 || tier.min_size_usd <= prev_tier.min_size_usd {
+                    return false;
+                }
+            }
+            if tier.max_initial_leverage < self.pricing.min_initial_leverage
+                || tier.max_initial_leverage > tier.max_leverage
+                || tier.max_initial_leverage > self.pricing.max_initial_leverage
+                || tier.max_leverage > self.pricing.max_leverage
+                || (tier.liquidation_fee as u128) > Perpetuals::BPS_POWER
+            {
+                return false;
+            }
+            prev_tier = Some(tier);
+        }
+
         (!self.is_virtual || !self.is_stable)
             && self.token_account != Pubkey::default()
             && self.mint != Pubkey::default()
@@ -263,6 +732,237 @@ impl Custody {
             && self.pricing.validate()
             && self.fees.validate()
             && self.borrow_rate.validate()
+            && self.trading_schedule.validate()
+            && self.circuit_breaker.validate()
+            && self.spread.validate()
+            && self.depeg.validate()
+            && self.borrow_rate_model.validate(&self.borrow_rate)
+            && (self.is_stable || !self.depeg.is_enabled())
+            && (self.is_virtual || self.tranche == Pubkey::default())
+    }
+
+    // Returns leverage params that apply to a position of the given size
+    pub fn get_leverage_tier(&self, size_usd: u64) -> LeverageTier {
+        let mut leverage_tier = LeverageTier {
+            min_size_usd: 0,
+            max_initial_leverage: self.pricing.max_initial_leverage,
+            max_leverage: self.pricing.max_leverage,
+            liquidation_fee: self.fees.liquidation,
+        };
+        for tier in &self.leverage_tiers {
+            if tier.max_leverage == 0 || size_usd < tier.min_size_usd {
+                break;
+            }
+            leverage_tier = *tier;
+        }
+        leverage_tier
+    }
+
+    // Returns oracle params in effect at the given time, older prices are accepted
+    // while the market is closed
+    pub fn get_oracle_params(&self, curtime: i64) -> OracleParams {
+        let mut oracle = self.oracle;
+        if self.trading_schedule.off_hours_max_price_age_sec > oracle.max_price_age_sec
+            && !self.trading_schedule.is_open(curtime)
+        {
+            oracle.max_price_age_sec = self.trading_schedule.off_hours_max_price_age_sec;
+        }
+        oracle
+    }
+
+    // Runs the oracle circuit breaker with the latest prices, returns false if
+    // the custody is in close-only mode
+    pub fn update_circuit_breaker(
+        &mut self,
+        oracle_account: &AccountInfo,
+        curtime: i64,
+    ) -> Result<bool> {
+        if !self.circuit_breaker.is_enabled() {
+            return Ok(!self.circuit_breaker.is_tripped(curtime));
+        }
+        let oracle = self.get_oracle_params(curtime);
+        let token_price = OraclePrice::new_from_oracle(
+            oracle_account,
+            &oracle,
+            curtime,
+            false,
+            ActionClass::Increase,
+        )?;
+        let token_ema_price = OraclePrice::new_from_oracle(
+            oracle_account,
+            &oracle,
+            curtime,
+            true,
+            ActionClass::Increase,
+        )?;
+        self.circuit_breaker
+            .update(&token_price, &token_ema_price, curtime)
+    }
+
+    // Returns the spread for entering a position on the given side
+    pub fn get_trade_spread(
+        &self,
+        side: Side,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<u64> {
+        let (spread, off_hours_spread) = if side == Side::Long {
+            (
+                self.pricing.trade_spread_long,
+                self.trading_schedule.off_hours_trade_spread_long,
+            )
+        } else {
+            (
+                self.pricing.trade_spread_short,
+                self.trading_schedule.off_hours_trade_spread_short,
+            )
+        };
+        let spread = if self.trading_schedule.is_restricted(curtime) {
+            std::cmp::max(spread, off_hours_spread)
+        } else {
+            spread
+        };
+        Ok(std::cmp::max(spread, self.get_conf_spread(token_price)?))
+    }
+
+    // Returns the spread implied by the oracle confidence interval, zero in Fixed mode
+    pub fn get_conf_spread(&self, token_price: &OraclePrice) -> Result<u64> {
+        if self.spread.mode == SpreadMode::Fixed {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                token_price.get_conf_ratio()? as u128,
+                self.spread.conf_mult as u128,
+            )?,
+            Perpetuals::BPS_POWER,
+        )?)
+    }
+
+    // Returns the depeg status of a stable custody at the given price
+    pub fn get_depeg_status(&self, token_price: &OraclePrice) -> Result<DepegStatus> {
+        if !self.depeg.is_enabled() {
+            return Ok(DepegStatus::Pegged);
+        }
+        let price = token_price
+            .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
+            .price;
+        if price < self.depeg.hard_threshold {
+            Ok(DepegStatus::Hard)
+        } else if price < self.depeg.soft_threshold {
+            Ok(DepegStatus::Soft)
+        } else {
+            Ok(DepegStatus::Pegged)
+        }
+    }
+
+    // Returns (spot, ema) prices for a swap, checks trading hours and the oracle circuit
+    // breaker, depegged stables can only be swapped out
+    pub fn get_swap_prices(
+        &mut self,
+        oracle_account: &AccountInfo,
+        curtime: i64,
+        swap_in: bool,
+    ) -> Result<(OraclePrice, OraclePrice)> {
+        let oracle = self.get_oracle_params(curtime);
+        let token_price = OraclePrice::new_from_oracle(
+            oracle_account,
+            &oracle,
+            curtime,
+            false,
+            ActionClass::Increase,
+        )?;
+        let token_ema_price = OraclePrice::new_from_oracle(
+            oracle_account,
+            &oracle,
+            curtime,
+            self.pricing.use_ema,
+            ActionClass::Increase,
+        )?;
+
+        self.trading_schedule
+            .check_increase_allowed(&token_price, &token_ema_price, curtime)?;
+        require!(
+            self.update_circuit_breaker(oracle_account, curtime)?,
+            PerpetualsError::CircuitBreakerTripped
+        );
+        if swap_in {
+            require!(
+                self.get_depeg_status(&token_price)? != DepegStatus::Hard,
+                PerpetualsError::StablecoinDepegged
+            );
+        }
+
+        Ok((token_price, token_ema_price))
+    }
+
+    // Returns (spot, ema) prices for adding or removing liquidity, depegged stables
+    // can only be withdrawn
+    pub fn get_liquidity_prices(
+        &self,
+        oracle_account: &AccountInfo,
+        curtime: i64,
+        deposit: bool,
+    ) -> Result<(OraclePrice, OraclePrice)> {
+        // withdrawals accept the reducing price age so that LPs can exit on a slow oracle
+        let oracle = self.get_oracle_params(curtime);
+        let action = if deposit {
+            ActionClass::Increase
+        } else {
+            ActionClass::Reduce
+        };
+        let token_price =
+            OraclePrice::new_from_oracle(oracle_account, &oracle, curtime, false, action)?;
+        let token_ema_price = OraclePrice::new_from_oracle(
+            oracle_account,
+            &oracle,
+            curtime,
+            self.pricing.use_ema,
+            action,
+        )?;
+
+        if deposit {
+            require!(
+                self.get_depeg_status(&token_price)? != DepegStatus::Hard,
+                PerpetualsError::StablecoinDepegged
+            );
+        }
+
+        Ok((token_price, token_ema_price))
+    }
+
+    // Returns the swap in fee, raised below the soft depeg threshold
+    pub fn get_swap_in_fee(&self, base_fee: u64, token_price: &OraclePrice) -> Result<u64> {
+        if self.depeg.swap_in_fee_mult == 0
+            || self.get_depeg_status(token_price)? == DepegStatus::Pegged
+        {
+            return Ok(base_fee);
+        }
+        Ok(std::cmp::min(
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(base_fee as u128, self.depeg.swap_in_fee_mult as u128)?,
+                Perpetuals::BPS_POWER,
+            )?)?,
+            Perpetuals::BPS_POWER as u64,
+        ))
+    }
+
+    // Applies the valuation haircut below the hard depeg threshold
+    pub fn apply_depeg_haircut(&self, amount_usd: u64, token_price: &OraclePrice) -> Result<u64> {
+        if self.depeg.haircut == 0 || self.get_depeg_status(token_price)? != DepegStatus::Hard {
+            return Ok(amount_usd);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                amount_usd as u128,
+                math::checked_sub(Perpetuals::BPS_POWER, self.depeg.haircut as u128)?,
+            )?,
+            Perpetuals::BPS_POWER,
+        )?)
+    }
+
+    pub fn is_tranche_backed(&self) -> bool {
+        self.tranche != Pubkey::default()
     }
 
     pub fn lock_funds(&mut self, amount: u64) -> Result<()> {
@@ -321,7 +1021,7 @@ impl Custody {
             return Ok(0);
         }
 
-        let cumulative_interest = self.get_cumulative_interest(curtime)?;
+        let cumulative_interest = self.get_cumulative_interest(position.side, curtime)?;
 
         let position_interest = if cumulative_interest > position.cumulative_interest_snapshot {
             math::checked_sub(cumulative_interest, position.cumulative_interest_snapshot)?
@@ -335,82 +1035,98 @@ impl Custody {
         )?)
     }
 
-    pub fn get_cumulative_interest(&self, curtime: i64) -> Result<u128> {
-        if curtime > self.borrow_rate_state.last_update {
-            let cumulative_interest = math::checked_ceil_div(
-                math::checked_mul(
-                    math::checked_sub(curtime, self.borrow_rate_state.last_update)? as u128,
-                    self.borrow_rate_state.current_rate as u128,
-                )?,
-                3600,
-            )?;
-            math::checked_add(
-                self.borrow_rate_state.cumulative_interest,
-                cumulative_interest,
-            )
+    // Returns the rate state positions on the given side accrue interest from
+    pub fn get_borrow_rate_state(&self, side: Side) -> &BorrowRateState {
+        if !self.borrow_rate_model.per_side {
+            &self.borrow_rate_state
+        } else if side == Side::Long {
+            &self.borrow_rate_model_state.long
         } else {
-            Ok(self.borrow_rate_state.cumulative_interest)
+            &self.borrow_rate_model_state.short
         }
     }
 
-    pub fn update_borrow_rate(&mut self, curtime: i64) -> Result<()> {
-        // if current_utilization < optimal_utilization:
-        //   rate = base_rate + (current_utilization / optimal_utilization) * slope1
-        // else:
-        //   rate = base_rate + slope1 + (current_utilization - optimal_utilization) / (1 - optimal_utilization) * slope2
+    pub fn get_cumulative_interest(&self, side: Side, curtime: i64) -> Result<u128> {
+        self.get_borrow_rate_state(side)
+            .get_cumulative_interest(curtime)
+    }
 
+    pub fn update_borrow_rate(&mut self, curtime: i64) -> Result<()> {
         if self.assets.owned == 0 {
-            self.borrow_rate_state.current_rate = 0;
-            self.borrow_rate_state.last_update =
-                std::cmp::max(curtime, self.borrow_rate_state.last_update);
+            for state in [
+                &mut self.borrow_rate_state,
+                &mut self.borrow_rate_model_state.long,
+                &mut self.borrow_rate_model_state.short,
+            ] {
+                state.current_rate = 0;
+                state.last_update = std::cmp::max(curtime, state.last_update);
+            }
             return Ok(());
         }
 
-        if curtime > self.borrow_rate_state.last_update {
-            // compute interest accumulated since previous update
-            self.borrow_rate_state.cumulative_interest = self.get_cumulative_interest(curtime)?;
-            self.borrow_rate_state.last_update = curtime;
-        }
-
         // get current utilization
         let current_utilization = math::checked_div(
             math::checked_mul(self.assets.locked as u128, Perpetuals::RATE_POWER)?,
             self.assets.owned as u128,
         )?;
 
-        // compute and save new borrow rate
-        let hourly_rate = if current_utilization < (self.borrow_rate.optimal_utilization as u128)
-            || (self.borrow_rate.optimal_utilization as u128) >= Perpetuals::RATE_POWER
-        {
-            math::checked_div(
-                math::checked_mul(current_utilization, self.borrow_rate.slope1 as u128)?,
-                self.borrow_rate.optimal_utilization as u128,
-            )?
-        } else {
-            math::checked_add(
-                self.borrow_rate.slope1 as u128,
-                math::checked_div(
-                    math::checked_mul(
-                        math::checked_sub(
-                            current_utilization,
-                            self.borrow_rate.optimal_utilization as u128,
-                        )?,
-                        self.borrow_rate.slope2 as u128,
-                    )?,
-                    Perpetuals::RATE_POWER - self.borrow_rate.optimal_utilization as u128,
-                )?,
-            )?
-        };
-        let hourly_rate = math::checked_add(
-            math::checked_as_u64(hourly_rate)?,
-            self.borrow_rate.base_rate,
+        // compute and save new borrow rates
+        let model = self.borrow_rate_model;
+        model.update_rate_state(
+            &self.borrow_rate,
+            &mut self.borrow_rate_state,
+            &mut self.borrow_rate_model_state.adaptive,
+            current_utilization,
+            curtime,
         )?;
 
-        self.borrow_rate_state.current_rate = hourly_rate;
+        if model.per_side {
+            let long_utilization = self.get_side_utilization(Side::Long, current_utilization)?;
+            let short_utilization = self.get_side_utilization(Side::Short, current_utilization)?;
+            let state = &mut self.borrow_rate_model_state;
+            model.update_rate_state(
+                &self.borrow_rate,
+                &mut state.long,
+                &mut state.long_adaptive,
+                long_utilization,
+                curtime,
+            )?;
+            model.update_rate_state(
+                &self.borrow_rate,
+                &mut state.short,
+                &mut state.short_adaptive,
+                short_utilization,
+                curtime,
+            )?;
+        }
 
         Ok(())
     }
 
+    // Scales utilization by the side's share of borrowed size, so that the side with
+    // more borrowed size pays more. Both sides get the custody utilization if balanced.
+    pub fn get_side_utilization(&self, side: Side, utilization: u128) -> Result<u128> {
+        let total_borrow_usd = math::checked_add(
+            self.long_positions.borrow_size_usd as u128,
+            self.short_positions.borrow_size_usd as u128,
+        )?;
+        if total_borrow_usd == 0 {
+            return Ok(utilization);
+        }
+        let side_borrow_usd = if side == Side::Long {
+            self.long_positions.borrow_size_usd
+        } else {
+            self.short_positions.borrow_size_usd
+        };
+        Ok(std::cmp::min(
+            math::checked_div(
+                math::checked_mul(math::checked_mul(utilization, 2)?, side_borrow_usd as u128)?,
+                total_borrow_usd,
+            )?,
+            Perpetuals::RATE_POWER,
+        ))
+    }
+
     pub fn get_collective_position(&self, side: Side) -> Result<Position> {
         let stats = if side == Side::Long {
             &self.long_positions
@@ -533,11 +1249,32 @@ impl Custody {
         position: &Position,
         curtime: i64,
         collateral_custody: Option<&mut Custody>,
+    ) -> Result<()> {
+        self.remove_position_amounts(position, curtime, collateral_custody, true)
+    }
+
+    // Removes the given part of a position that stays open from the position stats
+    pub fn reduce_position(
+        &mut self,
+        closed_position: &Position,
+        curtime: i64,
+        collateral_custody: Option<&mut Custody>,
+    ) -> Result<()> {
+        self.remove_position_amounts(closed_position, curtime, collateral_custody, false)
+    }
+
+    // private helpers
+    fn remove_position_amounts(
+        &mut self,
+        position: &Position,
+        curtime: i64,
+        collateral_custody: Option<&mut Custody>,
+        close_position: bool,
     ) -> Result<()> {
         // compute accumulated interest
         let collective_position = self.get_collective_position(position.side)?;
         let interest_usd = self.get_interest_amount_usd(&collective_position, curtime)?;
-        let cumulative_interest_snapshot = self.get_cumulative_interest(curtime)?;
+        let cumulative_interest_snapshot = self.get_cumulative_interest(position.side, curtime)?;
         let position_interest_usd = self.get_interest_amount_usd(position, curtime)?;
 
         // update stats
@@ -547,7 +1284,7 @@ impl Custody {
             &mut self.short_positions
         };
 
-        if stats.open_positions == 1 {
+        if close_position && stats.open_positions == 1 {
             *stats = PositionStats::default();
             return Ok(());
         }
@@ -564,7 +1301,9 @@ impl Custody {
                 math::checked_sub(stats.borrow_size_usd, position.borrow_size_usd)?;
         }
 
-        stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
+        if close_position {
+            stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
+        }
         stats.size_usd = math::checked_sub(stats.size_usd, position.size_usd)?;
         stats.locked_amount = math::checked_sub(stats.locked_amount, position.locked_amount)?;
 
@@ -595,7 +1334,7 @@ impl Custody {
                 &mut custody.short_positions
             };
 
-            if stats.open_positions == 1 {
+            if close_position && stats.open_positions == 1 {
                 *stats = PositionStats::default();
                 return Ok(());
             }
@@ -607,7 +1346,9 @@ impl Custody {
                 .saturating_sub(position_interest_usd);
             stats.cumulative_interest_snapshot = cumulative_interest_snapshot;
 
-            stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
+            if close_position {
+                stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
+            }
             stats.borrow_size_usd =
                 math::checked_sub(stats.borrow_size_usd, position.borrow_size_usd)?;
         }
@@ -622,7 +1363,13 @@ impl DeprecatedCustody {
 
 #[cfg(test)]
 mod test {
-    use super::*;
+    use {
+        super::*,
+        crate::state::{
+            oracle::CustomOracle,
+            trading_schedule::{Holiday, TradingSession},
+        },
+    };
 
     fn get_fixture() -> Custody {
         let assets = Assets {
@@ -740,4 +1487,341 @@ mod test {
         custody.update_borrow_rate(3600).unwrap();
         assert_eq!(custody.borrow_rate_state.current_rate, 199400);
     }
+
+    #[test]
+    fn test_multi_kink_rate() {
+        let mut custody = get_fixture();
+        custody.borrow_rate_model.model = BorrowRateModel::MultiKink;
+        custody.borrow_rate_model.breakpoints[..3].copy_from_slice(&[
+            RateBreakpoint {
+                utilization: 500_000_000,
+                rate: 40000,
+            },
+            RateBreakpoint {
+                utilization: 900_000_000,
+                rate: 60000,
+            },
+            RateBreakpoint {
+                utilization: 1_000_000_000,
+                rate: 200000,
+            },
+        ]);
+        assert!(custody.borrow_rate_model.validate(&custody.borrow_rate));
+
+        for (locked, rate) in [
+            (0, 0),
+            (250, 20000),
+            (500, 40000),
+            (700, 50000),
+            (950, 130000),
+        ] {
+            custody.assets.locked = locked;
+            custody.update_borrow_rate(3600).unwrap();
+            assert_eq!(custody.borrow_rate_state.current_rate, rate);
+        }
+
+        // the curve must end at 100% utilization with sorted breakpoints and no gaps
+        let mut model = custody.borrow_rate_model;
+        model.breakpoints[2].utilization = 950_000_000;
+        assert!(!model.validate(&custody.borrow_rate));
+        let mut model = custody.borrow_rate_model;
+        model.breakpoints[1].rate = 30000;
+        assert!(!model.validate(&custody.borrow_rate));
+        let mut model = custody.borrow_rate_model;
+        model.breakpoints[1] = RateBreakpoint::default();
+        assert!(!model.validate(&custody.borrow_rate));
+        let mut model = custody.borrow_rate_model;
+        model.breakpoints = Default::default();
+        assert!(!model.validate(&custody.borrow_rate));
+    }
+
+    #[test]
+    fn test_adaptive_rate() {
+        let mut custody = get_fixture();
+        custody.borrow_rate_model = BorrowRateModelParams {
+            model: BorrowRateModel::Adaptive,
+            adjustment_speed: 100_000_000,
+            min_target_rate: 10000,
+            max_target_rate: 1_000_000,
+            curve_steepness: 40000,
+            ..BorrowRateModelParams::default()
+        };
+        assert!(custody.borrow_rate_model.validate(&custody.borrow_rate));
+
+        // target starts at base_rate + slope1, the rate at 0% is a quarter of it
+        custody.assets.locked = 500;
+        custody.update_borrow_rate(0).unwrap();
+        assert_eq!(custody.borrow_rate_model_state.adaptive.target_rate, 80000);
+        assert_eq!(custody.borrow_rate_state.current_rate, 57500);
+
+        // target drifts down while utilization was below optimal
+        custody.assets.locked = 1000;
+        custody.update_borrow_rate(3600).unwrap();
+        assert_eq!(custody.borrow_rate_model_state.adaptive.target_rate, 77000);
+        assert_eq!(custody.borrow_rate_state.current_rate, 308000);
+        assert_eq!(custody.borrow_rate_state.cumulative_interest, 57500);
+
+        // and up while it was above
+        custody.update_borrow_rate(7200).unwrap();
+        assert_eq!(custody.borrow_rate_model_state.adaptive.target_rate, 84700);
+        assert_eq!(custody.borrow_rate_state.current_rate, 338800);
+        assert_eq!(custody.borrow_rate_state.cumulative_interest, 365500);
+
+        // target is kept within the limits
+        custody.borrow_rate_model.max_target_rate = 90000;
+        custody.update_borrow_rate(72000).unwrap();
+        assert_eq!(custody.borrow_rate_model_state.adaptive.target_rate, 90000);
+
+        let mut model = custody.borrow_rate_model;
+        model.curve_steepness = 5000;
+        assert!(!model.validate(&custody.borrow_rate));
+        let mut borrow_rate = custody.borrow_rate;
+        borrow_rate.optimal_utilization = 1_000_000_000;
+        assert!(!custody.borrow_rate_model.validate(&borrow_rate));
+    }
+
+    #[test]
+    fn test_per_side_rates() {
+        let mut custody = get_fixture();
+        custody.long_positions.borrow_size_usd = 300;
+        custody.short_positions.borrow_size_usd = 100;
+        custody.borrow_rate_model.per_side = true;
+
+        custody.update_borrow_rate(3600).unwrap();
+        assert_eq!(custody.borrow_rate_state.current_rate, 50000);
+        assert_eq!(custody.borrow_rate_model_state.long.current_rate, 75000);
+        assert_eq!(custody.borrow_rate_model_state.short.current_rate, 25000);
+
+        // positions accrue interest at their side's rate
+        custody.update_borrow_rate(7200).unwrap();
+        assert_eq!(
+            custody.get_cumulative_interest(Side::Long, 7200).unwrap(),
+            75000
+        );
+        assert_eq!(
+            custody.get_cumulative_interest(Side::Short, 9000).unwrap(),
+            37500
+        );
+
+        let position = Position {
+            side: Side::Long,
+            borrow_size_usd: 2_000_000,
+            cumulative_interest_snapshot: 25000,
+            ..Position::default()
+        };
+        assert_eq!(
+            custody.get_interest_amount_usd(&position, 7200).unwrap(),
+            100
+        );
+
+        custody.borrow_rate_model.per_side = false;
+        assert_eq!(
+            custody.get_cumulative_interest(Side::Long, 7200).unwrap(),
+            50000
+        );
+        assert_eq!(
+            custody.get_interest_amount_usd(&position, 7200).unwrap(),
+            50
+        );
+    }
+
+    #[test]
+    fn test_get_leverage_tier() {
+        let mut custody = get_fixture();
+        custody.pricing.max_initial_leverage = 1_000_000;
+        custody.pricing.max_leverage = 1_000_000;
+        custody.fees.liquidation = 100;
+
+        // no tiers configured, base params apply
+        let base_tier = LeverageTier {
+            min_size_usd: 0,
+            max_initial_leverage: 1_000_000,
+            max_leverage: 1_000_000,
+            liquidation_fee: 100,
+        };
+        assert_eq!(custody.get_leverage_tier(u64::MAX), base_tier);
+
+        custody.leverage_tiers[0] = LeverageTier {
+            min_size_usd: 100_000_000_000,
+            max_initial_leverage: 500_000,
+            max_leverage: 500_000,
+            liquidation_fee: 200,
+        };
+        custody.leverage_tiers[1] = LeverageTier {
+            min_size_usd: 1_000_000_000_000,
+            max_initial_leverage: 100_000,
+            max_leverage: 200_000,
+            liquidation_fee: 300,
+        };
+
+        assert_eq!(custody.get_leverage_tier(99_999_999_999), base_tier);
+        assert_eq!(
+            custody.get_leverage_tier(100_000_000_000),
+            custody.leverage_tiers[0]
+        );
+        assert_eq!(
+            custody.get_leverage_tier(999_999_999_999),
+            custody.leverage_tiers[0]
+        );
+        assert_eq!(
+            custody.get_leverage_tier(u64::MAX),
+            custody.leverage_tiers[1]
+        );
+    }
+
+    #[test]
+    fn test_get_max_price_age() {
+        let mut custody = get_fixture();
+        custody.oracle.max_price_age_sec = 30;
+
+        // risk reducing actions fall back to the regular max age
+        let oracle = custody.get_oracle_params(0);
+        assert_eq!(oracle.get_max_price_age(ActionClass::Increase), 30);
+        assert_eq!(oracle.get_max_price_age(ActionClass::Reduce), 30);
+
+        custody.oracle.max_reducing_price_age_sec = 300;
+        let oracle = custody.get_oracle_params(0);
+        assert_eq!(oracle.get_max_price_age(ActionClass::Increase), 30);
+        assert_eq!(oracle.get_max_price_age(ActionClass::Reduce), 300);
+
+        // can't be stricter than the regular max age
+        custody.oracle.max_reducing_price_age_sec = 10;
+        assert_eq!(custody.oracle.get_max_price_age(ActionClass::Reduce), 30);
+    }
+
+    #[test]
+    fn test_get_liquidity_prices() {
+        let owner = crate::ID;
+        let key = Pubkey::new_unique();
+        let mut lamports = 1;
+        let mut data = Vec::new();
+        CustomOracle {
+            price: 100_000_000,
+            expo: -8,
+            conf: 0,
+            ema: 100_000_000,
+            publish_time: 1_000,
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+
+        let mut custody = get_fixture();
+        custody.oracle = OracleParams {
+            oracle_account: key,
+            oracle_type: OracleType::Custom,
+            max_price_error: 10_000,
+            max_price_age_sec: 30,
+            max_reducing_price_age_sec: 300,
+            ..OracleParams::default()
+        };
+
+        // deposits take the regular max age, withdrawals the reducing one
+        assert!(custody.get_liquidity_prices(&account, 1_030, true).is_ok());
+        assert!(custody.get_liquidity_prices(&account, 1_100, true).is_err());
+        assert!(custody.get_liquidity_prices(&account, 1_100, false).is_ok());
+        assert!(custody
+            .get_liquidity_prices(&account, 1_400, false)
+            .is_err());
+
+        // off hours max age applies while the market is closed
+        custody.trading_schedule.sessions[0] = TradingSession {
+            open_time: 0,
+            close_time: TradingSchedule::WEEK_SEC as u32,
+        };
+        custody.trading_schedule.holidays[0] = Holiday {
+            start_time: 0,
+            end_time: 2_000,
+        };
+        custody.trading_schedule.off_hours_max_price_age_sec = 600;
+        assert!(custody.get_liquidity_prices(&account, 1_400, true).is_ok());
+        assert!(custody.get_liquidity_prices(&account, 1_400, false).is_ok());
+        assert!(custody.get_liquidity_prices(&account, 1_700, true).is_err());
+    }
+
+    #[test]
+    fn test_depeg() {
+        let mut custody = get_fixture();
+        custody.is_stable = true;
+        custody.depeg = DepegParams {
+            soft_threshold: 990_000,
+            hard_threshold: 950_000,
+            swap_in_fee_mult: 30_000,
+            haircut: 2_000,
+        };
+        assert!(custody.depeg.validate());
+
+        let pegged_price = OraclePrice::new(99_950_000, -8);
+        let soft_price = OraclePrice::new(98_000_000, -8);
+        let hard_price = OraclePrice::new(94_000_000, -8);
+        assert_eq!(
+            custody.get_depeg_status(&pegged_price).unwrap(),
+            DepegStatus::Pegged
+        );
+        assert_eq!(
+            custody.get_depeg_status(&soft_price).unwrap(),
+            DepegStatus::Soft
+        );
+        assert_eq!(
+            custody.get_depeg_status(&hard_price).unwrap(),
+            DepegStatus::Hard
+        );
+
+        // swap in fees go up below the soft threshold
+        assert_eq!(custody.get_swap_in_fee(100, &pegged_price).unwrap(), 100);
+        assert_eq!(custody.get_swap_in_fee(100, &soft_price).unwrap(), 300);
+        assert_eq!(custody.get_swap_in_fee(100, &hard_price).unwrap(), 300);
+        assert_eq!(custody.get_swap_in_fee(5_000, &soft_price).unwrap(), 10_000);
+
+        // haircut applies below the hard threshold
+        assert_eq!(
+            custody.apply_depeg_haircut(1_000, &soft_price).unwrap(),
+            1_000
+        );
+        assert_eq!(
+            custody.apply_depeg_haircut(1_000, &hard_price).unwrap(),
+            800
+        );
+
+        // disabled thresholds
+        custody.depeg.hard_threshold = 0;
+        assert_eq!(
+            custody.get_depeg_status(&hard_price).unwrap(),
+            DepegStatus::Soft
+        );
+        custody.depeg = DepegParams::default();
+        assert_eq!(
+            custody.get_depeg_status(&hard_price).unwrap(),
+            DepegStatus::Pegged
+        );
+        assert_eq!(custody.get_swap_in_fee(100, &hard_price).unwrap(), 100);
+
+        // invalid configs
+        let depeg = DepegParams {
+            soft_threshold: 950_000,
+            hard_threshold: 990_000,
+            ..DepegParams::default()
+        };
+        assert!(!depeg.validate());
+        let depeg = DepegParams {
+            soft_threshold: 1_100_000,
+            ..DepegParams::default()
+        };
+        assert!(!depeg.validate());
+        let depeg = DepegParams {
+            soft_threshold: 990_000,
+            swap_in_fee_mult: 5_000,
+            ..DepegParams::default()
+        };
+        assert!(!depeg.validate());
+    }
 }
@@ -0,0 +1,271 @@
+use {
+    crate::{
+        math,
+        state::{custody::Custody, perpetuals::Perpetuals},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct StatsBucket {
+    // unix time / SECONDS_PER_DAY
+    pub day: i64,
+    // volume and fees accumulated during the day, have implied USD_DECIMALS decimals
+    pub volume_usd: u64,
+    pub fees_usd: u64,
+    // values at the last update of the day
+    pub oi_long_usd: u64,
+    pub oi_short_usd: u64,
+    // locked / owned, has implied BPS_DECIMALS decimals
+    pub utilization: u64,
+    // has implied RATE_DECIMALS decimals
+    pub borrow_rate: u64,
+    // value of the custody's owned assets, has implied USD_DECIMALS decimals
+    pub owned_usd: u64,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct CustodyStatsHistory {
+    pub custody: Pubkey,
+    // daily buckets indexed by day % HISTORY_DAYS
+    pub buckets: [StatsBucket; CustodyStatsHistory::HISTORY_DAYS],
+    pub update_time: i64,
+    // lifetime custody counters at the last update, used to compute bucket increments
+    pub volume_snapshot_usd: u64,
+    pub fees_snapshot_usd: u64,
+
+    pub bump: u8,
+}
+
+impl StatsBucket {
+    // builds a bucket with the current custody state, volume and fees are left empty
+    pub fn from_custody(custody: &Custody, owned_usd: u64, day: i64) -> Result<Self> {
+        let utilization = if custody.assets.owned > 0 {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(custody.assets.locked as u128, Perpetuals::BPS_POWER)?,
+                custody.assets.owned as u128,
+            )?)?
+        } else {
+            0
+        };
+
+        Ok(Self {
+            day,
+            volume_usd: 0,
+            fees_usd: 0,
+            oi_long_usd: custody.trade_stats.oi_long_usd,
+            oi_short_usd: custody.trade_stats.oi_short_usd,
+            utilization,
+            borrow_rate: custody.borrow_rate_state.current_rate,
+            owned_usd,
+        })
+    }
+}
+
+impl CustodyStatsHistory {
+    pub const LEN: usize = 8 + std::mem::size_of::<CustodyStatsHistory>();
+    pub const HISTORY_DAYS: usize = 30;
+    // max number of buckets returned by a view, limited by the return data size
+    pub const MAX_VIEW_DAYS: usize = 15;
+    pub const SECONDS_PER_DAY: i64 = 86400;
+
+    pub fn get_volume_usd(custody: &Custody) -> u64 {
+        let stats = &custody.volume_stats;
+        stats
+            .swap_usd
+            .wrapping_add(stats.add_liquidity_usd)
+            .wrapping_add(stats.remove_liquidity_usd)
+            .wrapping_add(stats.open_position_usd)
+            .wrapping_add(stats.close_position_usd)
+            .wrapping_add(stats.liquidation_usd)
+    }
+
+    pub fn get_fees_usd(custody: &Custody) -> u64 {
+        let stats = &custody.collected_fees;
+        stats
+            .swap_usd
+            .wrapping_add(stats.add_liquidity_usd)
+            .wrapping_add(stats.remove_liquidity_usd)
+            .wrapping_add(stats.open_position_usd)
+            .wrapping_add(stats.close_position_usd)
+            .wrapping_add(stats.liquidation_usd)
+    }
+
+    // Records custody state into the current day bucket, volume and fees since
+    // the previous update are split across the days it spans pro rata to time
+    pub fn update(&mut self, custody: &Custody, owned_usd: u64, curtime: i64) -> Result<()> {
+        require_gte!(curtime, self.update_time);
+        let day = math::checked_div(curtime, Self::SECONDS_PER_DAY)?;
+        let window = Self::HISTORY_DAYS as i64;
+
+        let volume_usd = Self::get_volume_usd(custody);
+        let fees_usd = Self::get_fees_usd(custody);
+
+        if self.update_time > 0 {
+            // lifetime counters wrap on overflow, so do the increments
+            let volume_inc = volume_usd.wrapping_sub(self.volume_snapshot_usd);
+            let fees_inc = fees_usd.wrapping_sub(self.fees_snapshot_usd);
+            let elapsed = math::checked_sub(curtime, self.update_time)?;
+            let last_day = math::checked_div(self.update_time, Self::SECONDS_PER_DAY)?;
+            let last_bucket = self.buckets[(last_day % window) as usize];
+
+            // days that fell out of the window are skipped
+            let first_day = std::cmp::max(last_day, math::checked_sub(day, window - 1)?);
+            for bucket_day in first_day..=day {
+                // shares are differences of cumulative amounts so that they add up to the increment
+                let (volume_share, fees_share) = if bucket_day == day {
+                    let day_start = math::checked_mul(day, Self::SECONDS_PER_DAY)?;
+                    let time = math::checked_sub(
+                        std::cmp::max(day_start, self.update_time),
+                        self.update_time,
+                    )?;
+                    (
+                        volume_inc.wrapping_sub(Self::get_share(volume_inc, time, elapsed)?),
+                        fees_inc.wrapping_sub(Self::get_share(fees_inc, time, elapsed)?),
+                    )
+                } else {
+                    let day_start = math::checked_mul(bucket_day, Self::SECONDS_PER_DAY)?;
+                    let start = math::checked_sub(
+                        std::cmp::max(day_start, self.update_time),
+                        self.update_time,
+                    )?;
+                    let end = math::checked_sub(
+                        math::checked_add(day_start, Self::SECONDS_PER_DAY)?,
+                        self.update_time,
+                    )?;
+                    (
+                        Self::get_share(volume_inc, end, elapsed)?
+                            .wrapping_sub(Self::get_share(volume_inc, start, elapsed)?),
+                        Self::get_share(fees_inc, end, elapsed)?
+                            .wrapping_sub(Self::get_share(fees_inc, start, elapsed)?),
+                    )
+                };
+
+                let bucket = &mut self.buckets[(bucket_day % window) as usize];
+                if bucket.day != bucket_day {
+                    // days without updates keep the state of the last update
+                    *bucket = StatsBucket {
+                        day: bucket_day,
+                        volume_usd: 0,
+                        fees_usd: 0,
+                        ..last_bucket
+                    };
+                }
+                bucket.volume_usd = bucket.volume_usd.wrapping_add(volume_share);
+                bucket.fees_usd = bucket.fees_usd.wrapping_add(fees_share);
+            }
+        }
+
+        // refresh the current day
+        let bucket = &mut self.buckets[(day % window) as usize];
+        let mut new_bucket = StatsBucket::from_custody(custody, owned_usd, day)?;
+        if self.update_time > 0 && bucket.day == day {
+            new_bucket.volume_usd = bucket.volume_usd;
+            new_bucket.fees_usd = bucket.fees_usd;
+        }
+        *bucket = new_bucket;
+
+        self.update_time = curtime;
+        self.volume_snapshot_usd = volume_usd;
+        self.fees_snapshot_usd = fees_usd;
+
+        Ok(())
+    }
+
+    // Returns the part of the amount accrued over time out of elapsed seconds
+    fn get_share(amount: u64, time: i64, elapsed: i64) -> Result<u64> {
+        if time == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(amount as u128, time as u128)?,
+            elapsed as u128,
+        )?)
+    }
+
+    // Returns recorded buckets of the last num_days days, oldest first
+    pub fn get_window(&self, num_days: usize, curtime: i64) -> Result<Vec<StatsBucket>> {
+        let num_days = std::cmp::min(num_days, Self::MAX_VIEW_DAYS) as i64;
+        let day = math::checked_div(curtime, Self::SECONDS_PER_DAY)?;
+        let window = Self::HISTORY_DAYS as i64;
+
+        let mut buckets = Vec::new();
+        let first_day = std::cmp::max(math::checked_sub(day, num_days - 1)?, 0);
+        for bucket_day in first_day..=day {
+            let bucket = &self.buckets[(bucket_day % window) as usize];
+            if bucket.day == bucket_day && self.update_time > 0 {
+                buckets.push(*bucket);
+            }
+        }
+        Ok(buckets)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let day = CustodyStatsHistory::SECONDS_PER_DAY;
+        let mut history = CustodyStatsHistory::default();
+        let mut custody = Custody::default();
+        custody.volume_stats.swap_usd = 1_000;
+        custody.collected_fees.swap_usd = 10;
+
+        // first update only takes snapshots
+        history.update(&custody, 500, 10 * day).unwrap();
+        assert_eq!(history.buckets[10].day, 10);
+        assert_eq!(history.buckets[10].volume_usd, 0);
+        assert_eq!(history.buckets[10].owned_usd, 500);
+
+        custody.volume_stats.open_position_usd = 200;
+        custody.collected_fees.open_position_usd = 2;
+        custody.trade_stats.oi_long_usd = 200;
+        history.update(&custody, 600, 10 * day + 100).unwrap();
+        assert_eq!(history.buckets[10].volume_usd, 200);
+        assert_eq!(history.buckets[10].fees_usd, 2);
+        assert_eq!(history.buckets[10].oi_long_usd, 200);
+        assert_eq!(history.buckets[10].owned_usd, 600);
+
+        // increments since the last update are split across the days pro rata to time
+        custody.volume_stats.close_position_usd = 300;
+        history.update(&custody, 700, 12 * day).unwrap();
+        assert_eq!(history.buckets[10].volume_usd, 349);
+        assert_eq!(history.buckets[11].volume_usd, 151);
+        assert_eq!(history.buckets[11].day, 11);
+        assert_eq!(history.buckets[11].oi_long_usd, 200);
+        assert_eq!(history.buckets[11].owned_usd, 600);
+        assert_eq!(history.buckets[12].volume_usd, 0);
+        assert_eq!(history.buckets[12].day, 12);
+        assert_eq!(history.buckets[12].owned_usd, 700);
+
+        // lifetime counter overflow
+        custody.volume_stats.swap_usd = custody.volume_stats.swap_usd.wrapping_add(u64::MAX);
+        history.update(&custody, 700, 12 * day + 1).unwrap();
+        assert_eq!(history.buckets[12].volume_usd, u64::MAX);
+
+        let buckets = history.get_window(5, 12 * day + 2).unwrap();
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].day, 10);
+        assert_eq!(buckets[1].day, 11);
+        assert_eq!(buckets[2].day, 12);
+
+        // stale buckets are not returned
+        let buckets = history.get_window(5, 41 * day).unwrap();
+        assert!(buckets.is_empty());
+        assert!(history.update(&custody, 700, 12 * day).is_err());
+
+        // days that fell out of the window only get their share
+        custody.volume_stats.swap_usd = custody.volume_stats.swap_usd.wrapping_add(880);
+        history.update(&custody, 800, 100 * day + 1).unwrap();
+        let buckets = history
+            .get_window(CustodyStatsHistory::MAX_VIEW_DAYS, 100 * day + 1)
+            .unwrap();
+        assert_eq!(buckets.len(), CustodyStatsHistory::MAX_VIEW_DAYS);
+        assert!(buckets[..14].iter().all(|bucket| bucket.volume_usd == 10));
+        assert_eq!(buckets[14].volume_usd, 1);
+        assert_eq!(buckets[14].owned_usd, 800);
+    }
+}
@@ -0,0 +1,320 @@
+//! Dated futures markets
+//!
+//! A dated market trades a pool custody with a fixed expiry, using the same custody
+//! liquidity and position accounting as perpetuals. Opens are rejected within
+//! `open_cutoff_sec` of expiry. The settlement price is the time weighted average
+//! of at least `min_samples` oracle prices sampled over the final
+//! `settlement_window_sec` before expiry, and positions stop accruing borrow
+//! interest at expiry. If the crank took fewer samples, the market is settled with
+//! the samples it has once another `settlement_window_sec` has passed after expiry,
+//! so that positions can always be unwound.
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{custody::BorrowRateState, position::Side},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct DatedMarket {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    // stablecoin collateral custody of shorts and virtual custody longs
+    pub collateral_custody: Pubkey,
+
+    pub expiry_time: i64,
+    pub open_cutoff_sec: u32,
+    pub settlement_window_sec: u32,
+    // max weight of a single sample, so that a stalled crank can't let one price
+    // dominate the average
+    pub max_sample_interval_sec: u32,
+    // samples required before expiry to settle the market before the grace period ends
+    pub min_samples: u32,
+
+    // time weighted sum of price samples within the settlement window and the sum
+    // of their weights, prices have implied PRICE_DECIMALS decimals
+    pub cumulative_price: u128,
+    pub cumulative_weight: i64,
+    pub num_samples: u32,
+    pub last_price: u64,
+    pub last_sample_time: i64,
+    // borrow rate states of the collateral custodies at the last sample before expiry
+    pub long_rate_state: BorrowRateState,
+    pub short_rate_state: BorrowRateState,
+
+    // zero until the market is settled
+    pub settlement_price: u64,
+    pub settlement_time: i64,
+    // cumulative interest at expiry, used instead of the custody interest
+    pub long_cumulative_interest: u128,
+    pub short_cumulative_interest: u128,
+
+    pub bump: u8,
+}
+
+impl DatedMarket {
+    pub const LEN: usize = 8 + std::mem::size_of::<DatedMarket>();
+
+    pub fn validate(&self) -> bool {
+        self.expiry_time > 0
+            && self.settlement_window_sec > 0
+            && self.open_cutoff_sec >= self.settlement_window_sec
+            && self.max_sample_interval_sec > 0
+            && self.min_samples > 0
+    }
+
+    pub fn is_open_allowed(&self, curtime: i64) -> bool {
+        curtime < self.expiry_time.saturating_sub(self.open_cutoff_sec as i64)
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.settlement_price > 0
+    }
+
+    pub fn get_window_start(&self) -> i64 {
+        self.expiry_time
+            .saturating_sub(self.settlement_window_sec as i64)
+    }
+
+    // Returns the time after which the market is settled even without min_samples
+    pub fn get_grace_period_end(&self) -> i64 {
+        self.expiry_time
+            .saturating_add(self.settlement_window_sec as i64)
+    }
+
+    // Returns the cumulative interest positions on the given side are charged up to
+    pub fn get_cumulative_interest(&self, side: Side) -> u128 {
+        if side == Side::Long {
+            self.long_cumulative_interest
+        } else {
+            self.short_cumulative_interest
+        }
+    }
+
+    // Records a price sample within the settlement window. Each sample is weighted
+    // by the time until the next one or expiry, capped at max_sample_interval_sec.
+    // The first update at or after expiry settles the market with the samples taken
+    // before expiry only. Without min_samples, settlement waits for the grace period
+    // and falls back to the current price if no samples were taken at all.
+    pub fn update(
+        &mut self,
+        price: u64,
+        long_rate_state: &BorrowRateState,
+        short_rate_state: &BorrowRateState,
+        curtime: i64,
+    ) -> Result<()> {
+        if self.is_settled() {
+            msg!("Market is already settled");
+            return err!(PerpetualsError::InvalidDatedMarketState);
+        }
+        if curtime < self.get_window_start() {
+            msg!("Settlement window has not started");
+            return err!(PerpetualsError::InvalidDatedMarketState);
+        }
+        require!(price > 0, PerpetualsError::InvalidOraclePrice);
+        if curtime >= self.expiry_time
+            && curtime < self.get_grace_period_end()
+            && self.num_samples < self.min_samples
+        {
+            msg!(
+                "Not enough samples before expiry: {}/{}, retry after {}",
+                self.num_samples,
+                self.min_samples,
+                self.get_grace_period_end()
+            );
+            return err!(PerpetualsError::InvalidDatedMarketState);
+        }
+
+        if self.num_samples > 0 {
+            let sample_time = std::cmp::min(curtime, self.expiry_time);
+            let weight = std::cmp::min(
+                math::checked_sub(sample_time, self.last_sample_time)?,
+                self.max_sample_interval_sec as i64,
+            );
+            self.cumulative_price = math::checked_add(
+                self.cumulative_price,
+                math::checked_mul(self.last_price as u128, weight as u128)?,
+            )?;
+            self.cumulative_weight = math::checked_add(self.cumulative_weight, weight)?;
+        }
+
+        if curtime < self.expiry_time {
+            self.last_price = price;
+            self.last_sample_time = curtime;
+            self.num_samples = math::checked_add(self.num_samples, 1)?;
+            self.long_rate_state = *long_rate_state;
+            self.short_rate_state = *short_rate_state;
+            return Ok(());
+        }
+
+        // settle, samples after expiry are only used if none were taken before it
+        if self.num_samples == 0 {
+            self.last_price = price;
+            self.long_rate_state = *long_rate_state;
+            self.short_rate_state = *short_rate_state;
+            self.settlement_price = price;
+        } else {
+            self.settlement_price = math::checked_as_u64(math::checked_div(
+                self.cumulative_price,
+                self.cumulative_weight as u128,
+            )?)?;
+        }
+        self.settlement_time = curtime;
+        self.long_cumulative_interest = self
+            .long_rate_state
+            .get_cumulative_interest(self.expiry_time)?;
+        self.short_cumulative_interest = self
+            .short_rate_state
+            .get_cumulative_interest(self.expiry_time)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_fixture() -> DatedMarket {
+        DatedMarket {
+            expiry_time: 10_000,
+            open_cutoff_sec: 3600,
+            settlement_window_sec: 1800,
+            max_sample_interval_sec: 600,
+            min_samples: 2,
+            ..DatedMarket::default()
+        }
+    }
+
+    fn get_rate_state(current_rate: u64, last_update: i64) -> BorrowRateState {
+        BorrowRateState {
+            current_rate,
+            cumulative_interest: 1000,
+            last_update,
+        }
+    }
+
+    #[test]
+    fn test_open_cutoff() {
+        let market = get_fixture();
+        assert!(market.validate());
+        assert!(market.is_open_allowed(6_399));
+        assert!(!market.is_open_allowed(6_400));
+        assert!(!market.is_open_allowed(20_000));
+
+        let mut market = get_fixture();
+        market.open_cutoff_sec = 600;
+        assert!(!market.validate());
+
+        let mut market = get_fixture();
+        market.min_samples = 0;
+        assert!(!market.validate());
+    }
+
+    #[test]
+    fn test_settlement_twap() {
+        let mut market = get_fixture();
+        let rate_state = get_rate_state(3600, 8000);
+
+        // samples before the window are rejected
+        assert!(market.update(100, &rate_state, &rate_state, 8_000).is_err());
+
+        // sample weights are capped at max_sample_interval_sec
+        market.update(100, &rate_state, &rate_state, 8_200).unwrap();
+        market.update(200, &rate_state, &rate_state, 9_000).unwrap();
+        assert!(!market.is_settled());
+        assert_eq!(market.cumulative_weight, 600);
+
+        // the last sample before expiry is weighted until expiry
+        let long_rate_state = get_rate_state(7200, 9_600);
+        market
+            .update(400, &long_rate_state, &rate_state, 9_600)
+            .unwrap();
+        market
+            .update(1_000_000, &rate_state, &rate_state, 12_000)
+            .unwrap();
+        assert!(market.is_settled());
+        assert_eq!(market.num_samples, 3);
+        assert_eq!(market.settlement_price, 212);
+        assert_eq!(market.settlement_time, 12_000);
+        assert_eq!(market.last_price, 400);
+
+        // interest accrues at the snapshot rates until expiry
+        assert_eq!(market.get_cumulative_interest(Side::Long), 1800);
+        assert_eq!(market.get_cumulative_interest(Side::Short), 3000);
+
+        assert!(market
+            .update(100, &rate_state, &rate_state, 12_100)
+            .is_err());
+    }
+
+    #[test]
+    fn test_settlement_without_samples() {
+        // samples after expiry are never used
+        let mut market = get_fixture();
+        let rate_state = get_rate_state(3600, 9_000);
+        assert!(market
+            .update(300, &rate_state, &rate_state, 11_000)
+            .is_err());
+        assert!(market
+            .update(300, &rate_state, &rate_state, 10_000)
+            .is_err());
+        assert!(!market.is_settled());
+
+        // fewer than min_samples before expiry
+        market.update(300, &rate_state, &rate_state, 9_500).unwrap();
+        assert!(market
+            .update(300, &rate_state, &rate_state, 10_000)
+            .is_err());
+
+        market.update(500, &rate_state, &rate_state, 9_900).unwrap();
+        market
+            .update(1_000, &rate_state, &rate_state, 10_000)
+            .unwrap();
+        assert!(market.is_settled());
+        assert_eq!(market.settlement_price, 340);
+    }
+
+    #[test]
+    fn test_settlement_after_grace_period() {
+        // a single sample is not enough to settle at expiry
+        let mut market = get_fixture();
+        let rate_state = get_rate_state(3600, 9_000);
+        market.update(300, &rate_state, &rate_state, 9_700).unwrap();
+        assert!(market
+            .update(1_000, &rate_state, &rate_state, 10_000)
+            .is_err());
+        assert!(market
+            .update(1_000, &rate_state, &rate_state, 11_799)
+            .is_err());
+        assert!(!market.is_settled());
+
+        // the market is settled with the available samples once the grace period is over,
+        // so that expired positions can be unwound
+        market
+            .update(1_000, &rate_state, &rate_state, 11_800)
+            .unwrap();
+        assert!(market.is_settled());
+        assert_eq!(market.num_samples, 1);
+        assert_eq!(market.settlement_price, 300);
+        assert_eq!(market.settlement_time, 11_800);
+        assert_eq!(market.get_cumulative_interest(Side::Long), 2000);
+
+        // without any samples the current price and rate states are used
+        let mut market = get_fixture();
+        let rate_state = get_rate_state(3600, 11_000);
+        market
+            .update(1_000, &rate_state, &rate_state, 12_000)
+            .unwrap();
+        assert!(market.is_settled());
+        assert_eq!(market.settlement_price, 1_000);
+        assert_eq!(market.get_cumulative_interest(Side::Short), 1000);
+        assert!(market
+            .update(1_000, &rate_state, &rate_state, 12_100)
+            .is_err());
+    }
+}
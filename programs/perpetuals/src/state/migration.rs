@@ -0,0 +1,623 @@
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::{
+                Assets, BorrowRateParams, BorrowRateState, Custody, DeprecatedCustody, Fees,
+                FeesStats, PositionStats, PricingParams, TradeStats, VolumeStats,
+            },
+            oracle::{DeprecatedOracleParams, OracleParams},
+            perpetuals::{Permissions, Perpetuals},
+            pool::{Pool, TokenRatios},
+            position::{Position, Side},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+// Accounts with a version byte right after the discriminator. Accounts created before
+// versioning have no version byte, they are recognized by their length and treated as
+// version 0. Layout changes take new fields from the reserved space where possible,
+// otherwise they bump CURRENT_VERSION and add a step to migrate_from.
+pub trait Versioned {
+    const CURRENT_VERSION: u8;
+
+    // Returns true if the data (including the discriminator) has the layout
+    // from before versioning
+    fn is_legacy(data: &[u8]) -> bool;
+
+    // Rejects data that can't be migrated, e.g. accounts that have to be converted
+    // with a dedicated instruction
+    fn validate_data(_data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    // Converts serialized account data (including the discriminator) of the given
+    // version into the layout of the next version
+    fn migrate_from(version: u8, _data: &[u8]) -> Result<Vec<u8>> {
+        msg!("No migration from version {}", version);
+        err!(PerpetualsError::UnsupportedAccountVersion)
+    }
+}
+
+// Account layouts from before versioning
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct LegacyPerpetuals {
+    pub permissions: Permissions,
+    pub pools: Vec<Pubkey>,
+
+    pub transfer_authority_bump: u8,
+    pub perpetuals_bump: u8,
+    pub inception_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct LegacyPool {
+    pub name: String,
+    pub custodies: Vec<Pubkey>,
+    pub ratios: Vec<TokenRatios>,
+    pub aum_usd: u128,
+
+    pub bump: u8,
+    pub lp_token_bump: u8,
+    pub inception_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct LegacyCustody {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+    pub decimals: u8,
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub oracle: DeprecatedOracleParams,
+    pub pricing: PricingParams,
+    pub permissions: Permissions,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+
+    pub assets: Assets,
+    pub collected_fees: FeesStats,
+    pub volume_stats: VolumeStats,
+    pub trade_stats: TradeStats,
+    pub long_positions: PositionStats,
+    pub short_positions: PositionStats,
+    pub borrow_rate_state: BorrowRateState,
+
+    pub bump: u8,
+    pub token_account_bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct LegacyPosition {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+
+    pub open_time: i64,
+    pub update_time: i64,
+    pub side: Side,
+    pub price: u64,
+    pub size_usd: u64,
+    pub borrow_size_usd: u64,
+    pub collateral_usd: u64,
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    pub cumulative_interest_snapshot: u128,
+    pub locked_amount: u64,
+    pub collateral_amount: u64,
+
+    pub bump: u8,
+}
+
+impl LegacyPerpetuals {
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyPerpetuals>();
+
+    // account size after num_pools pools were added
+    pub fn get_len(num_pools: usize) -> usize {
+        Self::LEN + num_pools * std::mem::size_of::<Pubkey>()
+    }
+}
+
+impl LegacyPool {
+    pub const LEN: usize = 8 + 64 + std::mem::size_of::<LegacyPool>();
+
+    // account size after num_custodies custodies were added
+    pub fn get_len(num_custodies: usize) -> usize {
+        Self::LEN
+            + num_custodies * (std::mem::size_of::<Pubkey>() + std::mem::size_of::<TokenRatios>())
+    }
+}
+
+impl LegacyCustody {
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyCustody>();
+}
+
+impl LegacyPosition {
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyPosition>();
+}
+
+fn deserialize_legacy<T: AnchorDeserialize>(data: &[u8]) -> Result<T> {
+    let mut legacy_data = data.get(8..).ok_or(ProgramError::AccountDataTooSmall)?;
+    T::deserialize(&mut legacy_data).map_err(|_| ProgramError::InvalidAccountData.into())
+}
+
+// Checks if the token account address matches the custody token account PDA derived
+// with the given bump
+fn is_custody_token_account(
+    pool: &Pubkey,
+    mint: &Pubkey,
+    token_account: &Pubkey,
+    bump: u8,
+) -> bool {
+    Pubkey::create_program_address(
+        &[
+            b"custody_token_account",
+            pool.as_ref(),
+            mint.as_ref(),
+            &[bump],
+        ],
+        &crate::ID,
+    )
+    .map(|key| key == *token_account)
+    .unwrap_or(false)
+}
+
+// Serializes the account and pads it to the size of a newly created account
+fn serialize_account<T: AccountSerialize>(account: &T, len: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    account.try_serialize(&mut data)?;
+    if data.len() < len {
+        data.resize(len, 0);
+    }
+    Ok(data)
+}
+
+impl Versioned for Perpetuals {
+    const CURRENT_VERSION: u8 = 1;
+
+    fn is_legacy(data: &[u8]) -> bool {
+        // legacy accounts are resized by pool count, so the length has to match it
+        deserialize_legacy::<LegacyPerpetuals>(data)
+            .map(|legacy| data.len() == LegacyPerpetuals::get_len(legacy.pools.len()))
+            .unwrap_or(false)
+    }
+
+    fn migrate_from(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+        require_eq!(version, 0, PerpetualsError::UnsupportedAccountVersion);
+        let legacy = deserialize_legacy::<LegacyPerpetuals>(data)?;
+        let len = Perpetuals::LEN + legacy.pools.len() * std::mem::size_of::<Pubkey>();
+        let perpetuals = Perpetuals {
+            version: 1,
+            permissions: legacy.permissions,
+            pools: legacy.pools,
+            transfer_authority_bump: legacy.transfer_authority_bump,
+            perpetuals_bump: legacy.perpetuals_bump,
+            inception_time: legacy.inception_time,
+            ..Perpetuals::default()
+        };
+        serialize_account(&perpetuals, len)
+    }
+}
+
+impl Versioned for Pool {
+    const CURRENT_VERSION: u8 = 1;
+
+    fn is_legacy(data: &[u8]) -> bool {
+        // legacy accounts are resized by custody count, so the length has to match it
+        deserialize_legacy::<LegacyPool>(data)
+            .map(|legacy| data.len() == LegacyPool::get_len(legacy.custodies.len()))
+            .unwrap_or(false)
+    }
+
+    fn migrate_from(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+        require_eq!(version, 0, PerpetualsError::UnsupportedAccountVersion);
+        let legacy = deserialize_legacy::<LegacyPool>(data)?;
+        let len = Pool::LEN
+            + legacy.custodies.len() * std::mem::size_of::<Pubkey>()
+            + legacy.ratios.len() * std::mem::size_of::<TokenRatios>();
+        let pool = Pool {
+            version: 1,
+            name: legacy.name,
+            custodies: legacy.custodies,
+            ratios: legacy.ratios,
+            aum_usd: legacy.aum_usd,
+            bump: legacy.bump,
+            lp_token_bump: legacy.lp_token_bump,
+            inception_time: legacy.inception_time,
+            ..Pool::default()
+        };
+        serialize_account(&pool, len)
+    }
+}
+
+impl Versioned for Custody {
+    const CURRENT_VERSION: u8 = 1;
+
+    // DeprecatedCustody accounts have the same length but no is_virtual flag, so the
+    // layouts are told apart by the token account bump which sits one byte earlier
+    fn is_legacy(data: &[u8]) -> bool {
+        data.len() == LegacyCustody::LEN
+            && deserialize_legacy::<LegacyCustody>(data)
+                .map(|legacy| {
+                    is_custody_token_account(
+                        &legacy.pool,
+                        &legacy.mint,
+                        &legacy.token_account,
+                        legacy.token_account_bump,
+                    )
+                })
+                .unwrap_or(false)
+    }
+
+    fn validate_data(data: &[u8]) -> Result<()> {
+        let is_deprecated = data.len() == DeprecatedCustody::LEN
+            && !Self::is_legacy(data)
+            && deserialize_legacy::<DeprecatedCustody>(data)
+                .map(|deprecated| {
+                    is_custody_token_account(
+                        &deprecated.pool,
+                        &deprecated.mint,
+                        &deprecated.token_account,
+                        deprecated.token_account_bump,
+                    )
+                })
+                .unwrap_or(false);
+        if is_deprecated {
+            msg!("Deprecated custody, use upgrade_custody instead");
+            return err!(PerpetualsError::DeprecatedCustodyUpgradeRequired);
+        }
+        Ok(())
+    }
+
+    fn migrate_from(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+        require_eq!(version, 0, PerpetualsError::UnsupportedAccountVersion);
+        let legacy = deserialize_legacy::<LegacyCustody>(data)?;
+        // same defaults as upgrade_custody
+        let custody = Custody {
+            version: 1,
+            pool: legacy.pool,
+            mint: legacy.mint,
+            token_account: legacy.token_account,
+            decimals: legacy.decimals,
+            is_stable: legacy.is_stable,
+            is_virtual: legacy.is_virtual,
+            oracle: OracleParams {
+                oracle_account: legacy.oracle.oracle_account,
+                oracle_type: legacy.oracle.oracle_type,
+                oracle_authority: legacy.oracle.oracle_authority,
+                max_price_error: legacy.oracle.max_price_error,
+                max_price_age_sec: legacy.oracle.max_price_age_sec,
+                max_reducing_price_age_sec: legacy.oracle.max_price_age_sec,
+            },
+            pricing: legacy.pricing,
+            permissions: legacy.permissions,
+            fees: legacy.fees,
+            borrow_rate: legacy.borrow_rate,
+            assets: legacy.assets,
+            collected_fees: legacy.collected_fees,
+            volume_stats: legacy.volume_stats,
+            trade_stats: legacy.trade_stats,
+            long_positions: legacy.long_positions,
+            short_positions: legacy.short_positions,
+            borrow_rate_state: legacy.borrow_rate_state,
+            bump: legacy.bump,
+            token_account_bump: legacy.token_account_bump,
+            ..Custody::default()
+        };
+        serialize_account(&custody, Custody::LEN)
+    }
+}
+
+impl Versioned for Position {
+    const CURRENT_VERSION: u8 = 1;
+
+    fn is_legacy(data: &[u8]) -> bool {
+        data.len() == LegacyPosition::LEN
+    }
+
+    fn migrate_from(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+        require_eq!(version, 0, PerpetualsError::UnsupportedAccountVersion);
+        let legacy = deserialize_legacy::<LegacyPosition>(data)?;
+        let position = Position {
+            version: 1,
+            owner: legacy.owner,
+            pool: legacy.pool,
+            custody: legacy.custody,
+            collateral_custody: legacy.collateral_custody,
+            open_time: legacy.open_time,
+            update_time: legacy.update_time,
+            side: legacy.side,
+            price: legacy.price,
+            size_usd: legacy.size_usd,
+            borrow_size_usd: legacy.borrow_size_usd,
+            collateral_usd: legacy.collateral_usd,
+            unrealized_profit_usd: legacy.unrealized_profit_usd,
+            unrealized_loss_usd: legacy.unrealized_loss_usd,
+            cumulative_interest_snapshot: legacy.cumulative_interest_snapshot,
+            locked_amount: legacy.locked_amount,
+            collateral_amount: legacy.collateral_amount,
+            // legacy positions were paid for by the owner
+            rent_payer: legacy.owner,
+            bump: legacy.bump,
+            ..Position::default()
+        };
+        serialize_account(&position, Position::LEN)
+    }
+}
+
+pub fn get_account_version<T: Versioned>(data: &[u8]) -> Result<u8> {
+    if T::is_legacy(data) {
+        return Ok(0);
+    }
+    data.get(8)
+        .copied()
+        .ok_or_else(|| ProgramError::AccountDataTooSmall.into())
+}
+
+// Runs the migration chain up to the current version, returns None if the data
+// is up to date
+pub fn migrate_data<T: Versioned>(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    T::validate_data(data)?;
+    let mut version = get_account_version::<T>(data)?;
+    require!(
+        version <= T::CURRENT_VERSION,
+        PerpetualsError::UnsupportedAccountVersion
+    );
+    if version == T::CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    let mut data = data.to_vec();
+    while version < T::CURRENT_VERSION {
+        data = T::migrate_from(version, &data)?;
+        version += 1;
+        require!(
+            get_account_version::<T>(&data)? == version,
+            PerpetualsError::UnsupportedAccountVersion
+        );
+    }
+
+    Ok(Some(data))
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, anchor_lang::Discriminator};
+
+    struct TestAccount;
+
+    impl Versioned for TestAccount {
+        const CURRENT_VERSION: u8 = 3;
+
+        // legacy accounts have no version byte and a single u8 field
+        fn is_legacy(data: &[u8]) -> bool {
+            data.len() == 9
+        }
+
+        // each version appends a new u8 field
+        fn migrate_from(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+            let mut data = data.to_vec();
+            if version == 0 {
+                data.insert(8, 0);
+            }
+            data[8] = version + 1;
+            data.push(version * 10);
+            Ok(data)
+        }
+    }
+
+    fn get_legacy_data<T: AnchorSerialize>(discriminator: [u8; 8], account: &T) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        data.extend_from_slice(&account.try_to_vec().unwrap());
+        data
+    }
+
+    #[test]
+    fn test_migrate_data() {
+        let data = [0u8, 0, 0, 0, 0, 0, 0, 0, 7];
+        assert_eq!(
+            migrate_data::<TestAccount>(&data).unwrap(),
+            Some(vec![0, 0, 0, 0, 0, 0, 0, 0, 3, 7, 0, 10, 20])
+        );
+
+        let data = [0u8, 0, 0, 0, 0, 0, 0, 0, 1, 7];
+        assert_eq!(
+            migrate_data::<TestAccount>(&data).unwrap(),
+            Some(vec![0, 0, 0, 0, 0, 0, 0, 0, 3, 7, 10, 20])
+        );
+
+        let data = [0u8, 0, 0, 0, 0, 0, 0, 0, 3, 7, 10, 20];
+        assert_eq!(migrate_data::<TestAccount>(&data).unwrap(), None);
+
+        let data = [0u8, 0, 0, 0, 0, 0, 0, 0, 4, 7];
+        assert!(migrate_data::<TestAccount>(&data).is_err());
+        assert!(migrate_data::<Position>(&data).is_err());
+        assert!(migrate_data::<TestAccount>(&data[..8]).is_err());
+    }
+
+    #[test]
+    fn test_migrate_legacy_position() {
+        // the first byte of the owner key would be read as version 1
+        let legacy = LegacyPosition {
+            owner: Pubkey::new_from_array([1; 32]),
+            pool: Pubkey::new_unique(),
+            side: Side::Short,
+            size_usd: 1_000,
+            collateral_amount: 50,
+            bump: 255,
+            ..LegacyPosition::default()
+        };
+        let mut data = get_legacy_data(Position::discriminator(), &legacy);
+        data.resize(LegacyPosition::LEN, 0);
+
+        let migrated = migrate_data::<Position>(&data).unwrap().unwrap();
+        assert_eq!(migrated.len(), Position::LEN);
+        let position = Position::try_deserialize(&mut migrated.as_slice()).unwrap();
+        assert_eq!(position.version, Position::CURRENT_VERSION);
+        assert_eq!(position.owner, legacy.owner);
+        assert_eq!(position.pool, legacy.pool);
+        assert_eq!(position.side, Side::Short);
+        assert_eq!(position.size_usd, 1_000);
+        assert_eq!(position.collateral_amount, 50);
+        assert_eq!(position.rent_payer, legacy.owner);
+        assert_eq!(position.bump, 255);
+        assert_eq!(migrate_data::<Position>(&migrated).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_legacy_pool() {
+        let legacy = LegacyPool {
+            name: "test pool".to_string(),
+            custodies: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            ratios: vec![
+                TokenRatios {
+                    target: 5_000,
+                    min: 0,
+                    max: 10_000
+                };
+                2
+            ],
+            aum_usd: 1_000_000,
+            bump: 254,
+            lp_token_bump: 253,
+            inception_time: 1,
+        };
+        let mut data = get_legacy_data(Pool::discriminator(), &legacy);
+        data.resize(LegacyPool::get_len(2), 0);
+
+        let migrated = migrate_data::<Pool>(&data).unwrap().unwrap();
+        let pool = Pool::try_deserialize(&mut migrated.as_slice()).unwrap();
+        assert_eq!(pool.version, Pool::CURRENT_VERSION);
+        assert_eq!(pool.name, legacy.name);
+        assert_eq!(pool.custodies, legacy.custodies);
+        assert_eq!(pool.ratios, legacy.ratios);
+        assert_eq!(pool.aum_usd, 1_000_000);
+        assert_eq!(pool.lp_token_bump, 253);
+        assert_eq!(pool.inception_time, 1);
+        assert_eq!(migrate_data::<Pool>(&migrated).unwrap(), None);
+
+        // the length has to match the custody count
+        data.push(0);
+        assert!(migrate_data::<Pool>(&data).is_err());
+    }
+
+    fn get_custody_token_account(pool: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"custody_token_account", pool.as_ref(), mint.as_ref()],
+            &crate::ID,
+        )
+    }
+
+    #[test]
+    fn test_migrate_legacy_custody() {
+        let pool = Pubkey::new_from_array([1; 32]);
+        let mint = Pubkey::new_unique();
+        let (token_account, token_account_bump) = get_custody_token_account(&pool, &mint);
+        let mut legacy = LegacyCustody {
+            pool,
+            mint,
+            token_account,
+            decimals: 9,
+            is_stable: true,
+            bump: 255,
+            token_account_bump,
+            ..LegacyCustody::default()
+        };
+        legacy.oracle.max_price_age_sec = 60;
+        legacy.assets.owned = 1_000;
+        let mut data = get_legacy_data(Custody::discriminator(), &legacy);
+        data.resize(LegacyCustody::LEN, 0);
+
+        let migrated = migrate_data::<Custody>(&data).unwrap().unwrap();
+        assert_eq!(migrated.len(), Custody::LEN);
+        let custody = Custody::try_deserialize(&mut migrated.as_slice()).unwrap();
+        assert_eq!(custody.version, Custody::CURRENT_VERSION);
+        assert_eq!(custody.pool, legacy.pool);
+        assert_eq!(custody.decimals, 9);
+        assert!(custody.is_stable);
+        assert_eq!(custody.oracle.max_price_age_sec, 60);
+        assert_eq!(custody.oracle.max_reducing_price_age_sec, 60);
+        assert_eq!(custody.assets.owned, 1_000);
+        assert_eq!(custody.bump, 255);
+        assert_eq!(custody.token_account_bump, token_account_bump);
+        assert_eq!(migrate_data::<Custody>(&migrated).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_deprecated_custody() {
+        let pool = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (token_account, token_account_bump) = get_custody_token_account(&pool, &mint);
+        let mut deprecated = DeprecatedCustody {
+            pool,
+            mint,
+            token_account,
+            decimals: 6,
+            is_stable: true,
+            bump: 253,
+            token_account_bump,
+            ..DeprecatedCustody::default()
+        };
+        deprecated.oracle.max_price_age_sec = 60;
+        deprecated.assets.owned = 1_000;
+        let mut data = get_legacy_data(Custody::discriminator(), &deprecated);
+        data.resize(DeprecatedCustody::LEN, 0);
+
+        // same length as a legacy custody, but it has to go through upgrade_custody
+        assert_eq!(data.len(), LegacyCustody::LEN);
+        assert!(!Custody::is_legacy(&data));
+        assert_eq!(
+            migrate_data::<Custody>(&data).unwrap_err(),
+            PerpetualsError::DeprecatedCustodyUpgradeRequired.into()
+        );
+
+        // the same fields in the legacy layout are migrated
+        let legacy = LegacyCustody {
+            pool,
+            mint,
+            token_account,
+            decimals: 6,
+            is_stable: true,
+            bump: 253,
+            token_account_bump,
+            ..LegacyCustody::default()
+        };
+        let mut data = get_legacy_data(Custody::discriminator(), &legacy);
+        data.resize(LegacyCustody::LEN, 0);
+        assert!(Custody::is_legacy(&data));
+        assert!(migrate_data::<Custody>(&data).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_migrate_legacy_perpetuals() {
+        let legacy = LegacyPerpetuals {
+            pools: vec![Pubkey::new_unique()],
+            perpetuals_bump: 252,
+            inception_time: 2,
+            ..LegacyPerpetuals::default()
+        };
+        let mut data = get_legacy_data(Perpetuals::discriminator(), &legacy);
+        data.resize(LegacyPerpetuals::get_len(1), 0);
+
+        let migrated = migrate_data::<Perpetuals>(&data).unwrap().unwrap();
+        let perpetuals = Perpetuals::try_deserialize(&mut migrated.as_slice()).unwrap();
+        assert_eq!(perpetuals.version, Perpetuals::CURRENT_VERSION);
+        assert_eq!(perpetuals.pools, legacy.pools);
+        assert_eq!(perpetuals.perpetuals_bump, 252);
+        assert_eq!(perpetuals.inception_time, 2);
+        assert_eq!(migrate_data::<Perpetuals>(&migrated).unwrap(), None);
+    }
+
+    #[test]
+    fn test_legacy_len() {
+        // new layouts can't be mistaken for legacy ones
+        assert_ne!(LegacyCustody::LEN, Custody::LEN);
+        assert_ne!(LegacyPosition::LEN, Position::LEN);
+        assert!(!Custody::is_legacy(&vec![0; Custody::LEN]));
+        assert!(!Position::is_legacy(&vec![0; Position::LEN]));
+    }
+}
@@ -36,6 +36,15 @@ pub enum AdminInstruction {
     SetCustomOraclePrice,
     SetTestTime,
     UpgradeCustody,
+    SetPoolConfig,
+    AddTranche,
+    SetTargetRatios,
+    MigrateAccount,
+    SetTradingSchedule,
+    SetCircuitBreaker,
+    SetDerivedOracle,
+    SetBorrowRateModel,
+    AddDatedMarket,
 }
 
 impl Multisig {
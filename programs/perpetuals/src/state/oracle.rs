@@ -15,6 +15,8 @@ pub enum OracleType {
     None,
     Custom,
     Pyth,
+    Derived,
+    Switchboard,
 }
 
 impl Default for OracleType {
@@ -27,6 +29,8 @@ impl Default for OracleType {
 pub struct OraclePrice {
     pub price: u64,
     pub exponent: i32,
+    // confidence interval reported by the oracle, same exponent as the price
+    pub conf: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -36,9 +40,30 @@ pub struct OracleParams {
     // The oracle_authority pubkey is allowed to sign permissionless off-chain price updates.
     pub oracle_authority: Pubkey,
     pub max_price_error: u64,
+    // max price age for risk increasing actions
+    pub max_price_age_sec: u32,
+    // max price age for risk reducing actions, lower values fall back to max_price_age_sec
+    pub max_reducing_price_age_sec: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedOracleParams {
+    pub oracle_account: Pubkey,
+    pub oracle_type: OracleType,
+    pub oracle_authority: Pubkey,
+    pub max_price_error: u64,
     pub max_price_age_sec: u32,
 }
 
+// Risk profile of the action an oracle price is used for
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ActionClass {
+    // opening and increasing positions, removing collateral, adding liquidity, swaps
+    Increase,
+    // closing positions, adding collateral, removing liquidity, liquidations
+    Reduce,
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct CustomOracle {
@@ -61,6 +86,257 @@ impl CustomOracle {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum DerivedOperation {
+    // base * quote
+    Multiply,
+    // base / quote
+    Divide,
+    // 1 / base, quote is not used
+    Invert,
+}
+
+impl Default for DerivedOperation {
+    fn default() -> Self {
+        Self::Multiply
+    }
+}
+
+// Cross-rate price built from two oracle feeds, e.g. EUR/JPY = EUR/USD / JPY/USD.
+// The price is computed by the permissionless update_derived_oracle instruction and
+// read like a custom oracle.
+#[account]
+#[derive(Default, Debug)]
+pub struct DerivedOracle {
+    // legs are validated with their own max_price_error and max_price_age_sec
+    pub base_oracle: OracleParams,
+    pub quote_oracle: OracleParams,
+    pub operation: DerivedOperation,
+    pub price: u64,
+    pub expo: i32,
+    // combined confidence of both legs
+    pub conf: u64,
+    pub ema: u64,
+    // publish time of the older leg
+    pub publish_time: i64,
+    pub bump: u8,
+}
+
+impl DerivedOracle {
+    pub const LEN: usize = 8 + std::mem::size_of::<DerivedOracle>();
+
+    pub fn validate(&self) -> bool {
+        let is_valid_leg = |leg: &OracleParams| {
+            (leg.oracle_type == OracleType::Custom
+                || leg.oracle_type == OracleType::Pyth
+                || leg.oracle_type == OracleType::Switchboard)
+                && leg.oracle_account != Pubkey::default()
+        };
+        is_valid_leg(&self.base_oracle)
+            && (self.operation == DerivedOperation::Invert || is_valid_leg(&self.quote_oracle))
+    }
+
+    // Recomputes the price from the oracle legs
+    pub fn update(
+        &mut self,
+        base_account: &AccountInfo,
+        quote_account: Option<&AccountInfo>,
+        current_time: i64,
+    ) -> Result<()> {
+        require_keys_eq!(
+            base_account.key(),
+            self.base_oracle.oracle_account,
+            PerpetualsError::InvalidOracleAccount
+        );
+        let quote_account = if self.operation == DerivedOperation::Invert {
+            None
+        } else {
+            let quote_account = quote_account.ok_or(PerpetualsError::InvalidOracleAccount)?;
+            require_keys_eq!(
+                quote_account.key(),
+                self.quote_oracle.oracle_account,
+                PerpetualsError::InvalidOracleAccount
+            );
+            Some(quote_account)
+        };
+
+        let price = self.get_leg_price(base_account, quote_account, current_time, false)?;
+        let ema_price = self
+            .get_leg_price(base_account, quote_account, current_time, true)?
+            .scale_to_exponent(price.exponent)?;
+        let mut publish_time = OraclePrice::get_publish_time(base_account, &self.base_oracle)?;
+        if let Some(quote_account) = quote_account {
+            publish_time = std::cmp::min(
+                publish_time,
+                OraclePrice::get_publish_time(quote_account, &self.quote_oracle)?,
+            );
+        }
+
+        self.price = price.price;
+        self.expo = price.exponent;
+        self.conf = price.conf;
+        self.ema = ema_price.price;
+        self.publish_time = publish_time;
+
+        Ok(())
+    }
+
+    fn get_leg_price(
+        &self,
+        base_account: &AccountInfo,
+        quote_account: Option<&AccountInfo>,
+        current_time: i64,
+        use_ema: bool,
+    ) -> Result<OraclePrice> {
+        let base_price = OraclePrice::new_from_oracle(
+            base_account,
+            &self.base_oracle,
+            current_time,
+            use_ema,
+            ActionClass::Increase,
+        )?;
+        let Some(quote_account) = quote_account else {
+            return OraclePrice::new(ORACLE_PRICE_SCALE, ORACLE_EXPONENT_SCALE)
+                .checked_div(&base_price);
+        };
+        let quote_price = OraclePrice::new_from_oracle(
+            quote_account,
+            &self.quote_oracle,
+            current_time,
+            use_ema,
+            ActionClass::Increase,
+        )?;
+
+        if self.operation == DerivedOperation::Multiply {
+            base_price
+                .normalize()?
+                .checked_mul(&quote_price.normalize()?)
+        } else {
+            base_price.checked_div(&quote_price)
+        }
+    }
+}
+
+// Switchboard On-Demand PullFeedAccountData fields used for pricing. The account
+// is decoded at fixed offsets, values have SWITCHBOARD_DECIMALS decimals.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SwitchboardPullFeed {
+    pub value: i128,
+    pub std_dev: i128,
+    pub last_update_timestamp: i64,
+    // means of the historical results, oldest first
+    pub historical_means: [f32; SwitchboardPullFeed::HISTORY_LEN],
+    pub num_historical: usize,
+}
+
+impl SwitchboardPullFeed {
+    pub const DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+    pub const LEN: usize = 3208;
+    pub const HISTORY_LEN: usize = 32;
+    pub const SWITCHBOARD_DECIMALS: u8 = 18;
+
+    // offsets in account data, including the discriminator
+    const HISTORICAL_RESULT_IDX_OFFSET: usize = 2214;
+    const LAST_UPDATE_TIMESTAMP_OFFSET: usize = 2216;
+    const RESULT_VALUE_OFFSET: usize = 2264;
+    const RESULT_STD_DEV_OFFSET: usize = 2280;
+    const HISTORICAL_RESULTS_OFFSET: usize = 2408;
+    // CompactResult { std_dev: f32, mean: f32, slot: u64 }
+    const COMPACT_RESULT_LEN: usize = 16;
+
+    pub fn load(account_info: &AccountInfo) -> Result<Self> {
+        let data = account_info.try_borrow_data()?;
+        require!(
+            data.len() >= Self::LEN && data[..8] == Self::DISCRIMINATOR,
+            PerpetualsError::InvalidOracleAccount
+        );
+
+        let read_i128 =
+            |offset: usize| i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap());
+
+        // historical results are a ring buffer, the oldest entry is at the write index
+        let mut historical_means = [0.0; Self::HISTORY_LEN];
+        let mut num_historical = 0;
+        let start_idx = data[Self::HISTORICAL_RESULT_IDX_OFFSET] as usize;
+        for i in 0..Self::HISTORY_LEN {
+            let offset = Self::HISTORICAL_RESULTS_OFFSET
+                + ((start_idx + i) % Self::HISTORY_LEN) * Self::COMPACT_RESULT_LEN;
+            let mean = f32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap());
+            let slot = u64::from_le_bytes(data[offset + 8..offset + 16].try_into().unwrap());
+            if slot != 0 && mean.is_finite() && mean > 0.0 {
+                historical_means[num_historical] = mean;
+                num_historical += 1;
+            }
+        }
+
+        Ok(Self {
+            value: read_i128(Self::RESULT_VALUE_OFFSET),
+            std_dev: read_i128(Self::RESULT_STD_DEV_OFFSET),
+            last_update_timestamp: i64::from_le_bytes(
+                data[Self::LAST_UPDATE_TIMESTAMP_OFFSET..Self::LAST_UPDATE_TIMESTAMP_OFFSET + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+            historical_means,
+            num_historical,
+        })
+    }
+
+    // Returns the latest value with ORACLE_EXPONENT_SCALE exponent
+    pub fn get_price(&self) -> Result<u64> {
+        Self::scale_value(self.value)
+    }
+
+    pub fn get_conf(&self) -> Result<u64> {
+        Self::scale_value(self.std_dev)
+    }
+
+    // Switchboard feeds have no ema, it is derived from the historical results
+    // and the latest value with smoothing factor 2 / (n + 1)
+    pub fn get_ema_price(&self) -> Result<u64> {
+        let price = self.get_price()?;
+        if price == 0 || self.num_historical == 0 {
+            return Ok(price);
+        }
+        let num_samples = math::checked_add(self.num_historical as u128, 1)?;
+        let mut ema: u128 = 0;
+        for (i, mean) in self.historical_means[..self.num_historical]
+            .iter()
+            .enumerate()
+        {
+            let sample = math::to_token_amount(*mean as f64, -ORACLE_EXPONENT_SCALE as u8)?;
+            ema = Self::get_next_ema(ema, sample as u128, i == 0, num_samples)?;
+        }
+        math::checked_as_u64(Self::get_next_ema(ema, price as u128, false, num_samples)?)
+    }
+
+    fn get_next_ema(ema: u128, sample: u128, is_first: bool, num_samples: u128) -> Result<u128> {
+        if is_first {
+            return Ok(sample);
+        }
+        math::checked_div(
+            math::checked_add(
+                math::checked_mul(ema, math::checked_sub(num_samples, 1)?)?,
+                math::checked_mul(sample, 2)?,
+            )?,
+            math::checked_add(num_samples, 1)?,
+        )
+    }
+
+    fn scale_value(value: i128) -> Result<u64> {
+        if value <= 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            value as u128,
+            math::checked_pow(
+                10u128,
+                (Self::SWITCHBOARD_DECIMALS as i32 + ORACLE_EXPONENT_SCALE) as usize,
+            )?,
+        )?)
+    }
+}
+
 impl PartialOrd for OraclePrice {
     fn partial_cmp(&self, other: &OraclePrice) -> Option<Ordering> {
         let (lhs, rhs) = if self.exponent == other.exponent {
@@ -83,13 +359,18 @@ impl PartialOrd for OraclePrice {
 #[allow(dead_code)]
 impl OraclePrice {
     pub fn new(price: u64, exponent: i32) -> Self {
-        Self { price, exponent }
+        Self {
+            price,
+            exponent,
+            conf: 0,
+        }
     }
 
     pub fn new_from_token(amount_and_decimals: (u64, u8)) -> Self {
         Self {
             price: amount_and_decimals.0,
             exponent: -(amount_and_decimals.1 as i32),
+            conf: 0,
         }
     }
 
@@ -98,19 +379,35 @@ impl OraclePrice {
         oracle_params: &OracleParams,
         current_time: i64,
         use_ema: bool,
+        action: ActionClass,
     ) -> Result<Self> {
+        let max_price_age_sec = oracle_params.get_max_price_age(action);
         match oracle_params.oracle_type {
             OracleType::Custom => Self::get_custom_price(
                 oracle_account,
                 oracle_params.max_price_error,
-                oracle_params.max_price_age_sec,
+                max_price_age_sec,
                 current_time,
                 use_ema,
             ),
             OracleType::Pyth => Self::get_pyth_price(
                 oracle_account,
                 oracle_params.max_price_error,
-                oracle_params.max_price_age_sec,
+                max_price_age_sec,
+                current_time,
+                use_ema,
+            ),
+            OracleType::Derived => Self::get_derived_price(
+                oracle_account,
+                oracle_params.max_price_error,
+                max_price_age_sec,
+                current_time,
+                use_ema,
+            ),
+            OracleType::Switchboard => Self::get_switchboard_price(
+                oracle_account,
+                oracle_params.max_price_error,
+                max_price_age_sec,
                 current_time,
                 use_ema,
             ),
@@ -118,6 +415,34 @@ impl OraclePrice {
         }
     }
 
+    // Returns the time the current oracle price was published
+    pub fn get_publish_time(
+        oracle_account: &AccountInfo,
+        oracle_params: &OracleParams,
+    ) -> Result<i64> {
+        require!(
+            !Perpetuals::is_empty_account(oracle_account)?,
+            PerpetualsError::InvalidOracleAccount
+        );
+        match oracle_params.oracle_type {
+            OracleType::Custom => {
+                Ok(Account::<CustomOracle>::try_from(oracle_account)?.publish_time)
+            }
+            OracleType::Pyth => {
+                let price_feed = pyth_sdk_solana::load_price_feed_from_account_info(oracle_account)
+                    .map_err(|_| PerpetualsError::InvalidOracleAccount)?;
+                Ok(price_feed.get_price_unchecked().publish_time)
+            }
+            OracleType::Derived => {
+                Ok(Account::<DerivedOracle>::try_from(oracle_account)?.publish_time)
+            }
+            OracleType::Switchboard => {
+                Ok(SwitchboardPullFeed::load(oracle_account)?.last_update_timestamp)
+            }
+            _ => err!(PerpetualsError::UnsupportedOracle),
+        }
+    }
+
     // Converts token amount to USD with implied USD_DECIMALS decimals using oracle price
     pub fn get_asset_amount_usd(&self, token_amount: u64, token_decimals: u8) -> Result<u64> {
         if token_amount == 0 || self.price == 0 {
@@ -146,19 +471,33 @@ impl OraclePrice {
         )
     }
 
+    // Returns the smallest token amount that is worth at least the given USD amount
+    pub fn get_min_token_amount(&self, asset_amount_usd: u64, token_decimals: u8) -> Result<u64> {
+        math::checked_decimal_mul_inverse(
+            asset_amount_usd,
+            -(token_decimals as i32),
+            self.price,
+            self.exponent,
+            -(Perpetuals::USD_DECIMALS as i32),
+        )
+    }
+
     /// Returns price with mantissa normalized to be less than ORACLE_MAX_PRICE
     pub fn normalize(&self) -> Result<OraclePrice> {
         let mut p = self.price;
         let mut e = self.exponent;
+        let mut c = self.conf;
 
         while p > ORACLE_MAX_PRICE {
             p = math::checked_div(p, 10)?;
             e = math::checked_add(e, 1)?;
+            c = math::checked_div(c, 10)?;
         }
 
         Ok(OraclePrice {
             price: p,
             exponent: e,
+            conf: c,
         })
     }
 
@@ -166,43 +505,82 @@ impl OraclePrice {
         let base = self.normalize()?;
         let other = other.normalize()?;
 
+        let price = math::checked_div(
+            math::checked_mul(base.price, ORACLE_PRICE_SCALE)?,
+            other.price,
+        )?;
+
         Ok(OraclePrice {
-            price: math::checked_div(
-                math::checked_mul(base.price, ORACLE_PRICE_SCALE)?,
-                other.price,
-            )?,
+            price,
             exponent: math::checked_sub(
                 math::checked_add(base.exponent, ORACLE_EXPONENT_SCALE)?,
                 other.exponent,
             )?,
+            conf: base.get_combined_conf(&other, price)?,
         })
     }
 
     pub fn checked_mul(&self, other: &OraclePrice) -> Result<OraclePrice> {
+        let price = math::checked_mul(self.price, other.price)?;
+
         Ok(OraclePrice {
-            price: math::checked_mul(self.price, other.price)?,
+            price,
             exponent: math::checked_add(self.exponent, other.exponent)?,
+            conf: self.get_combined_conf(other, price)?,
         })
     }
 
+    // Returns the confidence of a product or quotient of two prices with the given
+    // mantissa, relative confidence intervals add up
+    fn get_combined_conf(&self, other: &OraclePrice, price: u64) -> Result<u64> {
+        let mut conf: u128 = 0;
+        for leg in [self, other] {
+            if leg.price > 0 && leg.conf > 0 {
+                conf = math::checked_add(
+                    conf,
+                    math::checked_div(
+                        math::checked_mul(price as u128, leg.conf as u128)?,
+                        leg.price as u128,
+                    )?,
+                )?;
+            }
+        }
+        math::checked_as_u64(conf)
+    }
+
     pub fn scale_to_exponent(&self, target_exponent: i32) -> Result<OraclePrice> {
         if target_exponent == self.exponent {
             return Ok(*self);
         }
         let delta = math::checked_sub(target_exponent, self.exponent)?;
         if delta > 0 {
+            let scale = math::checked_pow(10, delta as usize)?;
             Ok(OraclePrice {
-                price: math::checked_div(self.price, math::checked_pow(10, delta as usize)?)?,
+                price: math::checked_div(self.price, scale)?,
                 exponent: target_exponent,
+                conf: math::checked_div(self.conf, scale)?,
             })
         } else {
+            let scale = math::checked_pow(10, (-delta) as usize)?;
             Ok(OraclePrice {
-                price: math::checked_mul(self.price, math::checked_pow(10, (-delta) as usize)?)?,
+                price: math::checked_mul(self.price, scale)?,
                 exponent: target_exponent,
+                conf: math::checked_mul(self.conf, scale)?,
             })
         }
     }
 
+    // Returns the confidence interval relative to the price in BPS
+    pub fn get_conf_ratio(&self) -> Result<u64> {
+        if self.price == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.conf as u128, Perpetuals::BPS_POWER)?,
+            self.price as u128,
+        )?)
+    }
+
     pub fn checked_as_f64(&self) -> Result<f64> {
         math::checked_float_mul(
             math::checked_as_f64(self.price)?,
@@ -220,6 +598,7 @@ impl OraclePrice {
                     return Ok(OraclePrice {
                         price: 1000000u64,
                         exponent: -6,
+                        conf: 0,
                     });
                 }
             }
@@ -228,6 +607,7 @@ impl OraclePrice {
                 Ok(OraclePrice {
                     price: one_usd,
                     exponent: min_price.exponent,
+                    conf: min_price.conf,
                 })
             } else {
                 Ok(*min_price)
@@ -277,6 +657,92 @@ impl OraclePrice {
             // price is i64 and > 0 per check above
             price,
             exponent: oracle_acc.expo,
+            conf: oracle_acc.conf,
+        })
+    }
+
+    fn get_derived_price(
+        derived_price_info: &AccountInfo,
+        max_price_error: u64,
+        max_price_age_sec: u32,
+        current_time: i64,
+        use_ema: bool,
+    ) -> Result<OraclePrice> {
+        require!(
+            !Perpetuals::is_empty_account(derived_price_info)?,
+            PerpetualsError::InvalidOracleAccount
+        );
+
+        let oracle_acc = Account::<DerivedOracle>::try_from(derived_price_info)?;
+
+        let last_update_age_sec = math::checked_sub(current_time, oracle_acc.publish_time)?;
+        if last_update_age_sec > max_price_age_sec as i64 {
+            msg!("Error: Derived oracle price is stale");
+            return err!(PerpetualsError::StaleOraclePrice);
+        }
+        let price = if use_ema {
+            oracle_acc.ema
+        } else {
+            oracle_acc.price
+        };
+
+        if price == 0
+            || math::checked_div(
+                math::checked_mul(oracle_acc.conf as u128, Perpetuals::BPS_POWER)?,
+                price as u128,
+            )? > max_price_error as u128
+        {
+            msg!("Error: Derived oracle price is out of bounds");
+            return err!(PerpetualsError::InvalidOraclePrice);
+        }
+
+        Ok(OraclePrice {
+            price,
+            exponent: oracle_acc.expo,
+            conf: oracle_acc.conf,
+        })
+    }
+
+    fn get_switchboard_price(
+        switchboard_price_info: &AccountInfo,
+        max_price_error: u64,
+        max_price_age_sec: u32,
+        current_time: i64,
+        use_ema: bool,
+    ) -> Result<OraclePrice> {
+        require!(
+            !Perpetuals::is_empty_account(switchboard_price_info)?,
+            PerpetualsError::InvalidOracleAccount
+        );
+        let pull_feed = SwitchboardPullFeed::load(switchboard_price_info)?;
+
+        let last_update_age_sec = math::checked_sub(current_time, pull_feed.last_update_timestamp)?;
+        if last_update_age_sec > max_price_age_sec as i64 {
+            msg!("Error: Switchboard oracle price is stale");
+            return err!(PerpetualsError::StaleOraclePrice);
+        }
+        let price = if use_ema {
+            pull_feed.get_ema_price()?
+        } else {
+            pull_feed.get_price()?
+        };
+        let conf = pull_feed.get_conf()?;
+
+        // std_dev is used as the confidence interval
+        if price == 0
+            || math::checked_div(
+                math::checked_mul(conf as u128, Perpetuals::BPS_POWER)?,
+                price as u128,
+            )? > max_price_error as u128
+        {
+            msg!("Error: Switchboard oracle price is out of bounds");
+            return err!(PerpetualsError::InvalidOraclePrice);
+        }
+
+        Ok(OraclePrice {
+            price,
+            exponent: ORACLE_EXPONENT_SCALE,
+            conf,
         })
     }
 
@@ -319,6 +785,7 @@ impl OraclePrice {
             // price is i64 and > 0 per check above
             price: pyth_price.price as u64,
             exponent: pyth_price.expo,
+            conf: pyth_price.conf,
         })
     }
 }
@@ -351,4 +818,351 @@ mod test {
         assert_eq!(1, scaled.price);
         assert_eq!(1, scaled.exponent);
     }
+
+    #[test]
+    fn test_combined_conf() {
+        let price1 = OraclePrice {
+            price: 10000,
+            exponent: -4,
+            conf: 10,
+        };
+        let price2 = OraclePrice {
+            price: 20000,
+            exponent: -4,
+            conf: 40,
+        };
+
+        // 0.1% + 0.2%
+        let product = price1.checked_mul(&price2).unwrap();
+        assert_eq!(200_000_000, product.price);
+        assert_eq!(-8, product.exponent);
+        assert_eq!(600_000, product.conf);
+
+        let quotient = price1.checked_div(&price2).unwrap();
+        assert_eq!(500_000_000, quotient.price);
+        assert_eq!(-9, quotient.exponent);
+        assert_eq!(1_500_000, quotient.conf);
+    }
+
+    fn get_custom_oracle_data(price: u64, conf: u64, ema: u64, publish_time: i64) -> Vec<u8> {
+        let mut data = Vec::new();
+        CustomOracle {
+            price,
+            expo: -8,
+            conf,
+            ema,
+            publish_time,
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        data
+    }
+
+    fn get_leg_params(oracle_account: Pubkey) -> OracleParams {
+        OracleParams {
+            oracle_account,
+            oracle_type: OracleType::Custom,
+            oracle_authority: Pubkey::default(),
+            max_price_error: 100,
+            max_price_age_sec: 60,
+            max_reducing_price_age_sec: 60,
+        }
+    }
+
+    #[test]
+    fn test_derived_oracle() {
+        let owner = crate::ID;
+        // EUR/USD 1.10 and JPY/USD 0.0067, both with 0.1% confidence
+        let base_key = Pubkey::new_unique();
+        let mut base_lamports = 1;
+        let mut base_data = get_custom_oracle_data(110_000_000, 110_000, 108_000_000, 1000);
+        let base_account = AccountInfo::new(
+            &base_key,
+            false,
+            false,
+            &mut base_lamports,
+            &mut base_data,
+            &owner,
+            false,
+            0,
+        );
+        let quote_key = Pubkey::new_unique();
+        let mut quote_lamports = 1;
+        let mut quote_data = get_custom_oracle_data(670_000, 670, 670_000, 990);
+        let quote_account = AccountInfo::new(
+            &quote_key,
+            false,
+            false,
+            &mut quote_lamports,
+            &mut quote_data,
+            &owner,
+            false,
+            0,
+        );
+
+        let mut oracle = DerivedOracle {
+            base_oracle: get_leg_params(base_key),
+            quote_oracle: get_leg_params(quote_key),
+            operation: DerivedOperation::Divide,
+            ..Default::default()
+        };
+        assert!(oracle.validate());
+
+        // EUR/JPY, staleness is taken from the older leg
+        oracle
+            .update(&base_account, Some(&quote_account), 1000)
+            .unwrap();
+        assert_eq!(164_179_104_477, oracle.price);
+        assert_eq!(-9, oracle.expo);
+        assert_eq!(328_358_208, oracle.conf);
+        assert_eq!(161_194_029_850, oracle.ema);
+        assert_eq!(990, oracle.publish_time);
+
+        // each leg is checked against its own max age and error
+        assert!(oracle
+            .update(&base_account, Some(&quote_account), 1055)
+            .is_err());
+        oracle.quote_oracle.max_price_age_sec = 70;
+        oracle.quote_oracle.max_reducing_price_age_sec = 70;
+        assert!(oracle
+            .update(&base_account, Some(&quote_account), 1055)
+            .is_ok());
+        oracle.base_oracle.max_price_error = 5;
+        assert!(oracle
+            .update(&base_account, Some(&quote_account), 1000)
+            .is_err());
+        oracle.base_oracle.max_price_error = 100;
+
+        // legs must match the config
+        assert!(oracle.update(&base_account, None, 1000).is_err());
+        assert!(oracle
+            .update(&quote_account, Some(&base_account), 1000)
+            .is_err());
+
+        // quote leg is not used by Invert
+        oracle.operation = DerivedOperation::Invert;
+        oracle.quote_oracle = OracleParams::default();
+        assert!(oracle.validate());
+        oracle.update(&base_account, None, 1000).unwrap();
+        assert_eq!(909_090_909, oracle.price);
+        assert_eq!(-9, oracle.expo);
+        assert_eq!(1000, oracle.publish_time);
+
+        oracle.operation = DerivedOperation::Divide;
+        assert!(!oracle.validate());
+    }
+
+    #[test]
+    fn test_derived_oracle_price() {
+        let owner = crate::ID;
+        let key = Pubkey::new_unique();
+        let mut lamports = 1;
+        let mut data = Vec::new();
+        DerivedOracle {
+            price: 164_179_104_477,
+            expo: -9,
+            conf: 328_358_208,
+            ema: 161_194_029_850,
+            publish_time: 990,
+            ..Default::default()
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let mut params = get_leg_params(key);
+        params.oracle_type = OracleType::Derived;
+
+        let price =
+            OraclePrice::new_from_oracle(&account, &params, 1000, false, ActionClass::Increase)
+                .unwrap();
+        assert_eq!(
+            OraclePrice {
+                price: 164_179_104_477,
+                exponent: -9,
+                conf: 328_358_208,
+            },
+            price
+        );
+        let ema_price =
+            OraclePrice::new_from_oracle(&account, &params, 1000, true, ActionClass::Increase)
+                .unwrap();
+        assert_eq!(161_194_029_850, ema_price.price);
+        assert_eq!(
+            990,
+            OraclePrice::get_publish_time(&account, &params).unwrap()
+        );
+
+        assert!(OraclePrice::new_from_oracle(
+            &account,
+            &params,
+            1060,
+            false,
+            ActionClass::Increase
+        )
+        .is_err());
+    }
+
+    fn get_switchboard_data(
+        value: i128,
+        std_dev: i128,
+        last_update_timestamp: i64,
+        historical_result_idx: u8,
+        historical_means: &[(usize, f32)],
+    ) -> Vec<u8> {
+        let mut data = vec![0u8; SwitchboardPullFeed::LEN];
+        data[..8].copy_from_slice(&SwitchboardPullFeed::DISCRIMINATOR);
+        data[2214] = historical_result_idx;
+        data[2216..2224].copy_from_slice(&last_update_timestamp.to_le_bytes());
+        data[2264..2280].copy_from_slice(&value.to_le_bytes());
+        data[2280..2296].copy_from_slice(&std_dev.to_le_bytes());
+        for (idx, mean) in historical_means {
+            let offset = 2408 + idx * 16;
+            data[offset + 4..offset + 8].copy_from_slice(&mean.to_le_bytes());
+            data[offset + 8..offset + 16].copy_from_slice(&1u64.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_switchboard_price() {
+        let owner = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 1;
+        // 1500.5 +- 1.5, history is 1400, 1420, 1450 starting from the ring index
+        let mut data = get_switchboard_data(
+            1_500_500_000_000_000_000_000,
+            1_500_000_000_000_000_000,
+            1000,
+            5,
+            &[(5, 1400.0), (31, 1420.0), (4, 1450.0)],
+        );
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let mut params = get_leg_params(key);
+        params.oracle_type = OracleType::Switchboard;
+
+        let price =
+            OraclePrice::new_from_oracle(&account, &params, 1030, false, ActionClass::Increase)
+                .unwrap();
+        assert_eq!(
+            OraclePrice {
+                price: 1_500_500_000_000,
+                exponent: -9,
+                conf: 1_500_000_000,
+            },
+            price
+        );
+        assert_eq!(
+            1000,
+            OraclePrice::get_publish_time(&account, &params).unwrap()
+        );
+
+        // ema is derived from the history and the latest value
+        let ema_price =
+            OraclePrice::new_from_oracle(&account, &params, 1030, true, ActionClass::Increase)
+                .unwrap();
+        assert_eq!(1_455_080_000_000, ema_price.price);
+
+        // staleness and std_dev checks
+        assert!(OraclePrice::new_from_oracle(
+            &account,
+            &params,
+            1061,
+            false,
+            ActionClass::Increase
+        )
+        .is_err());
+        params.max_price_error = 5;
+        assert!(OraclePrice::new_from_oracle(
+            &account,
+            &params,
+            1030,
+            false,
+            ActionClass::Increase
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_switchboard_pull_feed() {
+        let owner = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 1;
+
+        // without history the ema falls back to the latest value
+        let mut data = get_switchboard_data(2_000_000_000_000_000_000, 0, 1000, 0, &[]);
+        {
+            let account = AccountInfo::new(
+                &key,
+                false,
+                false,
+                &mut lamports,
+                &mut data,
+                &owner,
+                false,
+                0,
+            );
+            let pull_feed = SwitchboardPullFeed::load(&account).unwrap();
+            assert_eq!(0, pull_feed.num_historical);
+            assert_eq!(2_000_000_000, pull_feed.get_price().unwrap());
+            assert_eq!(2_000_000_000, pull_feed.get_ema_price().unwrap());
+        }
+
+        // negative values are rejected
+        let mut data = get_switchboard_data(-2_000_000_000_000_000_000, 0, 1000, 0, &[]);
+        {
+            let account = AccountInfo::new(
+                &key,
+                false,
+                false,
+                &mut lamports,
+                &mut data,
+                &owner,
+                false,
+                0,
+            );
+            let mut params = get_leg_params(key);
+            params.oracle_type = OracleType::Switchboard;
+            assert!(OraclePrice::new_from_oracle(
+                &account,
+                &params,
+                1000,
+                false,
+                ActionClass::Increase
+            )
+            .is_err());
+        }
+
+        // other accounts are rejected
+        let mut data = get_switchboard_data(2_000_000_000_000_000_000, 0, 1000, 0, &[]);
+        data[0] = 0;
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert!(SwitchboardPullFeed::load(&account).is_err());
+    }
 }
@@ -0,0 +1,57 @@
+use {crate::state::position::Side, anchor_lang::prelude::*};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum OrderType {
+    None,
+    OpenPosition,
+    ClosePosition,
+}
+
+impl Default for OrderType {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct PendingOrder {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    // dated market of the position, default for perpetual positions
+    pub dated_market: Pubkey,
+    // account that paid for the order and position accounts, receives the escrow and
+    // position rent back. The order account rent goes to the keeper that executes the
+    // order as its incentive, or back to the rent payer if the order is cancelled.
+    pub rent_payer: Pubkey,
+    // referral used when the order was requested, default if none
+    pub referral: Pubkey,
+
+    pub order_type: OrderType,
+    pub side: Side,
+    // worst acceptable execution price
+    pub price: u64,
+    // escrowed collateral amount, the entry fee is paid out of it
+    pub collateral: u64,
+    pub size: u64,
+    // position size limit of the requesting trading delegate, u64::MAX for the owner
+    pub max_size_usd: u64,
+    pub request_time: i64,
+
+    pub bump: u8,
+    pub token_account_bump: u8,
+}
+
+impl PendingOrder {
+    pub const LEN: usize = 8 + std::mem::size_of::<PendingOrder>();
+
+    pub fn get_market_seed(&self) -> &[u8] {
+        if self.dated_market == Pubkey::default() {
+            &[]
+        } else {
+            self.dated_market.as_ref()
+        }
+    }
+}
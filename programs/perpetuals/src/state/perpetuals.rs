@@ -7,6 +7,8 @@ use {
 pub struct PriceAndFee {
     pub price: u64,
     pub fee: u64,
+    // spread applied to the price, BPS
+    pub spread: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -20,6 +22,8 @@ pub struct NewPositionPricesAndFee {
     pub entry_price: u64,
     pub liquidation_price: u64,
     pub fee: u64,
+    // spread applied to the entry price, BPS
+    pub spread: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -27,6 +31,17 @@ pub struct SwapAmountAndFees {
     pub amount_out: u64,
     pub fee_in: u64,
     pub fee_out: u64,
+    // spread applied to the swap price, BPS
+    pub spread: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct SwapAmountInAndFees {
+    pub amount_in: u64,
+    pub fee_in: u64,
+    pub fee_out: u64,
+    // spread applied to the swap price, BPS
+    pub spread: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -50,6 +65,8 @@ pub struct Permissions {
 #[account]
 #[derive(Default, Debug)]
 pub struct Perpetuals {
+    // account layout version, see migration::Versioned
+    pub version: u8,
     pub permissions: Permissions,
     pub pools: Vec<Pubkey>,
 
@@ -57,6 +74,9 @@ pub struct Perpetuals {
     pub perpetuals_bump: u8,
     // time of inception, also used as current wall clock time for testing
     pub inception_time: i64,
+
+    // reserved for future fields
+    pub reserved: [u64; 8],
 }
 
 impl anchor_lang::Id for Perpetuals {
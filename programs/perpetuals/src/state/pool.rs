@@ -4,9 +4,10 @@ use {
         math,
         state::{
             custody::{Custody, FeesMode},
-            oracle::OraclePrice,
+            oracle::{ActionClass, OraclePrice},
             perpetuals::Perpetuals,
             position::{Position, Side},
+            tranche::Tranche,
         },
     },
     anchor_lang::prelude::*,
@@ -28,9 +29,46 @@ pub struct TokenRatios {
     pub max: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct AdlParams {
+    // auto-deleveraging is allowed once aggregate unrealized traders profit exceeds
+    // this share of pool assets, has implied BPS_DECIMALS decimals, 0 disables it
+    pub max_profit_to_aum: u64,
+    // only positions with unrealized profit of at least this share of their collateral
+    // can be deleveraged, has implied BPS_DECIMALS decimals
+    pub min_pnl_ratio: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct ReferralParams {
+    // shares of the trading fee left after the protocol share that are credited to the
+    // referrer and rebated to the trader, have implied BPS_DECIMALS decimals
+    pub referrer_share: u64,
+    pub trader_rebate: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct FeeTier {
+    // tier applies to traders with rolling volume >= min_volume_usd (USD_DECIMALS decimals),
+    // unused tiers have zero fee_discount
+    pub min_volume_usd: u64,
+    // discount off trading fees, has implied BPS_DECIMALS decimals
+    pub fee_discount: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct OrderParams {
+    // positions are opened and closed via delayed orders only if max_delay_sec is non-zero,
+    // keepers can execute an order between min_delay_sec and max_delay_sec after the request
+    pub min_delay_sec: u32,
+    pub max_delay_sec: u32,
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Pool {
+    // account layout version, see migration::Versioned
+    pub version: u8,
     pub name: String,
     pub custodies: Vec<Pubkey>,
     pub ratios: Vec<TokenRatios>,
@@ -39,6 +77,20 @@ pub struct Pool {
     pub bump: u8,
     pub lp_token_bump: u8,
     pub inception_time: i64,
+
+    pub adl: AdlParams,
+    pub orders: OrderParams,
+    pub fee_tiers: [FeeTier; Pool::MAX_FEE_TIERS],
+    pub referral: ReferralParams,
+
+    // scheduled re-weighting, effective targets move linearly from ratios[i].target
+    // to next_targets[i] between reweight_start_time and reweight_end_time
+    pub next_targets: Vec<u64>,
+    pub reweight_start_time: i64,
+    pub reweight_end_time: i64,
+
+    // reserved for future fields
+    pub reserved: [u64; 8],
 }
 
 impl TokenRatios {
@@ -51,12 +103,48 @@ impl TokenRatios {
     }
 }
 
+impl AdlParams {
+    pub fn validate(&self) -> bool {
+        (self.max_profit_to_aum as u128) < Perpetuals::BPS_POWER
+            && (self.max_profit_to_aum == 0 || self.min_pnl_ratio > 0)
+    }
+}
+
+impl ReferralParams {
+    pub fn validate(&self) -> bool {
+        (self.referrer_share as u128 + self.trader_rebate as u128) <= Perpetuals::BPS_POWER
+    }
+}
+
+impl OrderParams {
+    pub fn validate(&self) -> bool {
+        self.min_delay_sec <= self.max_delay_sec
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_delay_sec > 0
+    }
+
+    // Checks if an order requested at request_time can be executed at curtime
+    pub fn is_executable(&self, request_time: i64, curtime: i64) -> Result<bool> {
+        let elapsed = math::checked_sub(curtime, request_time)?;
+        Ok(elapsed >= self.min_delay_sec as i64 && elapsed <= self.max_delay_sec as i64)
+    }
+
+    // Checks if an order requested at request_time can no longer be executed
+    pub fn is_expired(&self, request_time: i64, curtime: i64) -> Result<bool> {
+        Ok(math::checked_sub(curtime, request_time)? > self.max_delay_sec as i64)
+    }
+}
+
 /// Token Pool
 /// All returned prices are scaled to PRICE_DECIMALS.
 /// All returned amounts are scaled to corresponding custody decimals.
 ///
 impl Pool {
     pub const LEN: usize = 8 + 64 + std::mem::size_of::<Pool>();
+    pub const MAX_FEE_TIERS: usize = 6;
+    const MAX_FEE_ITERATIONS: usize = 8;
 
     pub fn validate(&self) -> bool {
         for ratio in &self.ratios {
@@ -77,6 +165,42 @@ impl Pool {
             return false;
         }
 
+        // check scheduled targets are within bounds and add up to 1
+        if !self.next_targets.is_empty()
+            && (self.next_targets.len() != self.ratios.len()
+                || self.reweight_end_time < self.reweight_start_time
+                || self
+                    .next_targets
+                    .iter()
+                    .zip(self.ratios.iter())
+                    .any(|(&target, ratio)| target < ratio.min || target > ratio.max)
+                || self.next_targets.iter().map(|&x| x as u128).sum::<u128>()
+                    != Perpetuals::BPS_POWER)
+        {
+            return false;
+        }
+
+        // check fee tiers are sorted and unused tiers are last
+        let mut prev_tier: Option<&FeeTier> = None;
+        for tier in &self.fee_tiers {
+            if tier.fee_discount == 0 {
+                if *tier != FeeTier::default() {
+                    return false;
+                }
+                prev_tier = Some(tier);
+                continue;
+            }
+            if let Some(prev_tier) = prev_tier {
+                if prev_tier.fee_discount == 0 || tier.min_volume_usd <= prev_tier.min_volume_usd {
+                    return false;
+                }
+            }
+            if (tier.fee_discount as u128) > Perpetuals::BPS_POWER {
+                return false;
+            }
+            prev_tier = Some(tier);
+        }
+
         // check custodies are unique
         for i in 1..self.custodies.len() {
             if self.custodies[i..].contains(&self.custodies[i - 1]) {
@@ -84,7 +208,12 @@ impl Pool {
             }
         }
 
-        !self.name.is_empty() && self.name.len() <= 64 && self.custodies.len() == self.ratios.len()
+        !self.name.is_empty()
+            && self.name.len() <= 64
+            && self.custodies.len() == self.ratios.len()
+            && self.adl.validate()
+            && self.orders.validate()
+            && self.referral.validate()
     }
 
     pub fn get_token_id(&self, custody: &Pubkey) -> Result<usize> {
@@ -94,22 +223,155 @@ impl Pool {
             .ok_or_else(|| PerpetualsError::UnsupportedToken.into())
     }
 
+    // Returns trading fee discount for the given trader rolling volume
+    pub fn get_fee_discount(&self, trader_volume_usd: u64) -> u64 {
+        let mut fee_discount = 0;
+        for tier in &self.fee_tiers {
+            if tier.fee_discount == 0 || trader_volume_usd < tier.min_volume_usd {
+                break;
+            }
+            fee_discount = tier.fee_discount;
+        }
+        fee_discount
+    }
+
+    fn apply_fee_discount(&self, fee: u64, trader_volume_usd: u64) -> Result<u64> {
+        let fee_discount = self.get_fee_discount(trader_volume_usd);
+        if fee_discount == 0 {
+            return Ok(fee);
+        }
+        math::checked_sub(fee, Self::get_fee_amount(fee_discount, fee)?)
+    }
+
+    // Returns (referrer_reward, trader_rebate) out of the given trading fee
+    pub fn get_referral_amounts(&self, fee_amount: u64, protocol_share: u64) -> Result<(u64, u64)> {
+        // rounded down so that rewards and rebates never exceed the LP fee
+        let lp_fee = math::checked_sub(
+            fee_amount,
+            Self::get_fee_amount(protocol_share, fee_amount)?,
+        )?;
+        let referrer_reward = math::checked_div(
+            math::checked_mul(lp_fee as u128, self.referral.referrer_share as u128)?,
+            Perpetuals::BPS_POWER,
+        )?;
+        let trader_rebate = math::checked_div(
+            math::checked_mul(lp_fee as u128, self.referral.trader_rebate as u128)?,
+            Perpetuals::BPS_POWER,
+        )?;
+        Ok((
+            math::checked_as_u64(referrer_reward)?,
+            math::checked_as_u64(trader_rebate)?,
+        ))
+    }
+
+    // Returns token ratios with the target adjusted by the re-weighting schedule
+    pub fn get_token_ratios(&self, token_id: usize, curtime: i64) -> Result<TokenRatios> {
+        let mut ratios = self.ratios[token_id];
+        if self.next_targets.len() != self.ratios.len() || curtime <= self.reweight_start_time {
+            return Ok(ratios);
+        }
+
+        let next_target = self.next_targets[token_id];
+        if curtime >= self.reweight_end_time {
+            ratios.target = next_target;
+            return Ok(ratios);
+        }
+
+        let elapsed = math::checked_sub(curtime, self.reweight_start_time)? as u128;
+        let duration = math::checked_sub(self.reweight_end_time, self.reweight_start_time)? as u128;
+        ratios.target = if next_target > ratios.target {
+            math::checked_add(
+                ratios.target,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul((next_target - ratios.target) as u128, elapsed)?,
+                    duration,
+                )?)?,
+            )?
+        } else {
+            math::checked_sub(
+                ratios.target,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul((ratios.target - next_target) as u128, elapsed)?,
+                    duration,
+                )?)?,
+            )?
+        };
+
+        Ok(ratios)
+    }
+
+    // Replaces base targets with the effective ones and schedules re-weighting
+    // to new targets over the given duration
+    pub fn set_next_targets(
+        &mut self,
+        next_targets: &[u64],
+        duration: i64,
+        curtime: i64,
+    ) -> Result<()> {
+        require!(
+            next_targets.len() == self.ratios.len()
+                && duration >= 0
+                && (next_targets.is_empty()
+                    || next_targets.iter().map(|&x| x as u128).sum::<u128>()
+                        == Perpetuals::BPS_POWER),
+            PerpetualsError::InvalidPoolConfig
+        );
+
+        let mut targets = Vec::with_capacity(self.ratios.len());
+        for token_id in 0..self.ratios.len() {
+            targets.push(self.get_token_ratios(token_id, curtime)?.target);
+        }
+
+        // assign interpolation rounding remainder to the first token that stays within its bounds
+        let remainder = if targets.is_empty() {
+            0
+        } else {
+            Perpetuals::BPS_POWER as i128 - targets.iter().map(|&x| x as i128).sum::<i128>()
+        };
+        if remainder != 0 {
+            let mut adjusted = false;
+            for (target, ratio) in targets.iter_mut().zip(self.ratios.iter()) {
+                let new_target = *target as i128 + remainder;
+                if new_target >= ratio.min as i128 && new_target <= ratio.max as i128 {
+                    *target = math::checked_as_u64(new_target)?;
+                    adjusted = true;
+                    break;
+                }
+            }
+            require!(adjusted, PerpetualsError::InvalidPoolConfig);
+        }
+
+        for (ratio, target) in self.ratios.iter_mut().zip(targets) {
+            ratio.target = target;
+        }
+
+        if duration == 0 {
+            for (ratio, &target) in self.ratios.iter_mut().zip(next_targets) {
+                ratio.target = target;
+            }
+            self.next_targets = Vec::new();
+        } else {
+            self.next_targets = next_targets.to_vec();
+        }
+        self.reweight_start_time = curtime;
+        self.reweight_end_time = math::checked_add(curtime, duration)?;
+
+        Ok(())
+    }
+
     pub fn get_entry_price(
         &self,
         token_price: &OraclePrice,
         token_ema_price: &OraclePrice,
         side: Side,
         custody: &Custody,
+        curtime: i64,
     ) -> Result<u64> {
         let price = self.get_price(
             token_price,
             token_ema_price,
             side,
-            if side == Side::Long {
-                custody.pricing.trade_spread_long
-            } else {
-                custody.pricing.trade_spread_short
-            },
+            custody.get_trade_spread(side, token_price, curtime)?,
         )?;
         require_gt!(price.price, 0, PerpetualsError::MaxPriceSlippage);
 
@@ -124,6 +386,7 @@ impl Pool {
         size: u64,
         locked_amount: u64,
         collateral_custody: &Custody,
+        trader_volume_usd: u64,
     ) -> Result<u64> {
         // The "optimal" algorithm is always used to compute the fee for entering a position.
         // entry_fee = custody.fees.open_position * utilization_fee * size
@@ -170,7 +433,7 @@ impl Pool {
             )?)?;
         }
 
-        Ok(size_fee)
+        self.apply_fee_discount(size_fee, trader_volume_usd)
     }
 
     pub fn get_exit_price(
@@ -179,20 +442,18 @@ impl Pool {
         token_ema_price: &OraclePrice,
         side: Side,
         custody: &Custody,
+        curtime: i64,
     ) -> Result<u64> {
+        let exit_side = if side == Side::Long {
+            Side::Short
+        } else {
+            Side::Long
+        };
         let price = self.get_price(
             token_price,
             token_ema_price,
-            if side == Side::Long {
-                Side::Short
-            } else {
-                Side::Long
-            },
-            if side == Side::Long {
-                custody.pricing.trade_spread_short
-            } else {
-                custody.pricing.trade_spread_long
-            },
+            exit_side,
+            custody.get_trade_spread(exit_side, token_price, curtime)?,
         )?;
 
         Ok(price
@@ -200,8 +461,33 @@ impl Pool {
             .price)
     }
 
-    pub fn get_exit_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
-        Self::get_fee_amount(custody.fees.close_position, size)
+    pub fn get_exit_fee(
+        &self,
+        size: u64,
+        custody: &Custody,
+        trader_volume_usd: u64,
+    ) -> Result<u64> {
+        self.apply_fee_discount(
+            Self::get_fee_amount(custody.fees.close_position, size)?,
+            trader_volume_usd,
+        )
+    }
+
+    // Returns the fee charged to close or liquidate the whole position
+    pub fn get_position_exit_fee(
+        &self,
+        position: &Position,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        liquidation: bool,
+        trader_volume_usd: u64,
+    ) -> Result<u64> {
+        let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
+        if liquidation {
+            self.get_liquidation_fee(size, position.size_usd, custody)
+        } else {
+            self.get_exit_fee(size, custody, trader_volume_usd)
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -216,8 +502,16 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
+        trader_volume_usd: u64,
     ) -> Result<(u64, u64, u64, u64)> {
-        let (profit_usd, loss_usd, fee_amount) = self.get_pnl_usd(
+        let exit_fee = self.get_position_exit_fee(
+            position,
+            token_ema_price,
+            custody,
+            liquidation,
+            trader_volume_usd,
+        )?;
+        self.get_close_amount_with_fee(
             position,
             token_price,
             token_ema_price,
@@ -226,7 +520,34 @@ impl Pool {
             collateral_token_ema_price,
             collateral_custody,
             curtime,
-            liquidation,
+            exit_fee,
+        )
+    }
+
+    // Same as get_close_amount() but with the exit fee (in position tokens) given by the caller
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_close_amount_with_fee(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        exit_fee: u64,
+    ) -> Result<(u64, u64, u64, u64)> {
+        let (profit_usd, loss_usd, fee_amount) = self.get_pnl_usd_with_fee(
+            position,
+            token_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            exit_fee,
         )?;
 
         let available_amount_usd = if profit_usd > 0 {
@@ -257,6 +578,23 @@ impl Pool {
         ))
     }
 
+    // Returns the spread for swaps, the confidence of both prices adds up in the pair price
+    pub fn get_swap_spread(
+        &self,
+        token_in_price: &OraclePrice,
+        token_out_price: &OraclePrice,
+        custody_in: &Custody,
+        custody_out: &Custody,
+    ) -> Result<u64> {
+        Ok(std::cmp::max(
+            custody_in.pricing.swap_spread,
+            math::checked_add(
+                custody_in.get_conf_spread(token_in_price)?,
+                custody_out.get_conf_spread(token_out_price)?,
+            )?,
+        ))
+    }
+
     pub fn get_swap_price(
         &self,
         token_in_price: &OraclePrice,
@@ -264,6 +602,7 @@ impl Pool {
         token_out_price: &OraclePrice,
         token_out_ema_price: &OraclePrice,
         custody_in: &Custody,
+        custody_out: &Custody,
     ) -> Result<OraclePrice> {
         let min_price = if token_in_price < token_in_ema_price {
             token_in_price
@@ -283,7 +622,7 @@ impl Pool {
             &pair_price,
             &pair_price,
             Side::Short,
-            custody_in.pricing.swap_spread,
+            self.get_swap_spread(token_in_price, token_out_price, custody_in, custody_out)?,
         )
     }
 
@@ -304,6 +643,7 @@ impl Pool {
             token_out_price,
             token_out_ema_price,
             custody_in,
+            custody_out,
         )?;
 
         math::checked_decimal_mul(
@@ -326,20 +666,24 @@ impl Pool {
         token_price_in: &OraclePrice,
         custody_out: &Custody,
         token_price_out: &OraclePrice,
+        curtime: i64,
+        trader_volume_usd: u64,
     ) -> Result<(u64, u64)> {
         let stable_swap = custody_in.is_stable && custody_out.is_stable;
 
+        let base_fee_in = if stable_swap {
+            custody_in.fees.stable_swap_in
+        } else {
+            custody_in.fees.swap_in
+        };
         let swap_in_fee = self.get_fee(
             token_id_in,
-            if stable_swap {
-                custody_in.fees.stable_swap_in
-            } else {
-                custody_in.fees.swap_in
-            },
+            custody_in.get_swap_in_fee(base_fee_in, token_price_in)?,
             amount_in,
             0u64,
             custody_in,
             token_price_in,
+            curtime,
         )?;
 
         let swap_out_fee = self.get_fee(
@@ -353,66 +697,587 @@ impl Pool {
             amount_out,
             custody_out,
             token_price_out,
+            curtime,
+        )?;
+
+        Ok((
+            self.apply_fee_discount(swap_in_fee, trader_volume_usd)?,
+            self.apply_fee_discount(swap_out_fee, trader_volume_usd)?,
+        ))
+    }
+
+    // Returns the smallest amount_in for which get_swap_amount() returns at least amount_out
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_swap_amount_in(
+        &self,
+        token_in_price: &OraclePrice,
+        token_in_ema_price: &OraclePrice,
+        token_out_price: &OraclePrice,
+        token_out_ema_price: &OraclePrice,
+        custody_in: &Custody,
+        custody_out: &Custody,
+        amount_out: u64,
+    ) -> Result<u64> {
+        let swap_price = self.get_swap_price(
+            token_in_price,
+            token_in_ema_price,
+            token_out_price,
+            token_out_ema_price,
+            custody_in,
+            custody_out,
         )?;
 
-        Ok((swap_in_fee, swap_out_fee))
-    }
+        math::checked_decimal_mul_inverse(
+            amount_out,
+            -(custody_in.decimals as i32),
+            swap_price.price,
+            swap_price.exponent,
+            -(custody_out.decimals as i32),
+        )
+    }
+
+    // Returns (amount_in, fee_in, fee_out) required to receive amount_out after fees,
+    // fee_out includes rounding in favor of the pool
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_swap_amount_in_and_fees(
+        &self,
+        token_id_in: usize,
+        token_id_out: usize,
+        amount_out: u64,
+        token_in_price: &OraclePrice,
+        token_in_ema_price: &OraclePrice,
+        token_out_price: &OraclePrice,
+        token_out_ema_price: &OraclePrice,
+        custody_in: &Custody,
+        custody_out: &Custody,
+        curtime: i64,
+        trader_volume_usd: u64,
+    ) -> Result<(u64, u64, u64)> {
+        let stable_swap = custody_in.is_stable && custody_out.is_stable;
+        let base_fee_out = if stable_swap {
+            custody_out.fees.stable_swap_out
+        } else {
+            custody_out.fees.swap_out
+        };
+
+        let gross_amount_out = Self::get_amount_before_fee(amount_out, |amount| {
+            self.apply_fee_discount(
+                self.get_fee(
+                    token_id_out,
+                    base_fee_out,
+                    0u64,
+                    amount,
+                    custody_out,
+                    token_out_price,
+                    curtime,
+                )?,
+                trader_volume_usd,
+            )
+        })?;
+
+        let amount_in = self.get_swap_amount_in(
+            token_in_price,
+            token_in_ema_price,
+            token_out_price,
+            token_out_ema_price,
+            custody_in,
+            custody_out,
+            gross_amount_out,
+        )?;
+
+        let (fee_in, _) = self.get_swap_fees(
+            token_id_in,
+            token_id_out,
+            amount_in,
+            gross_amount_out,
+            custody_in,
+            token_in_price,
+            custody_out,
+            token_out_price,
+            curtime,
+            trader_volume_usd,
+        )?;
+
+        Ok((
+            amount_in,
+            fee_in,
+            math::checked_sub(gross_amount_out, amount_out)?,
+        ))
+    }
+
+    pub fn get_add_liquidity_fee(
+        &self,
+        token_id: usize,
+        amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<u64> {
+        self.get_fee(
+            token_id,
+            custody.fees.add_liquidity,
+            amount,
+            0u64,
+            custody,
+            token_price,
+            curtime,
+        )
+    }
+
+    pub fn get_remove_liquidity_fee(
+        &self,
+        token_id: usize,
+        amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<u64> {
+        self.get_fee(
+            token_id,
+            custody.fees.remove_liquidity,
+            0u64,
+            amount,
+            custody,
+            token_price,
+            curtime,
+        )
+    }
+
+    // Returns (amount_in, fee) required to mint lp_amount_out
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_add_liquidity_amount_in(
+        &self,
+        token_id: usize,
+        lp_amount_out: u64,
+        lp_supply: u64,
+        pool_amount_usd: u128,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        min_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<(u64, u64)> {
+        let token_amount_usd = if pool_amount_usd == 0 {
+            lp_amount_out
+        } else {
+            math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(lp_amount_out as u128, pool_amount_usd)?,
+                lp_supply as u128,
+            )?)?
+        };
+
+        let no_fee_amount = min_price.get_min_token_amount(token_amount_usd, custody.decimals)?;
+
+        let amount_in = Self::get_amount_before_fee(no_fee_amount, |amount| {
+            self.get_add_liquidity_fee(token_id, amount, custody, token_price, curtime)
+        })?;
+
+        Ok((
+            amount_in,
+            self.get_add_liquidity_fee(token_id, amount_in, custody, token_price, curtime)?,
+        ))
+    }
+
+    // Returns (lp_amount_in, remove_amount, fee) required to receive amount_out after fees,
+    // fee includes rounding in favor of the pool
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_remove_liquidity_amount_in(
+        &self,
+        token_id: usize,
+        amount_out: u64,
+        lp_supply: u64,
+        pool_amount_usd: u128,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        max_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<(u64, u64, u64)> {
+        let remove_amount = Self::get_amount_before_fee(amount_out, |amount| {
+            self.get_remove_liquidity_fee(token_id, amount, custody, token_price, curtime)
+        })?;
+
+        let remove_amount_usd = math::checked_decimal_ceil_mul(
+            remove_amount,
+            -(custody.decimals as i32),
+            max_price.price,
+            max_price.exponent,
+            -(Perpetuals::USD_DECIMALS as i32),
+        )?;
+
+        let lp_amount_in = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(remove_amount_usd as u128, lp_supply as u128)?,
+            pool_amount_usd,
+        )?)?;
+
+        Ok((
+            lp_amount_in,
+            remove_amount,
+            math::checked_sub(remove_amount, amount_out)?,
+        ))
+    }
+
+    pub fn get_liquidation_fee(&self, size: u64, size_usd: u64, custody: &Custody) -> Result<u64> {
+        Self::get_fee_amount(custody.get_leverage_tier(size_usd).liquidation_fee, size)
+    }
+
+    pub fn check_token_ratio(
+        &self,
+        token_id: usize,
+        amount_add: u64,
+        amount_remove: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<bool> {
+        let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
+        let ratios = self.get_token_ratios(token_id, curtime)?;
+
+        if new_ratio < ratios.min {
+            Ok(new_ratio >= self.get_current_ratio(custody, token_price)?)
+        } else if new_ratio > ratios.max {
+            Ok(new_ratio <= self.get_current_ratio(custody, token_price)?)
+        } else {
+            Ok(true)
+        }
+    }
+
+    // Checks pool constraints for a swap and updates custody balances and stats. amount_out,
+    // fees.1 and referral amounts (referrer_reward, trader_rebate) are in the dispensed token,
+    // amount_out - fees.1 + trader_rebate is sent to the user.
+    // Returns (deposit_amount, withdrawal_amount) after protocol fees.
+    #[allow(clippy::too_many_arguments)]
+    pub fn settle_swap(
+        &self,
+        token_id_in: usize,
+        token_id_out: usize,
+        amount_in: u64,
+        amount_out: u64,
+        fees: (u64, u64),
+        referral_amounts: (u64, u64),
+        custody_in: &mut Custody,
+        token_price_in: &OraclePrice,
+        custody_out: &mut Custody,
+        token_price_out: &OraclePrice,
+        curtime: i64,
+    ) -> Result<(u64, u64)> {
+        let (referrer_reward, trader_rebate) = referral_amounts;
+        let fee_out = math::checked_sub(fees.1, trader_rebate)?;
+        let no_fee_amount = math::checked_sub(amount_out, fee_out)?;
+        let protocol_fee_in = Self::get_fee_amount(custody_in.fees.protocol_share, fees.0)?;
+        let protocol_fee_out = Self::get_fee_amount(custody_out.fees.protocol_share, fees.1)?;
+        let deposit_amount = math::checked_sub(amount_in, protocol_fee_in)?;
+        let withdrawal_amount = math::checked_add(
+            math::checked_add(no_fee_amount, protocol_fee_out)?,
+            referrer_reward,
+        )?;
+
+        require!(
+            self.check_token_ratio(
+                token_id_in,
+                deposit_amount,
+                0,
+                custody_in,
+                token_price_in,
+                curtime
+            )? && self.check_token_ratio(
+                token_id_out,
+                0,
+                withdrawal_amount,
+                custody_out,
+                token_price_out,
+                curtime
+            )?,
+            PerpetualsError::TokenRatioOutOfRange
+        );
+        require!(
+            math::checked_sub(custody_out.assets.owned, custody_out.assets.locked)?
+                >= withdrawal_amount,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        custody_in.volume_stats.swap_usd = custody_in
+            .volume_stats
+            .swap_usd
+            .wrapping_add(token_price_in.get_asset_amount_usd(amount_in, custody_in.decimals)?);
+
+        custody_in.collected_fees.swap_usd = custody_in
+            .collected_fees
+            .swap_usd
+            .wrapping_add(token_price_in.get_asset_amount_usd(fees.0, custody_in.decimals)?);
+
+        custody_in.assets.owned = math::checked_add(custody_in.assets.owned, deposit_amount)?;
+
+        custody_in.assets.protocol_fees =
+            math::checked_add(custody_in.assets.protocol_fees, protocol_fee_in)?;
+
+        custody_out.collected_fees.swap_usd = custody_out
+            .collected_fees
+            .swap_usd
+            .wrapping_add(token_price_out.get_asset_amount_usd(fee_out, custody_out.decimals)?);
+
+        custody_out.volume_stats.swap_usd = custody_out
+            .volume_stats
+            .swap_usd
+            .wrapping_add(token_price_out.get_asset_amount_usd(amount_out, custody_out.decimals)?);
+
+        custody_out.assets.protocol_fees =
+            math::checked_add(custody_out.assets.protocol_fees, protocol_fee_out)?;
+
+        custody_out.assets.owned = math::checked_sub(custody_out.assets.owned, withdrawal_amount)?;
+
+        custody_in.update_borrow_rate(curtime)?;
+        custody_out.update_borrow_rate(curtime)?;
+
+        Ok((deposit_amount, withdrawal_amount))
+    }
+
+    // Checks pool constraints for a liquidity deposit and updates custody balances and stats
+    pub fn settle_add_liquidity(
+        &self,
+        token_id: usize,
+        amount_in: u64,
+        fee_amount: u64,
+        custody: &mut Custody,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<()> {
+        let protocol_fee = Self::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        let deposit_amount = math::checked_sub(amount_in, protocol_fee)?;
+        require!(
+            self.check_token_ratio(token_id, deposit_amount, 0, custody, token_price, curtime)?,
+            PerpetualsError::TokenRatioOutOfRange
+        );
+
+        custody.collected_fees.add_liquidity_usd = custody
+            .collected_fees
+            .add_liquidity_usd
+            .wrapping_add(token_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+
+        custody.volume_stats.add_liquidity_usd = custody
+            .volume_stats
+            .add_liquidity_usd
+            .wrapping_add(token_price.get_asset_amount_usd(amount_in, custody.decimals)?);
+
+        custody.assets.protocol_fees =
+            math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
+
+        custody.assets.owned = math::checked_add(custody.assets.owned, deposit_amount)?;
+
+        custody.update_borrow_rate(curtime)
+    }
+
+    // Checks pool constraints for a liquidity withdrawal of amount_out after fees
+    // and updates custody balances and stats
+    #[allow(clippy::too_many_arguments)]
+    pub fn settle_remove_liquidity(
+        &self,
+        token_id: usize,
+        amount_out: u64,
+        fee_amount: u64,
+        remove_amount_usd: u64,
+        custody: &mut Custody,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<()> {
+        let protocol_fee = Self::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        let withdrawal_amount = math::checked_add(amount_out, protocol_fee)?;
+        require!(
+            self.check_token_ratio(
+                token_id,
+                0,
+                withdrawal_amount,
+                custody,
+                token_price,
+                curtime
+            )?,
+            PerpetualsError::TokenRatioOutOfRange
+        );
+        require!(
+            math::checked_sub(custody.assets.owned, custody.assets.locked)? >= withdrawal_amount,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        custody.collected_fees.remove_liquidity_usd = custody
+            .collected_fees
+            .remove_liquidity_usd
+            .wrapping_add(token_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+
+        custody.volume_stats.remove_liquidity_usd = custody
+            .volume_stats
+            .remove_liquidity_usd
+            .wrapping_add(remove_amount_usd);
+
+        custody.assets.protocol_fees =
+            math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
+
+        custody.assets.owned = math::checked_sub(custody.assets.owned, withdrawal_amount)?;
+
+        custody.update_borrow_rate(curtime)
+    }
+
+    // Checks pool constraints for a closed (or reduced) position paying out amount_out and
+    // updates custody and tranche balances and stats. fee_amounts are the full close fee
+    // (collateral tokens, usd) before any trader rebate. Returns the referrer reward that
+    // could be paid, the caller credits it and transfers tokens.
+    #[allow(clippy::too_many_arguments)]
+    pub fn settle_close_position(
+        &self,
+        closed_position: &Position,
+        full_close: bool,
+        liquidation: bool,
+        amount_out: u64,
+        fee_amounts: (u64, u64),
+        referrer_reward: u64,
+        pnl_usd: (u64, u64),
+        custody: &mut Custody,
+        collateral_custody: &mut Custody,
+        mut tranche: Option<&mut Tranche>,
+        curtime: i64,
+    ) -> Result<u64> {
+        let (fee_amount, fee_amount_usd) = fee_amounts;
+        let (profit_usd, loss_usd) = pnl_usd;
+
+        // unlock pool funds
+        if let Some(tranche) = tranche.as_mut() {
+            tranche.unlock_funds(closed_position.locked_amount)?;
+            // the position collateral is returned from the custody collateral
+            require!(
+                tranche.check_available_amount(
+                    amount_out.saturating_sub(closed_position.collateral_amount)
+                )?,
+                PerpetualsError::CustodyAmountLimit
+            );
+        } else {
+            collateral_custody.unlock_funds(closed_position.locked_amount)?;
+            require!(
+                self.check_available_amount(amount_out, collateral_custody)?,
+                PerpetualsError::CustodyAmountLimit
+            );
+        }
+
+        if liquidation {
+            collateral_custody.collected_fees.liquidation_usd = collateral_custody
+                .collected_fees
+                .liquidation_usd
+                .wrapping_add(fee_amount_usd);
+        } else {
+            collateral_custody.collected_fees.close_position_usd = collateral_custody
+                .collected_fees
+                .close_position_usd
+                .wrapping_add(fee_amount_usd);
+        }
+
+        if let Some(tranche) = tranche.as_mut() {
+            tranche.settle_position(
+                closed_position.collateral_amount,
+                amount_out,
+                profit_usd,
+                loss_usd,
+            )?;
+        } else if amount_out > closed_position.collateral_amount {
+            let amount_lost = amount_out.saturating_sub(closed_position.collateral_amount);
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
+        } else {
+            let amount_gained = closed_position.collateral_amount.saturating_sub(amount_out);
+            collateral_custody.assets.owned =
+                math::checked_add(collateral_custody.assets.owned, amount_gained)?;
+        }
+        collateral_custody.assets.collateral = math::checked_sub(
+            collateral_custody.assets.collateral,
+            closed_position.collateral_amount,
+        )?;
+
+        // pay protocol fee and referrer reward if possible, otherwise they are skipped
+        let protocol_fee = Self::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        if self.withdraw_owned(protocol_fee, collateral_custody, tranche.as_deref_mut())? {
+            collateral_custody.assets.protocol_fees =
+                math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+        }
+        let paid_reward = if self.withdraw_owned(referrer_reward, collateral_custody, tranche)? {
+            referrer_reward
+        } else {
+            0
+        };
+
+        // if custody and collateral_custody accounts are the same, ensure that data is in sync
+        let same_custody = closed_position.side == Side::Long && !custody.is_virtual;
+        let position_custody = if same_custody {
+            &mut *collateral_custody
+        } else {
+            &mut *custody
+        };
 
-    pub fn get_add_liquidity_fee(
-        &self,
-        token_id: usize,
-        amount: u64,
-        custody: &Custody,
-        token_price: &OraclePrice,
-    ) -> Result<u64> {
-        self.get_fee(
-            token_id,
-            custody.fees.add_liquidity,
-            amount,
-            0u64,
-            custody,
-            token_price,
-        )
-    }
+        if liquidation {
+            position_custody.volume_stats.liquidation_usd = position_custody
+                .volume_stats
+                .liquidation_usd
+                .wrapping_add(closed_position.size_usd);
+        } else {
+            position_custody.volume_stats.close_position_usd = position_custody
+                .volume_stats
+                .close_position_usd
+                .wrapping_add(closed_position.size_usd);
+        }
 
-    pub fn get_remove_liquidity_fee(
-        &self,
-        token_id: usize,
-        amount: u64,
-        custody: &Custody,
-        token_price: &OraclePrice,
-    ) -> Result<u64> {
-        self.get_fee(
-            token_id,
-            custody.fees.remove_liquidity,
-            0u64,
-            amount,
-            custody,
-            token_price,
-        )
-    }
+        if closed_position.side == Side::Long {
+            position_custody.trade_stats.oi_long_usd = position_custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(closed_position.size_usd);
+        } else {
+            position_custody.trade_stats.oi_short_usd = position_custody
+                .trade_stats
+                .oi_short_usd
+                .saturating_sub(closed_position.size_usd);
+        }
+
+        position_custody.trade_stats.profit_usd = position_custody
+            .trade_stats
+            .profit_usd
+            .wrapping_add(profit_usd);
+        position_custody.trade_stats.loss_usd =
+            position_custody.trade_stats.loss_usd.wrapping_add(loss_usd);
+
+        if same_custody {
+            if full_close {
+                collateral_custody.remove_position(closed_position, curtime, None)?;
+            } else {
+                collateral_custody.reduce_position(closed_position, curtime, None)?;
+            }
+            collateral_custody.update_borrow_rate(curtime)?;
+            *custody = collateral_custody.clone();
+        } else {
+            if full_close {
+                custody.remove_position(closed_position, curtime, Some(collateral_custody))?;
+            } else {
+                custody.reduce_position(closed_position, curtime, Some(collateral_custody))?;
+            }
+            collateral_custody.update_borrow_rate(curtime)?;
+        }
 
-    pub fn get_liquidation_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
-        Self::get_fee_amount(custody.fees.liquidation, size)
+        Ok(paid_reward)
     }
 
-    pub fn check_token_ratio(
+    // Removes amount from the tranche or custody owned assets, returns false if not available
+    fn withdraw_owned(
         &self,
-        token_id: usize,
-        amount_add: u64,
-        amount_remove: u64,
-        custody: &Custody,
-        token_price: &OraclePrice,
+        amount: u64,
+        collateral_custody: &mut Custody,
+        tranche: Option<&mut Tranche>,
     ) -> Result<bool> {
-        let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
-
-        if new_ratio < self.ratios[token_id].min {
-            Ok(new_ratio >= self.get_current_ratio(custody, token_price)?)
-        } else if new_ratio > self.ratios[token_id].max {
-            Ok(new_ratio <= self.get_current_ratio(custody, token_price)?)
+        if let Some(tranche) = tranche {
+            if !tranche.check_available_amount(amount)? {
+                return Ok(false);
+            }
+            tranche.assets.owned = math::checked_sub(tranche.assets.owned, amount)?;
         } else {
-            Ok(true)
+            if !self.check_available_amount(amount, collateral_custody)? {
+                return Ok(false);
+            }
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount)?;
         }
+        Ok(true)
     }
 
     pub fn check_available_amount(&self, amount: u64, custody: &Custody) -> Result<bool> {
@@ -445,12 +1310,16 @@ impl Pool {
             collateral_custody,
             curtime,
             false,
+            0,
         )?;
 
+        // depegged stable collateral is valued with a haircut
+        let collateral_usd = collateral_custody
+            .apply_depeg_haircut(position.collateral_usd, collateral_token_price)?;
         let current_margin_usd = if profit_usd > 0 {
-            math::checked_add(position.collateral_usd, profit_usd)?
-        } else if loss_usd <= position.collateral_usd {
-            math::checked_sub(position.collateral_usd, loss_usd)?
+            math::checked_add(collateral_usd, profit_usd)?
+        } else if loss_usd <= collateral_usd {
+            math::checked_sub(collateral_usd, loss_usd)?
         } else {
             0
         };
@@ -489,10 +1358,20 @@ impl Pool {
             curtime,
         )?;
 
-        Ok(current_leverage <= custody.pricing.max_leverage
+        let leverage_tier = custody.get_leverage_tier(position.size_usd);
+        let max_initial_leverage = if custody.trading_schedule.is_restricted(curtime) {
+            std::cmp::min(
+                leverage_tier.max_initial_leverage,
+                custody.trading_schedule.off_hours_max_initial_leverage,
+            )
+        } else {
+            leverage_tier.max_initial_leverage
+        };
+
+        Ok(current_leverage <= leverage_tier.max_leverage
             && (!initial
                 || (current_leverage >= custody.pricing.min_initial_leverage
-                    && current_leverage <= custody.pricing.max_initial_leverage)))
+                    && current_leverage <= max_initial_leverage)))
     }
 
     pub fn get_liquidation_price(
@@ -510,7 +1389,7 @@ impl Pool {
         }
 
         let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
-        let exit_fee_tokens = self.get_exit_fee(size, custody)?;
+        let exit_fee_tokens = self.get_exit_fee(size, custody, 0)?;
         let exit_fee_usd =
             token_ema_price.get_asset_amount_usd(exit_fee_tokens, custody.decimals)?;
         let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
@@ -521,7 +1400,7 @@ impl Pool {
 
         let max_loss_usd = math::checked_as_u64(math::checked_div(
             math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
-            custody.pricing.max_leverage as u128,
+            custody.get_leverage_tier(position.size_usd).max_leverage as u128,
         )?)?;
         let max_loss_usd = math::checked_add(max_loss_usd, unrealized_loss_usd)?;
 
@@ -583,21 +1462,57 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
+        trader_volume_usd: u64,
     ) -> Result<(u64, u64, u64)> {
         if position.size_usd == 0 || position.price == 0 {
             return Ok((0, 0, 0));
         }
 
-        let exit_price =
-            self.get_exit_price(token_price, token_ema_price, position.side, custody)?;
+        let exit_fee = self.get_position_exit_fee(
+            position,
+            token_ema_price,
+            custody,
+            liquidation,
+            trader_volume_usd,
+        )?;
+        self.get_pnl_usd_with_fee(
+            position,
+            token_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            exit_fee,
+        )
+    }
 
-        let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
+    // Same as get_pnl_usd() but with the exit fee (in position tokens) given by the caller
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_pnl_usd_with_fee(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        exit_fee: u64,
+    ) -> Result<(u64, u64, u64)> {
+        if position.size_usd == 0 || position.price == 0 {
+            return Ok((0, 0, 0));
+        }
 
-        let exit_fee = if liquidation {
-            self.get_liquidation_fee(size, custody)?
-        } else {
-            self.get_exit_fee(size, custody)?
-        };
+        let exit_price = self.get_exit_price(
+            token_price,
+            token_ema_price,
+            position.side,
+            custody,
+            curtime,
+        )?;
 
         let exit_fee_usd = token_ema_price.get_asset_amount_usd(exit_fee, custody.decimals)?;
         let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
@@ -642,6 +1557,7 @@ impl Pool {
                     OraclePrice {
                         price: 10u64.pow(Perpetuals::USD_DECIMALS as u32),
                         exponent: -(Perpetuals::USD_DECIMALS as i32),
+                        conf: 0,
                     }
                 } else {
                     collateral_token_price
@@ -686,6 +1602,7 @@ impl Pool {
                     OraclePrice {
                         price: 10u64.pow(Perpetuals::USD_DECIMALS as u32),
                         exponent: -(Perpetuals::USD_DECIMALS as i32),
+                        conf: 0,
                     }
                 } else {
                     collateral_token_price
@@ -712,7 +1629,158 @@ impl Pool {
         accounts: &[AccountInfo],
         curtime: i64,
     ) -> Result<u128> {
+        let (_, aum_usd, _) = self.get_pool_amounts_usd(aum_calc_mode, accounts, curtime)?;
+        Ok(aum_usd)
+    }
+
+    pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
+        if fee == 0 || amount == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(amount as u128, fee as u128)?,
+            Perpetuals::BPS_POWER,
+        )?)
+    }
+
+    // returns (assets_usd, unrealized_profit_usd) where assets_usd is the value of tokens
+    // owned by the pool and unrealized_profit_usd is the aggregate profit of open positions
+    pub fn get_assets_and_unrealized_profit_usd(
+        &self,
+        aum_calc_mode: AumCalcMode,
+        accounts: &[AccountInfo],
+        curtime: i64,
+    ) -> Result<(u128, u128)> {
+        let (assets_usd, _, profit_usd) =
+            self.get_pool_amounts_usd(aum_calc_mode, accounts, curtime)?;
+        Ok((assets_usd, profit_usd))
+    }
+
+    pub fn check_adl_required(&self, assets_usd: u128, profit_usd: u128) -> Result<bool> {
+        if self.adl.max_profit_to_aum == 0 || profit_usd == 0 {
+            return Ok(false);
+        }
+        if assets_usd == 0 {
+            return Ok(true);
+        }
+        let profit_to_aum = math::checked_div(
+            math::checked_mul(profit_usd, Perpetuals::BPS_POWER)?,
+            assets_usd,
+        )?;
+        Ok(profit_to_aum > self.adl.max_profit_to_aum as u128)
+    }
+
+    // Returns the profit to realize to get back to max_profit_to_aum. Realized profit
+    // is paid out of pool assets, so both sides of the ratio go down by that amount.
+    pub fn get_adl_required_profit_usd(&self, assets_usd: u128, profit_usd: u128) -> Result<u128> {
+        if !self.check_adl_required(assets_usd, profit_usd)? {
+            return Ok(0);
+        }
+        let max_profit_to_aum = self.adl.max_profit_to_aum as u128;
+        math::checked_ceil_div(
+            math::checked_sub(
+                math::checked_mul(profit_usd, Perpetuals::BPS_POWER)?,
+                math::checked_mul(assets_usd, max_profit_to_aum)?,
+            )?,
+            math::checked_sub(Perpetuals::BPS_POWER, max_profit_to_aum)?,
+        )
+    }
+
+    // Checks if the position profit is high enough relative to its collateral
+    pub fn check_adl_eligible(&self, profit_usd: u64, collateral_usd: u64) -> Result<bool> {
+        if profit_usd == 0 {
+            return Ok(false);
+        }
+        Ok(
+            math::checked_mul(profit_usd as u128, Perpetuals::BPS_POWER)?
+                >= math::checked_mul(collateral_usd as u128, self.adl.min_pnl_ratio as u128)?,
+        )
+    }
+
+    // Auto-deleveraging priority: positions with higher pnl ratio and leverage go first.
+    // score = (profit / collateral) * (size / collateral)
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_adl_score(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+    ) -> Result<u64> {
+        if position.collateral_usd == 0 {
+            return Ok(0);
+        }
+
+        let (profit_usd, _, _) = self.get_pnl_usd(
+            position,
+            token_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            false,
+            0,
+        )?;
+
+        let pnl_ratio = math::checked_div(
+            math::checked_mul(profit_usd as u128, Perpetuals::BPS_POWER)?,
+            position.collateral_usd as u128,
+        )?;
+
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(pnl_ratio, position.get_initial_leverage()? as u128)?,
+            Perpetuals::BPS_POWER,
+        )?)
+    }
+
+    // Returns the smallest amount found that still covers net_amount after the fee is taken
+    pub fn get_amount_before_fee<F>(net_amount: u64, get_fee: F) -> Result<u64>
+    where
+        F: Fn(u64) -> Result<u64>,
+    {
+        if net_amount == 0 {
+            return Ok(0);
+        }
+
+        // start from net / (1 - fee_rate) estimated at the net amount
+        let fee = get_fee(net_amount)?;
+        let mut amount = if fee < net_amount {
+            math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(net_amount as u128, net_amount as u128)?,
+                math::checked_sub(net_amount, fee)? as u128,
+            )?)?
+        } else {
+            math::checked_add(net_amount, fee)?
+        };
+
+        for _ in 0..Self::MAX_FEE_ITERATIONS {
+            let next_amount = math::checked_add(net_amount, get_fee(amount)?)?;
+            if next_amount <= amount {
+                return Ok(amount);
+            }
+            amount = next_amount;
+        }
+
+        err!(PerpetualsError::FeeInversionFailed)
+    }
+
+    // private helpers
+    // returns (assets_usd, aum_usd, unrealized_profit_usd)
+    fn get_pool_amounts_usd(
+        &self,
+        aum_calc_mode: AumCalcMode,
+        accounts: &[AccountInfo],
+        curtime: i64,
+    ) -> Result<(u128, u128, u128)> {
+        let mut assets_usd: u128 = 0;
         let mut pool_amount_usd: u128 = 0;
+        let mut unrealized_profit_usd: u128 = 0;
         for (idx, &custody) in self.custodies.iter().enumerate() {
             let oracle_idx = idx + self.custodies.len();
             if oracle_idx >= accounts.len() {
@@ -729,6 +1797,7 @@ impl Pool {
                 &custody.oracle,
                 curtime,
                 false,
+                ActionClass::Increase,
             )?;
 
             let token_ema_price = OraclePrice::new_from_oracle(
@@ -736,6 +1805,7 @@ impl Pool {
                 &custody.oracle,
                 curtime,
                 custody.pricing.use_ema,
+                ActionClass::Increase,
             )?;
 
             let aum_token_price = match aum_calc_mode {
@@ -757,13 +1827,17 @@ impl Pool {
                 }
             };
 
-            let token_amount_usd =
-                aum_token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
+            // depegged stables are valued with a haircut
+            let token_amount_usd = custody.apply_depeg_haircut(
+                aum_token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?,
+                &token_price,
+            )?;
 
+            assets_usd = math::checked_add(assets_usd, token_amount_usd as u128)?;
             pool_amount_usd = math::checked_add(pool_amount_usd, token_amount_usd as u128)?;
 
-            if custody.pricing.use_unrealized_pnl_in_aum {
-                if custody.is_stable {
+            if custody.is_stable {
+                if custody.pricing.use_unrealized_pnl_in_aum {
                     // compute accumulated interest
                     let collective_position = custody.get_collective_position(Side::Long)?;
                     let interest_usd =
@@ -774,31 +1848,40 @@ impl Pool {
                     let interest_usd =
                         custody.get_interest_amount_usd(&collective_position, curtime)?;
                     pool_amount_usd = math::checked_add(pool_amount_usd, interest_usd as u128)?;
-                } else {
-                    // compute aggregate unrealized pnl
-                    let (long_profit, long_loss, _) = self.get_pnl_usd(
-                        &custody.get_collective_position(Side::Long)?,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        curtime,
-                        false,
-                    )?;
-                    let (short_profit, short_loss, _) = self.get_pnl_usd(
-                        &custody.get_collective_position(Side::Short)?,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        curtime,
-                        false,
-                    )?;
-
+                }
+            } else if !custody.is_tranche_backed() {
+                // compute aggregate unrealized pnl, tranche markets are accounted in the tranche
+                let (long_profit, long_loss, _) = self.get_pnl_usd(
+                    &custody.get_collective_position(Side::Long)?,
+                    &token_price,
+                    &token_ema_price,
+                    &custody,
+                    &token_price,
+                    &token_ema_price,
+                    &custody,
+                    curtime,
+                    false,
+                    0,
+                )?;
+                let (short_profit, short_loss, _) = self.get_pnl_usd(
+                    &custody.get_collective_position(Side::Short)?,
+                    &token_price,
+                    &token_ema_price,
+                    &custody,
+                    &token_price,
+                    &token_ema_price,
+                    &custody,
+                    curtime,
+                    false,
+                    0,
+                )?;
+
+                unrealized_profit_usd = math::checked_add(
+                    unrealized_profit_usd,
+                    math::checked_add(long_profit as u128, short_profit as u128)?,
+                )?;
+
+                if custody.pricing.use_unrealized_pnl_in_aum {
                     // adjust pool amount by collective profit/loss
                     pool_amount_usd = math::checked_add(pool_amount_usd, long_loss as u128)?;
                     pool_amount_usd = math::checked_add(pool_amount_usd, short_loss as u128)?;
@@ -808,20 +1891,9 @@ impl Pool {
             }
         }
 
-        Ok(pool_amount_usd)
-    }
-
-    pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
-        if fee == 0 || amount == 0 {
-            return Ok(0);
-        }
-        math::checked_as_u64(math::checked_ceil_div(
-            math::checked_mul(amount as u128, fee as u128)?,
-            Perpetuals::BPS_POWER,
-        )?)
+        Ok((assets_usd, pool_amount_usd, unrealized_profit_usd))
     }
 
-    // private helpers
     fn get_current_ratio(&self, custody: &Custody, token_price: &OraclePrice) -> Result<u64> {
         if self.aum_usd == 0 || custody.is_virtual {
             return Ok(0);
@@ -917,6 +1989,7 @@ impl Pool {
                     )?,
                 )?,
                 exponent: max_price.exponent,
+                conf: max_price.conf,
             })
         } else {
             let min_price = if token_price < token_ema_price {
@@ -942,10 +2015,12 @@ impl Pool {
             Ok(OraclePrice {
                 price,
                 exponent: min_price.exponent,
+                conf: min_price.conf,
             })
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn get_fee(
         &self,
         token_id: usize,
@@ -954,6 +2029,7 @@ impl Pool {
         amount_remove: u64,
         custody: &Custody,
         token_price: &OraclePrice,
+        curtime: i64,
     ) -> Result<u64> {
         require!(!custody.is_virtual, PerpetualsError::InstructionNotAllowed);
 
@@ -967,6 +2043,7 @@ impl Pool {
                 amount_remove,
                 custody,
                 token_price,
+                curtime,
             )
         } else {
             self.get_fee_optimal(
@@ -976,10 +2053,12 @@ impl Pool {
                 amount_remove,
                 custody,
                 token_price,
+                curtime,
             )
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn get_fee_linear(
         &self,
         token_id: usize,
@@ -988,6 +2067,7 @@ impl Pool {
         amount_remove: u64,
         custody: &Custody,
         token_price: &OraclePrice,
+        curtime: i64,
     ) -> Result<u64> {
         // if token ratio is improved:
         //    fee = base_fee / ratio_fee
@@ -999,7 +2079,7 @@ impl Pool {
         //   otherwise:
         //     ratio_fee = 1 + custody.fees.ratio_mult * (new_ratio - ratios.target) / (ratios.max - ratios.target);
 
-        let ratios = &self.ratios[token_id];
+        let ratios = self.get_token_ratios(token_id, curtime)?;
         let current_ratio = self.get_current_ratio(custody, token_price)?;
         let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
 
@@ -1065,6 +2145,7 @@ impl Pool {
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn get_fee_optimal(
         &self,
         token_id: usize,
@@ -1073,14 +2154,16 @@ impl Pool {
         amount_remove: u64,
         custody: &Custody,
         token_price: &OraclePrice,
+        curtime: i64,
     ) -> Result<u64> {
         // Fee calculations must temporarily be in i64 because of negative slope.
         let fee_max: i64 = custody.fees.fee_max as i64;
         let fee_optimal: i64 = custody.fees.fee_optimal as i64;
 
-        let target_ratio: i64 = self.ratios[token_id].target as i64;
-        let min_ratio: i64 = self.ratios[token_id].min as i64;
-        let max_ratio: i64 = self.ratios[token_id].max as i64;
+        let ratios = self.get_token_ratios(token_id, curtime)?;
+        let target_ratio: i64 = ratios.target as i64;
+        let min_ratio: i64 = ratios.min as i64;
+        let max_ratio: i64 = ratios.max as i64;
         let post_lp_ratio: i64 =
             self.get_new_ratio(amount_add, amount_remove, custody, token_price)? as i64;
 
@@ -1128,7 +2211,9 @@ mod test {
     use {
         super::*,
         crate::state::{
-            custody::{BorrowRateParams, Fees, PricingParams},
+            custody::{
+                BorrowRateParams, Fees, LeverageTier, PricingParams, SpreadMode, SpreadParams,
+            },
             oracle::{OracleParams, OracleType},
             perpetuals::Permissions,
         },
@@ -1147,6 +2232,7 @@ mod test {
             oracle_authority: Pubkey::default(),
             max_price_error: 100,
             max_price_age_sec: 1,
+            max_reducing_price_age_sec: 1,
         };
 
         let pricing = PricingParams {
@@ -1219,10 +2305,12 @@ mod test {
         let token_price = OraclePrice {
             price: 25_000_000,
             exponent: -3,
+            conf: 0,
         };
         let token_ema_price = OraclePrice {
             price: 25_300_000,
             exponent: -3,
+            conf: 0,
         };
 
         (
@@ -1305,38 +2393,107 @@ mod test {
             5_000,
             pool.get_new_ratio(0, 0, &custody, &token_price).unwrap()
         );
-    }
+    }
+
+    #[test]
+    fn test_get_price() {
+        let (pool, custody, _position, token_price, token_ema_price) = get_fixture();
+
+        assert_eq!(
+            OraclePrice {
+                price: 25_553_000,
+                exponent: -3,
+                conf: 0,
+            },
+            pool.get_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                custody.pricing.trade_spread_long,
+            )
+            .unwrap()
+        );
+
+        assert_eq!(
+            OraclePrice {
+                price: 24_750_000,
+                exponent: -3,
+                conf: 0,
+            },
+            pool.get_price(
+                &token_price,
+                &token_ema_price,
+                Side::Short,
+                custody.pricing.trade_spread_short,
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_conf_spread() {
+        let (pool, mut custody, _position, mut token_price, token_ema_price) = get_fixture();
+        let curtime = 0;
+        // 0.2% confidence interval
+        token_price.conf = 50_000;
+
+        let fixed_entry_price = pool
+            .get_entry_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                &custody,
+                curtime,
+            )
+            .unwrap();
+        assert_eq!(
+            custody
+                .get_trade_spread(Side::Long, &token_price, curtime)
+                .unwrap(),
+            100
+        );
 
-    #[test]
-    fn test_get_price() {
-        let (pool, custody, _position, token_price, token_ema_price) = get_fixture();
+        custody.spread = SpreadParams {
+            mode: SpreadMode::Confidence,
+            conf_mult: 10_000,
+        };
+        assert_eq!(custody.get_conf_spread(&token_price).unwrap(), 20);
+        assert_eq!(
+            custody
+                .get_trade_spread(Side::Long, &token_price, curtime)
+                .unwrap(),
+            100
+        );
 
+        custody.spread.conf_mult = 100_000;
         assert_eq!(
-            OraclePrice {
-                price: 25_553_000,
-                exponent: -3
-            },
-            pool.get_price(
+            custody
+                .get_trade_spread(Side::Long, &token_price, curtime)
+                .unwrap(),
+            200
+        );
+        assert!(
+            pool.get_entry_price(
                 &token_price,
                 &token_ema_price,
                 Side::Long,
-                custody.pricing.trade_spread_long,
+                &custody,
+                curtime
             )
             .unwrap()
+                > fixed_entry_price
         );
 
+        // confidence of both legs adds up for swaps
         assert_eq!(
-            OraclePrice {
-                price: 24_750_000,
-                exponent: -3
-            },
-            pool.get_price(
-                &token_price,
-                &token_ema_price,
-                Side::Short,
-                custody.pricing.trade_spread_short,
-            )
-            .unwrap()
+            pool.get_swap_spread(&token_price, &token_price, &custody, &custody)
+                .unwrap(),
+            400
+        );
+        assert_eq!(
+            pool.get_swap_spread(&token_price, &token_ema_price, &custody, &custody)
+                .unwrap(),
+            300
         );
     }
 
@@ -1354,7 +2511,8 @@ mod test {
                 custody.fees.open_position,
                 0,
                 custody.get_locked_amount(0, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1365,7 +2523,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1376,7 +2535,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1387,7 +2547,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1398,7 +2559,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1413,7 +2575,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1424,7 +2587,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1435,7 +2599,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1446,7 +2611,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1459,7 +2625,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1470,7 +2637,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1481,7 +2649,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1492,7 +2661,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1506,7 +2676,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1517,7 +2688,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1528,7 +2700,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1539,7 +2712,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1558,7 +2732,8 @@ mod test {
                 scale(20, custody.decimals),
                 0,
                 &custody,
-                &token_price
+                &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1583,6 +2758,7 @@ mod test {
                 0,
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1597,6 +2773,7 @@ mod test {
                 scale(2, custody.decimals),
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1611,6 +2788,7 @@ mod test {
                 scale(6, custody.decimals),
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1628,6 +2806,7 @@ mod test {
                 0,
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1642,6 +2821,7 @@ mod test {
                 scale(5, custody.decimals),
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1656,6 +2836,7 @@ mod test {
                 0,
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1673,6 +2854,7 @@ mod test {
                 0,
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1685,6 +2867,7 @@ mod test {
                 0,
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1697,6 +2880,7 @@ mod test {
                 scale(1, custody.decimals),
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1709,6 +2893,7 @@ mod test {
                 scale(10, custody.decimals),
                 &custody,
                 &token_price,
+                0,
             )
             .unwrap()
         );
@@ -1722,6 +2907,7 @@ mod test {
                 scale(15, custody.decimals),
                 &custody,
                 &token_price,
+                0,
             )
         );
         // Adding too much liquidity takes the token ratio out of range.
@@ -1734,10 +2920,206 @@ mod test {
                 0,
                 &custody,
                 &token_price,
+                0,
             )
         );
     }
 
+    #[test]
+    fn test_get_token_ratios() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+        pool.custodies = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+
+        // no schedule
+        assert_eq!(5_000, pool.get_token_ratios(0, 100).unwrap().target);
+
+        pool.set_next_targets(&[3_000, 7_000], 1_000, 100).unwrap();
+        assert!(pool.validate());
+        assert_eq!(5_000, pool.get_token_ratios(0, 100).unwrap().target);
+        assert_eq!(4_500, pool.get_token_ratios(0, 350).unwrap().target);
+        assert_eq!(5_500, pool.get_token_ratios(1, 350).unwrap().target);
+        assert_eq!(3_000, pool.get_token_ratios(0, 1_100).unwrap().target);
+        assert_eq!(7_000, pool.get_token_ratios(1, 5_000).unwrap().target);
+        assert_eq!(1_000, pool.get_token_ratios(0, 5_000).unwrap().min);
+
+        // reschedule mid-way starts from effective targets
+        pool.set_next_targets(&[5_000, 5_000], 100, 350).unwrap();
+        assert_eq!(4_500, pool.ratios[0].target);
+        assert_eq!(5_500, pool.ratios[1].target);
+        assert_eq!(4_750, pool.get_token_ratios(0, 400).unwrap().target);
+
+        // immediate update
+        pool.set_next_targets(&[6_000, 4_000], 0, 400).unwrap();
+        assert!(pool.next_targets.is_empty());
+        assert_eq!(6_000, pool.get_token_ratios(0, 400).unwrap().target);
+
+        // targets out of bounds
+        pool.set_next_targets(&[9_500, 500], 1_000, 400).unwrap();
+        assert!(!pool.validate());
+
+        // targets must add up to 1
+        assert!(pool.set_next_targets(&[6_000, 3_000], 1_000, 400).is_err());
+
+        // rounding remainder goes to the first token that stays within bounds
+        let ratio = |target, max| TokenRatios {
+            target,
+            min: 0,
+            max,
+        };
+        pool.ratios = vec![
+            ratio(4_000, 3_667),
+            ratio(3_000, 9_000),
+            ratio(3_000, 9_000),
+        ];
+        pool.next_targets = Vec::new();
+        pool.set_next_targets(&[3_000, 3_500, 3_500], 3, 0).unwrap();
+        // effective targets at 1 are 3_667, 3_166 and 3_166
+        pool.set_next_targets(&[3_000, 3_500, 3_500], 3, 1).unwrap();
+        assert_eq!(3_667, pool.ratios[0].target);
+        assert_eq!(3_167, pool.ratios[1].target);
+        assert_eq!(3_166, pool.ratios[2].target);
+
+        // no token can absorb the remainder
+        pool.ratios = vec![
+            ratio(4_000, 3_667),
+            ratio(3_000, 3_166),
+            ratio(3_000, 3_166),
+        ];
+        pool.next_targets = Vec::new();
+        pool.set_next_targets(&[3_000, 3_500, 3_500], 3, 0).unwrap();
+        assert!(pool.set_next_targets(&[3_000, 3_500, 3_500], 3, 1).is_err());
+    }
+
+    #[test]
+    fn test_get_amount_before_fee() {
+        let get_fee = |amount| Pool::get_fee_amount(100, amount);
+
+        for net_amount in [1u64, 99, 100, 12_345, 1_000_000, 987_654_321_000] {
+            let amount = Pool::get_amount_before_fee(net_amount, get_fee).unwrap();
+            let amount_after_fee = amount - get_fee(amount).unwrap();
+            assert!(amount_after_fee >= net_amount);
+            assert!(amount_after_fee - net_amount <= 1);
+        }
+
+        assert_eq!(0, Pool::get_amount_before_fee(0, get_fee).unwrap());
+
+        // no amount covers a 100% fee
+        assert!(Pool::get_amount_before_fee(100, Ok).is_err());
+    }
+
+    #[test]
+    fn test_get_swap_amount_in_and_fees() {
+        let (mut pool, mut custody_in, _position, token_price, token_ema_price) = get_fixture();
+        custody_in.fees.mode = FeesMode::Fixed;
+
+        let mut custody_out = custody_in.clone();
+        custody_out.decimals = 6;
+        let token_out_price = OraclePrice {
+            price: 1_000_000,
+            exponent: -6,
+            conf: 0,
+        };
+
+        for amount_out in [1u64, 1_234, scale(25_000, 6), scale_f64(12.345678, 6)] {
+            let amount_in = pool
+                .get_swap_amount_in(
+                    &token_price,
+                    &token_ema_price,
+                    &token_out_price,
+                    &token_out_price,
+                    &custody_in,
+                    &custody_out,
+                    amount_out,
+                )
+                .unwrap();
+            let get_swap_amount = |amount_in| {
+                pool.get_swap_amount(
+                    &token_price,
+                    &token_ema_price,
+                    &token_out_price,
+                    &token_out_price,
+                    &custody_in,
+                    &custody_out,
+                    amount_in,
+                )
+                .unwrap()
+            };
+            assert!(get_swap_amount(amount_in) >= amount_out);
+            assert!(get_swap_amount(amount_in - 1) < amount_out);
+        }
+
+        let amount_out = scale(25_000, 6);
+        let (amount_in, fee_in, fee_out) = pool
+            .get_swap_amount_in_and_fees(
+                0,
+                1,
+                amount_out,
+                &token_price,
+                &token_ema_price,
+                &token_out_price,
+                &token_out_price,
+                &custody_in,
+                &custody_out,
+                0,
+                0,
+            )
+            .unwrap();
+
+        let swap_amount_out = pool
+            .get_swap_amount(
+                &token_price,
+                &token_ema_price,
+                &token_out_price,
+                &token_out_price,
+                &custody_in,
+                &custody_out,
+                amount_in,
+            )
+            .unwrap();
+        let fees = pool
+            .get_swap_fees(
+                0,
+                1,
+                amount_in,
+                swap_amount_out,
+                &custody_in,
+                &token_price,
+                &custody_out,
+                &token_out_price,
+                0,
+                0,
+            )
+            .unwrap();
+        assert_eq!(fee_in, fees.0);
+        assert!(swap_amount_out - fees.1 >= amount_out);
+        assert!(swap_amount_out >= amount_out + fee_out);
+        assert_eq!(252_525_253, fee_out);
+
+        // fee discounts apply to both sides
+        pool.fee_tiers[0] = FeeTier {
+            min_volume_usd: 1,
+            fee_discount: 5_000,
+        };
+        let (discounted_amount_in, discounted_fee_in, discounted_fee_out) = pool
+            .get_swap_amount_in_and_fees(
+                0,
+                1,
+                amount_out,
+                &token_price,
+                &token_ema_price,
+                &token_out_price,
+                &token_out_price,
+                &custody_in,
+                &custody_out,
+                0,
+                1,
+            )
+            .unwrap();
+        assert!(discounted_amount_in < amount_in);
+        assert!(discounted_fee_in < fee_in);
+        assert!(discounted_fee_out < fee_out);
+    }
+
     #[test]
     fn test_get_pnl_usd() {
         let (pool, custody, mut position, token_price, token_ema_price) = get_fixture();
@@ -1754,7 +3136,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
@@ -1772,7 +3155,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
@@ -1790,7 +3174,121 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_adl_required() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+
+        let assets_usd = scale(1_000_000, Perpetuals::USD_DECIMALS) as u128;
+        let profit_usd = scale(300_000, Perpetuals::USD_DECIMALS) as u128;
+
+        // disabled
+        assert!(!pool.check_adl_required(assets_usd, profit_usd).unwrap());
+
+        pool.adl.max_profit_to_aum = 5_000;
+        assert!(!pool.check_adl_required(assets_usd, profit_usd).unwrap());
+        assert!(!pool.check_adl_required(assets_usd, 0).unwrap());
+        assert!(!pool.check_adl_required(assets_usd, assets_usd / 2).unwrap());
+        assert!(pool
+            .check_adl_required(assets_usd, assets_usd / 2 + assets_usd / 1_000)
+            .unwrap());
+        assert!(pool.check_adl_required(0, profit_usd).unwrap());
+    }
+
+    #[test]
+    fn test_get_adl_required_profit_usd() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+
+        let assets_usd = scale(1_000_000, Perpetuals::USD_DECIMALS) as u128;
+        let profit_usd = scale(600_000, Perpetuals::USD_DECIMALS) as u128;
+
+        // disabled
+        assert_eq!(
+            0,
+            pool.get_adl_required_profit_usd(assets_usd, profit_usd)
+                .unwrap()
+        );
+
+        // realizing 200k leaves 400k profit on 800k assets
+        pool.adl.max_profit_to_aum = 5_000;
+        assert_eq!(
+            scale(200_000, Perpetuals::USD_DECIMALS) as u128,
+            pool.get_adl_required_profit_usd(assets_usd, profit_usd)
+                .unwrap()
+        );
+        assert_eq!(
+            0,
+            pool.get_adl_required_profit_usd(assets_usd, assets_usd / 2)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_adl_eligible() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+        pool.adl.min_pnl_ratio = 5_000;
+
+        assert!(pool.check_adl_eligible(500, 1_000).unwrap());
+        assert!(!pool.check_adl_eligible(499, 1_000).unwrap());
+        assert!(!pool.check_adl_eligible(0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_get_adl_score() {
+        let (pool, custody, mut position, token_price, token_ema_price) = get_fixture();
+
+        // losing position
+        assert_eq!(
+            0,
+            pool.get_adl_score(
+                &position,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                1,
+            )
+            .unwrap()
+        );
+
+        // winning position, 4.08% pnl at x4 leverage
+        position.price = scale(24_500, Perpetuals::PRICE_DECIMALS);
+        assert_eq!(
+            1_632,
+            pool.get_adl_score(
+                &position,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                1,
+            )
+            .unwrap()
+        );
+
+        // same pnl with half the collateral ranks higher
+        position.collateral_usd = scale(12_500, Perpetuals::USD_DECIMALS);
+        assert_eq!(
+            6_528,
+            pool.get_adl_score(
+                &position,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                1,
             )
             .unwrap()
         );
@@ -1966,6 +3464,126 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_get_liquidation_price_tiered() {
+        let (pool, mut custody, position, token_price, _token_ema_price) = get_fixture();
+
+        // tier below position size applies
+        custody.leverage_tiers[0] = LeverageTier {
+            min_size_usd: scale(50_000, Perpetuals::USD_DECIMALS),
+            max_initial_leverage: 50_000,
+            max_leverage: 50_000,
+            liquidation_fee: 100,
+        };
+        assert_eq!(
+            scale(23_750, Perpetuals::PRICE_DECIMALS),
+            pool.get_liquidation_price(&position, &token_price, &custody, &custody, 1)
+                .unwrap()
+        );
+
+        // tier above position size is ignored
+        custody.leverage_tiers[0].min_size_usd = scale(200_000, Perpetuals::USD_DECIMALS);
+        assert_eq!(
+            scale(21_250, Perpetuals::PRICE_DECIMALS),
+            pool.get_liquidation_price(&position, &token_price, &custody, &custody, 1)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fee_tiers() {
+        let (mut pool, mut custody, _position, _token_price, _token_ema_price) = get_fixture();
+        pool.custodies = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        custody.fees.close_position = 100;
+        let size = scale(1, 9);
+
+        // no tiers configured
+        assert!(pool.validate());
+        assert_eq!(pool.get_fee_discount(u64::MAX), 0);
+        assert_eq!(
+            pool.get_exit_fee(size, &custody, u64::MAX).unwrap(),
+            10_000_000
+        );
+
+        pool.fee_tiers[0] = FeeTier {
+            min_volume_usd: scale(1_000_000, Perpetuals::USD_DECIMALS),
+            fee_discount: 1_000,
+        };
+        pool.fee_tiers[1] = FeeTier {
+            min_volume_usd: scale(10_000_000, Perpetuals::USD_DECIMALS),
+            fee_discount: 2_500,
+        };
+        assert!(pool.validate());
+
+        assert_eq!(pool.get_fee_discount(0), 0);
+        assert_eq!(
+            pool.get_fee_discount(scale(1_000_000, Perpetuals::USD_DECIMALS)),
+            1_000
+        );
+        assert_eq!(pool.get_fee_discount(u64::MAX), 2_500);
+        assert_eq!(pool.get_exit_fee(size, &custody, 0).unwrap(), 10_000_000);
+        assert_eq!(
+            pool.get_exit_fee(size, &custody, scale(2_000_000, Perpetuals::USD_DECIMALS))
+                .unwrap(),
+            9_000_000
+        );
+        assert_eq!(
+            pool.get_exit_fee(size, &custody, u64::MAX).unwrap(),
+            7_500_000
+        );
+
+        // tiers must be sorted and unused tiers must be last
+        pool.fee_tiers[1].min_volume_usd = pool.fee_tiers[0].min_volume_usd;
+        assert!(!pool.validate());
+        pool.fee_tiers[1] = FeeTier::default();
+        pool.fee_tiers[2].fee_discount = 3_000;
+        assert!(!pool.validate());
+    }
+
+    #[test]
+    fn test_get_referral_amounts() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+        assert_eq!(pool.get_referral_amounts(1_000, 2_000).unwrap(), (0, 0));
+
+        pool.referral = ReferralParams {
+            referrer_share: 2_000,
+            trader_rebate: 1_000,
+        };
+        assert!(pool.referral.validate());
+        assert_eq!(pool.get_referral_amounts(1_000, 2_000).unwrap(), (160, 80));
+        assert_eq!(pool.get_referral_amounts(1_000, 0).unwrap(), (200, 100));
+        assert_eq!(pool.get_referral_amounts(1, 0).unwrap(), (0, 0));
+
+        pool.referral.trader_rebate = 8_001;
+        assert!(!pool.referral.validate());
+    }
+
+    #[test]
+    fn test_order_params() {
+        let orders = OrderParams::default();
+        assert!(!orders.is_enabled());
+        assert!(orders.validate());
+
+        let orders = OrderParams {
+            min_delay_sec: 2,
+            max_delay_sec: 60,
+        };
+        assert!(orders.is_enabled());
+        assert!(orders.validate());
+        assert!(!orders.is_executable(100, 101).unwrap());
+        assert!(orders.is_executable(100, 102).unwrap());
+        assert!(orders.is_executable(100, 160).unwrap());
+        assert!(!orders.is_executable(100, 161).unwrap());
+        assert!(!orders.is_expired(100, 160).unwrap());
+        assert!(orders.is_expired(100, 161).unwrap());
+
+        let orders = OrderParams {
+            min_delay_sec: 61,
+            max_delay_sec: 60,
+        };
+        assert!(!orders.validate());
+    }
+
     #[test]
     fn test_get_close_amount() {
         let (pool, custody, position, token_price, token_ema_price) = get_fixture();
@@ -1986,12 +3604,95 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
     }
 
+    #[test]
+    fn test_settle_close_position() {
+        let (pool, mut custody, position, token_price, _token_ema_price) = get_fixture();
+        custody.assets.owned = scale(100, 9);
+        custody.assets.collateral = position.collateral_amount;
+        custody.assets.locked = position.locked_amount;
+        custody
+            .add_position(&position, &token_price, 0, None)
+            .unwrap();
+        let mut collateral_custody = custody.clone();
+
+        // long position settled against the same custody, pays out 1 token of profit
+        let fee_amount = scale(1, 8);
+        let referrer_reward = pool
+            .settle_close_position(
+                &position,
+                true,
+                false,
+                scale(2, 9),
+                (fee_amount, 2_500),
+                scale(1, 7),
+                (scale(25_000, Perpetuals::USD_DECIMALS), 0),
+                &mut custody,
+                &mut collateral_custody,
+                None,
+                1,
+            )
+            .unwrap();
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount).unwrap();
+        assert_eq!(referrer_reward, scale(1, 7));
+        assert_eq!(custody.assets.locked, 0);
+        assert_eq!(custody.assets.collateral, 0);
+        assert_eq!(custody.assets.protocol_fees, protocol_fee);
+        assert_eq!(
+            custody.assets.owned,
+            scale(99, 9) - protocol_fee - referrer_reward
+        );
+        assert_eq!(custody.collected_fees.close_position_usd, 2_500);
+        assert_eq!(custody.volume_stats.close_position_usd, position.size_usd);
+        assert_eq!(
+            custody.trade_stats.profit_usd,
+            scale(25_000, Perpetuals::USD_DECIMALS)
+        );
+        assert_eq!(custody.long_positions.open_positions, 0);
+        assert_eq!(custody.assets, collateral_custody.assets);
+
+        // tranche position, tranche can't cover the referrer reward
+        let (pool, mut custody, position, token_price, _token_ema_price) = get_fixture();
+        custody.assets.collateral = position.collateral_amount;
+        custody
+            .add_position(&position, &token_price, 0, None)
+            .unwrap();
+        let mut collateral_custody = custody.clone();
+        let mut tranche = Tranche::default();
+        tranche.assets.owned = scale(1, 9) + protocol_fee;
+        tranche.assets.locked = position.locked_amount;
+
+        let referrer_reward = pool
+            .settle_close_position(
+                &position,
+                true,
+                true,
+                scale(2, 9),
+                (fee_amount, 2_500),
+                scale(1, 7),
+                (scale(25_000, Perpetuals::USD_DECIMALS), 0),
+                &mut custody,
+                &mut collateral_custody,
+                Some(&mut tranche),
+                1,
+            )
+            .unwrap();
+        assert_eq!(referrer_reward, 0);
+        assert_eq!(tranche.assets.locked, 0);
+        assert_eq!(tranche.assets.owned, 0);
+        assert_eq!(custody.assets.owned, 0);
+        assert_eq!(custody.assets.collateral, 0);
+        assert_eq!(custody.assets.protocol_fees, protocol_fee);
+        assert_eq!(custody.collected_fees.liquidation_usd, 2_500);
+        assert_eq!(custody.volume_stats.liquidation_usd, position.size_usd);
+    }
+
     #[test]
     fn test_get_interest_amount_usd() {
         let (_pool, mut custody, mut position, _token_price, _token_ema_price) = get_fixture();
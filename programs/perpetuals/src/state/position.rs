@@ -32,6 +32,9 @@ impl Default for CollateralChange {
 #[account]
 #[derive(Default, Debug)]
 pub struct Position {
+    // account layout version, see migration::Versioned
+    pub version: u8,
+
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub custody: Pubkey,
@@ -49,8 +52,20 @@ pub struct Position {
     pub cumulative_interest_snapshot: u128,
     pub locked_amount: u64,
     pub collateral_amount: u64,
+    // tranche that locked funds for the position, default if locked in the pool
+    pub tranche: Pubkey,
+    // account that paid for the position account, receives the rent on close
+    pub rent_payer: Pubkey,
 
     pub bump: u8,
+
+    // dated market and its expiry, default for perpetual positions. Taken from the
+    // reserved space, so older positions read them as zero.
+    pub dated_market: Pubkey,
+    pub expiry_time: i64,
+
+    // reserved for future fields
+    pub reserved: [u64; 3],
 }
 
 impl Position {
@@ -62,4 +77,91 @@ impl Position {
             self.collateral_usd as u128,
         )?)
     }
+
+    // Returns the position PDA seed of the dated market, empty for perpetuals so that
+    // their addresses don't change
+    pub fn get_market_seed(&self) -> &[u8] {
+        if self.dated_market == Pubkey::default() {
+            &[]
+        } else {
+            self.dated_market.as_ref()
+        }
+    }
+
+    pub fn is_expired(&self, curtime: i64) -> bool {
+        self.expiry_time > 0 && curtime >= self.expiry_time
+    }
+
+    // Splits off a part of the given size, amounts are taken out in proportion to it.
+    // Returns the part taken out, the position keeps the rest.
+    pub fn split(&mut self, size_usd: u64) -> Result<Position> {
+        require_gt!(self.size_usd, size_usd);
+        let size_usd_total = self.size_usd;
+        let get_share = |amount: u64| -> Result<u64> {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(amount as u128, size_usd as u128)?,
+                size_usd_total as u128,
+            )?)
+        };
+
+        let part = Position {
+            size_usd,
+            borrow_size_usd: get_share(self.borrow_size_usd)?,
+            collateral_usd: get_share(self.collateral_usd)?,
+            unrealized_profit_usd: get_share(self.unrealized_profit_usd)?,
+            unrealized_loss_usd: get_share(self.unrealized_loss_usd)?,
+            locked_amount: get_share(self.locked_amount)?,
+            collateral_amount: get_share(self.collateral_amount)?,
+            ..self.clone()
+        };
+
+        self.size_usd = math::checked_sub(self.size_usd, part.size_usd)?;
+        self.borrow_size_usd = math::checked_sub(self.borrow_size_usd, part.borrow_size_usd)?;
+        self.collateral_usd = math::checked_sub(self.collateral_usd, part.collateral_usd)?;
+        self.unrealized_profit_usd =
+            math::checked_sub(self.unrealized_profit_usd, part.unrealized_profit_usd)?;
+        self.unrealized_loss_usd =
+            math::checked_sub(self.unrealized_loss_usd, part.unrealized_loss_usd)?;
+        self.locked_amount = math::checked_sub(self.locked_amount, part.locked_amount)?;
+        self.collateral_amount = math::checked_sub(self.collateral_amount, part.collateral_amount)?;
+
+        Ok(part)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let mut position = Position {
+            side: Side::Long,
+            price: 25_000,
+            size_usd: 1_000,
+            borrow_size_usd: 900,
+            collateral_usd: 100,
+            locked_amount: 33,
+            collateral_amount: 7,
+            cumulative_interest_snapshot: 10,
+            ..Position::default()
+        };
+
+        let part = position.split(250).unwrap();
+        assert_eq!(part.size_usd, 250);
+        assert_eq!(part.borrow_size_usd, 225);
+        assert_eq!(part.collateral_usd, 25);
+        assert_eq!(part.locked_amount, 8);
+        assert_eq!(part.collateral_amount, 1);
+        assert_eq!(part.price, 25_000);
+        assert_eq!(part.cumulative_interest_snapshot, 10);
+
+        assert_eq!(position.size_usd, 750);
+        assert_eq!(position.borrow_size_usd, 675);
+        assert_eq!(position.collateral_usd, 75);
+        assert_eq!(position.locked_amount, 25);
+        assert_eq!(position.collateral_amount, 6);
+
+        assert!(position.split(750).is_err());
+    }
 }
@@ -0,0 +1,98 @@
+use {
+    crate::{error::PerpetualsError, math},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct ReferralReward {
+    pub custody: Pubkey,
+    pub amount: u64,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Referral {
+    pub code: String,
+    pub referrer: Pubkey,
+    // unclaimed rewards, denominated in custody tokens
+    pub rewards: Vec<ReferralReward>,
+
+    pub bump: u8,
+}
+
+impl Referral {
+    pub const MAX_CODE_LEN: usize = 32;
+    pub const MAX_REWARDS: usize = 16;
+    pub const LEN: usize = 8
+        + Referral::MAX_CODE_LEN
+        + std::mem::size_of::<Referral>()
+        + Referral::MAX_REWARDS * std::mem::size_of::<ReferralReward>();
+
+    pub fn validate(&self) -> bool {
+        !self.code.is_empty()
+            && self.code.len() <= Referral::MAX_CODE_LEN
+            && self.rewards.len() <= Referral::MAX_REWARDS
+    }
+
+    pub fn add_reward(&mut self, custody: &Pubkey, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        if let Some(reward) = self.rewards.iter_mut().find(|r| r.custody == *custody) {
+            reward.amount = math::checked_add(reward.amount, amount)?;
+        } else {
+            require!(
+                self.rewards.len() < Referral::MAX_REWARDS,
+                PerpetualsError::ReferralRewardsLimit
+            );
+            self.rewards.push(ReferralReward {
+                custody: *custody,
+                amount,
+            });
+        }
+        Ok(())
+    }
+
+    // Removes and returns unclaimed rewards for the given custody
+    pub fn take_reward(&mut self, custody: &Pubkey) -> u64 {
+        if let Some(idx) = self.rewards.iter().position(|r| r.custody == *custody) {
+            self.rewards.remove(idx).amount
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rewards() {
+        let mut referral = Referral {
+            code: "code".to_string(),
+            ..Referral::default()
+        };
+        let custody1 = Pubkey::new_unique();
+        let custody2 = Pubkey::new_unique();
+
+        referral.add_reward(&custody1, 100).unwrap();
+        referral.add_reward(&custody2, 0).unwrap();
+        referral.add_reward(&custody2, 50).unwrap();
+        referral.add_reward(&custody1, 20).unwrap();
+        assert_eq!(referral.rewards.len(), 2);
+        assert!(referral.validate());
+
+        assert_eq!(referral.take_reward(&custody1), 120);
+        assert_eq!(referral.take_reward(&custody1), 0);
+        assert_eq!(referral.take_reward(&custody2), 50);
+        assert!(referral.rewards.is_empty());
+
+        for _ in 0..Referral::MAX_REWARDS {
+            referral.add_reward(&Pubkey::new_unique(), 1).unwrap();
+        }
+        assert!(referral.add_reward(&Pubkey::new_unique(), 1).is_err());
+        let custody = referral.rewards[0].custody;
+        referral.add_reward(&custody, 1).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use {
+    crate::{error::PerpetualsError, math, state::referral::Referral},
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct TraderStats {
+    pub owner: Pubkey,
+    // daily trading volume buckets indexed by day % VOLUME_WINDOW_DAYS,
+    // has implied USD_DECIMALS decimals
+    pub daily_volume_usd: [u64; TraderStats::VOLUME_WINDOW_DAYS],
+    // day (unix time / SECONDS_PER_DAY) of the last recorded trade
+    pub last_day: i64,
+    // referral account the trader is bound to, set on the first referred trade
+    pub referral: Pubkey,
+
+    pub bump: u8,
+}
+
+impl TraderStats {
+    pub const LEN: usize = 8 + std::mem::size_of::<TraderStats>();
+    pub const VOLUME_WINDOW_DAYS: usize = 30;
+    pub const SECONDS_PER_DAY: i64 = 86400;
+
+    // Sets up the account if it was just created by an order request
+    pub fn init_if_empty(&mut self, owner: &Pubkey, bump: u8) {
+        if self.owner == Pubkey::default() {
+            self.owner = *owner;
+            self.bump = bump;
+        }
+    }
+
+    // Returns trading volume over the last VOLUME_WINDOW_DAYS days, including current day
+    pub fn get_volume_usd(&self, curtime: i64) -> Result<u64> {
+        let day = math::checked_div(curtime, Self::SECONDS_PER_DAY)?;
+        let window = Self::VOLUME_WINDOW_DAYS as i64;
+        if day < self.last_day || math::checked_sub(day, self.last_day)? >= window {
+            return Ok(0);
+        }
+
+        // buckets of days from day - window + 1 to last_day are still in the window
+        let mut volume_usd = 0u64;
+        let first_day = std::cmp::max(math::checked_sub(day, window - 1)?, 0);
+        for bucket_day in first_day..=self.last_day {
+            volume_usd =
+                volume_usd.saturating_add(self.daily_volume_usd[(bucket_day % window) as usize]);
+        }
+        Ok(volume_usd)
+    }
+
+    pub fn add_volume(&mut self, amount_usd: u64, curtime: i64) -> Result<()> {
+        let day = math::checked_div(curtime, Self::SECONDS_PER_DAY)?;
+        let window = Self::VOLUME_WINDOW_DAYS as i64;
+        if day > self.last_day {
+            // clear buckets of the days that passed since the last trade
+            if math::checked_sub(day, self.last_day)? >= window {
+                self.daily_volume_usd = [0; Self::VOLUME_WINDOW_DAYS];
+            } else {
+                for bucket_day in (self.last_day + 1)..=day {
+                    self.daily_volume_usd[(bucket_day % window) as usize] = 0;
+                }
+            }
+            self.last_day = day;
+        }
+
+        let bucket = (self.last_day % window) as usize;
+        self.daily_volume_usd[bucket] = self.daily_volume_usd[bucket].saturating_add(amount_usd);
+
+        Ok(())
+    }
+
+    // Binds the trader to the referral on first use, later trades must use the same
+    // referral and traders can't refer themselves
+    pub fn bind_referral(&mut self, referral_key: &Pubkey, referral: &Referral) -> Result<()> {
+        require_keys_neq!(
+            referral.referrer,
+            self.owner,
+            PerpetualsError::InvalidReferral
+        );
+        if self.referral == Pubkey::default() {
+            self.referral = *referral_key;
+        }
+        require_keys_eq!(
+            self.referral,
+            *referral_key,
+            PerpetualsError::InvalidReferral
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rolling_volume() {
+        let mut stats = TraderStats::default();
+        let day = TraderStats::SECONDS_PER_DAY;
+        assert_eq!(stats.get_volume_usd(100 * day).unwrap(), 0);
+
+        stats.add_volume(1_000, 100 * day).unwrap();
+        stats.add_volume(2_000, 100 * day + 10).unwrap();
+        stats.add_volume(3_000, 110 * day).unwrap();
+        assert_eq!(stats.get_volume_usd(110 * day).unwrap(), 6_000);
+        assert_eq!(stats.get_volume_usd(129 * day).unwrap(), 6_000);
+
+        // first bucket falls out of the window
+        assert_eq!(stats.get_volume_usd(130 * day).unwrap(), 3_000);
+        stats.add_volume(4_000, 130 * day).unwrap();
+        assert_eq!(stats.get_volume_usd(130 * day).unwrap(), 7_000);
+
+        // all buckets fall out of the window
+        assert_eq!(stats.get_volume_usd(160 * day).unwrap(), 0);
+        stats.add_volume(5_000, 200 * day).unwrap();
+        assert_eq!(stats.get_volume_usd(200 * day).unwrap(), 5_000);
+    }
+
+    #[test]
+    fn test_bind_referral() {
+        let mut stats = TraderStats {
+            owner: Pubkey::new_unique(),
+            ..TraderStats::default()
+        };
+        let referral_key = Pubkey::new_unique();
+        let referral = Referral {
+            referrer: Pubkey::new_unique(),
+            ..Referral::default()
+        };
+
+        stats.bind_referral(&referral_key, &referral).unwrap();
+        assert_eq!(stats.referral, referral_key);
+        stats.bind_referral(&referral_key, &referral).unwrap();
+
+        // referral can't be changed
+        assert!(stats
+            .bind_referral(&Pubkey::new_unique(), &referral)
+            .is_err());
+
+        // self-referral
+        let mut stats = TraderStats {
+            owner: referral.referrer,
+            ..TraderStats::default()
+        };
+        assert!(stats.bind_referral(&referral_key, &referral).is_err());
+        assert_eq!(stats.referral, Pubkey::default());
+    }
+}
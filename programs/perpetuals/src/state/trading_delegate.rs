@@ -0,0 +1,119 @@
+use {crate::error::PerpetualsError, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct TradingDelegate {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    // max size of positions opened by the delegate, has implied USD_DECIMALS decimals
+    pub max_size_usd: u64,
+    // custodies the delegate can trade, any custody if empty
+    pub custodies: Vec<Pubkey>,
+    // delegation is no longer valid after this time
+    pub expiry_time: i64,
+
+    pub bump: u8,
+}
+
+impl TradingDelegate {
+    pub const MAX_CUSTODIES: usize = 8;
+    pub const LEN: usize = 8
+        + std::mem::size_of::<TradingDelegate>()
+        + TradingDelegate::MAX_CUSTODIES * std::mem::size_of::<Pubkey>();
+
+    pub fn validate(&self) -> bool {
+        // check custodies are unique
+        for i in 1..self.custodies.len() {
+            if self.custodies[i..].contains(&self.custodies[i - 1]) {
+                return false;
+            }
+        }
+
+        self.owner != self.delegate
+            && self.delegate != Pubkey::default()
+            && self.custodies.len() <= TradingDelegate::MAX_CUSTODIES
+    }
+
+    // Checks that the authority can trade the given custody on behalf of the owner,
+    // the owner is always allowed, delegates need a valid trading delegate account
+    pub fn check_authority(
+        trading_delegate: Option<&TradingDelegate>,
+        owner: &Pubkey,
+        authority: &Pubkey,
+        custody: &Pubkey,
+        curtime: i64,
+    ) -> Result<()> {
+        if authority == owner {
+            return Ok(());
+        }
+        let trading_delegate = trading_delegate.ok_or(PerpetualsError::InvalidTradingDelegate)?;
+        require!(
+            trading_delegate.owner == *owner
+                && trading_delegate.delegate == *authority
+                && curtime <= trading_delegate.expiry_time
+                && (trading_delegate.custodies.is_empty()
+                    || trading_delegate.custodies.contains(custody)),
+            PerpetualsError::InvalidTradingDelegate
+        );
+        Ok(())
+    }
+
+    // Checks position size limit, always passes for the owner
+    pub fn check_size(
+        trading_delegate: Option<&TradingDelegate>,
+        owner: &Pubkey,
+        authority: &Pubkey,
+        size_usd: u64,
+    ) -> Result<()> {
+        if authority == owner {
+            return Ok(());
+        }
+        let trading_delegate = trading_delegate.ok_or(PerpetualsError::InvalidTradingDelegate)?;
+        require_gte!(
+            trading_delegate.max_size_usd,
+            size_usd,
+            PerpetualsError::PositionAmountLimit
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_authority() {
+        let owner = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let custody = Pubkey::new_unique();
+        let mut trading_delegate = TradingDelegate {
+            owner,
+            delegate,
+            max_size_usd: 1_000,
+            custodies: vec![],
+            expiry_time: 100,
+            bump: 0,
+        };
+        assert!(trading_delegate.validate());
+
+        // owner doesn't need a delegate account
+        assert!(TradingDelegate::check_authority(None, &owner, &owner, &custody, 200).is_ok());
+        assert!(TradingDelegate::check_size(None, &owner, &owner, u64::MAX).is_ok());
+        assert!(TradingDelegate::check_authority(None, &owner, &delegate, &custody, 0).is_err());
+
+        let td = Some(&trading_delegate);
+        assert!(TradingDelegate::check_authority(td, &owner, &delegate, &custody, 100).is_ok());
+        assert!(TradingDelegate::check_authority(td, &owner, &delegate, &custody, 101).is_err());
+        assert!(TradingDelegate::check_authority(td, &delegate, &owner, &custody, 100).is_err());
+        assert!(TradingDelegate::check_size(td, &owner, &delegate, 1_000).is_ok());
+        assert!(TradingDelegate::check_size(td, &owner, &delegate, 1_001).is_err());
+
+        trading_delegate.custodies = vec![Pubkey::new_unique()];
+        let td = Some(&trading_delegate);
+        assert!(TradingDelegate::check_authority(td, &owner, &delegate, &custody, 0).is_err());
+        trading_delegate.custodies.push(custody);
+        let td = Some(&trading_delegate);
+        assert!(TradingDelegate::check_authority(td, &owner, &delegate, &custody, 0).is_ok());
+    }
+}
@@ -0,0 +1,290 @@
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{oracle::OraclePrice, perpetuals::Perpetuals},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum OffHoursMode {
+    // only closing and liquidations are allowed
+    CloseOnly,
+    // trading is allowed with off-hours spreads and leverage
+    Restricted,
+}
+
+impl Default for OffHoursMode {
+    fn default() -> Self {
+        Self::CloseOnly
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct TradingSession {
+    // seconds since Sunday 00:00 UTC, sessions can't span the end of the week
+    pub open_time: u32,
+    pub close_time: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct Holiday {
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+// Weekly trading hours of a custody, markets trade 24x7 if no sessions are set.
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct TradingSchedule {
+    // sorted sessions, unused entries are last and set to zero
+    pub sessions: [TradingSession; TradingSchedule::MAX_SESSIONS],
+    pub holidays: [Holiday; TradingSchedule::MAX_HOLIDAYS],
+    pub off_hours_mode: OffHoursMode,
+    // used in Restricted mode, have implied BPS_DECIMALS decimals
+    pub off_hours_trade_spread_long: u64,
+    pub off_hours_trade_spread_short: u64,
+    pub off_hours_max_initial_leverage: u64,
+    // max age of oracle prices accepted while the market is closed
+    pub off_hours_max_price_age_sec: u32,
+    // risk increasing trades are rejected for this period after re-open while
+    // the spot price deviates from the ema price by more than max_gap
+    pub gap_protection_sec: u32,
+    pub max_gap: u64,
+}
+
+impl TradingSchedule {
+    pub const MAX_SESSIONS: usize = 8;
+    pub const MAX_HOLIDAYS: usize = 8;
+    pub const WEEK_SEC: i64 = 7 * 86400;
+    // 1970-01-04 was the first Sunday
+    const WEEK_START_OFFSET: i64 = 3 * 86400;
+
+    pub fn validate(&self) -> bool {
+        let mut prev_close_time = 0;
+        let mut unused = false;
+        for session in &self.sessions {
+            if *session == TradingSession::default() {
+                unused = true;
+                continue;
+            }
+            if unused
+                || session.open_time < prev_close_time
+                || session.open_time >= session.close_time
+                || session.close_time as i64 > Self::WEEK_SEC
+            {
+                return false;
+            }
+            prev_close_time = session.close_time;
+        }
+
+        for holiday in &self.holidays {
+            if *holiday != Holiday::default() && holiday.start_time >= holiday.end_time {
+                return false;
+            }
+        }
+
+        (self.off_hours_mode == OffHoursMode::CloseOnly
+            || (self.off_hours_max_initial_leverage as u128) >= Perpetuals::BPS_POWER)
+            && (self.off_hours_trade_spread_long as u128) < Perpetuals::BPS_POWER
+            && (self.off_hours_trade_spread_short as u128) < Perpetuals::BPS_POWER
+            && (self.max_gap as u128) <= Perpetuals::BPS_POWER
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sessions[0] != TradingSession::default()
+    }
+
+    pub fn is_open(&self, curtime: i64) -> bool {
+        self.get_open_time(curtime).is_some()
+    }
+
+    // Returns the time the market has (re-)opened, or None if the market is closed
+    pub fn get_open_time(&self, curtime: i64) -> Option<i64> {
+        if !self.is_enabled() {
+            return Some(0);
+        }
+        if self
+            .holidays
+            .iter()
+            .any(|holiday| curtime >= holiday.start_time && curtime < holiday.end_time)
+        {
+            return None;
+        }
+
+        let week_time = (curtime - Self::WEEK_START_OFFSET).rem_euclid(Self::WEEK_SEC);
+        let week_start = curtime - week_time;
+        let session = self.sessions.iter().find(|session| {
+            week_time >= session.open_time as i64 && week_time < session.close_time as i64
+        })?;
+
+        // a holiday that ended during the session delays the open time
+        let session_open_time = week_start + session.open_time as i64;
+        Some(
+            self.holidays
+                .iter()
+                .filter(|holiday| {
+                    holiday.end_time > session_open_time && holiday.end_time <= curtime
+                })
+                .map(|holiday| holiday.end_time)
+                .fold(session_open_time, std::cmp::max),
+        )
+    }
+
+    // Returns true if off-hours spreads and leverage limits are in effect
+    pub fn is_restricted(&self, curtime: i64) -> bool {
+        self.off_hours_mode == OffHoursMode::Restricted && !self.is_open(curtime)
+    }
+
+    // Checks that trades increasing pool exposure are allowed at the current time
+    pub fn check_increase_allowed(
+        &self,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<()> {
+        let Some(open_time) = self.get_open_time(curtime) else {
+            require!(
+                self.off_hours_mode == OffHoursMode::Restricted,
+                PerpetualsError::MarketClosed
+            );
+            return Ok(());
+        };
+
+        // gap protection
+        if self.is_enabled()
+            && math::checked_sub(curtime, open_time)? < self.gap_protection_sec as i64
+        {
+            let ema_price = token_ema_price.scale_to_exponent(token_price.exponent)?;
+            let gap = if token_price.price > ema_price.price {
+                math::checked_sub(token_price.price, ema_price.price)?
+            } else {
+                math::checked_sub(ema_price.price, token_price.price)?
+            };
+            require_gt!(ema_price.price, 0, PerpetualsError::InvalidOraclePrice);
+            require!(
+                math::checked_div(
+                    math::checked_mul(gap as u128, Perpetuals::BPS_POWER)?,
+                    ema_price.price as u128
+                )? <= self.max_gap as u128,
+                PerpetualsError::MarketGapProtection
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Sunday 2023-01-01 00:00:00 UTC
+    const SUNDAY: i64 = 1_672_531_200;
+    const HOUR: u32 = 3600;
+    const DAY: u32 = 86400;
+
+    fn get_fixture() -> TradingSchedule {
+        let mut schedule = TradingSchedule {
+            off_hours_mode: OffHoursMode::CloseOnly,
+            off_hours_max_initial_leverage: 100_000,
+            gap_protection_sec: 600,
+            max_gap: 100,
+            ..Default::default()
+        };
+        // forex hours, Sunday 22:00 - Friday 22:00 UTC
+        schedule.sessions[0] = TradingSession {
+            open_time: 22 * HOUR,
+            close_time: 5 * DAY + 22 * HOUR,
+        };
+        schedule.holidays[0] = Holiday {
+            start_time: SUNDAY + 2 * DAY as i64,
+            end_time: SUNDAY + 2 * DAY as i64 + 12 * HOUR as i64,
+        };
+        schedule
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut schedule = get_fixture();
+        assert!(schedule.validate());
+        assert!(TradingSchedule::default().validate());
+
+        schedule.sessions[1] = TradingSession {
+            open_time: 5 * DAY,
+            close_time: 6 * DAY,
+        };
+        assert!(!schedule.validate());
+
+        let mut schedule = get_fixture();
+        schedule.sessions[2] = schedule.sessions[0];
+        assert!(!schedule.validate());
+
+        let mut schedule = get_fixture();
+        schedule.off_hours_mode = OffHoursMode::Restricted;
+        assert!(schedule.validate());
+        schedule.off_hours_max_initial_leverage = 0;
+        assert!(!schedule.validate());
+    }
+
+    #[test]
+    fn test_get_open_time() {
+        let schedule = get_fixture();
+        assert_eq!(TradingSchedule::default().get_open_time(SUNDAY), Some(0));
+
+        // weekend
+        assert_eq!(schedule.get_open_time(SUNDAY), None);
+        assert_eq!(schedule.get_open_time(SUNDAY - 3600), None);
+        assert_eq!(
+            schedule.get_open_time(SUNDAY + 6 * DAY as i64 + HOUR as i64),
+            None
+        );
+
+        // trading hours
+        let open_time = SUNDAY + 22 * HOUR as i64;
+        assert_eq!(schedule.get_open_time(open_time), Some(open_time));
+        assert_eq!(schedule.get_open_time(open_time + 1000), Some(open_time));
+        assert_eq!(
+            schedule.get_open_time(open_time + TradingSchedule::WEEK_SEC),
+            Some(open_time + TradingSchedule::WEEK_SEC)
+        );
+
+        // holiday
+        let holiday = schedule.holidays[0];
+        assert_eq!(schedule.get_open_time(holiday.start_time), None);
+        assert_eq!(
+            schedule.get_open_time(holiday.end_time + 1),
+            Some(holiday.end_time)
+        );
+    }
+
+    #[test]
+    fn test_check_increase_allowed() {
+        let mut schedule = get_fixture();
+        let price = OraclePrice::new(10_000, -4);
+        let ema_price = OraclePrice::new(10_200, -4);
+        let open_time = SUNDAY + 22 * HOUR as i64;
+
+        // closed
+        assert!(schedule
+            .check_increase_allowed(&price, &price, SUNDAY)
+            .is_err());
+        assert!(!schedule.is_restricted(SUNDAY));
+        schedule.off_hours_mode = OffHoursMode::Restricted;
+        assert!(schedule
+            .check_increase_allowed(&price, &price, SUNDAY)
+            .is_ok());
+        assert!(schedule.is_restricted(SUNDAY));
+
+        // gap protection after re-open
+        assert!(schedule
+            .check_increase_allowed(&price, &ema_price, open_time + 10)
+            .is_err());
+        assert!(schedule
+            .check_increase_allowed(&price, &price, open_time + 10)
+            .is_ok());
+        assert!(schedule
+            .check_increase_allowed(&price, &ema_price, open_time + 600)
+            .is_ok());
+    }
+}
@@ -0,0 +1,289 @@
+//! Isolated liquidity tranches
+//!
+//! A tranche is funded with the stablecoin of one pool custody and backs only the
+//! (virtual) custodies listed in `markets`. Tranche deposits are held in the pool
+//! custody token account but are excluded from the custody `assets.owned`, so pool
+//! LPs are not exposed to the tranche markets and tranche LPs are only exposed to them.
+//!
+//! Tranche positions accrue borrow interest at the stablecoin custody rate, which is
+//! driven by pool utilization. Tranche locks are excluded from it: positions of the
+//! pool and of all tranches share one cumulative interest index per custody, so a
+//! tranche specific rate would need its own index. Tranche utilization is instead
+//! capped by `max_utilization`.
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::{Assets, Custody, TradeStats},
+            oracle::{ActionClass, OraclePrice},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::Side,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Tranche {
+    pub name: String,
+    pub pool: Pubkey,
+    // stablecoin custody that holds tranche deposits and positions collateral
+    pub custody: Pubkey,
+    // virtual custodies backed by the tranche
+    pub markets: Vec<Pubkey>,
+    // has implied BPS_DECIMALS decimals
+    pub max_utilization: u64,
+
+    // dynamic variables
+    pub assets: Assets,
+    pub trade_stats: TradeStats,
+
+    pub bump: u8,
+    pub lp_token_bump: u8,
+    pub inception_time: i64,
+}
+
+impl Tranche {
+    pub const MAX_MARKETS: usize = 16;
+    pub const LEN: usize = 8
+        + 64
+        + std::mem::size_of::<Tranche>()
+        + Tranche::MAX_MARKETS * std::mem::size_of::<Pubkey>();
+
+    pub fn validate(&self) -> bool {
+        // check markets are unique
+        for i in 1..self.markets.len() {
+            if self.markets[i..].contains(&self.markets[i - 1]) {
+                return false;
+            }
+        }
+
+        !self.name.is_empty()
+            && self.name.len() <= 64
+            && !self.markets.is_empty()
+            && self.markets.len() <= Tranche::MAX_MARKETS
+            && (self.max_utilization as u128) <= Perpetuals::BPS_POWER
+    }
+
+    pub fn lock_funds(&mut self, amount: u64) -> Result<()> {
+        self.assets.locked = math::checked_add(self.assets.locked, amount)?;
+
+        // check for max utilization
+        if self.max_utilization > 0
+            && (self.max_utilization as u128) < Perpetuals::BPS_POWER
+            && self.assets.owned > 0
+        {
+            let current_utilization = math::checked_as_u64(math::checked_div(
+                math::checked_mul(self.assets.locked as u128, Perpetuals::BPS_POWER)?,
+                self.assets.owned as u128,
+            )?)?;
+            require!(
+                current_utilization <= self.max_utilization,
+                PerpetualsError::MaxUtilization
+            );
+        }
+
+        if self.assets.owned < self.assets.locked {
+            Err(ProgramError::InsufficientFunds.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn unlock_funds(&mut self, amount: u64) -> Result<()> {
+        if amount > self.assets.locked {
+            self.assets.locked = 0;
+        } else {
+            self.assets.locked = math::checked_sub(self.assets.locked, amount)?;
+        }
+
+        Ok(())
+    }
+
+    // Checks if the amount can be paid out of tranche deposits. Collateral held in the
+    // custody belongs to traders of all markets and is not available to the tranche.
+    pub fn check_available_amount(&self, amount: u64) -> Result<bool> {
+        let available_amount = self.assets.owned.saturating_sub(self.assets.locked);
+        Ok(available_amount >= amount)
+    }
+
+    // Moves the settled position balance between the trader and the tranche
+    pub fn settle_position(
+        &mut self,
+        collateral_amount: u64,
+        transfer_amount: u64,
+        profit_usd: u64,
+        loss_usd: u64,
+    ) -> Result<()> {
+        if transfer_amount > collateral_amount {
+            let amount_lost = transfer_amount.saturating_sub(collateral_amount);
+            self.assets.owned = math::checked_sub(self.assets.owned, amount_lost)?;
+        } else {
+            let amount_gained = collateral_amount.saturating_sub(transfer_amount);
+            self.assets.owned = math::checked_add(self.assets.owned, amount_gained)?;
+        }
+
+        self.trade_stats.profit_usd = self.trade_stats.profit_usd.wrapping_add(profit_usd);
+        self.trade_stats.loss_usd = self.trade_stats.loss_usd.wrapping_add(loss_usd);
+
+        Ok(())
+    }
+
+    pub fn get_assets_under_management_usd(
+        &self,
+        pool: &Pool,
+        token_price: &OraclePrice,
+        custody: &Custody,
+        accounts: &[AccountInfo],
+        curtime: i64,
+    ) -> Result<u128> {
+        let mut tranche_amount_usd =
+            token_price.get_asset_amount_usd(self.assets.owned, custody.decimals)? as u128;
+
+        for (idx, &market) in self.markets.iter().enumerate() {
+            let oracle_idx = idx + self.markets.len();
+            if oracle_idx >= accounts.len() {
+                return Err(ProgramError::NotEnoughAccountKeys.into());
+            }
+
+            require_keys_eq!(accounts[idx].key(), market);
+            let market = Account::<Custody>::try_from(&accounts[idx])?;
+
+            require_keys_eq!(accounts[oracle_idx].key(), market.oracle.oracle_account);
+
+            let market_price = OraclePrice::new_from_oracle(
+                &accounts[oracle_idx],
+                &market.oracle,
+                curtime,
+                false,
+                ActionClass::Increase,
+            )?;
+
+            let market_ema_price = OraclePrice::new_from_oracle(
+                &accounts[oracle_idx],
+                &market.oracle,
+                curtime,
+                market.pricing.use_ema,
+                ActionClass::Increase,
+            )?;
+
+            // compute aggregate unrealized pnl
+            for side in [Side::Long, Side::Short] {
+                let (profit, loss, _) = pool.get_pnl_usd(
+                    &market.get_collective_position(side)?,
+                    &market_price,
+                    &market_ema_price,
+                    &market,
+                    &market_price,
+                    &market_ema_price,
+                    &market,
+                    curtime,
+                    false,
+                    0,
+                )?;
+
+                // adjust tranche amount by collective profit/loss
+                tranche_amount_usd = math::checked_add(tranche_amount_usd, loss as u128)?;
+                tranche_amount_usd = tranche_amount_usd.saturating_sub(profit as u128);
+            }
+        }
+
+        Ok(tranche_amount_usd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_fixture() -> Tranche {
+        Tranche {
+            name: "Forex".to_string(),
+            markets: vec![Pubkey::new_unique()],
+            max_utilization: 8_000,
+            assets: Assets {
+                owned: 1_000,
+                ..Assets::default()
+            },
+            ..Tranche::default()
+        }
+    }
+
+    #[test]
+    fn test_lock_funds() {
+        let mut tranche = get_fixture();
+
+        tranche.lock_funds(500).unwrap();
+        assert_eq!(500, tranche.assets.locked);
+
+        tranche.lock_funds(300).unwrap();
+        assert_eq!(800, tranche.assets.locked);
+
+        // over max utilization
+        assert!(tranche.lock_funds(1).is_err());
+
+        let mut tranche = get_fixture();
+        tranche.max_utilization = 0;
+        tranche.lock_funds(1_000).unwrap();
+        assert!(tranche.lock_funds(1).is_err());
+
+        let mut tranche = get_fixture();
+        tranche.lock_funds(800).unwrap();
+        tranche.unlock_funds(200).unwrap();
+        assert_eq!(600, tranche.assets.locked);
+        tranche.unlock_funds(2_000).unwrap();
+        assert_eq!(0, tranche.assets.locked);
+    }
+
+    #[test]
+    fn test_check_available_amount() {
+        let mut tranche = get_fixture();
+        tranche.lock_funds(600).unwrap();
+
+        // locked funds are not available
+        assert!(tranche.check_available_amount(400).unwrap());
+        assert!(!tranche.check_available_amount(401).unwrap());
+
+        tranche.unlock_funds(600).unwrap();
+        assert!(tranche.check_available_amount(1_000).unwrap());
+        assert!(!tranche.check_available_amount(1_001).unwrap());
+    }
+
+    #[test]
+    fn test_settle_position() {
+        let mut tranche = get_fixture();
+
+        // trader wins
+        tranche.settle_position(100, 250, 150, 0).unwrap();
+        assert_eq!(850, tranche.assets.owned);
+        assert_eq!(150, tranche.trade_stats.profit_usd);
+
+        // trader loses
+        tranche.settle_position(100, 40, 0, 60).unwrap();
+        assert_eq!(910, tranche.assets.owned);
+        assert_eq!(60, tranche.trade_stats.loss_usd);
+
+        // tranche can't pay more than it owns
+        assert!(tranche.settle_position(0, 1_000, 1_000, 0).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut tranche = get_fixture();
+        assert!(tranche.validate());
+
+        tranche.markets.push(tranche.markets[0]);
+        assert!(!tranche.validate());
+
+        tranche.markets = vec![];
+        assert!(!tranche.validate());
+
+        let mut tranche = get_fixture();
+        tranche.max_utilization = 10_001;
+        assert!(!tranche.validate());
+    }
+}
@@ -0,0 +1,148 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default, Debug)]
+pub struct UserStats {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+
+    // lifetime totals, all USD amounts have implied USD_DECIMALS decimals
+    // realized profit and loss are net of fees and interest
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    pub fees_paid_usd: u64,
+    pub interest_paid_usd: u64,
+    pub volume_usd: u64,
+    pub opened_positions: u64,
+    pub closed_positions: u64,
+    pub liquidations: u64,
+    pub collateral_added_usd: u64,
+    pub collateral_removed_usd: u64,
+    pub update_time: i64,
+
+    pub bump: u8,
+}
+
+impl UserStats {
+    pub const LEN: usize = 8 + std::mem::size_of::<UserStats>();
+
+    // Sets up the account if it was just created by a position instruction
+    pub fn init_if_empty(&mut self, owner: &Pubkey, pool: &Pubkey, bump: u8) {
+        if self.owner == Pubkey::default() {
+            self.owner = *owner;
+            self.pool = *pool;
+            self.bump = bump;
+        }
+    }
+
+    pub fn record_open(&mut self, size_usd: u64, fee_usd: u64, curtime: i64) {
+        self.volume_usd = self.volume_usd.saturating_add(size_usd);
+        self.fees_paid_usd = self.fees_paid_usd.saturating_add(fee_usd);
+        self.opened_positions = self.opened_positions.saturating_add(1);
+        self.update_time = curtime;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_close(
+        &mut self,
+        size_usd: u64,
+        fee_usd: u64,
+        interest_usd: u64,
+        profit_usd: u64,
+        loss_usd: u64,
+        liquidation: bool,
+        curtime: i64,
+    ) {
+        self.record_reduce(
+            size_usd,
+            fee_usd,
+            interest_usd,
+            profit_usd,
+            loss_usd,
+            curtime,
+        );
+        self.closed_positions = self.closed_positions.saturating_add(1);
+        if liquidation {
+            self.liquidations = self.liquidations.saturating_add(1);
+        }
+    }
+
+    // Records a partial close, the position stays open
+    pub fn record_reduce(
+        &mut self,
+        size_usd: u64,
+        fee_usd: u64,
+        interest_usd: u64,
+        profit_usd: u64,
+        loss_usd: u64,
+        curtime: i64,
+    ) {
+        self.volume_usd = self.volume_usd.saturating_add(size_usd);
+        self.fees_paid_usd = self.fees_paid_usd.saturating_add(fee_usd);
+        self.interest_paid_usd = self.interest_paid_usd.saturating_add(interest_usd);
+        self.profit_usd = self.profit_usd.saturating_add(profit_usd);
+        self.loss_usd = self.loss_usd.saturating_add(loss_usd);
+        self.update_time = curtime;
+    }
+
+    pub fn record_add_collateral(&mut self, collateral_usd: u64, curtime: i64) {
+        self.collateral_added_usd = self.collateral_added_usd.saturating_add(collateral_usd);
+        self.update_time = curtime;
+    }
+
+    pub fn record_remove_collateral(&mut self, collateral_usd: u64, curtime: i64) {
+        self.collateral_removed_usd = self.collateral_removed_usd.saturating_add(collateral_usd);
+        self.update_time = curtime;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut user_stats = UserStats::default();
+
+        user_stats.record_open(1_000, 10, 1);
+        user_stats.record_close(1_000, 12, 5, 100, 0, false, 2);
+        user_stats.record_open(2_000, 20, 3);
+        user_stats.record_close(2_000, 25, 7, 0, 300, true, 4);
+        user_stats.record_reduce(500, 0, 1, 50, 0, 5);
+        user_stats.record_add_collateral(400, 6);
+        user_stats.record_remove_collateral(150, 7);
+
+        assert_eq!(user_stats.volume_usd, 6_500);
+        assert_eq!(user_stats.fees_paid_usd, 67);
+        assert_eq!(user_stats.interest_paid_usd, 13);
+        assert_eq!(user_stats.profit_usd, 150);
+        assert_eq!(user_stats.loss_usd, 300);
+        assert_eq!(user_stats.opened_positions, 2);
+        assert_eq!(user_stats.closed_positions, 2);
+        assert_eq!(user_stats.liquidations, 1);
+        assert_eq!(user_stats.collateral_added_usd, 400);
+        assert_eq!(user_stats.collateral_removed_usd, 150);
+        assert_eq!(user_stats.update_time, 7);
+
+        // totals saturate instead of wrapping around
+        user_stats.record_open(u64::MAX, 0, 8);
+        assert_eq!(user_stats.volume_usd, u64::MAX);
+    }
+
+    #[test]
+    fn test_init_if_empty() {
+        let mut user_stats = UserStats::default();
+        let owner = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+
+        user_stats.init_if_empty(&owner, &pool, 255);
+        assert_eq!(user_stats.owner, owner);
+        assert_eq!(user_stats.pool, pool);
+        assert_eq!(user_stats.bump, 255);
+
+        user_stats.init_if_empty(&Pubkey::new_unique(), &Pubkey::new_unique(), 1);
+        assert_eq!(user_stats.owner, owner);
+        assert_eq!(user_stats.pool, pool);
+        assert_eq!(user_stats.bump, 255);
+    }
+}
@@ -2,20 +2,27 @@ pub mod get_update_pool_ix;
 pub mod test_add_custody;
 pub mod test_add_liquidity;
 pub mod test_add_pool;
+pub mod test_cancel_order;
 pub mod test_close_position;
 pub mod test_get_lp_token_price;
 pub mod test_init;
 pub mod test_liquidate;
 pub mod test_open_position;
 pub mod test_remove_liquidity;
+pub mod test_request_open_position;
 pub mod test_set_custody_config;
 pub mod test_set_custom_oracle_price;
+pub mod test_set_pool_config;
 pub mod test_swap;
+pub mod test_swap_route;
+pub mod test_transfer_position;
 pub mod test_update_pool_aum;
 
 pub use {
     get_update_pool_ix::*, test_add_custody::*, test_add_liquidity::*, test_add_pool::*,
-    test_close_position::*, test_get_lp_token_price::*, test_init::*, test_liquidate::*,
-    test_open_position::*, test_remove_liquidity::*, test_set_custody_config::*,
-    test_set_custom_oracle_price::*, test_swap::*, test_update_pool_aum::*,
+    test_cancel_order::*, test_close_position::*, test_get_lp_token_price::*, test_init::*,
+    test_liquidate::*, test_open_position::*, test_remove_liquidity::*,
+    test_request_open_position::*, test_set_custody_config::*, test_set_custom_oracle_price::*,
+    test_set_pool_config::*, test_swap::*, test_swap_route::*, test_transfer_position::*,
+    test_update_pool_aum::*,
 };
@@ -0,0 +1,81 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::CancelOrderParams,
+        state::order::{OrderType, PendingOrder},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_cancel_order(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    order_pda: &Pubkey,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    let order_account = utils::get_account::<PendingOrder>(program_test_ctx, *order_pda).await;
+
+    // Escrowed collateral is only returned for open position orders
+    let (receiving_account, order_token_account) = if order_account.order_type
+        == OrderType::OpenPosition
+    {
+        let order_token_account_pda = pda::get_order_token_account_pda(order_pda).0;
+        let order_token_account =
+            utils::get_token_account(program_test_ctx, order_token_account_pda).await;
+
+        (
+            Some(
+                utils::find_associated_token_account(&owner.pubkey(), &order_token_account.mint).0,
+            ),
+            Some(order_token_account_pda),
+        )
+    } else {
+        (None, None)
+    };
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::CancelOrder {
+            owner: owner.pubkey(),
+            rent_payer: order_account.rent_payer,
+            receiving_account,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            order: *order_pda,
+            order_token_account,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::CancelOrder {
+            params: CancelOrderParams {},
+        },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the order accounts are closed
+    {
+        assert!(!utils::account_exists(program_test_ctx, *order_pda).await);
+
+        if let Some(order_token_account) = order_token_account {
+            assert!(!utils::account_exists(program_test_ctx, order_token_account).await);
+        }
+    }
+
+    Ok(())
+}
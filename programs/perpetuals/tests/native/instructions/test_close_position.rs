@@ -2,7 +2,10 @@ use {
     super::get_update_pool_ix,
     crate::utils::{self, pda},
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
-    perpetuals::{instructions::ClosePositionParams, state::custody::Custody},
+    perpetuals::{
+        instructions::ClosePositionParams,
+        state::{custody::Custody, position::Position},
+    },
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -32,6 +35,11 @@ pub async fn test_close_position(
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
 
+    // Position account rent goes back to whoever paid for it
+    let rent_payer = utils::get_account::<Position>(program_test_ctx, *position_pda)
+        .await
+        .rent_payer;
+
     // Save account state before tx execution
     let owner_receiving_account_before =
         utils::get_token_account(program_test_ctx, receiving_account_address).await;
@@ -42,6 +50,8 @@ pub async fn test_close_position(
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
         perpetuals::accounts::ClosePosition {
+            authority: owner.pubkey(),
+            rent_payer,
             owner: owner.pubkey(),
             receiving_account: receiving_account_address,
             transfer_authority: transfer_authority_pda,
@@ -53,6 +63,12 @@ pub async fn test_close_position(
             collateral_custody: custody_pda,
             collateral_custody_oracle_account: custody_oracle_account_address,
             collateral_custody_token_account: custody_token_account_pda,
+            tranche: None,
+            user_stats: pda::get_user_stats_pda(&owner.pubkey(), pool_pda).0,
+            trader_stats: None,
+            referral: None,
+            trading_delegate: None,
+            system_program: anchor_lang::system_program::ID,
             token_program: anchor_spl::token::ID,
         }
         .to_account_metas(None),
@@ -20,10 +20,10 @@ pub async fn test_liquidate(
     position_pda: &Pubkey,
 ) -> std::result::Result<(), BanksClientError> {
     // ==== WHEN ==============================================================
-    let owner = {
+    let (owner, rent_payer) = {
         let position_account =
             utils::get_account::<Position>(program_test_ctx, *position_pda).await;
-        position_account.owner
+        (position_account.owner, position_account.rent_payer)
     };
 
     // Prepare PDA and addresses
@@ -56,6 +56,7 @@ pub async fn test_liquidate(
             signer: liquidator.pubkey(),
             rewards_receiving_account: rewards_receiving_account_address,
             receiving_account: receiving_account_address,
+            rent_payer,
             transfer_authority: transfer_authority_pda,
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
@@ -65,6 +66,8 @@ pub async fn test_liquidate(
             collateral_custody: custody_pda,
             collateral_custody_oracle_account: custody_oracle_account_address,
             collateral_custody_token_account: custody_token_account_pda,
+            tranche: None,
+            user_stats: pda::get_user_stats_pda(&owner, pool_pda).0,
             token_program: anchor_spl::token::ID,
         }
         .to_account_metas(None),
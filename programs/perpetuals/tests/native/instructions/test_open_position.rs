@@ -15,6 +15,7 @@ pub async fn test_open_position(
     program_test_ctx: &RwLock<ProgramTestContext>,
     owner: &Keypair,
     payer: &Keypair,
+    rent_payer: Option<&Keypair>,
     pool_pda: &Pubkey,
     custody_token_mint: &Pubkey,
     params: OpenPositionParams,
@@ -43,9 +44,16 @@ pub async fn test_open_position(
     let custody_token_account_before =
         utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
 
+    let mut signers = vec![owner, payer];
+    if let Some(rent_payer) = rent_payer {
+        signers.push(rent_payer);
+    }
+
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
         perpetuals::accounts::OpenPosition {
+            authority: owner.pubkey(),
+            rent_payer: rent_payer.map(|rent_payer| rent_payer.pubkey()),
             owner: owner.pubkey(),
             funding_account: funding_account_address,
             transfer_authority: transfer_authority_pda,
@@ -57,13 +65,19 @@ pub async fn test_open_position(
             collateral_custody: custody_pda,
             collateral_custody_oracle_account: custody_oracle_account_address,
             collateral_custody_token_account: custody_token_account_pda,
+            tranche: None,
+            user_stats: pda::get_user_stats_pda(&owner.pubkey(), pool_pda).0,
+            dated_market: None,
+            trader_stats: None,
+            referral: None,
+            trading_delegate: None,
             system_program: anchor_lang::system_program::ID,
             token_program: anchor_spl::token::ID,
         }
         .to_account_metas(None),
         perpetuals::instruction::OpenPosition { params },
         Some(&payer.pubkey()),
-        &[owner, payer],
+        &signers,
         Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
         None,
     )
@@ -99,6 +113,10 @@ pub async fn test_open_position(
         assert_eq!(position_account.unrealized_loss_usd, 0);
         assert_eq!(position_account.collateral_amount, params.collateral);
         assert_eq!(position_account.bump, position_bump);
+        assert_eq!(
+            position_account.rent_payer,
+            rent_payer.unwrap_or(owner).pubkey()
+        );
     }
 
     Ok((position_pda, position_bump))
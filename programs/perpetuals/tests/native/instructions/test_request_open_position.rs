@@ -0,0 +1,109 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::RequestOpenPositionParams, state::order::PendingOrder},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_request_open_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    rent_payer: Option<&Keypair>,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: RequestOpenPositionParams,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+
+    let position_pda =
+        pda::get_position_pda(&owner.pubkey(), pool_pda, &custody_pda, params.side).0;
+    let (order_pda, order_bump) =
+        pda::get_order_pda(&owner.pubkey(), pool_pda, &custody_pda, params.side);
+    let order_token_account_pda = pda::get_order_token_account_pda(&order_pda).0;
+
+    let funding_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
+
+    // Save account state before tx execution
+    let owner_funding_account_before =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+
+    let mut signers = vec![owner, payer];
+    if let Some(rent_payer) = rent_payer {
+        signers.push(rent_payer);
+    }
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::RequestOpenPosition {
+            authority: owner.pubkey(),
+            rent_payer: rent_payer.map(|rent_payer| rent_payer.pubkey()),
+            owner: owner.pubkey(),
+            funding_account: funding_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            position: position_pda,
+            order: order_pda,
+            order_token_account: order_token_account_pda,
+            custody: custody_pda,
+            collateral_custody: custody_pda,
+            collateral_custody_token_mint: *custody_token_mint,
+            trading_delegate: None,
+            user_stats: pda::get_user_stats_pda(&owner.pubkey(), pool_pda).0,
+            trader_stats: pda::get_trader_stats_pda(&owner.pubkey()).0,
+            referral: None,
+            dated_market: None,
+            system_program: anchor_lang::system_program::ID,
+            token_program: anchor_spl::token::ID,
+            rent: solana_program::sysvar::rent::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::RequestOpenPosition { params },
+        Some(&payer.pubkey()),
+        &signers,
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the balance change
+    {
+        let owner_funding_account_after =
+            utils::get_token_account(program_test_ctx, funding_account_address).await;
+        let order_token_account =
+            utils::get_token_account(program_test_ctx, order_token_account_pda).await;
+
+        assert_eq!(
+            owner_funding_account_after.amount,
+            owner_funding_account_before.amount - params.collateral
+        );
+        assert_eq!(order_token_account.amount, params.collateral);
+    }
+
+    // Check the order
+    {
+        let order_account = utils::get_account::<PendingOrder>(program_test_ctx, order_pda).await;
+
+        assert_eq!(order_account.owner, owner.pubkey());
+        assert_eq!(
+            order_account.rent_payer,
+            rent_payer.unwrap_or(owner).pubkey()
+        );
+        assert_eq!(order_account.pool, *pool_pda);
+        assert_eq!(order_account.custody, custody_pda);
+        assert_eq!(order_account.side, params.side);
+        assert_eq!(order_account.bump, order_bump);
+    }
+
+    Ok((order_pda, order_bump))
+}
@@ -67,6 +67,8 @@ pub async fn test_swap(
             dispensing_custody: dispensing_custody_pda,
             dispensing_custody_oracle_account: dispensing_custody_oracle_account_address,
             dispensing_custody_token_account: dispensing_custody_token_account_pda,
+            trader_stats: None,
+            referral: None,
             token_program: anchor_spl::token::ID,
         }
         .to_account_metas(None),
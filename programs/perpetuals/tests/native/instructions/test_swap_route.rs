@@ -0,0 +1,109 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::AccountMeta, ToAccountMetas},
+    perpetuals::{instructions::SwapRouteParams, state::custody::Custody},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_swap_route(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    params: SwapRouteParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    // Leg accounts, funding mint is the first received mint, receiving mint the last dispensed one
+    let mut remaining_accounts = Vec::new();
+    let mut funding_mint = None;
+    let mut receiving_mint = None;
+    for leg in &params.route {
+        let receiving_custody_account =
+            utils::get_account::<Custody>(program_test_ctx, leg.receiving_custody).await;
+        let dispensing_custody_account =
+            utils::get_account::<Custody>(program_test_ctx, leg.dispensing_custody).await;
+
+        if funding_mint.is_none() {
+            funding_mint = Some(receiving_custody_account.mint);
+        }
+        receiving_mint = Some(dispensing_custody_account.mint);
+
+        for (pubkey, is_writable) in [
+            (leg.pool, true),
+            (leg.receiving_custody, true),
+            (receiving_custody_account.oracle.oracle_account, false),
+            (receiving_custody_account.token_account, true),
+            (leg.dispensing_custody, true),
+            (dispensing_custody_account.oracle.oracle_account, false),
+            (dispensing_custody_account.token_account, true),
+        ] {
+            remaining_accounts.push(AccountMeta {
+                pubkey,
+                is_signer: false,
+                is_writable,
+            });
+        }
+    }
+
+    let funding_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), &funding_mint.unwrap()).0;
+    let receiving_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), &receiving_mint.unwrap()).0;
+
+    // Save account state before tx execution
+    let owner_funding_account_before =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    let min_amount_out = params.min_amount_out;
+
+    let accounts_meta = {
+        let accounts = perpetuals::accounts::SwapRoute {
+            owner: owner.pubkey(),
+            funding_account: funding_account_address,
+            receiving_account: receiving_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            trader_stats: None,
+            referral: None,
+            token_program: anchor_spl::token::ID,
+        };
+
+        let mut accounts_meta = accounts.to_account_metas(None);
+        accounts_meta.append(&mut remaining_accounts);
+
+        accounts_meta
+    };
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::SwapRoute { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the balance change
+    let owner_funding_account_after =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+    let owner_receiving_account_after =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    assert!(owner_funding_account_after.amount < owner_funding_account_before.amount);
+    assert!(
+        owner_receiving_account_after.amount
+            >= owner_receiving_account_before.amount + min_amount_out
+    );
+
+    Ok(())
+}
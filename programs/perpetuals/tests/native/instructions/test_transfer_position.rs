@@ -0,0 +1,152 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::TransferPositionParams,
+        state::{position::Position, user_stats::UserStats},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_transfer_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    new_owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+    params: TransferPositionParams,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    let position_account = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+
+    let (new_position_pda, new_position_bump) = pda::get_position_pda(
+        &new_owner.pubkey(),
+        pool_pda,
+        &position_account.custody,
+        position_account.side,
+    );
+    let order_pda = pda::get_order_pda(
+        &owner.pubkey(),
+        pool_pda,
+        &position_account.custody,
+        position_account.side,
+    )
+    .0;
+
+    // The new owner only signs if the transfer requires its consent
+    let mut signers = vec![owner, payer];
+    if params.require_new_owner_signature {
+        signers.push(new_owner);
+    }
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::TransferPosition {
+            owner: owner.pubkey(),
+            new_owner: new_owner.pubkey(),
+            rent_payer: position_account.rent_payer,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            position: *position_pda,
+            new_position: new_position_pda,
+            new_user_stats: pda::get_user_stats_pda(&new_owner.pubkey(), pool_pda).0,
+            order: order_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::TransferPosition { params },
+        Some(&payer.pubkey()),
+        &signers,
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the new owner stats exist so that keepers can update them
+    {
+        let new_user_stats_account = utils::get_account::<UserStats>(
+            program_test_ctx,
+            pda::get_user_stats_pda(&new_owner.pubkey(), pool_pda).0,
+        )
+        .await;
+
+        assert_eq!(new_user_stats_account.owner, new_owner.pubkey());
+        assert_eq!(new_user_stats_account.pool, *pool_pda);
+    }
+
+    // Check the old position is closed
+    assert!(!utils::account_exists(program_test_ctx, *position_pda).await);
+
+    // Check the new position
+    {
+        let new_position_account =
+            utils::get_account::<Position>(program_test_ctx, new_position_pda).await;
+
+        assert_eq!(new_position_account.owner, new_owner.pubkey());
+        assert_eq!(new_position_account.rent_payer, owner.pubkey());
+        assert_eq!(new_position_account.bump, new_position_bump);
+
+        // All accounting is carried over as is
+        assert_eq!(new_position_account.pool, position_account.pool);
+        assert_eq!(new_position_account.custody, position_account.custody);
+        assert_eq!(
+            new_position_account.collateral_custody,
+            position_account.collateral_custody
+        );
+        assert_eq!(new_position_account.open_time, position_account.open_time);
+        assert_eq!(
+            new_position_account.update_time,
+            position_account.update_time
+        );
+        assert_eq!(new_position_account.side, position_account.side);
+        assert_eq!(new_position_account.price, position_account.price);
+        assert_eq!(new_position_account.size_usd, position_account.size_usd);
+        assert_eq!(
+            new_position_account.borrow_size_usd,
+            position_account.borrow_size_usd
+        );
+        assert_eq!(
+            new_position_account.collateral_usd,
+            position_account.collateral_usd
+        );
+        assert_eq!(
+            new_position_account.unrealized_profit_usd,
+            position_account.unrealized_profit_usd
+        );
+        assert_eq!(
+            new_position_account.unrealized_loss_usd,
+            position_account.unrealized_loss_usd
+        );
+        assert_eq!(
+            new_position_account.cumulative_interest_snapshot,
+            position_account.cumulative_interest_snapshot
+        );
+        assert_eq!(
+            new_position_account.locked_amount,
+            position_account.locked_amount
+        );
+        assert_eq!(
+            new_position_account.collateral_amount,
+            position_account.collateral_amount
+        );
+        assert_eq!(new_position_account.tranche, position_account.tranche);
+        assert_eq!(
+            new_position_account.dated_market,
+            position_account.dated_market
+        );
+        assert_eq!(
+            new_position_account.expiry_time,
+            position_account.expiry_time
+        );
+    }
+
+    Ok((new_position_pda, new_position_bump))
+}
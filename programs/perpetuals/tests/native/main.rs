@@ -7,6 +7,7 @@ pub async fn test_integration() {
     tests_suite::basic_interactions().await;
 
     tests_suite::swap::insuffisient_fund().await;
+    tests_suite::swap::swap_route().await;
 
     tests_suite::liquidity::fixed_fees().await;
     tests_suite::liquidity::insuffisient_fund().await;
@@ -15,6 +16,10 @@ pub async fn test_integration() {
     tests_suite::position::min_max_leverage().await;
     tests_suite::position::liquidate_position().await;
     tests_suite::position::max_user_profit().await;
+    tests_suite::position::rent_payer().await;
+    tests_suite::position::transfer_position().await;
 
     tests_suite::lp_token::lp_token_price().await;
+
+    tests_suite::oracle::switchboard_oracle().await;
 }
@@ -104,6 +104,7 @@ pub async fn basic_interactions() {
             &test_setup.program_test_ctx,
             martin,
             &test_setup.payer_keypair,
+            None,
             &test_setup.pool_pda,
             eth_mint,
             OpenPositionParams {
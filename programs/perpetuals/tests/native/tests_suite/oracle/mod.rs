@@ -0,0 +1,3 @@
+pub mod switchboard_oracle;
+
+pub use switchboard_oracle::*;
@@ -0,0 +1,138 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    solana_sdk::pubkey::Pubkey,
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+// Switchboard values have 18 decimals
+fn switchboard_value(amount: i128) -> i128 {
+    amount * 10i128.pow(18)
+}
+
+pub async fn switchboard_oracle() {
+    let test_setup = utils::TestSetup::new(
+        vec![utils::UserParam {
+            name: "alice",
+            token_balances: hashmap! {
+                "usdc" => utils::scale(100_000, USDC_DECIMALS),
+                "eth" => utils::scale(50, ETH_DECIMALS),
+            },
+        }],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(15_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+    let eth_pull_feed = Pubkey::new_unique();
+
+    // Move ETH custody to a Switchboard feed with the same price
+    {
+        utils::set_switchboard_pull_feed(
+            &test_setup.program_test_ctx,
+            &eth_pull_feed,
+            switchboard_value(1_500),
+            switchboard_value(10),
+        )
+        .await;
+
+        utils::set_custody_oracle(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &eth_custody_pda,
+            utils::oracle_params_switchboard(eth_pull_feed),
+            &multisig_signers,
+        )
+        .await;
+    }
+
+    // LP token price is the same as with the custom oracle
+    assert_eq!(
+        instructions::test_get_lp_token_price(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &test_setup.lp_token_mint_pda,
+        )
+        .await
+        .unwrap(),
+        1_074_388
+    );
+
+    // Makes ETH price to increase of 10%
+    utils::set_switchboard_pull_feed(
+        &test_setup.program_test_ctx,
+        &eth_pull_feed,
+        switchboard_value(1_650),
+        switchboard_value(10),
+    )
+    .await;
+
+    assert_eq!(
+        instructions::test_get_lp_token_price(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &test_setup.lp_token_mint_pda,
+        )
+        .await
+        .unwrap(),
+        1_128_110
+    );
+}
@@ -106,6 +106,7 @@ pub async fn liquidate_position() {
         &test_setup.program_test_ctx,
         martin,
         &test_setup.payer_keypair,
+        None,
         &test_setup.pool_pda,
         eth_mint,
         OpenPositionParams {
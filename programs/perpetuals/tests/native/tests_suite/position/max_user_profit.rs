@@ -100,6 +100,7 @@ pub async fn max_user_profit() {
         &test_setup.program_test_ctx,
         martin,
         &test_setup.payer_keypair,
+        None,
         &test_setup.pool_pda,
         eth_mint,
         OpenPositionParams {
@@ -98,6 +98,7 @@ pub async fn min_max_leverage() {
         &test_setup.program_test_ctx,
         martin,
         &test_setup.payer_keypair,
+        None,
         &test_setup.pool_pda,
         eth_mint,
         OpenPositionParams {
@@ -116,6 +117,7 @@ pub async fn min_max_leverage() {
         &test_setup.program_test_ctx,
         martin,
         &test_setup.payer_keypair,
+        None,
         &test_setup.pool_pda,
         eth_mint,
         OpenPositionParams {
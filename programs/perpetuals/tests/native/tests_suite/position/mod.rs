@@ -1,5 +1,10 @@
 pub mod liquidate_position;
 pub mod max_user_profit;
 pub mod min_max_leverage;
+pub mod rent_payer;
+pub mod transfer_position;
 
-pub use {liquidate_position::*, max_user_profit::*, min_max_leverage::*};
+pub use {
+    liquidate_position::*, max_user_profit::*, min_max_leverage::*, rent_payer::*,
+    transfer_position::*,
+};
@@ -0,0 +1,283 @@
+use {
+    crate::{instructions, utils},
+    anchor_spl::token::TokenAccount,
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            ClosePositionParams, OpenPositionParams, RequestOpenPositionParams,
+            SetPoolConfigParams, TransferPositionParams,
+        },
+        state::{
+            custody::PricingParams,
+            order::PendingOrder,
+            pool::{OrderParams, Pool},
+            position::{Position, Side},
+            trader_stats::TraderStats,
+            user_stats::UserStats,
+        },
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn rent_payer() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "sponsor",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        // Expressed in BPS, with BPS = 10_000
+                        // 50_000 = x5, 100_000 = x10
+                        max_leverage: 100_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let sponsor = test_setup.get_user_keypair_by_name("sponsor");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let ctx = &test_setup.program_test_ctx;
+
+    let position_rent = utils::get_minimum_balance(ctx, Position::LEN).await;
+    let user_stats_rent = utils::get_minimum_balance(ctx, UserStats::LEN).await;
+
+    // Martin: Open 1 ETH long position x5, sponsor pays for the position account
+    let martin_balance_before = utils::get_balance(ctx, martin.pubkey()).await;
+    let sponsor_balance_before = utils::get_balance(ctx, sponsor.pubkey()).await;
+
+    let position_pda = instructions::test_open_position(
+        ctx,
+        martin,
+        &test_setup.payer_keypair,
+        Some(sponsor),
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Martin is reimbursed for the position account, only his stats account is left to pay
+    assert_eq!(
+        utils::get_balance(ctx, martin.pubkey()).await,
+        martin_balance_before - user_stats_rent
+    );
+    assert_eq!(
+        utils::get_balance(ctx, sponsor.pubkey()).await,
+        sponsor_balance_before - position_rent
+    );
+
+    // Martin: Transfer the position to Alice, the old position rent returns to the sponsor,
+    // Martin pays for the new position and Alice stats accounts
+    let martin_balance_before = utils::get_balance(ctx, martin.pubkey()).await;
+
+    let new_position_pda = instructions::test_transfer_position(
+        ctx,
+        martin,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+        TransferPositionParams {
+            require_new_owner_signature: true,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    assert_eq!(
+        utils::get_balance(ctx, sponsor.pubkey()).await,
+        sponsor_balance_before
+    );
+    assert_eq!(
+        utils::get_balance(ctx, martin.pubkey()).await,
+        martin_balance_before - position_rent - user_stats_rent
+    );
+
+    // Alice: Close the position, its rent returns to Martin who paid for it on transfer
+    let martin_balance_before = utils::get_balance(ctx, martin.pubkey()).await;
+
+    instructions::test_close_position(
+        ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &new_position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_450, ETH_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        utils::get_balance(ctx, martin.pubkey()).await,
+        martin_balance_before + position_rent
+    );
+
+    // Enable delayed orders
+    {
+        let pool_account = utils::get_account::<Pool>(ctx, test_setup.pool_pda).await;
+
+        instructions::test_set_pool_config(
+            ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            SetPoolConfigParams {
+                adl: pool_account.adl,
+                orders: OrderParams {
+                    min_delay_sec: 0,
+                    max_delay_sec: 5,
+                },
+                fee_tiers: pool_account.fee_tiers,
+                referral: pool_account.referral,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Martin: Request a position, sponsor pays for the order accounts and pre-funds the position
+    let order_rent = utils::get_minimum_balance(ctx, PendingOrder::LEN).await
+        + utils::get_minimum_balance(ctx, TokenAccount::LEN).await
+        + position_rent;
+    let trader_stats_rent = utils::get_minimum_balance(ctx, TraderStats::LEN).await;
+
+    let martin_balance_before = utils::get_balance(ctx, martin.pubkey()).await;
+    let sponsor_balance_before = utils::get_balance(ctx, sponsor.pubkey()).await;
+
+    let order_pda = instructions::test_request_open_position(
+        ctx,
+        martin,
+        &test_setup.payer_keypair,
+        Some(sponsor),
+        &test_setup.pool_pda,
+        eth_mint,
+        RequestOpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+            size: utils::scale_f64(2.5, ETH_DECIMALS),
+            side: Side::Long,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Martin is reimbursed for the order accounts, only his trader stats account is left to pay
+    assert_eq!(
+        utils::get_balance(ctx, martin.pubkey()).await,
+        martin_balance_before - trader_stats_rent
+    );
+    assert_eq!(
+        utils::get_balance(ctx, sponsor.pubkey()).await,
+        sponsor_balance_before - order_rent
+    );
+
+    // Martin: Cancel the expired order, all order accounts rent returns to the sponsor
+    utils::warp_forward(ctx, 6).await;
+
+    instructions::test_cancel_order(
+        ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &order_pda,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        utils::get_balance(ctx, sponsor.pubkey()).await,
+        sponsor_balance_before
+    );
+}
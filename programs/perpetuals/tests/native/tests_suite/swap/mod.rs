@@ -1,3 +1,4 @@
 pub mod insuffisient_fund;
+pub mod swap_route;
 
-pub use insuffisient_fund::*;
+pub use {insuffisient_fund::*, swap_route::*};
@@ -0,0 +1,185 @@
+use {
+    crate::{
+        instructions,
+        utils::{self, pda},
+    },
+    maplit::hashmap,
+    perpetuals::instructions::{SwapLeg, SwapRouteParams},
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+const BTC_DECIMALS: u8 = 8;
+
+pub async fn swap_route() {
+    let custody_params = |mint_name, is_stable, initial_price, initial_conf, liquidity_amount| {
+        utils::SetupCustodyWithLiquidityParams {
+            setup_custody_params: utils::SetupCustodyParams {
+                mint_name,
+                is_stable,
+                is_virtual: false,
+                target_ratio: utils::ratio_from_percentage(50.0),
+                min_ratio: utils::ratio_from_percentage(0.0),
+                max_ratio: utils::ratio_from_percentage(100.0),
+                initial_price,
+                initial_conf,
+                pricing_params: None,
+                permissions: None,
+                fees: None,
+                borrow_rate: None,
+            },
+            liquidity_amount,
+            payer_user_name: "alice",
+        }
+    };
+
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(7_500, USDC_DECIMALS),
+                    "eth" => utils::scale(10, ETH_DECIMALS),
+                    "btc" => utils::scale(1, BTC_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+            utils::MintParam {
+                name: "btc",
+                decimals: BTC_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            custody_params(
+                "usdc",
+                true,
+                utils::scale(1, USDC_DECIMALS),
+                utils::scale_f64(0.01, USDC_DECIMALS),
+                utils::scale(7_500, USDC_DECIMALS),
+            ),
+            custody_params(
+                "eth",
+                false,
+                utils::scale(1_500, ETH_DECIMALS),
+                utils::scale(10, ETH_DECIMALS),
+                utils::scale(5, ETH_DECIMALS),
+            ),
+        ],
+    )
+    .await;
+
+    // Second pool to route ETH to BTC
+    let (second_pool_pda, _) = test_setup
+        .add_pool(
+            "second_pool",
+            vec![
+                custody_params(
+                    "eth",
+                    false,
+                    utils::scale(1_500, ETH_DECIMALS),
+                    utils::scale(10, ETH_DECIMALS),
+                    utils::scale(5, ETH_DECIMALS),
+                ),
+                custody_params(
+                    "btc",
+                    false,
+                    utils::scale(30_000, BTC_DECIMALS),
+                    utils::scale(100, BTC_DECIMALS),
+                    utils::scale_f64(0.25, BTC_DECIMALS),
+                ),
+            ],
+        )
+        .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let btc_mint = &test_setup.get_mint_by_name("btc");
+
+    let main_pool_pda = &test_setup.pool_pda;
+    let usdc_to_eth = SwapLeg {
+        pool: *main_pool_pda,
+        receiving_custody: pda::get_custody_pda(main_pool_pda, usdc_mint).0,
+        dispensing_custody: pda::get_custody_pda(main_pool_pda, eth_mint).0,
+    };
+    let eth_to_btc = SwapLeg {
+        pool: second_pool_pda,
+        receiving_custody: pda::get_custody_pda(&second_pool_pda, eth_mint).0,
+        dispensing_custody: pda::get_custody_pda(&second_pool_pda, btc_mint).0,
+    };
+
+    // Route using the same custody twice should fail
+    {
+        // Martin: Swap 100 USDC for ETH in main pool then main pool ETH for BTC
+        assert!(instructions::test_swap_route(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            SwapRouteParams {
+                amount_in: utils::scale(100, USDC_DECIMALS),
+                min_amount_out: 0,
+                route: vec![
+                    usdc_to_eth,
+                    SwapLeg {
+                        receiving_custody: usdc_to_eth.dispensing_custody,
+                        ..eth_to_btc
+                    },
+                ],
+            },
+        )
+        .await
+        .is_err());
+    }
+
+    // Route returning less than the minimum amount should fail
+    {
+        // Martin: Swap 100 USDC for at least 1 BTC
+        assert!(instructions::test_swap_route(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            SwapRouteParams {
+                amount_in: utils::scale(100, USDC_DECIMALS),
+                min_amount_out: utils::scale(1, BTC_DECIMALS),
+                route: vec![usdc_to_eth, eth_to_btc],
+            },
+        )
+        .await
+        .is_err());
+    }
+
+    // Two legs route
+    {
+        // Martin: Swap 100 USDC for ETH in main pool then ETH for BTC in second pool
+        instructions::test_swap_route(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            SwapRouteParams {
+                amount_in: utils::scale(100, USDC_DECIMALS),
+                min_amount_out: utils::scale_f64(0.002, BTC_DECIMALS),
+                route: vec![usdc_to_eth, eth_to_btc],
+            },
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -6,7 +6,7 @@ use {
         instructions::InitParams,
         state::{
             custody::{BorrowRateParams, Fees, FeesMode, PricingParams},
-            oracle::{OracleParams, OracleType},
+            oracle::{OracleParams, OracleType, SwitchboardPullFeed},
             perpetuals::Permissions,
         },
     },
@@ -78,9 +78,35 @@ pub fn oracle_params_regular(oracle_account: Pubkey) -> OracleParams {
         oracle_authority: Pubkey::default(),
         max_price_error: 1_000_000,
         max_price_age_sec: 30,
+        max_reducing_price_age_sec: 30,
     }
 }
 
+pub fn oracle_params_switchboard(oracle_account: Pubkey) -> OracleParams {
+    OracleParams {
+        oracle_type: OracleType::Switchboard,
+        ..oracle_params_regular(oracle_account)
+    }
+}
+
+pub const SWITCHBOARD_ON_DEMAND_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+
+// Switchboard On-Demand PullFeedAccountData without historical results, values have 18 decimals
+pub fn switchboard_pull_feed_data(
+    value: i128,
+    std_dev: i128,
+    last_update_timestamp: i64,
+) -> Vec<u8> {
+    let mut data = vec![0u8; SwitchboardPullFeed::LEN];
+    data[..8].copy_from_slice(&SwitchboardPullFeed::DISCRIMINATOR);
+    data[2216..2224].copy_from_slice(&last_update_timestamp.to_le_bytes());
+    // result.value and result.std_dev
+    data[2264..2280].copy_from_slice(&value.to_le_bytes());
+    data[2280..2296].copy_from_slice(&std_dev.to_le_bytes());
+    data
+}
+
 pub fn init_params_permissions_full(min_signatures: u8) -> InitParams {
     InitParams {
         min_signatures,
@@ -59,6 +59,45 @@ pub fn get_position_pda(
     )
 }
 
+pub fn get_order_pda(
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+    side: Side,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "order".as_ref(),
+            owner.as_ref(),
+            pool_pda.as_ref(),
+            custody_pda.as_ref(),
+            &[side as u8],
+        ],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_order_token_account_pda(order_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["order_token_account".as_ref(), order_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_trader_stats_pda(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["trader_stats".as_ref(), owner.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_user_stats_pda(owner: &Pubkey, pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["user_stats".as_ref(), owner.as_ref(), pool_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
 pub fn get_custody_token_account_pda(
     pool_pda: &Pubkey,
     custody_token_mint: &Pubkey,
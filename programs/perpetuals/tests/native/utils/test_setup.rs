@@ -235,14 +235,54 @@ impl TestSetup {
             }
         }
 
+        let (pool_pda, pool_bump, lp_token_mint_pda, lp_token_mint_bump, custodies_info) =
+            TestSetup::setup_pool(
+                &program_test_ctx,
+                &multisig_members_keypairs[0],
+                payer_keypair,
+                &multisig_signers,
+                &users,
+                &mints,
+                pool_name,
+                custodies_params,
+            )
+            .await;
+
+        TestSetup {
+            program_test_ctx,
+            root_authority_keypair: utils::copy_keypair(root_authority_keypair),
+            payer_keypair: utils::copy_keypair(payer_keypair),
+            users,
+            mints,
+            multisig_members,
+            pool_pda,
+            pool_bump,
+            lp_token_mint_pda,
+            lp_token_mint_bump,
+            custodies_info,
+        }
+    }
+
+    // Create a pool with the given custodies and provide liquidity
+    #[allow(clippy::too_many_arguments)]
+    pub async fn setup_pool(
+        program_test_ctx: &RwLock<ProgramTestContext>,
+        admin: &Keypair,
+        payer_keypair: &Keypair,
+        multisig_signers: &[&Keypair],
+        users: &HashMap<String, Keypair>,
+        mints: &HashMap<String, MintInfo>,
+        pool_name: &str,
+        custodies_params: Vec<SetupCustodyWithLiquidityParams<'_>>,
+    ) -> (Pubkey, u8, Pubkey, u8, Vec<SetupCustodyInfo>) {
         // Setup the pool
         let (pool_pda, pool_bump, lp_token_mint_pda, lp_token_mint_bump) =
             instructions::test_add_pool(
-                &program_test_ctx,
-                &multisig_members_keypairs[0],
+                program_test_ctx,
+                admin,
                 payer_keypair,
                 pool_name,
-                &multisig_signers,
+                multisig_signers,
             )
             .await
             .unwrap();
@@ -305,25 +345,25 @@ impl TestSetup {
                     };
 
                     instructions::test_add_custody(
-                        &program_test_ctx,
-                        &multisig_members_keypairs[0],
+                        program_test_ctx,
+                        admin,
                         payer_keypair,
                         &pool_pda,
                         &mint_info.pubkey,
                         mint_info.decimals,
                         add_custody_params,
-                        &multisig_signers,
+                        multisig_signers,
                     )
                     .await
                     .unwrap()
                     .0
                 };
 
-                let publish_time = utils::get_current_unix_timestamp(&program_test_ctx).await;
+                let publish_time = utils::get_current_unix_timestamp(program_test_ctx).await;
 
                 instructions::test_set_custom_oracle_price(
-                    &program_test_ctx,
-                    &multisig_members_keypairs[0],
+                    program_test_ctx,
+                    admin,
                     payer_keypair,
                     &pool_pda,
                     &custody_pda,
@@ -335,7 +375,7 @@ impl TestSetup {
                         ema: custody_param.setup_custody_params.initial_price,
                         publish_time,
                     },
-                    &multisig_signers,
+                    multisig_signers,
                 )
                 .await
                 .unwrap();
@@ -355,7 +395,7 @@ impl TestSetup {
                 users.values().map(|keypair| keypair.pubkey()).collect();
 
             utils::initialize_users_token_accounts(
-                &program_test_ctx,
+                program_test_ctx,
                 vec![lp_token_mint_pda],
                 users_pubkeys,
             )
@@ -379,7 +419,7 @@ impl TestSetup {
 
             if custody_param.liquidity_amount > 0 {
                 instructions::test_add_liquidity(
-                    &program_test_ctx,
+                    program_test_ctx,
                     liquidity_provider,
                     payer_keypair,
                     &pool_pda,
@@ -415,29 +455,45 @@ impl TestSetup {
 
             for (idx, _params) in custodies_params.as_slice().iter().enumerate() {
                 utils::set_custody_ratios(
-                    &program_test_ctx,
-                    &multisig_members_keypairs[0],
+                    program_test_ctx,
+                    admin,
                     payer_keypair,
                     &custodies_info[idx].custody_pda,
                     ratios.clone(),
-                    &multisig_signers,
+                    multisig_signers,
                 )
                 .await;
             }
         }
 
-        TestSetup {
-            program_test_ctx,
-            root_authority_keypair: utils::copy_keypair(root_authority_keypair),
-            payer_keypair: utils::copy_keypair(payer_keypair),
-            users,
-            mints,
-            multisig_members,
+        (
             pool_pda,
             pool_bump,
             lp_token_mint_pda,
             lp_token_mint_bump,
             custodies_info,
-        }
+        )
+    }
+
+    // Create an additional pool with the given custodies and provide liquidity
+    pub async fn add_pool(
+        &self,
+        pool_name: &str,
+        custodies_params: Vec<SetupCustodyWithLiquidityParams<'_>>,
+    ) -> (Pubkey, Vec<SetupCustodyInfo>) {
+        let admin = self.multisig_members.values().next().unwrap();
+        let (pool_pda, _, _, _, custodies_info) = TestSetup::setup_pool(
+            &self.program_test_ctx,
+            admin,
+            &self.payer_keypair,
+            &self.get_multisig_signers(),
+            &self.users,
+            &self.mints,
+            pool_name,
+            custodies_params,
+        )
+        .await;
+
+        (pool_pda, custodies_info)
     }
 }
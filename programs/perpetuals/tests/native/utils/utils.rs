@@ -1,5 +1,5 @@
 use {
-    crate::instructions,
+    crate::{instructions, utils::fixtures},
     anchor_lang::{prelude::*, InstructionData},
     anchor_spl::token::spl_token,
     bonfida_test_utils::ProgramTestContextExt,
@@ -7,7 +7,12 @@ use {
     perpetuals::{
         instructions::SetCustodyConfigParams,
         math,
-        state::{custody::Custody, perpetuals::Perpetuals, pool::TokenRatios},
+        state::{
+            custody::Custody,
+            oracle::OracleParams,
+            perpetuals::Perpetuals,
+            pool::{Pool, TokenRatios},
+        },
     },
     solana_program::{
         clock::DEFAULT_MS_PER_SLOT, epoch_schedule::DEFAULT_SLOTS_PER_EPOCH, program_pack::Pack,
@@ -64,6 +69,27 @@ pub async fn get_token_account_balance(
     get_token_account(program_test_ctx, key).await.amount
 }
 
+pub async fn get_balance(program_test_ctx: &RwLock<ProgramTestContext>, key: Pubkey) -> u64 {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    banks_client.get_balance(key).await.unwrap()
+}
+
+pub async fn get_minimum_balance(program_test_ctx: &RwLock<ProgramTestContext>, len: usize) -> u64 {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    banks_client.get_rent().await.unwrap().minimum_balance(len)
+}
+
+pub async fn account_exists(program_test_ctx: &RwLock<ProgramTestContext>, key: Pubkey) -> bool {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    banks_client.get_account(key).await.unwrap().is_some()
+}
+
 pub async fn get_account<T: anchor_lang::AccountDeserialize>(
     program_test_ctx: &RwLock<ProgramTestContext>,
     key: Pubkey,
@@ -274,6 +300,46 @@ pub async fn set_custody_ratios(
             fees: custody_account.fees,
             borrow_rate: custody_account.borrow_rate,
             ratios,
+            leverage_tiers: custody_account.leverage_tiers,
+            spread: custody_account.spread,
+            depeg: custody_account.depeg,
+        },
+        multisig_signers,
+    )
+    .await
+    .unwrap();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn set_custody_oracle(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    custody_admin: &Keypair,
+    payer: &Keypair,
+    custody_pda: &Pubkey,
+    oracle: OracleParams,
+    multisig_signers: &[&Keypair],
+) {
+    let custody_account = get_account::<Custody>(program_test_ctx, *custody_pda).await;
+    let pool_account = get_account::<Pool>(program_test_ctx, custody_account.pool).await;
+
+    instructions::test_set_custody_config(
+        program_test_ctx,
+        custody_admin,
+        payer,
+        &custody_account.pool,
+        custody_pda,
+        SetCustodyConfigParams {
+            is_stable: custody_account.is_stable,
+            is_virtual: custody_account.is_virtual,
+            oracle,
+            pricing: custody_account.pricing,
+            permissions: custody_account.permissions,
+            fees: custody_account.fees,
+            borrow_rate: custody_account.borrow_rate,
+            ratios: pool_account.ratios,
+            leverage_tiers: custody_account.leverage_tiers,
+            spread: custody_account.spread,
+            depeg: custody_account.depeg,
         },
         multisig_signers,
     )
@@ -281,6 +347,29 @@ pub async fn set_custody_ratios(
     .unwrap();
 }
 
+// Writes a Switchboard On-Demand pull feed account published at the current time
+pub async fn set_switchboard_pull_feed(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    pull_feed: &Pubkey,
+    value: i128,
+    std_dev: i128,
+) {
+    let publish_time = get_current_unix_timestamp(program_test_ctx).await;
+    let data = fixtures::switchboard_pull_feed_data(value, std_dev, publish_time);
+
+    let mut ctx = program_test_ctx.write().await;
+    ctx.set_account(
+        pull_feed,
+        &account::AccountSharedData::from(account::Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: fixtures::SWITCHBOARD_ON_DEMAND_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        }),
+    );
+}
+
 #[derive(Clone, Copy)]
 pub struct SetupCustodyInfo {
     pub custom_oracle_pda: Pubkey,
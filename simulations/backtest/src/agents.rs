@@ -0,0 +1,104 @@
+//! Trader and liquidity provider agents
+
+use {
+    crate::{config::Config, market::Market},
+    perpetuals::state::position::{Position, Side},
+};
+
+// xorshift64* generator, keeps runs reproducible without external crates
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Uniform sample from [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+}
+
+pub struct LiquidityProvider {
+    pub deposit_usd: f64,
+    pub asset_share: f64,
+    // token amounts deposited, used for the hold benchmark
+    pub asset_amount: u64,
+    pub stable_amount: u64,
+}
+
+impl LiquidityProvider {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            deposit_usd: config.lp_deposit_usd,
+            asset_share: config.lp_asset_share,
+            asset_amount: 0,
+            stable_amount: 0,
+        }
+    }
+
+    pub fn deposit(&mut self, market: &mut Market) -> anchor_lang::Result<()> {
+        let asset_usd = self.deposit_usd * self.asset_share;
+        let (asset_amount, stable_amount) =
+            market.add_liquidity(asset_usd, self.deposit_usd - asset_usd)?;
+        self.asset_amount = asset_amount;
+        self.stable_amount = stable_amount;
+        Ok(())
+    }
+}
+
+pub struct OpenPosition {
+    pub position: Position,
+    pub close_time: i64,
+}
+
+#[derive(Default)]
+pub struct Trader {
+    pub position: Option<OpenPosition>,
+}
+
+impl Trader {
+    // Decides whether to open a new position, returns (side, collateral_usd, leverage, hold_sec)
+    pub fn get_order(&self, config: &Config, rng: &mut Rng) -> Option<(Side, f64, f64, i64)> {
+        if self.position.is_some() || rng.next_f64() >= config.trade_chance {
+            return None;
+        }
+        let side = if rng.next_f64() < config.long_chance {
+            Side::Long
+        } else {
+            Side::Short
+        };
+        let collateral_usd = rng.range(config.min_collateral_usd, config.max_collateral_usd);
+        let leverage = rng.range(config.min_leverage, config.max_leverage);
+        let hold_sec = (rng.range(1.0, config.max_hold_minutes.max(1) as f64) * 60.0) as i64;
+        Some((side, collateral_usd, leverage, hold_sec))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::new(42);
+        let mut other = Rng::new(42);
+        for _ in 0..1_000 {
+            let value = rng.range(2.0, 3.0);
+            assert!((2.0..3.0).contains(&value));
+            assert_eq!(value, other.range(2.0, 3.0));
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+}
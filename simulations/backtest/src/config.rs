@@ -0,0 +1,329 @@
+//! Backtest configuration and command line overrides
+
+use {
+    perpetuals::state::custody::{
+        BorrowRateModel, BorrowRateModelParams, BorrowRateParams, Custody, Fees, FeesMode,
+        PricingParams, RateBreakpoint, SpreadMode, SpreadParams,
+    },
+    std::{path::PathBuf, str::FromStr},
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub asset_prices: PathBuf,
+    // stable collateral prices, 1 USD if not set
+    pub stable_prices: Option<PathBuf>,
+    // per-step csv output
+    pub output: Option<PathBuf>,
+    pub seed: u64,
+
+    // liquidity providers
+    pub lp_deposit_usd: f64,
+    // share of the deposit that goes to the asset custody, the rest goes to stables
+    pub lp_asset_share: f64,
+
+    // traders
+    pub traders: usize,
+    // probability for an idle trader to open a position at every step
+    pub trade_chance: f64,
+    pub long_chance: f64,
+    pub min_leverage: f64,
+    pub max_leverage: f64,
+    pub min_collateral_usd: f64,
+    pub max_collateral_usd: f64,
+    pub max_hold_minutes: u64,
+
+    // protocol parameters
+    pub pricing: PricingParams,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+    pub borrow_rate_model: BorrowRateModelParams,
+    pub spread: SpreadParams,
+    pub max_price_error: u64,
+    pub max_price_age_sec: u32,
+    pub max_reducing_price_age_sec: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            asset_prices: PathBuf::from("../data/Btc/btc-1.csv"),
+            stable_prices: None,
+            output: None,
+            seed: 1,
+            lp_deposit_usd: 1_000_000.0,
+            lp_asset_share: 0.5,
+            traders: 100,
+            trade_chance: 0.01,
+            long_chance: 0.5,
+            min_leverage: 1.0,
+            max_leverage: 20.0,
+            min_collateral_usd: 100.0,
+            max_collateral_usd: 5_000.0,
+            max_hold_minutes: 240,
+            pricing: PricingParams {
+                use_ema: true,
+                use_unrealized_pnl_in_aum: true,
+                trade_spread_long: 10,
+                trade_spread_short: 10,
+                swap_spread: 0,
+                min_initial_leverage: 10_000,
+                max_initial_leverage: 500_000,
+                max_leverage: 1_000_000,
+                max_payoff_mult: 10_000,
+                max_utilization: 9_000,
+                max_position_locked_usd: 0,
+                max_total_locked_usd: 0,
+            },
+            fees: Fees {
+                mode: FeesMode::Linear,
+                ratio_mult: 20_000,
+                utilization_mult: 20_000,
+                swap_in: 0,
+                swap_out: 0,
+                stable_swap_in: 0,
+                stable_swap_out: 0,
+                add_liquidity: 0,
+                remove_liquidity: 0,
+                open_position: 10,
+                close_position: 10,
+                liquidation: 50,
+                protocol_share: 1_000,
+                fee_max: 0,
+                fee_optimal: 0,
+            },
+            borrow_rate: BorrowRateParams {
+                base_rate: 0,
+                slope1: 80_000,
+                slope2: 120_000,
+                optimal_utilization: 800_000_000,
+            },
+            borrow_rate_model: BorrowRateModelParams::default(),
+            spread: SpreadParams::default(),
+            max_price_error: 100,
+            max_price_age_sec: 60,
+            max_reducing_price_age_sec: 60,
+        }
+    }
+}
+
+impl Config {
+    pub const USAGE: &'static str = "Usage: backtest [--option value]...
+
+Data:
+  --asset-prices <path>          asset price csv from simulations/data
+  --stable-prices <path>         stable collateral price csv (default: 1 USD)
+  --output <path>                write per-step stats to a csv file
+  --seed <n>
+
+Agents:
+  --lp-deposit-usd <usd>         --lp-asset-share <0..1>
+  --traders <n>                  --trade-chance <0..1>
+  --long-chance <0..1>           --min-leverage <x>
+  --max-leverage <x>             --min-collateral-usd <usd>
+  --max-collateral-usd <usd>     --max-hold-minutes <n>
+
+Protocol (same units as on-chain params):
+  --trade-spread-long --trade-spread-short --min-initial-leverage
+  --max-initial-leverage --max-leverage-bps --max-payoff-mult
+  --max-utilization --max-position-locked-usd --max-total-locked-usd
+  --use-ema --use-unrealized-pnl-in-aum
+  --open-position-fee --close-position-fee --liquidation-fee
+  --utilization-mult --protocol-share
+  --base-rate --slope1 --slope2 --optimal-utilization
+  --borrow-rate-model <kinked|multi-kink|adaptive> --per-side-rates
+  --rate-breakpoints <utilization:rate,...> --adjustment-speed
+  --min-target-rate --max-target-rate --curve-steepness
+  --spread-mode <fixed|confidence> --conf-spread-mult
+  --max-price-error --max-price-age-sec --max-reducing-price-age-sec";
+
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(key) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", key))?;
+            config.set(&key, &value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "--asset-prices" => self.asset_prices = PathBuf::from(value),
+            "--stable-prices" => self.stable_prices = Some(PathBuf::from(value)),
+            "--output" => self.output = Some(PathBuf::from(value)),
+            "--seed" => self.seed = parse(key, value)?,
+            "--lp-deposit-usd" => self.lp_deposit_usd = parse(key, value)?,
+            "--lp-asset-share" => self.lp_asset_share = parse(key, value)?,
+            "--traders" => self.traders = parse(key, value)?,
+            "--trade-chance" => self.trade_chance = parse(key, value)?,
+            "--long-chance" => self.long_chance = parse(key, value)?,
+            "--min-leverage" => self.min_leverage = parse(key, value)?,
+            "--max-leverage" => self.max_leverage = parse(key, value)?,
+            "--min-collateral-usd" => self.min_collateral_usd = parse(key, value)?,
+            "--max-collateral-usd" => self.max_collateral_usd = parse(key, value)?,
+            "--max-hold-minutes" => self.max_hold_minutes = parse(key, value)?,
+            "--use-ema" => self.pricing.use_ema = parse(key, value)?,
+            "--use-unrealized-pnl-in-aum" => {
+                self.pricing.use_unrealized_pnl_in_aum = parse(key, value)?
+            }
+            "--trade-spread-long" => self.pricing.trade_spread_long = parse(key, value)?,
+            "--trade-spread-short" => self.pricing.trade_spread_short = parse(key, value)?,
+            "--min-initial-leverage" => self.pricing.min_initial_leverage = parse(key, value)?,
+            "--max-initial-leverage" => self.pricing.max_initial_leverage = parse(key, value)?,
+            "--max-leverage-bps" => self.pricing.max_leverage = parse(key, value)?,
+            "--max-payoff-mult" => self.pricing.max_payoff_mult = parse(key, value)?,
+            "--max-utilization" => self.pricing.max_utilization = parse(key, value)?,
+            "--max-position-locked-usd" => {
+                self.pricing.max_position_locked_usd = parse(key, value)?
+            }
+            "--max-total-locked-usd" => self.pricing.max_total_locked_usd = parse(key, value)?,
+            "--open-position-fee" => self.fees.open_position = parse(key, value)?,
+            "--close-position-fee" => self.fees.close_position = parse(key, value)?,
+            "--liquidation-fee" => self.fees.liquidation = parse(key, value)?,
+            "--utilization-mult" => self.fees.utilization_mult = parse(key, value)?,
+            "--protocol-share" => self.fees.protocol_share = parse(key, value)?,
+            "--base-rate" => self.borrow_rate.base_rate = parse(key, value)?,
+            "--slope1" => self.borrow_rate.slope1 = parse(key, value)?,
+            "--slope2" => self.borrow_rate.slope2 = parse(key, value)?,
+            "--optimal-utilization" => self.borrow_rate.optimal_utilization = parse(key, value)?,
+            "--borrow-rate-model" => {
+                self.borrow_rate_model.model = match value {
+                    "kinked" => BorrowRateModel::Kinked,
+                    "multi-kink" => BorrowRateModel::MultiKink,
+                    "adaptive" => BorrowRateModel::Adaptive,
+                    _ => return Err(format!("Invalid value for {}: {}", key, value)),
+                }
+            }
+            "--per-side-rates" => self.borrow_rate_model.per_side = parse(key, value)?,
+            "--rate-breakpoints" => {
+                let breakpoints: Vec<&str> = value.split(',').collect();
+                if breakpoints.len() > Custody::MAX_RATE_BREAKPOINTS {
+                    return Err(format!("Too many breakpoints for {}", key));
+                }
+                self.borrow_rate_model.breakpoints = Default::default();
+                for (idx, breakpoint) in breakpoints.iter().enumerate() {
+                    let (utilization, rate) = breakpoint
+                        .split_once(':')
+                        .ok_or_else(|| format!("Invalid value for {}: {}", key, value))?;
+                    self.borrow_rate_model.breakpoints[idx] = RateBreakpoint {
+                        utilization: parse(key, utilization)?,
+                        rate: parse(key, rate)?,
+                    };
+                }
+            }
+            "--adjustment-speed" => self.borrow_rate_model.adjustment_speed = parse(key, value)?,
+            "--min-target-rate" => self.borrow_rate_model.min_target_rate = parse(key, value)?,
+            "--max-target-rate" => self.borrow_rate_model.max_target_rate = parse(key, value)?,
+            "--curve-steepness" => self.borrow_rate_model.curve_steepness = parse(key, value)?,
+            "--spread-mode" => {
+                self.spread.mode = match value {
+                    "fixed" => SpreadMode::Fixed,
+                    "confidence" => SpreadMode::Confidence,
+                    _ => return Err(format!("Invalid value for {}: {}", key, value)),
+                }
+            }
+            "--conf-spread-mult" => self.spread.conf_mult = parse(key, value)?,
+            "--max-price-error" => self.max_price_error = parse(key, value)?,
+            "--max-price-age-sec" => self.max_price_age_sec = parse(key, value)?,
+            "--max-reducing-price-age-sec" => self.max_reducing_price_age_sec = parse(key, value)?,
+            _ => return Err(format!("Unknown option {}", key)),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.pricing.validate()
+            || !self.fees.validate()
+            || !self.borrow_rate.validate()
+            || !self.borrow_rate_model.validate(&self.borrow_rate)
+            || !self.spread.validate()
+        {
+            return Err("Invalid protocol parameters".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.lp_asset_share)
+            || !(0.0..=1.0).contains(&self.trade_chance)
+            || !(0.0..=1.0).contains(&self.long_chance)
+        {
+            return Err("Shares and chances must be within [0, 1]".to_string());
+        }
+        if self.lp_deposit_usd <= 0.0
+            || self.min_leverage < 1.0
+            || self.min_leverage > self.max_leverage
+            || self.min_collateral_usd <= 0.0
+            || self.min_collateral_usd > self.max_collateral_usd
+        {
+            return Err("Invalid agent parameters".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", key, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_args() {
+        let args = [
+            "--traders",
+            "10",
+            "--max-utilization",
+            "8000",
+            "--use-ema",
+            "false",
+        ];
+        let config = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(config.traders, 10);
+        assert_eq!(config.pricing.max_utilization, 8_000);
+        assert!(!config.pricing.use_ema);
+
+        assert!(Config::from_args(["--traders".to_string()]).is_err());
+        assert!(Config::from_args(["--unknown".to_string(), "1".to_string()]).is_err());
+        assert!(Config::from_args(["--long-chance".to_string(), "2".to_string()]).is_err());
+
+        let args = ["--spread-mode", "confidence", "--conf-spread-mult", "20000"];
+        let config = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(config.spread.mode, SpreadMode::Confidence);
+        assert_eq!(config.spread.conf_mult, 20_000);
+        // confidence mode requires a multiplier
+        assert!(
+            Config::from_args(["--spread-mode".to_string(), "confidence".to_string()]).is_err()
+        );
+
+        let args = [
+            "--borrow-rate-model",
+            "multi-kink",
+            "--rate-breakpoints",
+            "500000000:40000,1000000000:200000",
+            "--per-side-rates",
+            "true",
+        ];
+        let config = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(config.borrow_rate_model.model, BorrowRateModel::MultiKink);
+        assert_eq!(
+            config.borrow_rate_model.breakpoints[1],
+            RateBreakpoint {
+                utilization: 1_000_000_000,
+                rate: 200_000,
+            }
+        );
+        assert!(config.borrow_rate_model.per_side);
+        // multi-kink curve requires breakpoints
+        assert!(Config::from_args(
+            ["--borrow-rate-model", "multi-kink"]
+                .iter()
+                .map(|arg| arg.to_string())
+        )
+        .is_err());
+    }
+}
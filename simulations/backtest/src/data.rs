@@ -0,0 +1,145 @@
+//! Price series loading from simulations/data csv files
+
+use std::{fs, io, path::Path};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PricePoint {
+    // unix timestamp
+    pub time: i64,
+    pub price: f64,
+    pub ema_price: f64,
+    pub confidence: f64,
+}
+
+// Loads a price series, the file must have a "t" datetime column and a "price"
+// (or "c" for stables) column, "emaPrice" and "confidence" are used if present
+pub fn load_csv(path: &Path) -> io::Result<Vec<PricePoint>> {
+    let content = fs::read_to_string(path)?;
+    parse_csv(&content).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), err),
+        )
+    })
+}
+
+pub fn parse_csv(content: &str) -> Result<Vec<PricePoint>, String> {
+    let mut lines = content.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or("Empty file")?
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|col| *col == name);
+
+    let time_col = column("t").ok_or("Missing \"t\" column")?;
+    let price_col = column("price")
+        .or_else(|| column("c"))
+        .ok_or("Missing \"price\" column")?;
+    let ema_col = column("emaPrice");
+    let conf_col = column("confidence");
+
+    let mut points = Vec::new();
+    for (idx, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        let get = |col: usize| {
+            values
+                .get(col)
+                .ok_or_else(|| format!("Line {}: missing column {}", idx + 2, col))
+        };
+        let parse = |col: usize| -> Result<f64, String> {
+            get(col)?
+                .parse::<f64>()
+                .map_err(|err| format!("Line {}: {}", idx + 2, err))
+        };
+
+        let time = parse_datetime(get(time_col)?)
+            .ok_or_else(|| format!("Line {}: invalid datetime", idx + 2))?;
+        let price = parse(price_col)?;
+        let ema_price = match ema_col {
+            Some(col) => parse(col)?,
+            None => price,
+        };
+        let confidence = match conf_col {
+            Some(col) => parse(col)?,
+            None => 0.0,
+        };
+        points.push(PricePoint {
+            time,
+            price,
+            ema_price,
+            confidence,
+        });
+    }
+
+    Ok(points)
+}
+
+// Parses "YYYY-MM-DD HH:MM:SS[.fff]" UTC datetime into a unix timestamp
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    let (date, time) = value.split_once(' ')?;
+    let mut date_parts = date.split('-').map(|part| part.parse::<i64>().ok());
+    let year = date_parts.next()??;
+    let month = date_parts.next()??;
+    let day = date_parts.next()??;
+
+    let time = time.split('.').next()?;
+    let mut time_parts = time.split(':').map(|part| part.parse::<i64>().ok());
+    let hour = time_parts.next()??;
+    let minute = time_parts.next()??;
+    let second = time_parts.next()??;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// Number of days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(parse_datetime("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(parse_datetime("2022-05-11 00:01:00"), Some(1652227260));
+        assert_eq!(parse_datetime("2022-05-11 00:01:00.136"), Some(1652227260));
+        assert_eq!(parse_datetime("2022-13-11 00:01:00"), None);
+        assert_eq!(parse_datetime("2022-05-11"), None);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let content = "s,t,c,price,emaPrice,confidence\n\
+                       o,2022-05-11 00:00:00,1.0,30991.15,30935.48,9.84\n\
+                       ,2022-05-11 00:01:00,1.0,30992.5,30936.24,13.0\n";
+        let points = parse_csv(content).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].time - points[0].time, 60);
+        assert_eq!(points[0].price, 30991.15);
+        assert_eq!(points[0].ema_price, 30935.48);
+        assert_eq!(points[1].confidence, 13.0);
+
+        let content = "s,t,c,o,h,l,v\no,2022-05-11 00:00:00,1.00002525,1.0,1.00005,1.0,0\n";
+        let points = parse_csv(content).unwrap();
+        assert_eq!(points[0].price, 1.00002525);
+        assert_eq!(points[0].ema_price, 1.00002525);
+
+        assert!(parse_csv("s,c\no,1.0\n").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Backtests perpetuals pool parameters by replaying historical prices from
+//! simulations/data through the program's pool and custody math.
+
+#![allow(clippy::result_large_err)]
+
+mod agents;
+mod config;
+mod data;
+mod market;
+mod simulation;
+
+use {
+    anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs},
+    config::Config,
+    std::{fs::File, io::Write, process},
+};
+
+// Silences program logs
+struct QuietStubs;
+
+impl SyscallStubs for QuietStubs {
+    fn sol_log(&self, _message: &str) {}
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", Config::USAGE);
+        return Ok(());
+    }
+    let config = Config::from_args(args).map_err(|err| format!("{}\n\n{}", err, Config::USAGE))?;
+
+    set_syscall_stubs(Box::new(QuietStubs));
+
+    let asset_prices = data::load_csv(&config.asset_prices).map_err(|err| err.to_string())?;
+    let stable_prices = match &config.stable_prices {
+        Some(path) => data::load_csv(path).map_err(|err| err.to_string())?,
+        None => Vec::new(),
+    };
+
+    let mut output = match &config.output {
+        Some(path) => Some(File::create(path).map_err(|err| err.to_string())?),
+        None => None,
+    };
+    let report = simulation::run(
+        &config,
+        &asset_prices,
+        &stable_prices,
+        output.as_mut().map(|file| file as &mut dyn Write),
+    )?;
+
+    println!("Steps:             {}", report.steps);
+    println!(
+        "Period:            {:.1} days",
+        (report.end_time - report.start_time) as f64 / 86400.0
+    );
+    println!("Start AUM:         {:.2} USD", report.start_aum_usd);
+    println!("End AUM:           {:.2} USD", report.end_aum_usd);
+    println!("LP return:         {:.4}%", report.lp_return() * 100.0);
+    println!("Hold return:       {:.4}%", report.hold_return() * 100.0);
+    println!("Fees:              {:.2} USD", report.fees_usd);
+    println!("Interest:          {:.2} USD", report.interest_usd);
+    println!("Trader profit:     {:.2} USD", report.trader_profit_usd);
+    println!("Trader loss:       {:.2} USD", report.trader_loss_usd);
+    println!("Bad debt:          {:.2} USD", report.bad_debt_usd);
+    println!(
+        "Utilization:       avg {:.2}% / {:.2}%, max {:.2}% / {:.2}% (asset / stable)",
+        report.avg_utilization.0 * 100.0,
+        report.avg_utilization.1 * 100.0,
+        report.max_utilization.0 * 100.0,
+        report.max_utilization.1 * 100.0
+    );
+    println!(
+        "Positions:         {} opened, {} closed, {} liquidated",
+        report.positions_opened, report.positions_closed, report.liquidations
+    );
+    println!("Rejected orders:   {}", report.rejected_orders);
+    println!("Oracle errors:     {}", report.oracle_errors);
+
+    Ok(())
+}
@@ -0,0 +1,676 @@
+//! Off-chain pool and custody state driven by the program's own pricing and risk math.
+//! Trade handlers mirror the accounting of the corresponding instructions.
+
+use {
+    crate::{config::Config, data::PricePoint},
+    anchor_lang::{prelude::*, AccountSerialize},
+    perpetuals::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            migration::Versioned,
+            oracle::{ActionClass, CustomOracle, OracleParams, OraclePrice, OracleType},
+            perpetuals::{Permissions, Perpetuals},
+            pool::{AumCalcMode, Pool, TokenRatios},
+            position::{Position, Side},
+        },
+    },
+};
+
+pub const ASSET_DECIMALS: u8 = 9;
+pub const STABLE_DECIMALS: u8 = 6;
+pub const ORACLE_EXPONENT: i32 = -8;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CloseResult {
+    pub fee_usd: u64,
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    pub interest_usd: u64,
+}
+
+pub struct Market {
+    pub pool: Pool,
+    pub custody: Custody,
+    pub stable_custody: Custody,
+    custody_key: Pubkey,
+    stable_custody_key: Pubkey,
+    custody_oracle: CustomOracle,
+    stable_oracle: CustomOracle,
+    pub curtime: i64,
+}
+
+struct Prices {
+    token: OraclePrice,
+    token_ema: OraclePrice,
+    stable: OraclePrice,
+    stable_ema: OraclePrice,
+}
+
+impl Market {
+    pub fn new(config: &Config, start_time: i64) -> Self {
+        let custody_key = Pubkey::new_unique();
+        let stable_custody_key = Pubkey::new_unique();
+        let pool_key = Pubkey::new_unique();
+
+        let permissions = Permissions {
+            allow_swap: true,
+            allow_add_liquidity: true,
+            allow_remove_liquidity: true,
+            allow_open_position: true,
+            allow_close_position: true,
+            allow_pnl_withdrawal: true,
+            allow_collateral_withdrawal: true,
+            allow_size_change: true,
+        };
+
+        let new_custody = |decimals: u8, is_stable: bool| {
+            let mut custody = Custody {
+                version: Custody::CURRENT_VERSION,
+                pool: pool_key,
+                mint: Pubkey::new_unique(),
+                token_account: Pubkey::new_unique(),
+                decimals,
+                is_stable,
+                oracle: OracleParams {
+                    oracle_account: Pubkey::new_unique(),
+                    oracle_type: OracleType::Custom,
+                    oracle_authority: Pubkey::default(),
+                    max_price_error: config.max_price_error,
+                    max_price_age_sec: config.max_price_age_sec,
+                    max_reducing_price_age_sec: config.max_reducing_price_age_sec,
+                },
+                pricing: config.pricing,
+                permissions,
+                fees: config.fees,
+                borrow_rate: config.borrow_rate,
+                borrow_rate_model: config.borrow_rate_model,
+                spread: config.spread,
+                ..Custody::default()
+            };
+            custody.borrow_rate_state.last_update = start_time;
+            custody
+        };
+
+        let ratios = TokenRatios {
+            target: 5_000,
+            min: 0,
+            max: 10_000,
+        };
+
+        Self {
+            pool: Pool {
+                version: Pool::CURRENT_VERSION,
+                name: "Backtest".to_string(),
+                custodies: vec![custody_key, stable_custody_key],
+                ratios: vec![ratios, ratios],
+                inception_time: start_time,
+                ..Pool::default()
+            },
+            custody: new_custody(ASSET_DECIMALS, false),
+            stable_custody: new_custody(STABLE_DECIMALS, true),
+            custody_key,
+            stable_custody_key,
+            custody_oracle: CustomOracle::default(),
+            stable_oracle: CustomOracle::default(),
+            curtime: start_time,
+        }
+    }
+
+    // Publishes new oracle prices and refreshes borrow rates
+    pub fn set_prices(&mut self, asset: &PricePoint, stable: &PricePoint) -> Result<()> {
+        self.curtime = asset.time;
+        for (oracle, point) in [
+            (&mut self.custody_oracle, asset),
+            (&mut self.stable_oracle, stable),
+        ] {
+            oracle.set(
+                to_oracle_price(point.price),
+                ORACLE_EXPONENT,
+                to_oracle_price(point.confidence),
+                to_oracle_price(point.ema_price),
+                asset.time,
+            );
+        }
+        self.custody.update_borrow_rate(self.curtime)?;
+        self.stable_custody.update_borrow_rate(self.curtime)
+    }
+
+    pub fn add_liquidity(&mut self, asset_usd: f64, stable_usd: f64) -> Result<(u64, u64)> {
+        let prices = self.get_prices(ActionClass::Increase)?;
+        let asset_amount = prices
+            .token
+            .get_token_amount(to_usd(asset_usd), self.custody.decimals)?;
+        let stable_amount = prices
+            .stable
+            .get_token_amount(to_usd(stable_usd), self.stable_custody.decimals)?;
+        self.custody.assets.owned = math::checked_add(self.custody.assets.owned, asset_amount)?;
+        self.stable_custody.assets.owned =
+            math::checked_add(self.stable_custody.assets.owned, stable_amount)?;
+        Ok((asset_amount, stable_amount))
+    }
+
+    pub fn get_aum_usd(&self) -> Result<u128> {
+        let mut custody_data = serialize(&self.custody)?;
+        let mut stable_custody_data = serialize(&self.stable_custody)?;
+        let mut oracle_data = serialize(&self.custody_oracle)?;
+        let mut stable_oracle_data = serialize(&self.stable_oracle)?;
+        let mut lamports = [1u64; 4];
+        let [l0, l1, l2, l3] = &mut lamports;
+
+        let accounts = [
+            account_info(&self.custody_key, l0, &mut custody_data),
+            account_info(&self.stable_custody_key, l1, &mut stable_custody_data),
+            account_info(&self.custody.oracle.oracle_account, l2, &mut oracle_data),
+            account_info(
+                &self.stable_custody.oracle.oracle_account,
+                l3,
+                &mut stable_oracle_data,
+            ),
+        ];
+
+        self.pool
+            .get_assets_under_management_usd(AumCalcMode::Last, &accounts, self.curtime)
+    }
+
+    // Current token price in USD
+    pub fn get_asset_price(&self) -> Result<f64> {
+        self.get_prices(ActionClass::Increase)?
+            .token
+            .checked_as_f64()
+    }
+
+    // Returns (asset, stable) utilization with BPS_DECIMALS
+    pub fn get_utilization(&self) -> (u64, u64) {
+        let utilization = |custody: &Custody| {
+            if custody.assets.owned == 0 {
+                0
+            } else {
+                (custody.assets.locked as u128 * Perpetuals::BPS_POWER
+                    / custody.assets.owned as u128) as u64
+            }
+        };
+        (
+            utilization(&self.custody),
+            utilization(&self.stable_custody),
+        )
+    }
+
+    // Opens a position, mirrors open_position instruction. Returns the position and
+    // the entry fee in USD.
+    pub fn open_position(
+        &mut self,
+        side: Side,
+        collateral_usd: f64,
+        leverage: f64,
+    ) -> Result<(Position, u64)> {
+        let curtime = self.curtime;
+        let prices = self.get_prices(ActionClass::Increase)?;
+        let use_collateral_custody = side == Side::Short;
+        let mut custody = self.custody.clone();
+        let mut collateral_custody = if use_collateral_custody {
+            self.stable_custody.clone()
+        } else {
+            self.custody.clone()
+        };
+        let (collateral_token_price, collateral_token_ema_price) =
+            self.get_collateral_prices(&prices, side);
+
+        let min_collateral_price = collateral_token_price
+            .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
+
+        let position_price =
+            self.pool
+                .get_entry_price(&prices.token, &prices.token_ema, side, &custody, curtime)?;
+
+        // compute position parameters
+        let position_oracle_price = OraclePrice {
+            price: position_price,
+            exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+            conf: 0,
+        };
+        let collateral = min_collateral_price
+            .get_token_amount(to_usd(collateral_usd), collateral_custody.decimals)?;
+        let size = position_oracle_price
+            .get_token_amount(to_usd(collateral_usd * leverage), custody.decimals)?;
+        require!(
+            collateral > 0 && size > 0,
+            PerpetualsError::InsufficientAmountReturned
+        );
+
+        let size_usd = position_oracle_price.get_asset_amount_usd(size, custody.decimals)?;
+        let collateral_usd =
+            min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
+
+        let locked_amount = if use_collateral_custody {
+            custody.get_locked_amount(
+                min_collateral_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+                side,
+            )?
+        } else {
+            custody.get_locked_amount(size, side)?
+        };
+
+        let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+            if use_collateral_custody {
+                let max_collateral_price = if collateral_token_price < collateral_token_ema_price {
+                    collateral_token_ema_price
+                } else {
+                    collateral_token_price
+                };
+                max_collateral_price
+                    .get_asset_amount_usd(locked_amount, collateral_custody.decimals)?
+            } else {
+                position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+            }
+        } else {
+            size_usd
+        };
+
+        // compute fee
+        let mut fee_amount = self.pool.get_entry_fee(
+            custody.fees.open_position,
+            size,
+            locked_amount,
+            &collateral_custody,
+            0,
+        )?;
+        let fee_amount_usd = prices
+            .token_ema
+            .get_asset_amount_usd(fee_amount, custody.decimals)?;
+        if use_collateral_custody {
+            fee_amount = collateral_token_ema_price
+                .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+        }
+
+        let position = Position {
+            version: Position::CURRENT_VERSION,
+            pool: collateral_custody.pool,
+            custody: self.custody_key,
+            collateral_custody: if use_collateral_custody {
+                self.stable_custody_key
+            } else {
+                self.custody_key
+            },
+            open_time: curtime,
+            side,
+            price: position_price,
+            size_usd,
+            borrow_size_usd,
+            collateral_usd,
+            cumulative_interest_snapshot: collateral_custody
+                .get_cumulative_interest(side, curtime)?,
+            locked_amount,
+            collateral_amount: collateral,
+            ..Position::default()
+        };
+
+        // check position risk
+        require!(
+            position.locked_amount > 0,
+            PerpetualsError::InsufficientAmountReturned
+        );
+        require!(
+            self.pool.check_leverage(
+                &position,
+                &prices.token,
+                &prices.token_ema,
+                &custody,
+                &collateral_token_price,
+                &collateral_token_ema_price,
+                &collateral_custody,
+                curtime,
+                true
+            )?,
+            PerpetualsError::MaxLeverage
+        );
+
+        // lock funds for potential profit payoff
+        collateral_custody.lock_funds(position.locked_amount)?;
+
+        // update custody stats
+        collateral_custody.collected_fees.open_position_usd = collateral_custody
+            .collected_fees
+            .open_position_usd
+            .wrapping_add(fee_amount_usd);
+        collateral_custody.assets.collateral =
+            math::checked_add(collateral_custody.assets.collateral, collateral)?;
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        collateral_custody.assets.protocol_fees =
+            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+        if use_collateral_custody {
+            custody.volume_stats.open_position_usd = custody
+                .volume_stats
+                .open_position_usd
+                .wrapping_add(size_usd);
+            custody.trade_stats.oi_short_usd =
+                math::checked_add(custody.trade_stats.oi_short_usd, size_usd)?;
+            custody.add_position(
+                &position,
+                &prices.token_ema,
+                curtime,
+                Some(&mut collateral_custody),
+            )?;
+            collateral_custody.update_borrow_rate(curtime)?;
+            self.custody = custody;
+            self.stable_custody = collateral_custody;
+        } else {
+            collateral_custody.volume_stats.open_position_usd = collateral_custody
+                .volume_stats
+                .open_position_usd
+                .wrapping_add(size_usd);
+            collateral_custody.trade_stats.oi_long_usd =
+                math::checked_add(collateral_custody.trade_stats.oi_long_usd, size_usd)?;
+            collateral_custody.add_position(&position, &prices.token_ema, curtime, None)?;
+            collateral_custody.update_borrow_rate(curtime)?;
+            self.custody = collateral_custody;
+        }
+
+        Ok((position, fee_amount_usd))
+    }
+
+    // Returns true if the position is above max leverage
+    pub fn is_liquidatable(&self, position: &Position) -> Result<bool> {
+        let prices = self.get_prices(ActionClass::Reduce)?;
+        let (collateral_token_price, collateral_token_ema_price) =
+            self.get_collateral_prices(&prices, position.side);
+        Ok(!self.pool.check_leverage(
+            position,
+            &prices.token,
+            &prices.token_ema,
+            &self.custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            self.get_collateral_custody(position.side),
+            self.curtime,
+            false,
+        )?)
+    }
+
+    // Closes or liquidates the position, mirrors close_position and liquidate instructions
+    pub fn close_position(
+        &mut self,
+        position: &Position,
+        liquidation: bool,
+    ) -> Result<CloseResult> {
+        let curtime = self.curtime;
+        let prices = self.get_prices(ActionClass::Reduce)?;
+        let use_collateral_custody = position.side == Side::Short;
+        let mut custody = self.custody.clone();
+        let mut collateral_custody = self.get_collateral_custody(position.side).clone();
+        let (collateral_token_price, collateral_token_ema_price) =
+            self.get_collateral_prices(&prices, position.side);
+
+        if liquidation {
+            require!(
+                self.is_liquidatable(position)?,
+                PerpetualsError::InvalidPositionState
+            );
+        }
+
+        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+
+        let (total_amount_out, mut fee_amount, profit_usd, loss_usd) = self.pool.get_close_amount(
+            position,
+            &prices.token,
+            &prices.token_ema,
+            &custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            &collateral_custody,
+            curtime,
+            liquidation,
+            0,
+        )?;
+
+        let fee_amount_usd = prices
+            .token_ema
+            .get_asset_amount_usd(fee_amount, custody.decimals)?;
+        if use_collateral_custody {
+            fee_amount = collateral_token_ema_price
+                .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+        }
+
+        // unlock pool funds
+        collateral_custody.unlock_funds(position.locked_amount)?;
+        require!(
+            self.pool
+                .check_available_amount(total_amount_out, &collateral_custody)?,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        // update custody stats
+        if liquidation {
+            collateral_custody.collected_fees.liquidation_usd = collateral_custody
+                .collected_fees
+                .liquidation_usd
+                .wrapping_add(fee_amount_usd);
+        } else {
+            collateral_custody.collected_fees.close_position_usd = collateral_custody
+                .collected_fees
+                .close_position_usd
+                .wrapping_add(fee_amount_usd);
+        }
+
+        if total_amount_out > position.collateral_amount {
+            let amount_lost = total_amount_out.saturating_sub(position.collateral_amount);
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
+        } else {
+            let amount_gained = position.collateral_amount.saturating_sub(total_amount_out);
+            collateral_custody.assets.owned =
+                math::checked_add(collateral_custody.assets.owned, amount_gained)?;
+        }
+        collateral_custody.assets.collateral = math::checked_sub(
+            collateral_custody.assets.collateral,
+            position.collateral_amount,
+        )?;
+
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        if self
+            .pool
+            .check_available_amount(protocol_fee, &collateral_custody)?
+        {
+            collateral_custody.assets.protocol_fees =
+                math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
+        }
+
+        let stats_custody = if use_collateral_custody {
+            &mut custody
+        } else {
+            &mut collateral_custody
+        };
+        if liquidation {
+            stats_custody.volume_stats.liquidation_usd = math::checked_add(
+                stats_custody.volume_stats.liquidation_usd,
+                position.size_usd,
+            )?;
+        } else {
+            stats_custody.volume_stats.close_position_usd = stats_custody
+                .volume_stats
+                .close_position_usd
+                .wrapping_add(position.size_usd);
+        }
+        if position.side == Side::Long {
+            stats_custody.trade_stats.oi_long_usd = stats_custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(position.size_usd);
+        } else {
+            stats_custody.trade_stats.oi_short_usd = stats_custody
+                .trade_stats
+                .oi_short_usd
+                .saturating_sub(position.size_usd);
+        }
+        stats_custody.trade_stats.profit_usd = stats_custody
+            .trade_stats
+            .profit_usd
+            .wrapping_add(profit_usd);
+        stats_custody.trade_stats.loss_usd =
+            stats_custody.trade_stats.loss_usd.wrapping_add(loss_usd);
+
+        if use_collateral_custody {
+            custody.remove_position(position, curtime, Some(&mut collateral_custody))?;
+            collateral_custody.update_borrow_rate(curtime)?;
+            self.custody = custody;
+            self.stable_custody = collateral_custody;
+        } else {
+            collateral_custody.remove_position(position, curtime, None)?;
+            collateral_custody.update_borrow_rate(curtime)?;
+            self.custody = collateral_custody;
+        }
+
+        Ok(CloseResult {
+            fee_usd: fee_amount_usd,
+            profit_usd,
+            loss_usd,
+            interest_usd,
+        })
+    }
+
+    fn get_prices(&self, action: ActionClass) -> Result<Prices> {
+        let mut oracle_data = serialize(&self.custody_oracle)?;
+        let mut stable_oracle_data = serialize(&self.stable_oracle)?;
+        let mut lamports = [1u64; 2];
+        let [l0, l1] = &mut lamports;
+        let oracle = account_info(&self.custody.oracle.oracle_account, l0, &mut oracle_data);
+        let stable_oracle = account_info(
+            &self.stable_custody.oracle.oracle_account,
+            l1,
+            &mut stable_oracle_data,
+        );
+
+        let get_price = |account: &AccountInfo, custody: &Custody, use_ema: bool| {
+            OraclePrice::new_from_oracle(account, &custody.oracle, self.curtime, use_ema, action)
+        };
+
+        Ok(Prices {
+            token: get_price(&oracle, &self.custody, false)?,
+            token_ema: get_price(&oracle, &self.custody, self.custody.pricing.use_ema)?,
+            stable: get_price(&stable_oracle, &self.stable_custody, false)?,
+            stable_ema: get_price(
+                &stable_oracle,
+                &self.stable_custody,
+                self.stable_custody.pricing.use_ema,
+            )?,
+        })
+    }
+
+    fn get_collateral_prices(&self, prices: &Prices, side: Side) -> (OraclePrice, OraclePrice) {
+        if side == Side::Short {
+            (prices.stable, prices.stable_ema)
+        } else {
+            (prices.token, prices.token_ema)
+        }
+    }
+
+    fn get_collateral_custody(&self, side: Side) -> &Custody {
+        if side == Side::Short {
+            &self.stable_custody
+        } else {
+            &self.custody
+        }
+    }
+}
+
+pub fn to_usd(amount: f64) -> u64 {
+    (amount * 10f64.powi(Perpetuals::USD_DECIMALS as i32)) as u64
+}
+
+pub fn from_usd(amount: u64) -> f64 {
+    amount as f64 / 10f64.powi(Perpetuals::USD_DECIMALS as i32)
+}
+
+fn to_oracle_price(price: f64) -> u64 {
+    (price * 10f64.powi(-ORACLE_EXPONENT)).round() as u64
+}
+
+fn serialize<T: AccountSerialize>(account: &T) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    account.try_serialize(&mut data)?;
+    Ok(data)
+}
+
+fn account_info<'a>(key: &'a Pubkey, lamports: &'a mut u64, data: &'a mut [u8]) -> AccountInfo<'a> {
+    AccountInfo::new(key, false, false, lamports, data, &perpetuals::ID, false, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_fixture() -> Market {
+        let config = Config::default();
+        let mut market = Market::new(&config, 1_000);
+        let asset = PricePoint {
+            time: 1_000,
+            price: 25_000.0,
+            ema_price: 25_000.0,
+            confidence: 10.0,
+        };
+        let stable = PricePoint {
+            time: 1_000,
+            price: 1.0,
+            ema_price: 1.0,
+            confidence: 0.0,
+        };
+        market.set_prices(&asset, &stable).unwrap();
+        market.add_liquidity(500_000.0, 500_000.0).unwrap();
+        market
+    }
+
+    #[test]
+    fn test_open_close() {
+        let mut market = get_fixture();
+        let aum_usd = market.get_aum_usd().unwrap();
+        assert_eq!(aum_usd, to_usd(1_000_000.0) as u128);
+
+        for side in [Side::Long, Side::Short] {
+            let (position, fee_usd) = market.open_position(side, 1_000.0, 10.0).unwrap();
+            assert!(position.size_usd > to_usd(9_990.0) && position.size_usd <= to_usd(10_000.0));
+            assert!(fee_usd > 0);
+            assert!(market.custody.assets.locked > 0 || market.stable_custody.assets.locked > 0);
+            assert!(!market.is_liquidatable(&position).unwrap());
+            assert!(market.close_position(&position, true).is_err());
+
+            let result = market.close_position(&position, false).unwrap();
+            assert_eq!(result.profit_usd, 0);
+            assert!(result.loss_usd > 0);
+            assert_eq!(market.custody.assets.collateral, 0);
+            assert_eq!(market.custody.assets.locked, 0);
+            assert_eq!(market.stable_custody.assets.locked, 0);
+        }
+
+        // above max initial leverage
+        assert!(market.open_position(Side::Long, 1_000.0, 60.0).is_err());
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let mut market = get_fixture();
+        let (position, _) = market.open_position(Side::Long, 1_000.0, 40.0).unwrap();
+
+        let asset = PricePoint {
+            time: 1_060,
+            price: 24_000.0,
+            ema_price: 24_000.0,
+            confidence: 10.0,
+        };
+        let stable = PricePoint {
+            time: 1_060,
+            price: 1.0,
+            ema_price: 1.0,
+            confidence: 0.0,
+        };
+        market.set_prices(&asset, &stable).unwrap();
+
+        assert!(market.is_liquidatable(&position).unwrap());
+        let result = market.close_position(&position, true).unwrap();
+        assert!(result.loss_usd > position.collateral_usd);
+        assert_eq!(market.custody.long_positions.open_positions, 0);
+    }
+}
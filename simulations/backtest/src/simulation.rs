@@ -0,0 +1,259 @@
+//! Replays a price series through the market with trader and LP agents
+
+use {
+    crate::{
+        agents::{LiquidityProvider, OpenPosition, Rng, Trader},
+        config::Config,
+        data::PricePoint,
+        market::{from_usd, CloseResult, Market},
+    },
+    perpetuals::state::position::Position,
+    std::io::Write,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub steps: usize,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub start_aum_usd: f64,
+    pub end_aum_usd: f64,
+    // value of the initial LP deposit if tokens were held instead
+    pub hold_usd: f64,
+    pub positions_opened: u64,
+    pub positions_closed: u64,
+    pub liquidations: u64,
+    pub rejected_orders: u64,
+    pub oracle_errors: u64,
+    pub fees_usd: f64,
+    pub interest_usd: f64,
+    pub trader_profit_usd: f64,
+    pub trader_loss_usd: f64,
+    // losses that exceeded position collateral
+    pub bad_debt_usd: f64,
+    pub avg_utilization: (f64, f64),
+    pub max_utilization: (f64, f64),
+}
+
+impl Report {
+    pub fn lp_return(&self) -> f64 {
+        self.end_aum_usd / self.start_aum_usd - 1.0
+    }
+
+    pub fn hold_return(&self) -> f64 {
+        self.hold_usd / self.start_aum_usd - 1.0
+    }
+
+    fn record_close(&mut self, position: &Position, result: &CloseResult) {
+        self.fees_usd += from_usd(result.fee_usd);
+        self.interest_usd += from_usd(result.interest_usd);
+        self.trader_profit_usd += from_usd(result.profit_usd);
+        self.trader_loss_usd += from_usd(result.loss_usd);
+        self.bad_debt_usd += from_usd(result.loss_usd.saturating_sub(position.collateral_usd));
+    }
+}
+
+pub fn run(
+    config: &Config,
+    asset_prices: &[PricePoint],
+    stable_prices: &[PricePoint],
+    mut output: Option<&mut dyn Write>,
+) -> Result<Report, String> {
+    let first = asset_prices.first().ok_or("Empty price series")?;
+    let mut report = Report {
+        start_time: first.time,
+        ..Report::default()
+    };
+    let mut market = Market::new(config, first.time);
+    let mut rng = Rng::new(config.seed);
+    let mut lp = LiquidityProvider::new(config);
+    let mut traders: Vec<Trader> = (0..config.traders).map(|_| Trader::default()).collect();
+    let mut stable_idx = 0;
+    let mut utilization_sum = (0.0, 0.0);
+
+    if let Some(output) = output.as_mut() {
+        writeln!(
+            output,
+            "time,price,aum_usd,asset_utilization,stable_utilization,open_positions,bad_debt_usd"
+        )
+        .map_err(|err| err.to_string())?;
+    }
+
+    for (step, point) in asset_prices.iter().enumerate() {
+        // latest stable price published at or before the current time
+        while stable_idx + 1 < stable_prices.len()
+            && stable_prices[stable_idx + 1].time <= point.time
+        {
+            stable_idx += 1;
+        }
+        let stable_point = stable_prices
+            .get(stable_idx)
+            .copied()
+            .unwrap_or(PricePoint {
+                time: point.time,
+                price: 1.0,
+                ema_price: 1.0,
+                confidence: 0.0,
+            });
+
+        if market.set_prices(point, &stable_point).is_err() {
+            report.oracle_errors += 1;
+            continue;
+        }
+        let Ok(price) = market.get_asset_price() else {
+            report.oracle_errors += 1;
+            continue;
+        };
+
+        if step == 0 {
+            lp.deposit(&mut market).map_err(|err| err.to_string())?;
+            report.start_aum_usd =
+                from_usd(market.get_aum_usd().map_err(|err| err.to_string())? as u64);
+        }
+
+        // liquidations
+        for trader in traders.iter_mut() {
+            let Some(open) = trader.position.as_ref() else {
+                continue;
+            };
+            if market.is_liquidatable(&open.position).unwrap_or(false) {
+                if let Ok(result) = market.close_position(&open.position, true) {
+                    report.liquidations += 1;
+                    report.record_close(&open.position, &result);
+                    trader.position = None;
+                }
+            }
+        }
+
+        // closes
+        for trader in traders.iter_mut() {
+            let Some(open) = trader.position.as_ref() else {
+                continue;
+            };
+            if point.time < open.close_time {
+                continue;
+            }
+            match market.close_position(&open.position, false) {
+                Ok(result) => {
+                    report.positions_closed += 1;
+                    report.record_close(&open.position, &result);
+                    trader.position = None;
+                }
+                Err(_) => report.rejected_orders += 1,
+            }
+        }
+
+        // opens
+        for trader in traders.iter_mut() {
+            let Some((side, collateral_usd, leverage, hold_sec)) =
+                trader.get_order(config, &mut rng)
+            else {
+                continue;
+            };
+            match market.open_position(side, collateral_usd, leverage) {
+                Ok((position, fee_usd)) => {
+                    report.positions_opened += 1;
+                    report.fees_usd += from_usd(fee_usd);
+                    trader.position = Some(OpenPosition {
+                        position,
+                        close_time: point.time + hold_sec,
+                    });
+                }
+                Err(_) => report.rejected_orders += 1,
+            }
+        }
+
+        // sample pool state
+        let Ok(aum_usd) = market.get_aum_usd() else {
+            report.oracle_errors += 1;
+            continue;
+        };
+        let (asset_utilization, stable_utilization) = market.get_utilization();
+        let utilization = (
+            asset_utilization as f64 / 1e4,
+            stable_utilization as f64 / 1e4,
+        );
+        utilization_sum.0 += utilization.0;
+        utilization_sum.1 += utilization.1;
+        report.max_utilization.0 = report.max_utilization.0.max(utilization.0);
+        report.max_utilization.1 = report.max_utilization.1.max(utilization.1);
+        report.steps += 1;
+        report.end_time = point.time;
+        report.end_aum_usd = from_usd(aum_usd as u64);
+        report.hold_usd = lp.asset_amount as f64 / 10f64.powi(market.custody.decimals as i32)
+            * price
+            + lp.stable_amount as f64 / 10f64.powi(market.stable_custody.decimals as i32)
+                * stable_point.price;
+
+        if let Some(output) = output.as_mut() {
+            writeln!(
+                output,
+                "{},{},{:.6},{:.4},{:.4},{},{:.6}",
+                point.time,
+                price,
+                report.end_aum_usd,
+                utilization.0,
+                utilization.1,
+                traders.iter().filter(|t| t.position.is_some()).count(),
+                report.bad_debt_usd
+            )
+            .map_err(|err| err.to_string())?;
+        }
+    }
+
+    if report.steps == 0 {
+        return Err("No valid price points".to_string());
+    }
+    report.avg_utilization = (
+        utilization_sum.0 / report.steps as f64,
+        utilization_sum.1 / report.steps as f64,
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_prices(count: usize, start: f64, step: f64) -> Vec<PricePoint> {
+        (0..count)
+            .map(|idx| {
+                let price = start + step * idx as f64;
+                PricePoint {
+                    time: 1_652_227_200 + idx as i64 * 60,
+                    price,
+                    ema_price: price,
+                    confidence: 0.0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_run() {
+        let config = Config {
+            traders: 20,
+            trade_chance: 0.2,
+            ..Config::default()
+        };
+
+        // falling market, longs get liquidated
+        let prices = get_prices(300, 30_000.0, -20.0);
+        let mut output = Vec::new();
+        let report = run(&config, &prices, &[], Some(&mut output)).unwrap();
+        assert_eq!(report.steps, 300);
+        assert!(report.positions_opened > 0);
+        assert!(report.liquidations > 0);
+        assert!(report.fees_usd > 0.0);
+        assert!(report.max_utilization.0 >= report.avg_utilization.0);
+        assert!(report.hold_return() < 0.0);
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 301);
+
+        // same seed gives the same results
+        let other = run(&config, &prices, &[], None).unwrap();
+        assert_eq!(report.end_aum_usd, other.end_aum_usd);
+
+        assert!(run(&config, &[], &[], None).is_err());
+    }
+}